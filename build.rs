@@ -0,0 +1,72 @@
+// Only does something with the c-ref-tests feature, which compiles the reference
+// C implementation for the cross-check tests in tests/c_ref.rs:
+// - GRAVITY_C_REF_DIR: directory containing api.h and the reference sources
+//   (defaults to c_ref/reference),
+// - GRAVITY_C_REF_CFLAGS: extra compiler flags, e.g. to select the parameter
+//   set matching src/config.rs,
+// - GRAVITY_C_REF_LIBS: extra libraries to link, separated by spaces.
+fn main() {
+    #[cfg(feature = "c-ref-tests")]
+    c_ref::build();
+}
+
+#[cfg(feature = "c-ref-tests")]
+mod c_ref {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    // NIST harness files, replaced by c_ref/shim.c.
+    const EXCLUDED: [&str; 2] = ["rng.c", "PQCgenKAT_sign.c"];
+
+    pub fn build() {
+        println!("cargo:rerun-if-changed=c_ref/shim.c");
+        println!("cargo:rerun-if-env-changed=GRAVITY_C_REF_DIR");
+        println!("cargo:rerun-if-env-changed=GRAVITY_C_REF_CFLAGS");
+        println!("cargo:rerun-if-env-changed=GRAVITY_C_REF_LIBS");
+
+        let dir = env::var_os("GRAVITY_C_REF_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("c_ref/reference"));
+        if !dir.join("api.h").is_file() {
+            panic!(
+                "no api.h in {}: set GRAVITY_C_REF_DIR to the reference implementation",
+                dir.display()
+            );
+        }
+        println!("cargo:rerun-if-changed={}", dir.display());
+
+        let mut build = cc::Build::new();
+        build
+            .include(&dir)
+            .file("c_ref/shim.c")
+            .flag_if_supported("-maes")
+            .flag_if_supported("-msse4.1")
+            .warnings(false);
+
+        let mut sources: Vec<PathBuf> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "c"))
+            .filter(|path| {
+                let name = path.file_name().unwrap().to_str().unwrap();
+                !EXCLUDED.contains(&name)
+            })
+            .collect();
+        sources.sort();
+        build.files(sources);
+
+        if let Ok(flags) = env::var("GRAVITY_C_REF_CFLAGS") {
+            for flag in flags.split_whitespace() {
+                build.flag(flag);
+            }
+        }
+        build.compile("gravity_c_ref");
+
+        if let Ok(libs) = env::var("GRAVITY_C_REF_LIBS") {
+            for lib in libs.split_whitespace() {
+                println!("cargo:rustc-link-lib={}", lib);
+            }
+        }
+    }
+}
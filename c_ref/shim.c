@@ -0,0 +1,127 @@
+/*
+ * Deterministic wrapper around the NIST API of the reference implementation,
+ * for the cross-check tests of the c-ref-tests feature.
+ *
+ * randombytes() is provided here instead of by the reference rng.c, and returns
+ * the seed given by the caller, so that both implementations derive the same
+ * key from the same 64 bytes.
+ *
+ * Define GRAVITY_REF_SIG_LAST if the reference crypto_sign() writes the message
+ * before the signature.
+ */
+#include <stdlib.h>
+#include <string.h>
+
+#include "api.h"
+
+static const unsigned char *rng_seed = NULL;
+static unsigned long long rng_len = 0;
+
+int randombytes(unsigned char *x, unsigned long long xlen) {
+    if (xlen > rng_len) {
+        memset(x, 0, xlen);
+        return -1;
+    }
+    memcpy(x, rng_seed, xlen);
+    rng_seed += xlen;
+    rng_len -= xlen;
+    return 0;
+}
+
+unsigned long long gravity_ref_public_key_bytes(void) {
+    return CRYPTO_PUBLICKEYBYTES;
+}
+
+unsigned long long gravity_ref_signature_bytes(void) {
+    return CRYPTO_BYTES;
+}
+
+static unsigned char *keypair(unsigned char *pk, const unsigned char *seed) {
+    unsigned char *sk = malloc(CRYPTO_SECRETKEYBYTES);
+    if (sk == NULL) {
+        return NULL;
+    }
+    rng_seed = seed;
+    rng_len = 64;
+    if (crypto_sign_keypair(pk, sk) != 0) {
+        free(sk);
+        return NULL;
+    }
+    return sk;
+}
+
+int gravity_ref_genpk(unsigned char *pk, const unsigned char *seed) {
+    unsigned char *sk = keypair(pk, seed);
+    if (sk == NULL) {
+        return -1;
+    }
+    free(sk);
+    return 0;
+}
+
+unsigned long long gravity_ref_secret_key_bytes(void) {
+    return CRYPTO_SECRETKEYBYTES;
+}
+
+/* sk must hold gravity_ref_secret_key_bytes() bytes. */
+int gravity_ref_secret_key(unsigned char *sk_out, const unsigned char *seed) {
+    unsigned char pk[CRYPTO_PUBLICKEYBYTES];
+    unsigned char *sk = keypair(pk, seed);
+    if (sk == NULL) {
+        return -1;
+    }
+    memcpy(sk_out, sk, CRYPTO_SECRETKEYBYTES);
+    free(sk);
+    return 0;
+}
+
+/* sig must hold gravity_ref_signature_bytes() bytes. */
+int gravity_ref_sign(unsigned char *sig, unsigned long long *siglen,
+                     const unsigned char *m, unsigned long long mlen,
+                     const unsigned char *seed) {
+    unsigned char pk[CRYPTO_PUBLICKEYBYTES];
+    unsigned char *sk = keypair(pk, seed);
+    unsigned char *sm = malloc(CRYPTO_BYTES + mlen);
+    unsigned long long smlen;
+    int ret = -1;
+
+    if (sk != NULL && sm != NULL && crypto_sign(sm, &smlen, m, mlen, sk) == 0 &&
+        smlen >= mlen && smlen - mlen <= CRYPTO_BYTES) {
+        *siglen = smlen - mlen;
+#ifdef GRAVITY_REF_SIG_LAST
+        memcpy(sig, sm + mlen, *siglen);
+#else
+        memcpy(sig, sm, *siglen);
+#endif
+        ret = 0;
+    }
+    free(sm);
+    free(sk);
+    return ret;
+}
+
+int gravity_ref_verify(const unsigned char *pk, const unsigned char *sig,
+                       unsigned long long siglen, const unsigned char *m,
+                       unsigned long long mlen) {
+    unsigned char *sm = malloc(siglen + mlen);
+    unsigned char *mout = malloc(siglen + mlen);
+    unsigned long long moutlen;
+    int ret = -1;
+
+    if (sm != NULL && mout != NULL) {
+#ifdef GRAVITY_REF_SIG_LAST
+        memcpy(sm, m, mlen);
+        memcpy(sm + mlen, sig, siglen);
+#else
+        memcpy(sm, sig, siglen);
+        memcpy(sm + siglen, m, mlen);
+#endif
+        ret = crypto_sign_open(mout, &moutlen, sm, siglen + mlen, pk);
+        if (ret == 0 && (moutlen != mlen || memcmp(mout, m, mlen) != 0)) {
+            ret = -1;
+        }
+    }
+    free(mout);
+    free(sm);
+    return ret;
+}
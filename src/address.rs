@@ -1,5 +1,8 @@
+use crate::config::{GRAVITY_C, GRAVITY_D, GRAVITY_MASK, MERKLE_H};
+use crate::error::{AddressOverflow, GravityError};
 use arrayref::array_mut_ref;
 use byteorder::{BigEndian, ByteOrder};
+use std::cmp::Ordering;
 use std::fmt;
 
 #[derive(PartialEq, Eq)]
@@ -8,6 +11,42 @@ pub struct Address {
     layer: u32,
 }
 
+// Index of a leaf in the cached top-level tree, i.e. of a subtree on the last
+// layer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LeafIndex(pub usize);
+
+// Instance of a PORS key pair or of a subtree within its layer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SubtreeInstance(pub u64);
+
+impl LeafIndex {
+    pub const COUNT: usize = 1 << GRAVITY_C;
+
+    pub fn is_valid(&self) -> bool {
+        self.0 < Self::COUNT
+    }
+}
+
+impl TryFrom<usize> for LeafIndex {
+    type Error = GravityError;
+
+    fn try_from(index: usize) -> Result<Self, GravityError> {
+        let index = LeafIndex(index);
+        if index.is_valid() {
+            Ok(index)
+        } else {
+            Err(GravityError::InvalidParameter("leaf index"))
+        }
+    }
+}
+
+impl SubtreeInstance {
+    pub fn is_valid(&self) -> bool {
+        self.0 & !GRAVITY_MASK == 0
+    }
+}
+
 impl fmt::Debug for Address {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -18,34 +57,110 @@ impl fmt::Debug for Address {
     }
 }
 
+impl PartialOrd for Address {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Address {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.layer, self.instance).cmp(&(other.layer, other.instance))
+    }
+}
+
 impl Address {
     pub fn new(layer: u32, instance: u64) -> Self {
         Self { layer, instance }
     }
 
-    pub fn get_instance(&self) -> usize {
-        self.instance as usize
+    // Address of the PORS key pair used for a signing index (the leaf_index of
+    // a SigningRecord): the bottom layer, GRAVITY_D, at that instance. The
+    // subtree addresses above it follow by shifting, as in signing.
+    pub fn from_signing_index(index: u64) -> Self {
+        Self::new(GRAVITY_D as u32, index)
+    }
+
+    pub fn get_instance(&self) -> u64 {
+        self.instance
+    }
+
+    pub fn get_layer(&self) -> u32 {
+        self.layer
+    }
+
+    pub fn subtree_instance(&self) -> SubtreeInstance {
+        SubtreeInstance(self.instance)
     }
 
+    // Only meaningful once the address has been shifted up to the top layer.
+    pub fn leaf_index(&self) -> Result<LeafIndex, GravityError> {
+        usize::try_from(self.instance)
+            .map_err(|_| GravityError::InvalidParameter("leaf index"))
+            .and_then(LeafIndex::try_from)
+    }
+
+    // Address arithmetic wraps around rather than panicking, so that it is
+    // panic-free for all inputs; signing and verification stay in range.
     pub fn incr_instance(&mut self) {
-        self.instance += 1;
+        self.instance = self.instance.wrapping_add(1);
     }
 
-    pub fn normalize_index(&self, mask: u64) -> (Address, usize) {
+    // Checked counterpart of incr_instance, for an instance in subtree form (as
+    // after shift, see SubtreeInstance): layer 0 has 2^GRAVITY_C subtrees, each
+    // layer below 2^MERKLE_H times more, and layer GRAVITY_D as many PORS key
+    // pairs. The address is unchanged on error.
+    pub fn increment_instance(&mut self, delta: u64) -> Result<(), AddressOverflow> {
+        let layer = self.layer as usize;
+        if layer > GRAVITY_D {
+            return Err(AddressOverflow);
+        }
+        let instance = self.instance.checked_add(delta).ok_or(AddressOverflow)?;
+        let bits = GRAVITY_C + layer * MERKLE_H;
+        if bits < 64 && instance >> bits != 0 {
+            return Err(AddressOverflow);
+        }
+        self.instance = instance;
+        Ok(())
+    }
+
+    // Whether the address, in subtree form, is in a hyper-tree of `depth`
+    // layers: the layer is below depth, and the instance below 2^GRAVITY_C
+    // times 2^MERKLE_H per layer under the top one (layer 0). Signing addresses
+    // have GRAVITY_D + 1 layers, that of the PORS key pairs included.
+    pub fn is_valid_for_layer(&self, depth: usize) -> bool {
+        let layer = self.layer as usize;
+        if layer >= depth {
+            return false;
+        }
+        let bits = GRAVITY_C + layer * MERKLE_H;
+        bits >= 64 || self.instance >> bits == 0
+    }
+
+    pub fn normalize_index(&self, mask: u64) -> (Address, u64) {
         let index = self.instance & mask;
         let address = Address {
             layer: self.layer,
             instance: self.instance - index,
         };
-        (address, index as usize)
+        (address, index)
     }
 
     pub fn next_layer(&mut self) {
-        self.layer -= 1;
+        self.layer = self.layer.wrapping_sub(1);
     }
 
+    // Shifting by 64 or more clears the instance.
     pub fn shift(&mut self, height: usize) {
-        self.instance >>= height;
+        self.instance = u32::try_from(height)
+            .ok()
+            .and_then(|h| self.instance.checked_shr(h))
+            .unwrap_or(0);
+    }
+
+    // Fixed-width hex, so that the lexicographic order matches the Ord impl.
+    pub fn canonical_string(&self) -> String {
+        format!("{:08x}:{:016x}", self.layer, self.instance)
     }
 
     pub fn to_block(&self, counter: u32) -> [u8; 16] {
@@ -57,6 +172,25 @@ impl Address {
     }
 }
 
+// Addresses of all the subtrees of a hyper-tree with `gravity_d` layers and
+// `gravity_ccc` subtrees on the top layer (GRAVITY_CCC = 2^GRAVITY_C in the
+// reference implementation), in subtree form. Layers come in signing order,
+// from the bottom one (gravity_d - 1) up to the top one, and instances in
+// increasing order within a layer, which has 2^MERKLE_H times more subtrees
+// than the one above. The count of a layer saturates at u64::MAX instances.
+pub fn for_every_subtree(gravity_d: usize, gravity_ccc: usize) -> impl Iterator<Item = Address> {
+    (0..gravity_d).rev().flat_map(move |layer| {
+        let count = u32::try_from(layer.saturating_mul(MERKLE_H))
+            .ok()
+            .and_then(|shift| {
+                let count = (gravity_ccc as u64).checked_shl(shift)?;
+                (count >> shift == gravity_ccc as u64).then_some(count)
+            })
+            .unwrap_or(u64::MAX);
+        (0..count).map(move |instance| Address::new(layer as u32, instance))
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -71,6 +205,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_canonical_string() {
+        let address = Address::new(0x01020304, 0x05060708090a0b0c);
+        assert_eq!(address.canonical_string(), "01020304:05060708090a0b0c");
+        assert_eq!(
+            Address::new(1, 2).canonical_string(),
+            Address::new(1, 2).canonical_string()
+        );
+    }
+
+    #[test]
+    fn test_ordering() {
+        let addresses = [
+            Address::new(0, 0),
+            Address::new(0, 1),
+            Address::new(0, 0x100),
+            Address::new(0, u64::MAX),
+            Address::new(1, 0),
+            Address::new(1, 5),
+            Address::new(0x10, 0),
+        ];
+        for i in 0..addresses.len() {
+            for j in 0..addresses.len() {
+                let a = &addresses[i];
+                let b = &addresses[j];
+                assert_eq!(a.cmp(b), i.cmp(&j));
+                assert_eq!(a.canonical_string().cmp(&b.canonical_string()), i.cmp(&j));
+            }
+        }
+        assert_eq!(Address::new(3, 7), Address::new(3, 7));
+    }
+
     #[test]
     fn test_get_instance() {
         let address = Address::new(0x01020304, 0x05060708090a0b0c);
@@ -78,6 +244,36 @@ mod tests {
         assert_eq!(instance, 0x05060708090a0b0c);
     }
 
+    #[test]
+    fn test_leaf_index() {
+        assert_eq!(LeafIndex::try_from(0), Ok(LeafIndex(0)));
+        let last = LeafIndex::COUNT - 1;
+        assert_eq!(LeafIndex::try_from(last), Ok(LeafIndex(last)));
+        assert_eq!(
+            LeafIndex::try_from(LeafIndex::COUNT),
+            Err(GravityError::InvalidParameter("leaf index"))
+        );
+        assert!(!LeafIndex(usize::MAX).is_valid());
+        assert_eq!(Address::new(0, 3).leaf_index(), Ok(LeafIndex(3)));
+        // Must not be truncated to 32 bits on the way.
+        assert_eq!(
+            Address::new(0, (1 << 32) | 3).leaf_index(),
+            Err(GravityError::InvalidParameter("leaf index"))
+        );
+    }
+
+    #[test]
+    fn test_subtree_instance() {
+        assert!(SubtreeInstance(0).is_valid());
+        assert!(SubtreeInstance(GRAVITY_MASK).is_valid());
+        assert_eq!(
+            SubtreeInstance(u64::MAX).is_valid(),
+            GRAVITY_MASK == u64::MAX
+        );
+        let address = Address::new(7, 0x1234);
+        assert_eq!(address.subtree_instance(), SubtreeInstance(0x1234));
+    }
+
     #[test]
     fn test_incr_instance() {
         let mut address = Address::new(0x01020304, 0x05060708090a0b0c);
@@ -91,6 +287,107 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_for_every_subtree() {
+        let ccc = 1 << GRAVITY_C;
+        let addresses: Vec<Address> = for_every_subtree(GRAVITY_D, ccc).collect();
+        let expected: usize = (0..GRAVITY_D).map(|l| ccc << (l * MERKLE_H)).sum();
+        assert_eq!(addresses.len(), expected);
+        for layer in 0..GRAVITY_D {
+            let count = addresses
+                .iter()
+                .filter(|a| a.get_layer() as usize == layer)
+                .count();
+            assert_eq!(count, ccc << (layer * MERKLE_H));
+        }
+        // Sorted by layer from the bottom up, then by instance, hence unique.
+        for pair in addresses.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            assert!(
+                a.get_layer() > b.get_layer()
+                    || (a.get_layer() == b.get_layer() && a.get_instance() < b.get_instance())
+            );
+        }
+        for mut address in addresses {
+            assert!(address.subtree_instance().is_valid());
+            assert_eq!(address.increment_instance(0), Ok(()));
+        }
+
+        let small: Vec<Address> = for_every_subtree(2, 2).collect();
+        assert_eq!(small.len(), 2 + (2 << MERKLE_H));
+        assert_eq!(small[0], Address::new(1, 0));
+        assert_eq!(
+            small[(2 << MERKLE_H) - 1],
+            Address::new(1, (2 << MERKLE_H) - 1)
+        );
+        assert_eq!(
+            small[2 << MERKLE_H..],
+            [Address::new(0, 0), Address::new(0, 1)]
+        );
+        assert_eq!(for_every_subtree(0, ccc).count(), 0);
+    }
+
+    #[test]
+    fn test_increment_instance() {
+        // Last subtree of the top layer, i.e. GRAVITY_CCC - 1 in the reference
+        // implementation.
+        let last = (1u64 << GRAVITY_C) - 1;
+        let mut address = Address::new(0, last - 1);
+        assert_eq!(address.increment_instance(1), Ok(()));
+        assert_eq!(address, Address::new(0, last));
+        assert_eq!(address.increment_instance(0), Ok(()));
+        assert_eq!(address.increment_instance(1), Err(AddressOverflow));
+        assert_eq!(address, Address::new(0, last));
+
+        let mut address = Address::new(0, 0);
+        assert_eq!(address.increment_instance(last), Ok(()));
+        assert_eq!(
+            Address::new(0, 1).increment_instance(last),
+            Err(AddressOverflow)
+        );
+
+        // PORS key pairs on the bottom layer, up to GRAVITY_MASK.
+        let mut address = Address::new(GRAVITY_D as u32, GRAVITY_MASK - 1);
+        assert_eq!(address.increment_instance(1), Ok(()));
+        assert_eq!(address.increment_instance(1), Err(AddressOverflow));
+        assert_eq!(address, Address::new(GRAVITY_D as u32, GRAVITY_MASK));
+        assert_eq!(
+            Address::new(GRAVITY_D as u32, u64::MAX).increment_instance(1),
+            Err(AddressOverflow)
+        );
+
+        // Layers below the PORS key pairs, e.g. wrapped by next_layer.
+        let mut address = Address::new(0, 0);
+        address.next_layer();
+        assert_eq!(address.increment_instance(0), Err(AddressOverflow));
+    }
+
+    #[test]
+    fn test_is_valid_for_layer() {
+        let depth = GRAVITY_D + 1;
+        let last = (1u64 << GRAVITY_C) - 1;
+        assert!(Address::new(0, 0).is_valid_for_layer(depth));
+        assert!(Address::new(0, last).is_valid_for_layer(depth));
+        assert!(!Address::new(0, last + 1).is_valid_for_layer(depth));
+
+        // Each layer has 2^MERKLE_H times more instances than the one above.
+        let bottom = GRAVITY_D as u32;
+        assert!(Address::new(bottom, GRAVITY_MASK).is_valid_for_layer(depth));
+        assert!(!Address::new(bottom, GRAVITY_MASK + 1).is_valid_for_layer(depth));
+        assert!(!Address::new(bottom, u64::MAX).is_valid_for_layer(depth));
+        let below = ((last + 1) << MERKLE_H) - 1;
+        assert!(Address::new(1, below).is_valid_for_layer(2));
+        assert!(!Address::new(1, below + 1).is_valid_for_layer(2));
+
+        // Layers from depth on, including those wrapped by next_layer.
+        assert!(!Address::new(bottom + 1, 0).is_valid_for_layer(depth));
+        assert!(!Address::new(0, 0).is_valid_for_layer(0));
+        let mut address = Address::new(0, 0);
+        address.next_layer();
+        assert!(!address.is_valid_for_layer(depth));
+        assert!(Address::new(20, u64::MAX).is_valid_for_layer(21));
+    }
+
     #[test]
     fn test_next_layer() {
         let mut address = Address::new(0x01020304, 0x05060708090a0b0c);
@@ -104,6 +401,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_wrapping() {
+        let mut address = Address::new(0, u64::MAX);
+        address.next_layer();
+        address.incr_instance();
+        assert_eq!(address, Address::new(u32::MAX, 0));
+
+        for height in [64, 65, usize::MAX].iter() {
+            let mut address = Address::new(0, u64::MAX);
+            address.shift(*height);
+            assert_eq!(address, Address::new(0, 0));
+        }
+        let mut address = Address::new(0, u64::MAX);
+        address.shift(63);
+        assert_eq!(address, Address::new(0, 1));
+    }
+
     #[test]
     fn test_shift() {
         let mut address = Address::new(0x01020304, 0x05060708090a0b0c);
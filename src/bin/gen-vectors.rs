@@ -0,0 +1,24 @@
+// Regenerates the fixtures in test_files/ for the compiled parameter set:
+//     cargo run --release --features vector-gen --bin gen-vectors [DIR]
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+fn main() {
+    let dir = env::args_os()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_files"));
+
+    match gravity::vectors::write_fixtures(&dir) {
+        Ok(names) => {
+            for name in names {
+                println!("{}", dir.join(name).display());
+            }
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }
+    }
+}
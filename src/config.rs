@@ -1,12 +1,25 @@
+use crate::error::{GravityError, ParamError};
+use crate::subtree;
+use std::fmt;
+use std::sync::OnceLock;
+
 /* Can modify */
 const TAU: usize = 16; // 16 is often good
 const K: usize = 24;
 const H: usize = 5; // 5 is often good
+#[cfg(not(feature = "porst"))]
 const D: usize = 1;
+#[cfg(not(feature = "porst"))]
 const C: usize = 10;
+// Pure PORST: the PORS public keys are the leaves of the cached tree. Its
+// keygen derives 2^C PORS keys, which is why C is small.
+#[cfg(feature = "porst")]
+const D: usize = 0;
+#[cfg(feature = "porst")]
+const C: usize = 6;
 
 /* Don't modify */
-pub const HASH_SIZE: usize = 32; // Only implemented for 32
+pub const HASH_SIZE: usize = 32; // Of Hash; other sizes need a hash::HashBackend
 
 pub const WOTS_W: usize = 16; // Only implemented for 16
 pub const WOTS_LOG_ELL1: usize = 6; // Implicitly depends on HASH_SIZE and W
@@ -29,12 +42,212 @@ const GRAVITY_HD: usize = MERKLE_H * GRAVITY_D;
 pub const GRAVITY_MASK: u64 =
     0xFFFF_FFFF_FFFF_FFFF_u64 ^ ((0xFFFF_FFFF_FFFF_FFFF_u64 << GRAVITY_HD) << GRAVITY_C);
 
+/* Derived sizes */
+pub const HASH_BACKEND: &str = "haraka";
+pub const PUBLIC_KEY_BYTES: usize = HASH_SIZE;
+pub const SECRET_SEED_BYTES: usize = 2 * HASH_SIZE;
+// Secret key of the reference implementation: seed, salt and cached top tree.
+pub const REFERENCE_SECRET_KEY_BYTES: usize =
+    SECRET_SEED_BYTES + HASH_SIZE * ((2 << GRAVITY_C) - 1);
+pub const OCTOPUS_MAX_NODES: usize = PORS_K * PORS_TAU;
+pub const PORS_SIGNATURE_BYTES: usize = HASH_SIZE * (1 + PORS_K + OCTOPUS_MAX_NODES) + 16;
+pub const SUBTREE_SIGNATURE_BYTES: usize = subtree::Signature::SIZE;
+pub const SIGNATURE_BYTES: usize =
+    PORS_SIGNATURE_BYTES + GRAVITY_D * SUBTREE_SIGNATURE_BYTES + GRAVITY_C * HASH_SIZE;
+
+// Major: serialization format. Minor: parameter set (0, 1, 2 for the S, M, L
+// configurations of the specification, 255 for custom ones). Security level:
+// targeted post-quantum security in bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GravityVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub security_level: u8,
+}
+
+pub const GRAVITY_VERSION: GravityVersion = GravityVersion {
+    major: 1,
+    minor: match (PORS_TAU, PORS_K, MERKLE_H, GRAVITY_D, GRAVITY_C) {
+        (16, 24, 5, 1, 10) => 0,
+        (16, 32, 5, 7, 15) => 1,
+        (16, 28, 5, 10, 14) => 2,
+        _ => 255,
+    },
+    security_level: (HASH_SIZE * 4) as u8,
+};
+
+impl GravityVersion {
+    pub fn to_bytes(&self) -> [u8; 3] {
+        [self.major, self.minor, self.security_level]
+    }
+
+    pub fn from_bytes(bytes: &[u8; 3]) -> Self {
+        Self {
+            major: bytes[0],
+            minor: bytes[1],
+            security_level: bytes[2],
+        }
+    }
+}
+
+impl fmt::Display for GravityVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}.{} ({}-bit)",
+            self.major, self.minor, self.security_level
+        )
+    }
+}
+
+// Shape of the hyper-tree: d layers of subtrees of height merkle_h below a
+// cached tree of height c, with ccc = 2^c and merkle_hhh = 2^merkle_h as named
+// in the reference implementation. The scheme is compiled for the constants of
+// this file (see current), so that other shapes can be described and checked,
+// but not used for signing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GravityConfig {
+    pub d: usize,
+    pub c: usize,
+    pub ccc: usize,
+    pub merkle_h: usize,
+    pub merkle_hhh: usize,
+}
+
+impl GravityConfig {
+    pub fn custom(d: usize, c: usize, merkle_h: usize) -> Result<Self, GravityError> {
+        if c >= usize::BITS as usize || merkle_h >= usize::BITS as usize {
+            return Err(GravityError::InvalidParameter("height"));
+        }
+        let config = GravityConfig {
+            d,
+            c,
+            ccc: 1 << c,
+            merkle_h,
+            merkle_hhh: 1 << merkle_h,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    // The S parameter set of the specification, the smallest one.
+    pub fn default_level1() -> Self {
+        GravityConfig {
+            d: 1,
+            c: 10,
+            ccc: 1 << 10,
+            merkle_h: 5,
+            merkle_hhh: 1 << 5,
+        }
+    }
+
+    // The parameters this crate is compiled for.
+    pub fn current() -> Self {
+        GravityConfig {
+            d: GRAVITY_D,
+            c: GRAVITY_C,
+            ccc: 1 << GRAVITY_C,
+            merkle_h: MERKLE_H,
+            merkle_hhh: 1 << MERKLE_H,
+        }
+    }
+
+    // Subtrees of height at least 1, the powers of two consistent with the
+    // heights, and c + merkle_h * d <= 64, as the instance of an address is a
+    // u64. No layer of subtrees (d = 0) is pure PORST.
+    pub fn validate(&self) -> Result<(), GravityError> {
+        if self.merkle_h == 0 {
+            return Err(GravityError::InvalidParameter("merkle_h"));
+        }
+        if 1usize.checked_shl(self.c as u32) != Some(self.ccc) {
+            return Err(GravityError::InvalidParameter("ccc"));
+        }
+        if 1usize.checked_shl(self.merkle_h as u32) != Some(self.merkle_hhh) {
+            return Err(GravityError::InvalidParameter("merkle_hhh"));
+        }
+        let height = self
+            .merkle_h
+            .checked_mul(self.d)
+            .and_then(|hd| hd.checked_add(self.c));
+        if height.is_none_or(|height| height > 64) {
+            return Err(GravityError::InvalidParameter("height"));
+        }
+        Ok(())
+    }
+}
+
+// The relations between the constants of this file that the code relies on:
+// the Winternitz chains and checksum covering a hash, k distinct PORS leaves,
+// the mask of the hyper-tree, and the shape of GravityConfig::current.
+pub fn validate_params() -> Result<(), ParamError> {
+    let log_w = WOTS_W.trailing_zeros() as usize;
+    if WOTS_W != 16 {
+        return Err(ParamError("wots_w"));
+    }
+    if WOTS_ELL1 * log_w != HASH_SIZE * 8 {
+        return Err(ParamError("wots_ell1"));
+    }
+    // The checksum of the message digits is at most ELL1 * (W - 1).
+    if WOTS_CHKSUM * log_w >= usize::BITS as usize
+        || 1 << (WOTS_CHKSUM * log_w) <= WOTS_ELL1 * (WOTS_W - 1)
+    {
+        return Err(ParamError("wots_chksum"));
+    }
+    if PORS_K == 0 || PORS_K > PORS_T {
+        return Err(ParamError("pors_k"));
+    }
+    GravityConfig::current().validate().map_err(|e| match e {
+        GravityError::InvalidParameter(name) => ParamError(name),
+        _ => ParamError("gravity"),
+    })?;
+    if GRAVITY_MASK.count_ones() as usize != GRAVITY_C + GRAVITY_HD
+        || GRAVITY_MASK.leading_zeros() + GRAVITY_MASK.count_ones() != 64
+    {
+        return Err(ParamError("gravity_mask"));
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct ParamsInfo {
+    pub id: String,
+    pub hash_backend: &'static str,
+    pub gravity_d: usize,
+    pub gravity_c: usize,
+    pub merkle_h: usize,
+    pub pors_k: usize,
+    pub pors_t: usize,
+    pub public_key_bytes: usize,
+    pub max_signature_bytes: usize,
+    pub secret_seed_bytes: usize,
+}
+
+pub fn params() -> &'static ParamsInfo {
+    static PARAMS: OnceLock<ParamsInfo> = OnceLock::new();
+    PARAMS.get_or_init(|| ParamsInfo {
+        id: format!(
+            "gravity-{}-d{}-c{}-h{}",
+            HASH_BACKEND, GRAVITY_D, GRAVITY_C, MERKLE_H
+        ),
+        hash_backend: HASH_BACKEND,
+        gravity_d: GRAVITY_D,
+        gravity_c: GRAVITY_C,
+        merkle_h: MERKLE_H,
+        pors_k: PORS_K,
+        pors_t: PORS_T,
+        public_key_bytes: PUBLIC_KEY_BYTES,
+        max_signature_bytes: SIGNATURE_BYTES,
+        secret_seed_bytes: SECRET_SEED_BYTES,
+    })
+}
+
 #[cfg(test)]
 #[derive(Debug, PartialEq)]
 pub enum ConfigType {
     S,
     M,
     L,
+    Porst,
     Unknown,
 }
 
@@ -44,6 +257,7 @@ pub fn get_config_type() -> ConfigType {
         (16, 24, 5, 1, 10) => ConfigType::S,
         (16, 32, 5, 7, 15) => ConfigType::M,
         (16, 28, 5, 10, 14) => ConfigType::L,
+        (16, 24, 5, 0, 6) => ConfigType::Porst,
         _ => ConfigType::Unknown,
     }
 }
@@ -86,4 +300,95 @@ mod tests {
     fn test_gravity() {
         assert!(GRAVITY_C + MERKLE_H * GRAVITY_D <= 64);
     }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(validate_params(), Ok(()));
+    }
+
+    #[test]
+    fn test_params() {
+        let p = params();
+        assert_eq!(p.gravity_d, GRAVITY_D);
+        assert_eq!(p.pors_t, 1 << PORS_TAU);
+        assert_eq!(p.public_key_bytes, PUBLIC_KEY_BYTES);
+        assert_eq!(p.max_signature_bytes, SIGNATURE_BYTES);
+        assert_eq!(p.secret_seed_bytes, SECRET_SEED_BYTES);
+        match get_config_type() {
+            ConfigType::S => assert_eq!(p.id, "gravity-haraka-d1-c10-h5"),
+            ConfigType::M => assert_eq!(p.id, "gravity-haraka-d7-c15-h5"),
+            ConfigType::L => assert_eq!(p.id, "gravity-haraka-d10-c14-h5"),
+            ConfigType::Porst => assert_eq!(p.id, "gravity-haraka-d0-c6-h5"),
+            ConfigType::Unknown => unimplemented!(),
+        }
+        assert!(std::ptr::eq(p, params()));
+    }
+
+    #[test]
+    fn test_gravity_config() {
+        let current = GravityConfig::current();
+        assert_eq!(current.validate(), Ok(()));
+        assert_eq!(
+            GravityConfig::custom(GRAVITY_D, GRAVITY_C, MERKLE_H),
+            Ok(current)
+        );
+        assert_eq!(
+            current == GravityConfig::default_level1(),
+            get_config_type() == ConfigType::S
+        );
+        assert_eq!(GravityConfig::default_level1().validate(), Ok(()));
+
+        // M and L, and the largest hyper-tree.
+        assert!(GravityConfig::custom(7, 15, 5).is_ok());
+        assert!(GravityConfig::custom(10, 14, 5).is_ok());
+        assert!(GravityConfig::custom(12, 4, 5).is_ok());
+        assert_eq!(
+            GravityConfig::custom(12, 5, 5),
+            Err(GravityError::InvalidParameter("height"))
+        );
+        assert!(GravityConfig::custom(0, 6, 5).is_ok());
+        assert_eq!(
+            GravityConfig::custom(1, 10, 0),
+            Err(GravityError::InvalidParameter("merkle_h"))
+        );
+        assert_eq!(
+            GravityConfig::custom(usize::MAX, 10, 5),
+            Err(GravityError::InvalidParameter("height"))
+        );
+        assert_eq!(
+            GravityConfig::custom(1, 64, 0),
+            Err(GravityError::InvalidParameter("height"))
+        );
+
+        let mut config = current;
+        config.ccc += 1;
+        assert_eq!(
+            config.validate(),
+            Err(GravityError::InvalidParameter("ccc"))
+        );
+        let mut config = current;
+        config.merkle_hhh = 0;
+        assert_eq!(
+            config.validate(),
+            Err(GravityError::InvalidParameter("merkle_hhh"))
+        );
+    }
+
+    #[test]
+    fn test_version() {
+        let bytes = GRAVITY_VERSION.to_bytes();
+        assert_eq!(GravityVersion::from_bytes(&bytes), GRAVITY_VERSION);
+        assert_eq!(GRAVITY_VERSION.security_level, 128);
+        match get_config_type() {
+            ConfigType::S => assert_eq!(bytes, [1, 0, 128]),
+            ConfigType::M => assert_eq!(bytes, [1, 1, 128]),
+            ConfigType::L => assert_eq!(bytes, [1, 2, 128]),
+            ConfigType::Porst => assert_eq!(bytes, [1, 255, 128]),
+            ConfigType::Unknown => unimplemented!(),
+        }
+        assert_eq!(
+            GRAVITY_VERSION.to_string(),
+            format!("1.{} (128-bit)", bytes[1])
+        );
+    }
 }
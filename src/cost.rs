@@ -0,0 +1,367 @@
+use crate::config::{ParamsInfo, HASH_SIZE, WOTS_CHKSUM, WOTS_ELL, WOTS_ELL1, WOTS_W};
+use crate::error::GravityError;
+use std::ops::{Add, Mul};
+
+// Number of occurrences of something that may depend on the message. The
+// average is over uniformly distributed message hashes. Sums and products
+// saturate at u64::MAX.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Count {
+    pub min: u64,
+    pub max: u64,
+    pub average: f64,
+}
+
+// Calls to the Haraka-256 (hash_n_to_n) and Haraka-512 (hash_2n_to_n)
+// compression functions. The SHA-256 of the message is not included.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HashCalls {
+    pub haraka256: Count,
+    pub haraka512: Count,
+}
+
+// Cost of the operations of a parameter set, from counting hash calls:
+// - keygen builds the 2^GRAVITY_C subtrees of the cached top-level tree, or
+//   its 2^GRAVITY_C PORS public keys if GRAVITY_D = 0,
+// - signing builds the whole PORS tree and the GRAVITY_D subtrees on the path,
+//   then hashes each WOTS chain up to the message digit,
+// - verification hashes each WOTS chain from the message digit to the end, and
+//   compresses the octopus, whose size depends on the PORS subset.
+// Per subtree, the WOTS chains of signing and verification add up to
+// WOTS_ELL * (WOTS_W - 1) hashes, so the sum of both is exact.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CostModel {
+    pub keygen: HashCalls,
+    pub sign: HashCalls,
+    pub verify: HashCalls,
+    pub octopus_nodes: Count,
+    pub public_key_bytes: usize,
+    pub signature_bytes: usize,
+    // Without the octopus padding and length, see Signature::to_compact.
+    pub compact_signature_bytes: Count,
+}
+
+impl Count {
+    pub fn exact(n: u64) -> Self {
+        Self {
+            min: n,
+            max: n,
+            average: n as f64,
+        }
+    }
+
+    pub fn contains(&self, n: u64) -> bool {
+        self.min <= n && n <= self.max
+    }
+}
+
+impl Add for Count {
+    type Output = Count;
+
+    fn add(self, other: Count) -> Count {
+        Count {
+            min: self.min.saturating_add(other.min),
+            max: self.max.saturating_add(other.max),
+            average: self.average + other.average,
+        }
+    }
+}
+
+impl Mul<u64> for Count {
+    type Output = Count;
+
+    fn mul(self, n: u64) -> Count {
+        Count {
+            min: self.min.saturating_mul(n),
+            max: self.max.saturating_mul(n),
+            average: self.average * n as f64,
+        }
+    }
+}
+
+// Bounds on the parameters of CostModel::for_params, well above those of the
+// S, M and L sets, under which the counts fit in a u64 and the octopus model
+// takes at most PORS_K * PORS_TAU steps.
+const MAX_GRAVITY_C: usize = 32;
+const MAX_MERKLE_H: usize = 20;
+const MAX_PORS_TAU: u32 = 20;
+
+impl CostModel {
+    // Fails on parameters that no instance of the scheme can have, or beyond
+    // the bounds above: params() is always accepted.
+    pub fn for_params(params: &ParamsInfo) -> Result<Self, GravityError> {
+        check_params(params)?;
+        let d = params.gravity_d as u64;
+        let c = params.gravity_c as u64;
+        let h = params.merkle_h as u64;
+        let k = params.pors_k as u64;
+        let t = params.pors_t as u64;
+        let tau = params.pors_t.trailing_zeros() as u64;
+        let ell = WOTS_ELL as u64;
+        let chains = ell * (WOTS_W as u64 - 1);
+
+        // One subtree: 2^MERKLE_H WOTS public keys (chains and L-tree), and the
+        // Merkle tree above them.
+        let leaves = 1 << h;
+        let subtree256 = leaves * chains;
+        let subtree512 = leaves * (ell - 1) + leaves - 1;
+
+        let wots_sign = wots_sign_chains();
+        let wots_verify = Count {
+            min: chains - wots_sign.max,
+            max: chains - wots_sign.min,
+            average: chains as f64 - wots_sign.average,
+        };
+        let octopus = octopus_hashes(k, tau);
+
+        let (leaf256, leaf512) = if d == 0 {
+            (t, t - 1)
+        } else {
+            (subtree256, subtree512)
+        };
+        let keygen = HashCalls {
+            haraka256: Count::exact((1 << c) * leaf256),
+            haraka512: Count::exact((1 << c) * leaf512 + (1 << c) - 1),
+        };
+        // The pepper and the seed of the PORS subset take one hash each; the
+        // top-level authentication path is read from the cache.
+        let sign = HashCalls {
+            haraka256: Count::exact(t + d * subtree256) + wots_sign * d,
+            haraka512: Count::exact(2 + t - 1 + d * subtree512),
+        };
+        let verify = HashCalls {
+            haraka256: Count::exact(k) + wots_verify * d,
+            haraka512: Count::exact(1 + d * (ell - 1 + h) + c) + octopus,
+        };
+
+        // Each hash of the octopus compression consumes one octopus node, except
+        // when both children are known: the PORS_K leaves are merged into one
+        // root by octopus - (PORS_K - 1) such hashes.
+        let octopus_nodes = Count {
+            min: octopus.min + 1 - k,
+            max: octopus.max + 1 - k,
+            average: octopus.average + 1.0 - k as f64,
+        };
+        let n = HASH_SIZE as u64;
+        let max_octopus = k * tau;
+        let signature_bytes = n * (1 + k + max_octopus) + 16 + d * n * (ell + h) + c * n;
+        let compact_signature_bytes = Count {
+            min: signature_bytes - 16 - n * (max_octopus - octopus_nodes.min),
+            max: signature_bytes - 16 - n * (max_octopus - octopus_nodes.max),
+            average: signature_bytes as f64
+                - 16.0
+                - n as f64 * (max_octopus as f64 - octopus_nodes.average),
+        };
+
+        Ok(CostModel {
+            keygen,
+            sign,
+            verify,
+            octopus_nodes,
+            public_key_bytes: HASH_SIZE,
+            signature_bytes: signature_bytes as usize,
+            compact_signature_bytes,
+        })
+    }
+}
+
+// As GravityConfig::validate: subtrees of height at least 1, at most 64 levels
+// of instance bits, and k distinct PORS leaves out of a power of two.
+fn check_params(params: &ParamsInfo) -> Result<(), GravityError> {
+    if params.gravity_c > MAX_GRAVITY_C {
+        return Err(GravityError::InvalidParameter("gravity_c"));
+    }
+    if params.merkle_h == 0 || params.merkle_h > MAX_MERKLE_H {
+        return Err(GravityError::InvalidParameter("merkle_h"));
+    }
+    let height = params
+        .merkle_h
+        .checked_mul(params.gravity_d)
+        .and_then(|hd| hd.checked_add(params.gravity_c));
+    if height.is_none_or(|height| height > 64) {
+        return Err(GravityError::InvalidParameter("height"));
+    }
+    let tau = params.pors_t.trailing_zeros();
+    if !params.pors_t.is_power_of_two() || tau == 0 || tau > MAX_PORS_TAU {
+        return Err(GravityError::InvalidParameter("pors_t"));
+    }
+    if params.pors_k == 0 || params.pors_k > params.pors_t {
+        return Err(GravityError::InvalidParameter("pors_k"));
+    }
+    Ok(())
+}
+
+// Chain hashes of a WOTS signature: the sum of the WOTS_ELL1 message digits,
+// plus the digits of the checksum, which is WOTS_ELL1 * (WOTS_W - 1) minus that
+// sum. The distribution of the sum is computed digit by digit.
+fn wots_sign_chains() -> Count {
+    let w = WOTS_W as u64;
+    let max_sum = WOTS_ELL1 * (WOTS_W - 1);
+    let mut dist = vec![0.0; max_sum + 1];
+    dist[0] = 1.0;
+    for _ in 0..WOTS_ELL1 {
+        let mut next = vec![0.0; max_sum + 1];
+        for (sum, p) in dist.iter().enumerate() {
+            if *p == 0.0 {
+                continue;
+            }
+            for digit in 0..WOTS_W {
+                next[sum + digit] += p / WOTS_W as f64;
+            }
+        }
+        dist = next;
+    }
+
+    let mut count = Count {
+        min: u64::MAX,
+        max: 0,
+        average: 0.0,
+    };
+    for (sum, p) in dist.iter().enumerate() {
+        let mut checksum = (max_sum - sum) as u64;
+        let mut chains = sum as u64;
+        for _ in 0..WOTS_CHKSUM {
+            chains += checksum % w;
+            checksum /= w;
+        }
+        count.min = count.min.min(chains);
+        count.max = count.max.max(chains);
+        count.average += p * chains as f64;
+    }
+    count
+}
+
+// Hashes of the octopus compression for PORS_K distinct leaves out of 2^tau:
+// one per distinct ancestor at each level. At level l, there are at least
+// ceil(k / 2^l) of them (contiguous leaves) and at most min(k, 2^(tau - l))
+// (leaves spread over the tree); on average, each of the 2^(tau - l) nodes is an
+// ancestor unless all k leaves avoid its 2^l descendants.
+fn octopus_hashes(k: u64, tau: u64) -> Count {
+    let t = 1u64 << tau;
+    let mut count = Count::exact(0);
+    for l in 1..=tau {
+        let size = 1u64 << l;
+        let nodes = t / size;
+        let mut miss = 1.0;
+        if t - size < k {
+            miss = 0.0;
+        } else {
+            for i in 0..k {
+                miss *= (t - size - i) as f64 / (t - i) as f64;
+            }
+        }
+        count = count
+            + Count {
+                min: k.div_ceil(size),
+                max: k.min(nodes),
+                average: nodes as f64 * (1.0 - miss),
+            };
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::*;
+    #[cfg(not(feature = "tweaked"))]
+    use crate::{gravity, hash};
+
+    #[cfg(not(feature = "tweaked"))]
+    fn measure<T, F: FnOnce() -> T>(f: F) -> (T, u64, u64) {
+        let (start256, start512) = (
+            hash::tests::haraka256_count(),
+            hash::tests::haraka512_count(),
+        );
+        let result = f();
+        (
+            result,
+            (hash::tests::haraka256_count() - start256) as u64,
+            (hash::tests::haraka512_count() - start512) as u64,
+        )
+    }
+
+    fn assert_consistent(count: &Count) {
+        assert!(count.min <= count.max, "{:?}", count);
+        assert!(count.min as f64 <= count.average, "{:?}", count);
+        assert!(count.average <= count.max as f64, "{:?}", count);
+    }
+
+    #[test]
+    fn test_consistent() {
+        let model = CostModel::for_params(params()).unwrap();
+        for calls in [model.keygen, model.sign, model.verify].iter() {
+            assert_consistent(&calls.haraka256);
+            assert_consistent(&calls.haraka512);
+        }
+        assert_consistent(&model.octopus_nodes);
+        assert_consistent(&model.compact_signature_bytes);
+        assert!(model.octopus_nodes.max <= OCTOPUS_MAX_NODES as u64);
+        assert_eq!(model.public_key_bytes, PUBLIC_KEY_BYTES);
+        assert_eq!(model.signature_bytes, SIGNATURE_BYTES);
+        assert_eq!(model.keygen.haraka256.min, model.keygen.haraka256.max);
+        assert_eq!(model.sign.haraka512.min, model.sign.haraka512.max);
+    }
+
+    #[test]
+    fn test_wots_sign_chains() {
+        let chains = wots_sign_chains();
+        // All zero digits: a checksum of 0x3C0 for WOTS_W = 16.
+        assert_eq!(chains.min, 0x3 + 0xC);
+        let total = (WOTS_ELL * (WOTS_W - 1)) as u64;
+        assert!(chains.max < total);
+        assert!(chains.average > (total as f64) / 2.0 - 10.0);
+        assert!(chains.average < (total as f64) / 2.0 + 10.0);
+    }
+
+    #[test]
+    fn test_octopus_hashes() {
+        // One leaf: the whole authentication path.
+        assert_eq!(octopus_hashes(1, 4), Count::exact(4));
+        // All leaves: the whole tree.
+        assert_eq!(octopus_hashes(16, 4), Count::exact(15));
+        let count = octopus_hashes(2, 2);
+        assert_eq!((count.min, count.max), (2, 3));
+        // Siblings for 2 of the 6 pairs.
+        assert!((count.average - (3.0 - 2.0 / 6.0)).abs() < 1e-9);
+    }
+
+    // Tweaked hashes are all Haraka-512 calls, two per hash_2n_to_n, so the
+    // model does not apply to them.
+    #[cfg(not(feature = "tweaked"))]
+    #[test]
+    fn test_measured() {
+        let model = CostModel::for_params(params()).unwrap();
+
+        let (sk, h256, h512) = measure(|| gravity::SecKey::new(&[0u8; 64]));
+        // With the parallel feature, keygen runs on the rayon threads, whose
+        // counters are not those of this thread.
+        if !cfg!(feature = "parallel") {
+            assert_eq!(h256, model.keygen.haraka256.min);
+            assert_eq!(h512, model.keygen.haraka512.min);
+        }
+        let pk = sk.genpk();
+
+        for msg in [&b"Hello world"[..], b"", b"a", b"b"].iter() {
+            let (sign, sign256, sign512) = measure(|| sk.sign_bytes(msg));
+            assert!(model.sign.haraka256.contains(sign256));
+            assert_eq!(sign512, model.sign.haraka512.min);
+
+            let (result, verify256, verify512) = measure(|| pk.verify_bytes(&sign, msg));
+            assert!(result.is_ok());
+            assert!(model.verify.haraka256.contains(verify256));
+            assert!(model.verify.haraka512.contains(verify512));
+            assert_eq!(
+                sign256 + verify256,
+                model.sign.haraka256.min + model.verify.haraka256.max
+            );
+
+            let octopus_nodes = sign.size_breakdown().pors_octopus / HASH_SIZE;
+            assert!(model.octopus_nodes.contains(octopus_nodes as u64));
+            assert_eq!(
+                verify512 - model.verify.haraka512.min,
+                octopus_nodes as u64 - model.octopus_nodes.min
+            );
+        }
+    }
+}
@@ -0,0 +1,591 @@
+use crate::config::GravityVersion;
+use std::error;
+use std::fmt;
+use std::io;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeserializeError {
+    UnexpectedEnd,
+    InvalidOctopusLength,
+    NonZeroPadding,
+    InvalidHeader,
+    TrailingBytes,
+    FrameTooLong(usize),
+    VersionMismatch {
+        expected: GravityVersion,
+        found: GravityVersion,
+    },
+    Io(io::ErrorKind),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeygenError {
+    InvalidSeedLength(usize),
+    PublicKeyMismatch,
+}
+
+// Import of a secret key in the layout of the reference implementation, see
+// SecKey::from_reference_bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportError {
+    InvalidLength(usize),
+    PublicKeyMismatch,
+    // Index of the first differing node, in the order of the reference layout.
+    CacheMismatch(usize),
+}
+
+#[cfg(feature = "bip39")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bip39Error {
+    InvalidMnemonic(bip39::Error),
+    // Index of the first word that is not in the wordlist, from 0.
+    UnknownWord(usize),
+}
+
+// The secrets of a key could not be locked in memory, see SecKey::new_locked.
+#[cfg(feature = "memlock")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemlockError {
+    LockFailed,
+}
+
+// Stage of crate::self_test whose known answer did not match.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelfTestError {
+    Haraka256,
+    Haraka512,
+    MerkleAuthPath,
+    SignatureVerification,
+}
+
+// Parsing of the JSON representation of a signature, see Signature::from_json.
+// Fields are named as in the JSON object.
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JsonError {
+    Syntax(String),
+    UnsupportedVersion(String),
+    InvalidBase64(&'static str),
+    // Wrong number of elements in an array field.
+    InvalidLength(&'static str),
+    Deserialization(&'static str, DeserializeError),
+}
+
+// Parsing of a SubjectPublicKeyInfo, see PubKey::from_der.
+#[cfg(feature = "der")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DerError {
+    Malformed(spki::der::Error),
+    // Other algorithm OID, or parameters present.
+    UnknownAlgorithm,
+    // Length of the key, in bits.
+    InvalidKeyLength(usize),
+}
+
+// The random number generator of the OS failed, see
+// GravityKeyPair::generate_from_os_rng.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OsRngError(pub getrandom::Error);
+
+// Tag of PubKey::from_compressed_bytes other than COMPRESSED_KEY_TAG.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressedKeyError {
+    UnknownScheme(u8),
+}
+
+// Decoding of Signature::decode_hex and Hash::from_hex_array. The hex digits
+// are checked before the signature is deserialized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HexError {
+    OddLength,
+    // Non-hex character `c` at byte `index` of the string.
+    InvalidCharacter { c: char, index: usize },
+    Deserialization(DeserializeError),
+}
+
+// Decoding of Signature::from_base58.
+#[cfg(feature = "bs58")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Base58Error {
+    Decoding(bs58::decode::Error),
+    Deserialization(DeserializeError),
+}
+
+// Input of Hash::from_entropy shorter than hash::MIN_ENTROPY_BYTES.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntropyError {
+    TooShort,
+}
+
+// Parsing of hex test vectors (Signature::from_hex_test_vector), of
+// concatenated signatures (Signature::deserialize_many, SignatureStream), and
+// strict parsing (Signature::parse_and_validate).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParseError {
+    InvalidHex(hex::FromHexError),
+    Deserialization(DeserializeError),
+    // Failure of the signature number `index`, starting at byte `offset`.
+    Item {
+        index: usize,
+        offset: usize,
+        error: DeserializeError,
+    },
+    // All-zero node `index` of auth_c.
+    ZeroAuthC(usize),
+    // All-ones node `index` of auth_c, see Hash::all_ones.
+    AllOnesAuthC(usize),
+    // All-zero node `node` of the authentication path of subtree `layer`.
+    ZeroSubtreeAuth {
+        layer: usize,
+        node: usize,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MerkleError {
+    // The number of leaves is not a power of two.
+    InvalidLeafCount(usize),
+}
+
+// A signature failed the verification that follows signing, most likely due to
+// a computational fault. The signature is not released.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FaultDetected;
+
+// Address::increment_instance left the instances of the layer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AddressOverflow;
+
+// Relation between the compiled parameters that does not hold (see
+// validate_params), named after the constraint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParamError(pub &'static str);
+
+// Failures of a signing backend (e.g. an HSM or a remote signer).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OracleError {
+    Unavailable,
+    Denied,
+    Backend(String),
+}
+
+// Verification failures are deliberately reported without detail, so that the
+// error cannot be used as an oracle on which part of a forged signature failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GravityError {
+    Deserialization(DeserializeError),
+    Verification,
+    IndexOutOfRange,
+    KeyGeneration(KeygenError),
+    InvalidParameter(&'static str),
+}
+
+impl fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeserializeError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            DeserializeError::InvalidOctopusLength => write!(f, "invalid octopus length"),
+            DeserializeError::NonZeroPadding => write!(f, "non-zero padding"),
+            DeserializeError::InvalidHeader => write!(f, "invalid header"),
+            DeserializeError::TrailingBytes => write!(f, "trailing bytes"),
+            DeserializeError::FrameTooLong(len) => write!(f, "frame too long: {} bytes", len),
+            DeserializeError::VersionMismatch { expected, found } => {
+                write!(
+                    f,
+                    "version mismatch: expected {}, found {}",
+                    expected, found
+                )
+            }
+            DeserializeError::Io(kind) => write!(f, "I/O error: {}", kind),
+        }
+    }
+}
+
+impl fmt::Display for KeygenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeygenError::InvalidSeedLength(len) => {
+                write!(f, "invalid seed length: {} bytes", len)
+            }
+            KeygenError::PublicKeyMismatch => write!(f, "public key mismatch"),
+        }
+    }
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::InvalidLength(len) => {
+                write!(f, "invalid secret key length: {} bytes", len)
+            }
+            ImportError::PublicKeyMismatch => write!(f, "public key mismatch"),
+            ImportError::CacheMismatch(index) => {
+                write!(f, "cache mismatch at node {}", index)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "bip39")]
+impl fmt::Display for Bip39Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Bip39Error::InvalidMnemonic(e) => write!(f, "invalid mnemonic: {}", e),
+            Bip39Error::UnknownWord(index) => write!(f, "unknown word at index {}", index),
+        }
+    }
+}
+
+#[cfg(feature = "memlock")]
+impl fmt::Display for MemlockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MemlockError::LockFailed => write!(f, "failed to lock memory"),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JsonError::Syntax(e) => write!(f, "invalid JSON: {}", e),
+            JsonError::UnsupportedVersion(v) => write!(f, "unsupported version: {}", v),
+            JsonError::InvalidBase64(field) => write!(f, "invalid base64 in {}", field),
+            JsonError::InvalidLength(field) => write!(f, "invalid number of elements in {}", field),
+            JsonError::Deserialization(field, e) => {
+                write!(f, "deserialization of {} failed: {}", field, e)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "bs58")]
+impl fmt::Display for Base58Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Base58Error::Decoding(e) => write!(f, "invalid base58: {}", e),
+            Base58Error::Deserialization(e) => write!(f, "deserialization error: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "der")]
+impl fmt::Display for DerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DerError::Malformed(e) => write!(f, "malformed DER: {}", e),
+            DerError::UnknownAlgorithm => write!(f, "unknown algorithm identifier"),
+            DerError::InvalidKeyLength(bits) => write!(f, "invalid key length: {} bits", bits),
+        }
+    }
+}
+
+impl fmt::Display for OsRngError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "OS random number generator failed: {}", self.0)
+    }
+}
+
+impl fmt::Display for CompressedKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompressedKeyError::UnknownScheme(tag) => write!(f, "unknown scheme tag 0x{:02x}", tag),
+        }
+    }
+}
+
+impl fmt::Display for HexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HexError::OddLength => write!(f, "odd number of hex digits"),
+            HexError::InvalidCharacter { c, index } => {
+                write!(f, "invalid hex character {:?} at {}", c, index)
+            }
+            HexError::Deserialization(e) => write!(f, "deserialization failed: {}", e),
+        }
+    }
+}
+
+impl fmt::Display for EntropyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EntropyError::TooShort => write!(f, "entropy too short"),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::InvalidHex(e) => write!(f, "invalid hex: {}", e),
+            ParseError::Deserialization(e) => write!(f, "deserialization failed: {}", e),
+            ParseError::Item {
+                index,
+                offset,
+                error,
+            } => write!(f, "signature {} at byte {}: {}", index, offset, error),
+            ParseError::ZeroAuthC(index) => write!(f, "auth_c node {} is all-zero", index),
+            ParseError::AllOnesAuthC(index) => write!(f, "auth_c node {} is all-ones", index),
+            ParseError::ZeroSubtreeAuth { layer, node } => write!(
+                f,
+                "node {} of the authentication path of subtree {} is all-zero",
+                node, layer
+            ),
+        }
+    }
+}
+
+impl fmt::Display for SelfTestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stage = match self {
+            SelfTestError::Haraka256 => "Haraka-256",
+            SelfTestError::Haraka512 => "Haraka-512",
+            SelfTestError::MerkleAuthPath => "Merkle authentication path",
+            SelfTestError::SignatureVerification => "signature verification",
+        };
+        write!(f, "self-test failed: {}", stage)
+    }
+}
+
+impl fmt::Display for MerkleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MerkleError::InvalidLeafCount(count) => {
+                write!(f, "invalid number of leaves: {}", count)
+            }
+        }
+    }
+}
+
+impl fmt::Display for FaultDetected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "fault detected during signing")
+    }
+}
+
+impl fmt::Display for AddressOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "address instance out of range for its layer")
+    }
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "parameter constraint violated: {}", self.0)
+    }
+}
+
+impl fmt::Display for OracleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OracleError::Unavailable => write!(f, "signing oracle unavailable"),
+            OracleError::Denied => write!(f, "signing request denied"),
+            OracleError::Backend(msg) => write!(f, "signing backend error: {}", msg),
+        }
+    }
+}
+
+impl fmt::Display for GravityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GravityError::Deserialization(e) => write!(f, "deserialization failed: {}", e),
+            GravityError::Verification => write!(f, "verification failed"),
+            GravityError::IndexOutOfRange => write!(f, "instance index out of range"),
+            GravityError::KeyGeneration(e) => write!(f, "key generation failed: {}", e),
+            GravityError::InvalidParameter(name) => write!(f, "invalid parameter: {}", name),
+        }
+    }
+}
+
+impl error::Error for DeserializeError {}
+impl error::Error for KeygenError {}
+impl error::Error for ImportError {}
+impl error::Error for MerkleError {}
+impl error::Error for SelfTestError {}
+impl error::Error for FaultDetected {}
+impl error::Error for AddressOverflow {}
+impl error::Error for ParamError {}
+impl error::Error for OracleError {}
+#[cfg(feature = "memlock")]
+impl error::Error for MemlockError {}
+
+#[cfg(feature = "bip39")]
+impl error::Error for Bip39Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Bip39Error::InvalidMnemonic(e) => Some(e),
+            Bip39Error::UnknownWord(_) => None,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl error::Error for JsonError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            JsonError::Deserialization(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "bs58")]
+impl error::Error for Base58Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Base58Error::Decoding(e) => Some(e),
+            Base58Error::Deserialization(e) => Some(e),
+        }
+    }
+}
+
+#[cfg(feature = "der")]
+impl error::Error for DerError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            DerError::Malformed(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl error::Error for OsRngError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+impl error::Error for CompressedKeyError {}
+
+impl error::Error for HexError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            HexError::Deserialization(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl error::Error for EntropyError {}
+
+impl error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ParseError::InvalidHex(e) => Some(e),
+            ParseError::Deserialization(e) => Some(e),
+            ParseError::Item { error, .. } => Some(error),
+            ParseError::ZeroAuthC(_)
+            | ParseError::AllOnesAuthC(_)
+            | ParseError::ZeroSubtreeAuth { .. } => None,
+        }
+    }
+}
+
+impl error::Error for GravityError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            GravityError::Deserialization(e) => Some(e),
+            GravityError::KeyGeneration(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<DeserializeError> for GravityError {
+    fn from(e: DeserializeError) -> Self {
+        GravityError::Deserialization(e)
+    }
+}
+
+impl From<io::Error> for DeserializeError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => DeserializeError::UnexpectedEnd,
+            kind => DeserializeError::Io(kind),
+        }
+    }
+}
+
+#[cfg(feature = "bip39")]
+impl From<bip39::Error> for Bip39Error {
+    fn from(e: bip39::Error) -> Self {
+        match e {
+            bip39::Error::UnknownWord(index) => Bip39Error::UnknownWord(index),
+            e => Bip39Error::InvalidMnemonic(e),
+        }
+    }
+}
+
+impl From<KeygenError> for GravityError {
+    fn from(e: KeygenError) -> Self {
+        GravityError::KeyGeneration(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn test_from() {
+        let e: GravityError = DeserializeError::UnexpectedEnd.into();
+        assert_eq!(
+            e,
+            GravityError::Deserialization(DeserializeError::UnexpectedEnd)
+        );
+        assert!(e.source().is_some());
+
+        let e: GravityError = KeygenError::InvalidSeedLength(3).into();
+        assert_eq!(
+            e,
+            GravityError::KeyGeneration(KeygenError::InvalidSeedLength(3))
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            GravityError::Deserialization(DeserializeError::NonZeroPadding).to_string(),
+            "deserialization failed: non-zero padding"
+        );
+        assert_eq!(
+            GravityError::Verification.to_string(),
+            "verification failed"
+        );
+        assert_eq!(
+            GravityError::InvalidParameter("index").to_string(),
+            "invalid parameter: index"
+        );
+        assert_eq!(
+            OracleError::Backend("timeout".to_string()).to_string(),
+            "signing backend error: timeout"
+        );
+        assert_eq!(
+            ImportError::CacheMismatch(7).to_string(),
+            "cache mismatch at node 7"
+        );
+        assert_eq!(
+            ParseError::Item {
+                index: 1,
+                offset: 42,
+                error: DeserializeError::UnexpectedEnd
+            }
+            .to_string(),
+            "signature 1 at byte 42: unexpected end of input"
+        );
+        assert_eq!(
+            ParseError::ZeroSubtreeAuth { layer: 0, node: 4 }.to_string(),
+            "node 4 of the authentication path of subtree 0 is all-zero"
+        );
+        assert_eq!(
+            MerkleError::InvalidLeafCount(3).to_string(),
+            "invalid number of leaves: 3"
+        );
+        assert_eq!(
+            SelfTestError::MerkleAuthPath.to_string(),
+            "self-test failed: Merkle authentication path"
+        );
+    }
+}
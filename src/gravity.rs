@@ -1,21 +1,234 @@
 use crate::address;
 use crate::config::*;
+#[cfg(feature = "bs58")]
+use crate::error::Base58Error;
+#[cfg(feature = "bip39")]
+use crate::error::Bip39Error;
+#[cfg(feature = "der")]
+use crate::error::DerError;
+#[cfg(feature = "serde")]
+use crate::error::JsonError;
+#[cfg(feature = "memlock")]
+use crate::error::MemlockError;
+use crate::error::{
+    CompressedKeyError, DeserializeError, FaultDetected, GravityError, HexError, ImportError,
+    KeygenError, OracleError, OsRngError, ParseError,
+};
 use crate::hash;
-use crate::hash::Hash;
+use crate::hash::{Hash, Tweak, TweakKind};
+use crate::ltree;
+#[cfg(feature = "memlock")]
+use crate::memlock;
 use crate::merkle;
 use crate::pors;
 use crate::prng;
+#[cfg(feature = "der")]
+use crate::scheme::GravitySignatureScheme;
 use crate::subtree;
-use arrayref::array_ref;
+#[cfg(feature = "async")]
+use crate::task;
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use byteorder::{ByteOrder, LittleEndian};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use std::fmt;
+use std::fs;
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::io;
+use std::path::Path;
+use std::ptr;
+#[cfg(any(feature = "async", feature = "parallel"))]
+use std::sync::Arc;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use subtle::{Choice, ConstantTimeEq};
 
+// The cache is the top-level tree, built by new or, for keys from new_lazy, on
+// first use. With the parallel feature, the parallel operations run on `pool`,
+// or on the global rayon pool if None.
 pub struct SecKey {
+    secrets: Secrets,
+    cache: OnceLock<merkle::MerkleTree>,
+    pk_cache: OnceLock<PubKey>,
+    #[cfg(feature = "parallel")]
+    pool: Option<Arc<rayon::ThreadPool>>,
+}
+
+#[derive(Clone, Copy)]
+struct KeySecrets {
     seed: Hash,
     salt: Hash,
-    cache: merkle::MerkleTree,
 }
+
+// With the memlock feature, the seed and the salt are in a locked page.
+#[cfg(feature = "memlock")]
+type Secrets = memlock::Locked<KeySecrets>;
+#[cfg(not(feature = "memlock"))]
+type Secrets = KeySecrets;
+
+// Only public metadata is shown, never the seed nor the salt. Formatting does
+// not build the cache of a lazy key.
+impl fmt::Debug for SecKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Some(cache) = self.cache.get() else {
+            return write!(
+                f,
+                "SecKey {{ pubkey: <not generated>, seed: <redacted>, salt: <redacted> }}"
+            );
+        };
+        let pk = &cache.root().h;
+        write!(
+            f,
+            "SecKey {{ pubkey: {:02x}{:02x}…{:02x}{:02x}, cache_height: {}, seed: <redacted>, salt: <redacted> }}",
+            pk[0],
+            pk[1],
+            pk[HASH_SIZE - 2],
+            pk[HASH_SIZE - 1],
+            cache.height()
+        )
+    }
+}
+// Same layout as Hash.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(transparent)]
 pub struct PubKey {
     pub h: Hash,
 }
+
+// SAFETY: PubKey is a transparent wrapper of Hash, which is Pod.
+#[cfg(feature = "bytemuck")]
+unsafe impl bytemuck::Zeroable for PubKey {}
+#[cfg(feature = "bytemuck")]
+unsafe impl bytemuck::Pod for PubKey {}
+pub struct GravityKeyPair {
+    pub secret_key: SecKey,
+    pub public_key: PubKey,
+}
+pub struct VerificationKey {
+    pk: PubKey,
+}
+// Secret key whose seed stays in a SeedProvider: keygen and signing request
+// the blocks they need from it. The salt and the cached top-level tree are held
+// in memory. Keys and signatures are those of SecKey for the same seed and salt.
+pub struct RemoteSecKey<S> {
+    provider: S,
+    salt: Hash,
+    cache: merkle::MerkleTree,
+}
+// Wraps a secret key with a cache of PORS instances, to speed up signatures
+// that land on recently used instances. The cache is never serialized.
+pub struct Signer {
+    sk: SecKey,
+    cache: pors::Cache,
+}
+// Signs a message written in pieces, see SecKey::incremental_sign. The message
+// is hashed as it is written, never buffered.
+pub struct IncrementalSigner<'a> {
+    sk: &'a SecKey,
+    hasher: hash::HashState,
+}
+// Verifier for many signatures under the same public key. The nodes on the path
+// of verified signatures are memoized along with the rest of the signature above
+// them (the next subtree signatures and auth_c). A signature that reaches a
+// memoized node at the same position, with the same data above it, is accepted
+// without recomputing the upper layers. These are exactly the inputs of the
+// remaining computation, so the verdict is always that of PubKey::verify_bytes.
+pub struct CachedVerifier {
+    pk: PubKey,
+    capacity: usize,
+    memo: Mutex<Vec<VerifiedNode>>,
+}
+
+// Node `h` after extracting `level` subtrees, at `instance` in its layer (or at
+// the leaf index in the cached tree, for level GRAVITY_D), followed by the
+// serialized signature data `above` it.
+struct VerifiedNode {
+    level: usize,
+    instance: u64,
+    h: Hash,
+    above: Vec<u8>,
+}
+
+// Attached format: magic, message length (u64 little-endian), signature, message.
+// The magic records the message digest that was signed.
+const ATTACHED_MAGIC: [u8; 4] = *b"GRVa";
+const ATTACHED_BOUND_MAGIC: [u8; 4] = *b"GRVb";
+const ATTACHED_TREE_MAGIC: [u8; 4] = *b"GRVc";
+
+// Bound mode (sign_bytes_bound): the digest is long_hash(BOUND_DOMAIN || pk ||
+// msg) instead of long_hash(msg). With the plain digest, an attacker targeting
+// many keys at once can try each digest against all of them; prefixing the
+// public key, as SPHINCS+ does with R || PK, makes each attempt specific to one
+// key, so that the security of a key does not degrade with the number of keys.
+// The domain tag separates bound digests from plain ones.
+const BOUND_DOMAIN: &[u8] = b"gravity-bound";
+
+// Digest mode (sign_digest): the digest is long_hash(DIGEST_DOMAIN || output)
+// of the caller's 32-byte digest output, so that it differs from the plain
+// digest of these 32 bytes as a message.
+#[cfg(feature = "digest")]
+const DIGEST_DOMAIN: &[u8] = b"gravity-digest";
+
+// Tree mode (sign_bytes_tree), for messages too large to hash on one core: the
+// message is split into TREE_CHUNK_BYTES chunks (one empty chunk if the message
+// is empty), each hashed with long_hash, in parallel with the parallel feature.
+// The chunk digests are folded in order as the leaves of an L-tree with
+// Tweak::NONE (an odd last node is carried up), and the digest is
+// long_hash(TREE_DOMAIN || message length (u64 little-endian) || root).
+pub const TREE_CHUNK_BYTES: usize = 1 << 20;
+const TREE_DOMAIN: &[u8] = b"gravity-tree";
+
+// Epoch mode (sign_hash_with_epoch): the digest is long_hash(EPOCH_DOMAIN ||
+// epoch (u64 little-endian) || msg), so that the PORS instance depends on the
+// epoch, and a signature of one epoch does not verify in another. All epochs
+// share the hyper-tree of the key: the epoch does not add one-time keys.
+const EPOCH_DOMAIN: &[u8] = b"gravity-epoch";
+
+// Keccak mode (sign_bytes_keccak): the digest is long_hash(KECCAK_DOMAIN ||
+// keccak256(msg)), so that it differs from the plain digest of the Keccak-256
+// hash as a message, and from a signature of that hash by verify_hash.
+#[cfg(feature = "keccak")]
+const KECCAK_DOMAIN: &[u8] = b"gravity-keccak";
+pub(crate) const ATTACHED_HEADER_BYTES: usize = 12;
+
+const KEYPAIR_MAGIC: [u8; 4] = *b"GRVk";
+
+// Framed format: signature length (u32 little-endian), canonical signature.
+const FRAME_HEADER_BYTES: usize = 4;
+
+const STATE_HASH_PREFIX: &[u8] = b"state-hash";
+
+const SIGNING_SALT_PREFIX: &[u8] = b"signing-salt";
+
+// Prefixes of the seed and salt of SecKey::new_deterministic_from_master_secret.
+const MASTER_SEED_PREFIX: &[u8] = b"gravity-seed";
+const MASTER_SALT_PREFIX: &[u8] = b"gravity-salt";
+
+const METADATA_BYTES: usize = 28;
+
+// Version of the JSON representation of signatures.
+#[cfg(feature = "serde")]
+const JSON_VERSION: &str = "gravity-1";
+
+#[cfg(feature = "bip39")]
+const BIP39_HKDF_INFO: &[u8] = b"gravity-sphincs";
+
+// Rough per-operation costs, from bench_chain_1 and bench_genblocks_wots on an
+// AES-NI x86-64 CPU. A PRNG block is two AES-256 blocks, a SHAKE256 call with
+// the shake256 feature, or an HMAC-SHA256 with prng-hmac.
+const HASH_COST_NS: u64 = 65;
+#[cfg(not(any(feature = "shake256", feature = "prng-hmac")))]
+const PRNG_BLOCK_COST_NS: u64 = 155;
+#[cfg(feature = "shake256")]
+const PRNG_BLOCK_COST_NS: u64 = 800;
+#[cfg(feature = "prng-hmac")]
+const PRNG_BLOCK_COST_NS: u64 = 890;
+
+// Number of nodes memoized by PubKey::verifier. Each of them holds up to
+// GRAVITY_D subtree signatures.
+const VERIFIER_CAPACITY: usize = 64;
+
 #[derive(Default)]
 pub struct Signature {
     pors_sign: pors::Signature,
@@ -23,144 +236,4834 @@ pub struct Signature {
     auth_c: [Hash; GRAVITY_C],
 }
 
+// Structurally valid signature with random content, which always serializes:
+// see pors::Signature for the octopus.
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Signature {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Signature {
+            pors_sign: u.arbitrary()?,
+            subtrees: u.arbitrary()?,
+            auth_c: u.arbitrary()?,
+        })
+    }
+
+    fn size_hint(depth: usize) -> (usize, Option<usize>) {
+        arbitrary::size_hint::and_all(&[
+            <pors::Signature as arbitrary::Arbitrary>::size_hint(depth),
+            <[subtree::Signature; GRAVITY_D] as arbitrary::Arbitrary>::size_hint(depth),
+            <[Hash; GRAVITY_C] as arbitrary::Arbitrary>::size_hint(depth),
+        ])
+    }
+}
+
+// JSON representation of a signature, see Signature::to_json. Fields are
+// serialized in this order.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SignatureJson {
+    version: String,
+    pors: String,
+    subtrees: Vec<String>,
+    auth_c: Vec<String>,
+}
+
+// Audit log entry of a signature. The leaf index is the PORS instance used for
+// the signature; the timestamp is in seconds since the Unix epoch, and None if
+// the system clock is before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SigningRecord {
+    pub message_hash: Hash,
+    pub leaf_index: u64,
+    pub timestamp: Option<u64>,
+    pub public_key_hash: Hash,
+}
+
+// Inclusion proof, in the cached top-level tree, of the subtree that contains
+// the one-time keys of a PORS instance (the leaf index of a SigningRecord): the
+// root of that subtree and its authentication path, see SecKey::audit_log_path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AuditPath {
+    pub leaf_hash: Hash,
+    pub merkle_path: [Hash; GRAVITY_C],
+    pub index: u64,
+}
+
+// Commitment to the subtree root at leaf `instance` of the cached top-level
+// tree, with its authentication path, see SecKey::prove_instance. Once checked
+// with PubKey::verify_instance, the root verifies signatures of that instance
+// without the top-level path (see InstanceProof::verify_hash).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InstanceProof {
+    pub instance: u64,
+    pub root: Hash,
+    pub auth: [Hash; GRAVITY_C],
+}
+
+// Serialized InstanceProof: instance (u64 little-endian), root, auth.
+pub const INSTANCE_PROOF_BYTES: usize = 8 + HASH_SIZE * (1 + GRAVITY_C);
+
+// Metadata bound to a signed message, see SecKey::sign_bytes_with_metadata.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SigningMetadata {
+    pub timestamp: u64,
+    pub signer_id: [u8; 16],
+    pub version: u32,
+}
+// Signing backend, so that applications can switch between an in-memory key
+// and a remote one (e.g. in an HSM) without code changes.
+pub trait SigningOracle: Send + Sync {
+    fn sign_hash(&self, msg: &Hash) -> Result<Signature, OracleError>;
+    fn public_key(&self) -> Result<PubKey, OracleError>;
+}
+
+// Oracle recording the calls made to it, optionally failing all of them.
+#[cfg(any(test, feature = "test-utils"))]
+pub struct MockSigningOracle {
+    sk: SecKey,
+    failure: Option<OracleError>,
+    signed: Mutex<Vec<Hash>>,
+    public_key_calls: Mutex<usize>,
+}
+
+// Serialized byte counts of each component of a signature. The octopus is
+// padded to OCTOPUS_MAX_NODES, so the total is always SIGNATURE_BYTES; only the
+// split between octopus nodes and padding depends on the message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SizeBreakdown {
+    pub pors_pepper: usize,
+    pub pors_values: usize,
+    pub pors_octopus: usize,
+    pub pors_padding: usize,
+    pub subtree_wots: [usize; GRAVITY_D],
+    pub subtree_auth: [usize; GRAVITY_D],
+    pub auth_c: usize,
+}
+// Incremental decoder of framed signatures, fed with partial buffers (e.g. from a
+// socket).
+#[derive(Default)]
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+}
+
+// Signatures stored back to back in a buffer, in the canonical encoding. Each
+// item is parsed strictly; iteration stops after the first error, which is
+// reported with the index and offset of the failing signature.
+pub struct SignatureStream<'a> {
+    buf: &'a [u8],
+    offset: usize,
+    index: usize,
+    failed: bool,
+}
+
+// The bytes of a sequence of chunks, as if they were concatenated, for input
+// that arrives in pieces. Empty chunks are skipped.
+pub struct ChunkedIterator<'a> {
+    parts: &'a [&'a [u8]],
+    current: std::slice::Iter<'a, u8>,
+}
+
+// Components that differ between two signatures, for debugging.
+#[cfg(any(test, feature = "test-utils"))]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SignatureDiff {
+    pub pors_differs: bool,
+    pub differing_subtrees: Vec<usize>,
+    pub auth_c_differs: bool,
+}
+
+// Stage of a verification, see VerifyTrace. Subtree(i) is the i-th subtree
+// signature, from the bottom layer up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceStage {
+    Pors,
+    Subtree(usize),
+    Top,
+}
+
+// Hashes computed by PubKey::verify_hash_trace at each stage that it reached, in
+// order: the PORS root, the root of each subtree, and the root of the cached
+// tree. `failure` is the stage that could not be computed (e.g. an invalid
+// octopus or an out-of-range instance), or Top if the root differs from
+// `expected`. A wrong hash at one stage propagates to all the stages above, so
+// the stage at fault is found by comparison with a reference trace, see
+// first_divergence.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerifyTrace {
+    pub stages: Vec<(TraceStage, Hash)>,
+    pub expected: Hash,
+    pub failure: Option<TraceStage>,
+}
+
+// Signature encoding without the data that the verifier can recompute. The PORS
+// subset is derived from the pepper and the message, which determines how many
+// octopus nodes are needed; the zero padding of the octopus up to
+// OCTOPUS_MAX_NODES and its length field are therefore omitted. All other nodes
+// (WOTS chains, subtree and top-level authentication paths) are outputs of
+// one-way functions and must be kept.
+#[cfg(feature = "experimental")]
+pub struct CompactSignature {
+    bytes: Vec<u8>,
+}
+
+// Two-hop chain: a root key signs the serialized delegation key, which signs the
+// message.
+pub struct ChainedSignature {
+    pub delegation_pk: PubKey,
+    pub delegation_sign: Signature,
+    pub msg_sign: Signature,
+}
+
+// Tweak of the cached top-level tree, whose leaves are the roots of the
+// subtrees at layer 0.
+fn top_tree_tweak() -> Tweak {
+    Tweak::new(TweakKind::TopTree, &address::Address::new(0, 0))
+}
+
 impl SecKey {
+    // With the memlock feature, the secrets are locked with
+    // LockPolicy::BestEffort.
     pub fn new(random: &[u8; 64]) -> Self {
-        let mut sk = SecKey {
+        let sk = Self::new_lazy(random);
+        sk.ensure_cache();
+        sk
+    }
+
+    // Only stores the seed and the salt: the cache, whose generation is most of
+    // the cost of keygen, is built by the first call that needs it (genpk,
+    // signing, etc.) or by ensure_cache. For keys whose seed is exported without
+    // signing, see to_seed_bytes.
+    pub fn new_lazy(random: &[u8; 64]) -> Self {
+        let secrets = KeySecrets {
             seed: Hash {
                 h: *array_ref![random, 0, 32],
             },
             salt: Hash {
                 h: *array_ref![random, 32, 32],
             },
-            cache: merkle::MerkleTree::new(GRAVITY_C),
         };
+        #[cfg(feature = "memlock")]
+        let secrets = memlock::Locked::new(secrets);
+        Self::from_secrets(secrets)
+    }
 
-        let layer = 0u32;
-        let prng = prng::Prng::new(&sk.seed);
-        let subtree_sk = subtree::SecKey::new(&prng);
+    // Same as new, the key generation and the parallel operations of the key
+    // (sign_parallel_batch, sign_bytes_tree) running on `pool` rather than on
+    // the global rayon pool.
+    #[cfg(feature = "parallel")]
+    pub fn with_thread_pool(random: &[u8; 64], pool: Arc<rayon::ThreadPool>) -> Self {
+        let mut sk = Self::new_lazy(random);
+        sk.pool = Some(pool);
+        sk.ensure_cache();
+        sk
+    }
+
+    #[cfg(feature = "parallel")]
+    fn install<R: Send>(&self, op: impl FnOnce() -> R + Send) -> R {
+        match &self.pool {
+            Some(pool) => pool.install(op),
+            None => op(),
+        }
+    }
+
+    // Builds the cache if needed. Concurrent callers wait for a single build.
+    pub fn ensure_cache(&self) {
+        self.cache();
+    }
+
+    pub fn is_cache_built(&self) -> bool {
+        self.cache.get().is_some()
+    }
+
+    // Whether each node of the cache matches the hash of its children, to detect
+    // memory corruption before signing with it. The leaves are not regenerated
+    // from the seed. An unbuilt cache is consistent.
+    pub fn verify_integrity(&self) -> bool {
+        self.cache
+            .get()
+            .is_none_or(|cache| cache.verify_self_consistency())
+    }
+
+    // Indices of the leaves of the cache that differ from the subtree roots
+    // regenerated from the seed, e.g. after restoring a corrupted backup. Empty
+    // if the cache is intact or not built. Costs as much as building the cache.
+    pub fn verify_subtree_consistency(&self) -> Vec<usize> {
+        let Some(cache) = self.cache.get() else {
+            return Vec::new();
+        };
+        let prng = prng::Prng::new(&self.secrets.seed);
+        #[cfg(feature = "parallel")]
+        let leaves = self.install(|| gen_cache_leaves_parallel(&prng));
+        #[cfg(not(feature = "parallel"))]
+        let leaves = gen_cache_leaves(&prng);
+        cache
+            .nodes_at_layer(0)
+            .iter()
+            .zip(leaves.iter())
+            .enumerate()
+            .filter(|(_, (stored, expected))| stored != expected)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    fn cache(&self) -> &merkle::MerkleTree {
+        self.cache.get_or_init(|| {
+            let prng = prng::Prng::new(&self.secrets.seed);
+            #[cfg(feature = "parallel")]
+            return self.install(|| gen_cache_parallel(&prng));
+            #[cfg(not(feature = "parallel"))]
+            gen_cache(&prng)
+        })
+    }
+
+    // The keygen randomness, seed || salt, as passed to new.
+    pub fn to_seed_bytes(&self) -> [u8; SECRET_SEED_BYTES] {
+        let mut bytes = [0u8; SECRET_SEED_BYTES];
+        bytes[..HASH_SIZE].copy_from_slice(&self.secrets.seed.h);
+        bytes[HASH_SIZE..].copy_from_slice(&self.secrets.salt.h);
+        bytes
+    }
+
+    // WARNING: the seed is the whole secret key: anyone who learns it can sign,
+    // and the copy is neither locked nor zeroized. Only for backup or migration,
+    // with the copy wiped by the caller. SecKey::new(seed || salt) restores the
+    // key.
+    #[cfg(feature = "dangerous-api")]
+    pub fn expose_seed(&self) -> [u8; HASH_SIZE] {
+        self.secrets.seed.h
+    }
+
+    // WARNING: same as expose_seed. The salt alone does not allow signing, but
+    // it determines the PORS instances of future signatures.
+    #[cfg(feature = "dangerous-api")]
+    pub fn expose_salt(&self) -> [u8; HASH_SIZE] {
+        self.secrets.salt.h
+    }
+
+    // Errors with LockPolicy::Required if the secrets cannot be locked, before
+    // generating the key.
+    #[cfg(feature = "memlock")]
+    pub fn new_locked(
+        random: &[u8; 64],
+        policy: memlock::LockPolicy,
+    ) -> Result<Self, MemlockError> {
+        let secrets = memlock::Locked::new(KeySecrets {
+            seed: Hash {
+                h: *array_ref![random, 0, 32],
+            },
+            salt: Hash {
+                h: *array_ref![random, 32, 32],
+            },
+        });
+        if policy == memlock::LockPolicy::Required && !secrets.is_locked() {
+            return Err(MemlockError::LockFailed);
+        }
+        let sk = Self::from_secrets(secrets);
+        sk.ensure_cache();
+        Ok(sk)
+    }
+
+    // Same seed, hence the same subtrees and public key, with another salt for the
+    // PORS peppers: signatures of a message differ from those of self, and both
+    // verify under the same public key. The cached tree is copied.
+    pub fn clone_with_fresh_salt(&self, new_salt: Hash) -> SecKey {
+        let secrets = KeySecrets {
+            seed: self.secrets.seed,
+            salt: new_salt,
+        };
+        #[cfg(feature = "memlock")]
+        let secrets = memlock::Locked::new(secrets);
+        SecKey {
+            secrets,
+            cache: self.cache.clone(),
+            pk_cache: OnceLock::new(),
+            #[cfg(feature = "parallel")]
+            pool: self.pool.clone(),
+        }
+    }
+
+    // In-place clone_with_fresh_salt: the salt only enters the PORS peppers, so
+    // the cached tree and public key remain valid.
+    pub fn rotate_salt(&mut self, new_salt: Hash) {
+        self.secrets.salt = new_salt;
+    }
+
+    // Whether the seed and the salt are locked in memory.
+    #[cfg(feature = "memlock")]
+    pub fn is_memory_locked(&self) -> bool {
+        self.secrets.is_locked()
+    }
+
+    fn from_secrets(secrets: Secrets) -> Self {
+        SecKey {
+            secrets,
+            cache: OnceLock::new(),
+            pk_cache: OnceLock::new(),
+            #[cfg(feature = "parallel")]
+            pool: None,
+        }
+    }
+
+    pub fn from_slice(random: &[u8]) -> Result<Self, KeygenError> {
+        if random.len() != SECRET_SEED_BYTES {
+            return Err(KeygenError::InvalidSeedLength(random.len()));
+        }
+        Ok(Self::new(array_ref![random, 0, SECRET_SEED_BYTES]))
+    }
+
+    // Key number `index` of a master secret, for wallets that derive many
+    // independent keys from one secret: the seed is the SHA-256 of
+    // "gravity-seed" || master || index (u32 little-endian), and the salt that
+    // of "gravity-salt" || master || index.
+    pub fn new_deterministic_from_master_secret(master: &[u8; 32], index: u32) -> SecKey {
+        let derive = |prefix: &[u8]| {
+            let mut bytes = Vec::with_capacity(prefix.len() + 32 + 4);
+            bytes.extend_from_slice(prefix);
+            bytes.extend_from_slice(master);
+            bytes.extend_from_slice(&index.to_le_bytes());
+            let h = hash::long_hash(&bytes);
+            hash::wipe(&mut bytes);
+            h
+        };
+        let mut random = [0u8; 64];
+        random[..HASH_SIZE].copy_from_slice(&derive(MASTER_SEED_PREFIX).h);
+        random[HASH_SIZE..].copy_from_slice(&derive(MASTER_SALT_PREFIX).h);
+        let sk = Self::new(&random);
+        hash::wipe(&mut random);
+        sk
+    }
+
+    // Secret key in the layout of struct gravity_sk of the reference
+    // implementation, REFERENCE_SECRET_KEY_BYTES long:
+    // - seed: HASH_SIZE bytes, the first half of the keygen randomness,
+    // - salt: HASH_SIZE bytes, the second half,
+    // - cache: the 2^(GRAVITY_C + 1) - 1 nodes of the top tree, as a 0-based
+    //   heap: the root (the public key) first, then each level from left to
+    //   right, down to the 2^GRAVITY_C subtree roots.
+    // The layout is read from the C sources; only tests/c_ref.rs compares it
+    // with keys produced by the C code.
+    pub fn to_reference_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(REFERENCE_SECRET_KEY_BYTES);
+        bytes.extend_from_slice(&self.secrets.seed.h);
+        bytes.extend_from_slice(&self.secrets.salt.h);
+        for node in self.cache().heap() {
+            bytes.extend_from_slice(&node.h);
+        }
+        bytes
+    }
+
+    // Inverse of to_reference_bytes. The cache is regenerated from the seed and
+    // must match the imported one node by node, starting with the public key.
+    pub fn from_reference_bytes(bytes: &[u8]) -> Result<Self, ImportError> {
+        if bytes.len() != REFERENCE_SECRET_KEY_BYTES {
+            return Err(ImportError::InvalidLength(bytes.len()));
+        }
+        let (random, cache) = bytes.split_at(SECRET_SEED_BYTES);
+        let sk = Self::new(array_ref![random, 0, SECRET_SEED_BYTES]);
+        let nodes = cache.chunks_exact(HASH_SIZE).zip(sk.cache().heap());
+        for (i, (bytes, node)) in nodes.enumerate() {
+            if bytes != node.h {
+                return Err(match i {
+                    0 => ImportError::PublicKeyMismatch,
+                    _ => ImportError::CacheMismatch(i),
+                });
+            }
+        }
+        Ok(sk)
+    }
+
+    pub fn genpk(&self) -> PubKey {
+        PubKey {
+            h: self.cache().root(),
+        }
+    }
+
+    // Same as genpk, computed on the first call and borrowed afterwards.
+    pub fn public_key_cached(&self) -> &PubKey {
+        self.pk_cache.get_or_init(|| self.genpk())
+    }
+
+    // SHA-256 of "state-hash" || seed || salt || cache root, to compare loaded
+    // copies of a key without exposing the seed. Only the root of the cache is
+    // covered, not the rest of the cache.
+    pub fn state_hash(&self) -> Hash {
+        let mut bytes = Vec::with_capacity(STATE_HASH_PREFIX.len() + 3 * HASH_SIZE);
+        bytes.extend_from_slice(STATE_HASH_PREFIX);
+        bytes.extend_from_slice(&self.secrets.seed.h);
+        bytes.extend_from_slice(&self.secrets.salt.h);
+        bytes.extend_from_slice(&self.cache().root().h);
+        let h = hash::long_hash(&bytes);
+        hash::wipe(&mut bytes);
+        h
+    }
+
+    // Salt specific to PORS instance `index`, for protocols that bind a salt to
+    // an instance. Signing does not use it: the pepper, and thus the instance,
+    // derives from the salt, so an instance cannot be chosen before its salt.
+    pub fn derive_signing_salt(&self, index: u64) -> Hash {
+        derive_signing_salt(&self.secrets.salt, index)
+    }
+
+    pub fn sign_hash(&self, msg: &Hash) -> Signature {
+        self.sign_hash_instance(msg).0
+    }
+
+    // Opt-in countermeasure against fault attacks: the signature is verified
+    // against the cached root before being released, at the cost of an extra
+    // verification.
+    pub fn sign_hash_checked(&self, msg: &Hash) -> Result<Signature, FaultDetected> {
+        let sign = self.sign_hash(msg);
+        match self.genpk().verify_hash(&sign, msg) {
+            Ok(()) => Ok(sign),
+            Err(_) => Err(FaultDetected),
+        }
+    }
+
+    // Also returns the PORS instance used.
+    fn sign_hash_instance(&self, msg: &Hash) -> (Signature, u64) {
+        let prng = prng::Prng::new(&self.secrets.seed);
+        let (address, h, pors_sign) = pors::sign(&prng, &self.secrets.salt, msg);
+        let instance = address.subtree_instance().0;
+        (self.sign_layers(&prng, address, h, pors_sign), instance)
+    }
+
+    pub fn sign_bytes_and_record(&self, msg: &[u8]) -> (Signature, SigningRecord) {
+        let h = hash::long_hash(msg);
+        let (sign, leaf_index) = self.sign_hash_instance(&h);
+        let record = SigningRecord {
+            message_hash: h,
+            leaf_index,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|d| d.as_secs()),
+            public_key_hash: self.genpk().h,
+        };
+        (sign, record)
+    }
+
+    // Signing is deterministic, so this reproduces a recorded signature. The
+    // instance is derived from the message, hence any other leaf index fails,
+    // and one beyond the PORS key pairs fails before signing.
+    pub fn sign_message_at_index(
+        &self,
+        msg: &[u8],
+        leaf_index: u64,
+    ) -> Result<Signature, GravityError> {
+        if !address::Address::from_signing_index(leaf_index).is_valid_for_layer(GRAVITY_D + 1) {
+            return Err(GravityError::IndexOutOfRange);
+        }
+        let (sign, instance) = self.sign_hash_instance(&hash::long_hash(msg));
+        if instance != leaf_index {
+            return Err(GravityError::InvalidParameter("leaf index"));
+        }
+        Ok(sign)
+    }
+
+    // Errors if the instance is out of range.
+    pub fn audit_log_path(&self, index: u64) -> Result<AuditPath, GravityError> {
+        let leaf = top_leaf_index(index).ok_or(GravityError::IndexOutOfRange)?;
+        let leaf_hash = *self
+            .cache()
+            .node_at(0, leaf)
+            .ok_or(GravityError::IndexOutOfRange)?;
+        let mut merkle_path = [Hash::default(); GRAVITY_C];
+        self.cache().gen_auth(&mut merkle_path, leaf);
+        Ok(AuditPath {
+            leaf_hash,
+            merkle_path,
+            index,
+        })
+    }
+
+    // Errors if the instance is not a leaf of the cached tree, i.e. is at least
+    // 2^GRAVITY_C.
+    pub fn prove_instance(&self, instance: u64) -> Result<InstanceProof, GravityError> {
+        let leaf = usize::try_from(instance)
+            .ok()
+            .and_then(|i| address::LeafIndex::try_from(i).ok())
+            .ok_or(GravityError::IndexOutOfRange)?;
+        let root = *self
+            .cache()
+            .node_at(0, leaf.0)
+            .ok_or(GravityError::IndexOutOfRange)?;
+        let mut auth = [Hash::default(); GRAVITY_C];
+        self.cache().gen_auth(&mut auth, leaf.0);
+        Ok(InstanceProof {
+            instance,
+            root,
+            auth,
+        })
+    }
+
+    fn sign_layers(
+        &self,
+        prng: &prng::Prng,
+        address: address::Address,
+        h: Hash,
+        pors_sign: pors::Signature,
+    ) -> Signature {
+        sign_layers(prng, self.cache(), address, h, pors_sign)
+    }
+
+    // Insecure, for tests and research only: signs with a forced PORS selection,
+    // see pors::Selection. The result verifies as usual.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn sign_hash_forced(&self, msg: &Hash, forced: Option<pors::Selection>) -> Signature {
+        let prng = prng::Prng::new(&self.secrets.seed);
+        let (address, h, pors_sign) =
+            pors::sign_with_selection(&prng, &self.secrets.salt, msg, forced);
+        self.sign_layers(&prng, address, h, pors_sign)
+    }
+
+    // Same as sign_hash, building the signature in place on the heap, for small
+    // thread stacks.
+    pub fn sign_hash_boxed(&self, msg: &Hash) -> Box<Signature> {
+        let prng = prng::Prng::new(&self.secrets.seed);
+        let (address, h, pors_sign) = pors::sign(&prng, &self.secrets.salt, msg);
+        let mut sign = Signature::new_boxed();
+        sign.pors_sign = pors_sign;
+        sign_layers_into(&prng, self.cache(), address, h, &mut sign);
+        sign
+    }
+
+    pub fn sign_bytes(&self, msg: &[u8]) -> Signature {
+        let h = hash::long_hash(msg);
+        self.sign_hash(&h)
+    }
+
+    // sign_bytes with its wall-clock duration, for latency monitoring. Includes
+    // building the cache of a lazy key.
+    pub fn sign_bytes_timed(&self, msg: &[u8]) -> (Signature, Duration) {
+        let start = Instant::now();
+        let sign = self.sign_bytes(msg);
+        (sign, start.elapsed())
+    }
+
+    // Canonical encoding of sign_bytes(msg), written to a buffer of the caller
+    // (e.g. a static one on embedded targets) instead of a new Vec. The subtree
+    // keys are derived anew at each call, as for all signatures, but signing
+    // still allocates its own workspaces.
+    pub fn sign_bytes_no_cache<'buf>(
+        &self,
+        msg: &[u8],
+        buf: &'buf mut [u8; Signature::SIZE],
+    ) -> &'buf [u8; Signature::SIZE] {
+        self.sign_bytes(msg).serialize_into(buf);
+        buf
+    }
+
+    // Same signature as sign_bytes of everything read from `reader`, until its
+    // end.
+    pub fn sign_reader<R: io::Read>(&self, mut reader: R) -> io::Result<Signature> {
+        let mut signer = self.incremental_sign();
+        io::copy(&mut reader, &mut signer)?;
+        Ok(signer.finalize())
+    }
+
+    // Same signature as sign_bytes of the concatenation of the writes, once
+    // finalized.
+    pub fn incremental_sign(&self) -> IncrementalSigner<'_> {
+        IncrementalSigner {
+            sk: self,
+            hasher: hash::HashState::new(),
+        }
+    }
+
+    // Signs long_hash(meta || msg), meta being SigningMetadata::to_bytes.
+    pub fn sign_bytes_with_metadata(&self, msg: &[u8], meta: &SigningMetadata) -> Signature {
+        self.sign_hash(&meta.hash_message(msg))
+    }
+
+    // Signs on a separate thread, so as not to stall an async executor. The key
+    // is shared, not locked: dropping the future leaves it usable.
+    #[cfg(feature = "async")]
+    pub fn sign_bytes_async(
+        self: Arc<Self>,
+        msg: Vec<u8>,
+    ) -> impl Future<Output = Signature> + Send {
+        task::spawn_blocking(move || self.sign_bytes(&msg))
+    }
+
+    // Signs each message with sign_bytes, spreading the messages over the rayon
+    // thread pool. The layers of one signature depend on each other, but
+    // distinct messages are independent. Signatures are in the order of `msgs`.
+    #[cfg(feature = "parallel")]
+    pub fn sign_parallel_batch(&self, msgs: &[&[u8]]) -> Vec<Signature> {
+        self.ensure_cache();
+        self.install(|| msgs.par_iter().map(|msg| self.sign_bytes(msg)).collect())
+    }
+
+    // Signs the message bound to the public key, see BOUND_DOMAIN. The
+    // signature only verifies with PubKey::verify_bytes_bound.
+    pub fn sign_bytes_bound(&self, msg: &[u8]) -> Signature {
+        self.sign_hash(&bound_message_hash(self.public_key_cached(), msg))
+    }
+
+    // Signs a message that the caller already hashed with `d`, e.g. SHA-256 or
+    // SHA3-256, see DIGEST_DOMAIN. The signature only verifies with
+    // PubKey::verify_digest.
+    #[cfg(feature = "digest")]
+    pub fn sign_digest<D: digest::Digest<OutputSize = digest::consts::U32>>(
+        &self,
+        d: D,
+    ) -> Signature {
+        self.sign_hash(&digest_message_hash(&d.finalize()))
+    }
+
+    // Signs the Keccak-256 hash of the message, see KECCAK_DOMAIN, so that a
+    // verifier holding only that hash (e.g. an Ethereum contract) can check the
+    // signature with PubKey::verify_keccak_hash. The signature only verifies
+    // with these two.
+    #[cfg(feature = "keccak")]
+    pub fn sign_bytes_keccak(&self, msg: &[u8]) -> Signature {
+        self.sign_hash(&keccak_message_hash(&Hash::keccak256(msg)))
+    }
+
+    // Signs the tree hash of the message, see TREE_DOMAIN. The signature only
+    // verifies with PubKey::verify_bytes_tree.
+    pub fn sign_bytes_tree(&self, msg: &[u8]) -> Signature {
+        #[cfg(feature = "parallel")]
+        let h = self.install(|| tree_message_hash(msg));
+        #[cfg(not(feature = "parallel"))]
+        let h = tree_message_hash(msg);
+        self.sign_hash(&h)
+    }
+
+    // Signs the message within `epoch`, see EPOCH_DOMAIN. The signature only
+    // verifies with PubKey::verify_hash_with_epoch and the same epoch.
+    pub fn sign_hash_with_epoch(&self, msg: &Hash, epoch: u64) -> Signature {
+        self.sign_hash(&epoch_message_hash(msg, epoch))
+    }
+
+    pub fn sign_attached(&self, msg: &[u8]) -> Vec<u8> {
+        attach(&ATTACHED_MAGIC, &self.sign_bytes(msg), msg)
+    }
+
+    // Same as sign_attached in bound mode, which the header records.
+    pub fn sign_attached_bound(&self, msg: &[u8]) -> Vec<u8> {
+        attach(&ATTACHED_BOUND_MAGIC, &self.sign_bytes_bound(msg), msg)
+    }
+
+    // Same as sign_attached in tree mode, which the header records.
+    pub fn sign_attached_tree(&self, msg: &[u8]) -> Vec<u8> {
+        attach(&ATTACHED_TREE_MAGIC, &self.sign_bytes_tree(msg), msg)
+    }
+
+    // Estimate of the time taken by sign_hash, from counting hash and PRNG calls:
+    // - PORS: PORS_T PRNG blocks, PORS_T leaf hashes and PORS_T - 1 tree nodes,
+    // - each of the GRAVITY_D subtrees: for each of its 2^MERKLE_H WOTS keys,
+    //   WOTS_ELL PRNG blocks, WOTS_ELL * (WOTS_W - 1) chain hashes and
+    //   WOTS_ELL - 1 L-tree nodes; then 2^MERKLE_H - 1 tree nodes and on average
+    //   WOTS_ELL * (WOTS_W - 1) / 2 chain hashes for the WOTS signature.
+    // The authentication path in the cached tree is free: this is the cost once
+    // the cache is built, see ensure_cache.
+    pub fn estimate_sign_time_ns() -> u64 {
+        let wots_leaf_hashes = WOTS_ELL * (WOTS_W - 1) + WOTS_ELL - 1;
+        let subtree_hashes =
+            (1 << MERKLE_H) * wots_leaf_hashes + (1 << MERKLE_H) - 1 + WOTS_ELL * (WOTS_W - 1) / 2;
+        let subtree_blocks = (1 << MERKLE_H) * WOTS_ELL;
+
+        let hashes = 2 * PORS_T - 1 + GRAVITY_D * subtree_hashes;
+        let blocks = PORS_T + GRAVITY_D * subtree_blocks;
+        hashes as u64 * HASH_COST_NS + blocks as u64 * PRNG_BLOCK_COST_NS
+    }
+}
+
+impl PubKey {
+    pub fn verify_hash(&self, sign: &Signature, msg: &Hash) -> Result<(), GravityError> {
+        if self.h == sign.recover_root(msg)? {
+            Ok(())
+        } else {
+            Err(GravityError::Verification)
+        }
+    }
+
+    // Same checks as verify_hash, recording the hash computed at each stage, for
+    // debugging a signature that does not verify.
+    pub fn verify_hash_trace(&self, sign: &Signature, msg: &Hash) -> VerifyTrace {
+        let mut trace = VerifyTrace {
+            stages: Vec::new(),
+            expected: self.h,
+            failure: None,
+        };
+        let (mut address, mut h) = match sign.pors_sign.extract(msg) {
+            Ok(x) => x,
+            Err(_) => {
+                trace.failure = Some(TraceStage::Pors);
+                return trace;
+            }
+        };
+        trace.stages.push((TraceStage::Pors, h));
+        for (i, subtree_sign) in sign.subtrees.iter().enumerate() {
+            address.next_layer();
+            if check_instance(&address).is_err() {
+                trace.failure = Some(TraceStage::Subtree(i));
+                return trace;
+            }
+            h = subtree_sign.extract(&address, &h);
+            trace.stages.push((TraceStage::Subtree(i), h));
+            address.shift(MERKLE_H);
+        }
+        let index = match address.leaf_index() {
+            Ok(index) if merkle::check_auth(&sign.auth_c, index.0 as u64).is_ok() => index,
+            _ => {
+                trace.failure = Some(TraceStage::Top);
+                return trace;
+            }
+        };
+        let root = sign.auth_c_root(&h, index.0);
+        trace.stages.push((TraceStage::Top, root));
+        if root != self.h {
+            trace.failure = Some(TraceStage::Top);
+        }
+        trace
+    }
+
+    pub fn verify_bytes(&self, sign: &Signature, msg: &[u8]) -> Result<(), GravityError> {
+        let h = hash::long_hash(msg);
+        self.verify_hash(sign, &h)
+    }
+
+    // Same result as verify_bytes, but all layers are computed whichever check
+    // fails: a failed check substitutes a default node or index and carries on
+    // up to the root, which is compared in constant time. The octopus of the
+    // PORS signature is still compressed until its first inconsistency.
+    pub fn verify_timing_safe(&self, sign: &Signature, msg: &[u8]) -> bool {
+        let msg = hash::long_hash(msg);
+        let mut failed = Choice::from(0);
+
+        let (mut address, mut h) = match sign.pors_sign.extract(&msg) {
+            Ok(x) => x,
+            Err(_) => {
+                failed |= Choice::from(1);
+                (address::Address::new(0, 0), Hash::default())
+            }
+        };
+        for subtree_sign in sign.subtrees.iter() {
+            address.next_layer();
+            failed |= Choice::from(check_instance(&address).is_err() as u8);
+            h = subtree_sign.extract(&address, &h);
+            address.shift(MERKLE_H);
+        }
+        let index = match address.leaf_index() {
+            Ok(index) => index.0 as u64,
+            Err(_) => {
+                failed |= Choice::from(1);
+                0
+            }
+        };
+        failed |= Choice::from(merkle::check_auth(&sign.auth_c, index).is_err() as u8);
+        let index = index & ((1 << GRAVITY_C) - 1);
+        let root = merkle::root_from_auth(&h, &sign.auth_c, index, &top_tree_tweak());
+
+        bool::from(!failed & self.h.h.ct_eq(&root.h))
+    }
+
+    pub fn verify_bytes_with_metadata(
+        &self,
+        sign: &Signature,
+        msg: &[u8],
+        meta: &SigningMetadata,
+    ) -> Result<(), GravityError> {
+        self.verify_hash(sign, &meta.hash_message(msg))
+    }
+
+    // Verifies a serialized signature read from `sign_reader`, one component at
+    // a time, stopping at the first malformed component. Exactly
+    // Signature::SIZE bytes are consumed on success.
+    #[deny(clippy::indexing_slicing)]
+    pub fn verify_streaming<R: io::Read>(
+        &self,
+        mut sign_reader: R,
+        msg: &Hash,
+    ) -> Result<(), GravityError> {
+        let (mut address, mut h) = {
+            let mut buf = vec![0u8; PORS_SIGNATURE_BYTES];
+            sign_reader
+                .read_exact(&mut buf)
+                .map_err(DeserializeError::from)?;
+            let pors_sign = pors::Signature::deserialize(&mut buf.iter())?;
+            pors_sign.extract(msg)?
+        };
+
+        let mut buf = [0u8; SUBTREE_SIGNATURE_BYTES];
+        for _ in 0..GRAVITY_D {
+            sign_reader
+                .read_exact(&mut buf)
+                .map_err(DeserializeError::from)?;
+            let subtree_sign = subtree::Signature::deserialize(&mut buf.iter())?;
+            address.next_layer();
+            check_instance(&address)?;
+            h = subtree_sign.extract(&address, &h);
+            address.shift(MERKLE_H);
+        }
+
+        let mut index = address
+            .leaf_index()
+            .map_err(|_| GravityError::IndexOutOfRange)?
+            .0 as u64;
+        let mut buf = [0u8; HASH_SIZE];
+        for height in 0..GRAVITY_C {
+            sign_reader
+                .read_exact(&mut buf)
+                .map_err(DeserializeError::from)?;
+            let auth = Hash::deserialize(&mut buf.iter())?;
+            index = merkle::merkle_compress_auth(
+                &mut h,
+                &[auth],
+                1,
+                index,
+                height as u32,
+                &top_tree_tweak(),
+            );
+        }
+
+        if self.h == h {
+            Ok(())
+        } else {
+            Err(GravityError::Verification)
+        }
+    }
+
+    pub fn verifier(&self) -> CachedVerifier {
+        CachedVerifier::new(*self, VERIFIER_CAPACITY)
+    }
+
+    pub fn verify_bytes_bound(&self, sign: &Signature, msg: &[u8]) -> Result<(), GravityError> {
+        self.verify_hash(sign, &bound_message_hash(self, msg))
+    }
+
+    pub fn verify_bytes_tree(&self, sign: &Signature, msg: &[u8]) -> Result<(), GravityError> {
+        self.verify_hash(sign, &tree_message_hash(msg))
+    }
+
+    pub fn verify_hash_with_epoch(&self, sign: &Signature, msg: &Hash, epoch: u64) -> bool {
+        self.verify_hash(sign, &epoch_message_hash(msg, epoch))
+            .is_ok()
+    }
+
+    // Whether the proof's root is at leaf proof.instance of the cached tree of
+    // this key.
+    pub fn verify_instance(&self, proof: &InstanceProof) -> bool {
+        merkle::check_auth(&proof.auth, proof.instance).is_ok()
+            && merkle::root_from_auth(&proof.root, &proof.auth, proof.instance, &top_tree_tweak())
+                == self.h
+    }
+
+    #[cfg(feature = "digest")]
+    pub fn verify_digest<D: digest::Digest<OutputSize = digest::consts::U32>>(
+        &self,
+        sign: &Signature,
+        d: D,
+    ) -> Result<(), GravityError> {
+        self.verify_hash(sign, &digest_message_hash(&d.finalize()))
+    }
+
+    #[cfg(feature = "keccak")]
+    pub fn verify_bytes_keccak(&self, sign: &Signature, msg: &[u8]) -> Result<(), GravityError> {
+        self.verify_keccak_hash(sign, &Hash::keccak256(msg))
+    }
+
+    // Same as verify_bytes_keccak, from the Keccak-256 hash of the message.
+    #[cfg(feature = "keccak")]
+    pub fn verify_keccak_hash(&self, sign: &Signature, h: &Hash) -> Result<(), GravityError> {
+        self.verify_hash(sign, &keccak_message_hash(h))
+    }
+
+    // Returns the message embedded in a blob produced by SecKey::sign_attached,
+    // sign_attached_bound or sign_attached_tree.
+    #[deny(clippy::indexing_slicing)]
+    pub fn open_attached<'a>(&self, blob: &'a [u8]) -> Result<&'a [u8], GravityError> {
+        let (header, body) = blob
+            .split_first_chunk::<ATTACHED_HEADER_BYTES>()
+            .ok_or(DeserializeError::UnexpectedEnd)?;
+        let (magic, msg_len) = array_refs![header, 4, 8];
+        let verify = match *magic {
+            ATTACHED_MAGIC => PubKey::verify_bytes,
+            ATTACHED_BOUND_MAGIC => PubKey::verify_bytes_bound,
+            ATTACHED_TREE_MAGIC => PubKey::verify_bytes_tree,
+            _ => return Err(DeserializeError::InvalidHeader.into()),
+        };
+        let msg_len = LittleEndian::read_u64(msg_len);
+
+        let mut it = body.iter();
+        let sign = Signature::deserialize(&mut it)?;
+        let msg = it.as_slice();
+        if (msg.len() as u64) < msg_len {
+            return Err(DeserializeError::UnexpectedEnd.into());
+        }
+        if (msg.len() as u64) > msg_len {
+            return Err(DeserializeError::TrailingBytes.into());
+        }
+
+        verify(self, &sign, msg)?;
+        Ok(msg)
+    }
+}
+
+impl SigningOracle for SecKey {
+    fn sign_hash(&self, msg: &Hash) -> Result<Signature, OracleError> {
+        Ok(SecKey::sign_hash(self, msg))
+    }
+
+    fn public_key(&self) -> Result<PubKey, OracleError> {
+        Ok(self.genpk())
+    }
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+impl MockSigningOracle {
+    pub fn new(sk: SecKey) -> Self {
+        Self {
+            sk,
+            failure: None,
+            signed: Mutex::new(vec![]),
+            public_key_calls: Mutex::new(0),
+        }
+    }
+
+    pub fn failing(sk: SecKey, failure: OracleError) -> Self {
+        Self {
+            failure: Some(failure),
+            ..Self::new(sk)
+        }
+    }
+
+    // Messages passed to sign_hash, in call order.
+    pub fn signed_messages(&self) -> Vec<Hash> {
+        self.signed.lock().unwrap().clone()
+    }
+
+    pub fn public_key_calls(&self) -> usize {
+        *self.public_key_calls.lock().unwrap()
+    }
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+impl SigningOracle for MockSigningOracle {
+    fn sign_hash(&self, msg: &Hash) -> Result<Signature, OracleError> {
+        self.signed.lock().unwrap().push(*msg);
+        match &self.failure {
+            Some(e) => Err(e.clone()),
+            None => Ok(self.sk.sign_hash(msg)),
+        }
+    }
+
+    fn public_key(&self) -> Result<PubKey, OracleError> {
+        *self.public_key_calls.lock().unwrap() += 1;
+        match &self.failure {
+            Some(e) => Err(e.clone()),
+            None => Ok(self.sk.genpk()),
+        }
+    }
+}
+
+impl CachedVerifier {
+    // Memoizes up to `capacity` nodes, most recently used first.
+    pub fn new(pk: PubKey, capacity: usize) -> Self {
+        Self {
+            pk,
+            capacity,
+            memo: Mutex::new(Vec::with_capacity(capacity)),
+        }
+    }
+
+    pub fn pubkey(&self) -> &PubKey {
+        &self.pk
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn cached_nodes(&self) -> usize {
+        self.memo.lock().unwrap().len()
+    }
+
+    pub fn verify_bytes(&self, sign: &Signature, msg: &[u8]) -> Result<(), GravityError> {
+        let h = hash::long_hash(msg);
+        self.verify_hash(sign, &h)
+    }
+
+    // Same steps as Signature::recover_root, looking up each node before
+    // extracting the next subtree.
+    fn verify_hash(&self, sign: &Signature, msg: &Hash) -> Result<(), GravityError> {
+        let mut above = Vec::with_capacity(SIGNATURE_BYTES - PORS_SIGNATURE_BYTES);
+        for t in sign.subtrees.iter() {
+            t.serialize(&mut above);
+        }
+        for x in sign.auth_c.iter() {
+            x.serialize(&mut above);
+        }
+
+        let mut path = Vec::with_capacity(GRAVITY_D + 1);
+        let (mut address, mut h) = sign.pors_sign.extract(msg)?;
+        for i in 0..GRAVITY_D {
+            address.next_layer();
+            check_instance(&address)?;
+            if self.lookup(i, address.get_instance(), &h, &above) {
+                return Ok(());
+            }
+            path.push((address.get_instance(), h));
+            h = sign.subtrees[i].extract(&address, &h);
+            address.shift(MERKLE_H);
+        }
+        let index = address
+            .leaf_index()
+            .map_err(|_| GravityError::IndexOutOfRange)?
+            .0 as u64;
+        if self.lookup(GRAVITY_D, index, &h, &above) {
+            return Ok(());
+        }
+        path.push((index, h));
+
+        merkle::check_auth(&sign.auth_c, index)?;
+        if merkle::root_from_auth(&h, &sign.auth_c, index, &top_tree_tweak()) != self.pk.h {
+            return Err(GravityError::Verification);
+        }
+        self.insert(path, &above);
+        Ok(())
+    }
+
+    fn lookup(&self, level: usize, instance: u64, h: &Hash, above: &[u8]) -> bool {
+        let above = &above[level * SUBTREE_SIGNATURE_BYTES..];
+        let mut memo = self.memo.lock().unwrap();
+        match memo.iter().position(|x| {
+            x.level == level && x.instance == instance && x.h == *h && x.above == above
+        }) {
+            Some(i) => {
+                let node = memo.remove(i);
+                memo.insert(0, node);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn insert(&self, path: Vec<(u64, Hash)>, above: &[u8]) {
+        let mut memo = self.memo.lock().unwrap();
+        for (level, (instance, h)) in path.into_iter().enumerate() {
+            memo.insert(
+                0,
+                VerifiedNode {
+                    level,
+                    instance,
+                    h,
+                    above: above[level * SUBTREE_SIGNATURE_BYTES..].to_vec(),
+                },
+            );
+        }
+        memo.truncate(self.capacity);
+    }
+}
+
+impl IncrementalSigner<'_> {
+    pub fn finalize(self) -> Signature {
+        self.sk.sign_hash(&self.hasher.finalize())
+    }
+}
+
+impl io::Write for IncrementalSigner<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.hasher.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Signer {
+    // Caches up to `capacity` PORS instances, each taking 2 * PORS_T hashes.
+    pub fn new(sk: SecKey, capacity: usize) -> Self {
+        Self {
+            sk,
+            cache: pors::Cache::new(capacity),
+        }
+    }
+
+    pub fn secret_key(&self) -> &SecKey {
+        &self.sk
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.cache.capacity()
+    }
+
+    pub fn cached_instances(&self) -> usize {
+        self.cache.count()
+    }
+
+    pub fn sign_hash(&self, msg: &Hash) -> Signature {
+        let prng = prng::Prng::new(&self.sk.secrets.seed);
+        let (address, h, pors_sign) =
+            pors::sign_cached(&prng, &self.cache, &self.sk.secrets.salt, msg);
+        self.sk.sign_layers(&prng, address, h, pors_sign)
+    }
+
+    pub fn sign_bytes(&self, msg: &[u8]) -> Signature {
+        let h = hash::long_hash(msg);
+        self.sign_hash(&h)
+    }
+}
+
+impl<S: prng::SeedProvider> RemoteSecKey<S> {
+    pub fn new(provider: S, salt: Hash) -> Self {
+        let cache = gen_cache(&prng::ProviderPrng(&provider));
+        Self {
+            provider,
+            salt,
+            cache,
+        }
+    }
+
+    pub fn provider(&self) -> &S {
+        &self.provider
+    }
+
+    pub fn genpk(&self) -> PubKey {
+        PubKey {
+            h: self.cache.root(),
+        }
+    }
+
+    pub fn sign_hash(&self, msg: &Hash) -> Signature {
+        let prng = prng::ProviderPrng(&self.provider);
+        let (address, h, pors_sign) = pors::sign(&prng, &self.salt, msg);
+        sign_layers(&prng, &self.cache, address, h, pors_sign)
+    }
+
+    pub fn sign_bytes(&self, msg: &[u8]) -> Signature {
+        let h = hash::long_hash(msg);
+        self.sign_hash(&h)
+    }
+}
+
+impl SigningMetadata {
+    // Fixed-size encoding: the timestamp (u64 little-endian), the signer ID and
+    // the version (u32 little-endian).
+    pub fn to_bytes(&self) -> [u8; METADATA_BYTES] {
+        let mut bytes = [0u8; METADATA_BYTES];
+        let (timestamp, signer_id, version) = mut_array_refs![&mut bytes, 8, 16, 4];
+        LittleEndian::write_u64(timestamp, self.timestamp);
+        signer_id.copy_from_slice(&self.signer_id);
+        LittleEndian::write_u32(version, self.version);
+        bytes
+    }
+
+    fn hash_message(&self, msg: &[u8]) -> Hash {
+        let mut bytes = Vec::with_capacity(METADATA_BYTES + msg.len());
+        bytes.extend_from_slice(&self.to_bytes());
+        bytes.extend_from_slice(msg);
+        hash::long_hash(&bytes)
+    }
+}
+
+impl GravityKeyPair {
+    pub fn generate(random: &[u8; 64]) -> Self {
+        let secret_key = SecKey::new(random);
+        let public_key = secret_key.genpk();
+        Self {
+            secret_key,
+            public_key,
+        }
+    }
+
+    // Same as generate with 64 bytes from the OS (getrandom), which are wiped
+    // afterwards.
+    pub fn generate_from_os_rng() -> Result<Self, OsRngError> {
+        let mut random = [0u8; 64];
+        getrandom::getrandom(&mut random).map_err(OsRngError)?;
+        let keypair = Self::generate(&random);
+        hash::wipe(&mut random);
+        Ok(keypair)
+    }
+
+    #[cfg(feature = "async")]
+    pub fn generate_async(random: [u8; 64]) -> impl Future<Output = Self> + Send {
+        task::spawn_blocking(move || Self::generate(&random))
+    }
+
+    // The mnemonic entropy goes through HKDF-SHA512, with the passphrase as
+    // salt, rather than through the BIP39 seed derivation (PBKDF2).
+    #[cfg(feature = "bip39")]
+    pub fn from_bip39_mnemonic(words: &str, passphrase: &str) -> Result<Self, Bip39Error> {
+        let mnemonic = bip39::Mnemonic::parse(words)?;
+        let hkdf = hkdf::Hkdf::<sha2_hkdf::Sha512>::new(
+            Some(passphrase.as_bytes()),
+            &mnemonic.to_entropy(),
+        );
+        let mut random = [0u8; 64];
+        hkdf.expand(BIP39_HKDF_INFO, &mut random)
+            .expect("64 bytes is a valid HKDF-SHA512 output length");
+        let keypair = Self::generate(&random);
+        hash::wipe(&mut random);
+        Ok(keypair)
+    }
+
+    pub fn sign(&self, msg: &[u8]) -> Signature {
+        self.secret_key.sign_bytes(msg)
+    }
+
+    pub fn serialize(&self, output: &mut Vec<u8>) {
+        output.extend(KEYPAIR_MAGIC.iter());
+        output.extend(GRAVITY_VERSION.to_bytes().iter());
+        self.secret_key.secrets.seed.serialize(output);
+        self.secret_key.secrets.salt.serialize(output);
+        self.public_key.h.serialize(output);
+    }
+
+    // The cache is regenerated from the seed, and checked against the stored
+    // public key.
+    #[deny(clippy::indexing_slicing)]
+    pub fn deserialize<'a, I>(it: &mut I) -> Result<Self, GravityError>
+    where
+        I: Iterator<Item = &'a u8>,
+    {
+        for x in KEYPAIR_MAGIC.iter() {
+            if it.next().ok_or(DeserializeError::UnexpectedEnd)? != x {
+                return Err(DeserializeError::InvalidHeader.into());
+            }
+        }
+        let mut version = [0u8; 3];
+        for x in version.iter_mut() {
+            *x = *it.next().ok_or(DeserializeError::UnexpectedEnd)?;
+        }
+        let version = GravityVersion::from_bytes(&version);
+        if version != GRAVITY_VERSION {
+            return Err(DeserializeError::VersionMismatch {
+                expected: GRAVITY_VERSION,
+                found: version,
+            }
+            .into());
+        }
+        let seed = Hash::deserialize(it)?;
+        let salt = Hash::deserialize(it)?;
+        let public_key = PubKey {
+            h: Hash::deserialize(it)?,
+        };
+
+        let mut random = [0u8; 64];
+        random[..32].copy_from_slice(&seed.h);
+        random[32..].copy_from_slice(&salt.h);
+        let keypair = Self::generate(&random);
+        hash::wipe(&mut random);
+        if keypair.public_key != public_key {
+            return Err(KeygenError::PublicKeyMismatch.into());
+        }
+        Ok(keypair)
+    }
+}
+
+// Prefix of the SubjectPublicKeyInfo of PubKey::as_der: the algorithm is the
+// placeholder OID 1.3.9999.99.1 of DER_ALGORITHM_OID, without parameters.
+const SPKI_PREFIX: [u8; 14] = *b"\x30\x2c\x30\x07\x06\x05\x2b\xce\x0f\x63\x01\x03\x21\x00";
+
+impl VerificationKey {
+    pub fn new(pk: PubKey) -> Self {
+        Self { pk }
+    }
+
+    pub fn pubkey(&self) -> &PubKey {
+        &self.pk
+    }
+
+    pub fn is_revoked(&self, revocation_list: &[PubKey]) -> bool {
+        revocation_list.contains(&self.pk)
+    }
+
+    pub fn algorithm_id(&self) -> &'static str {
+        "gravity-sphincs"
+    }
+
+    // DER-encoded SubjectPublicKeyInfo, with the root hash as the BIT STRING.
+    pub fn encode_spki(&self) -> Vec<u8> {
+        let mut output = SPKI_PREFIX.to_vec();
+        self.pk.h.serialize(&mut output);
+        output
+    }
+
+    pub fn verify_bytes(&self, sign: &Signature, msg: &[u8]) -> Result<(), GravityError> {
+        self.pk.verify_bytes(sign, msg)
+    }
+
+    #[deny(clippy::indexing_slicing)]
+    pub fn deserialize<'a, I>(it: &mut I) -> Result<Self, DeserializeError>
+    where
+        I: Iterator<Item = &'a u8>,
+    {
+        let h = Hash::deserialize(it)?;
+        Ok(Self::new(PubKey { h }))
+    }
+}
+
+// Verifies that root_pk signed the serialized delegation_pk, and that
+// delegation_pk signed msg.
+pub fn verify_chain_signature(
+    root_pk: &PubKey,
+    delegation_sign: &Signature,
+    delegation_pk: &PubKey,
+    msg_sign: &Signature,
+    msg: &[u8],
+) -> bool {
+    let delegation_bytes = <[u8; PUBLIC_KEY_BYTES]>::from(delegation_pk);
+    root_pk
+        .verify_bytes(delegation_sign, &delegation_bytes)
+        .is_ok()
+        && delegation_pk.verify_bytes(msg_sign, msg).is_ok()
+}
+
+impl ChainedSignature {
+    pub fn new(root_sk: &SecKey, delegation_sk: &SecKey, msg: &[u8]) -> Self {
+        let delegation_pk = *delegation_sk.public_key_cached();
+        let delegation_sign = root_sk.sign_bytes(&<[u8; PUBLIC_KEY_BYTES]>::from(&delegation_pk));
+        Self {
+            delegation_pk,
+            delegation_sign,
+            msg_sign: delegation_sk.sign_bytes(msg),
+        }
+    }
+
+    pub fn verify(&self, root_pk: &PubKey, msg: &[u8]) -> bool {
+        verify_chain_signature(
+            root_pk,
+            &self.delegation_sign,
+            &self.delegation_pk,
+            &self.msg_sign,
+            msg,
+        )
+    }
+}
+
+// Hash signed in the bound mode, see BOUND_DOMAIN.
+fn bound_message_hash(pk: &PubKey, msg: &[u8]) -> Hash {
+    let mut bytes = Vec::with_capacity(BOUND_DOMAIN.len() + HASH_SIZE + msg.len());
+    bytes.extend_from_slice(BOUND_DOMAIN);
+    bytes.extend_from_slice(&pk.h.h);
+    bytes.extend_from_slice(msg);
+    hash::long_hash(&bytes)
+}
+
+#[cfg(feature = "digest")]
+fn digest_message_hash(output: &[u8]) -> Hash {
+    let mut bytes = Vec::with_capacity(DIGEST_DOMAIN.len() + output.len());
+    bytes.extend_from_slice(DIGEST_DOMAIN);
+    bytes.extend_from_slice(output);
+    hash::long_hash(&bytes)
+}
+
+#[cfg(feature = "keccak")]
+fn keccak_message_hash(h: &Hash) -> Hash {
+    let mut bytes = Vec::with_capacity(KECCAK_DOMAIN.len() + HASH_SIZE);
+    bytes.extend_from_slice(KECCAK_DOMAIN);
+    bytes.extend_from_slice(&h.h);
+    hash::long_hash(&bytes)
+}
+
+fn tree_message_hash(msg: &[u8]) -> Hash {
+    #[cfg(feature = "parallel")]
+    let chunks = msg.par_chunks(TREE_CHUNK_BYTES);
+    #[cfg(not(feature = "parallel"))]
+    let chunks = msg.chunks(TREE_CHUNK_BYTES);
+    let mut leaves: Vec<Hash> = chunks.map(hash::long_hash).collect();
+    if leaves.is_empty() {
+        leaves.push(hash::long_hash(&[]));
+    }
+    let root = ltree::ltree_leaves_ret(&leaves, &Tweak::NONE);
+
+    let mut bytes = Vec::with_capacity(TREE_DOMAIN.len() + 8 + HASH_SIZE);
+    bytes.extend_from_slice(TREE_DOMAIN);
+    bytes.extend_from_slice(&(msg.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&root.h);
+    hash::long_hash(&bytes)
+}
+
+fn epoch_message_hash(msg: &Hash, epoch: u64) -> Hash {
+    let mut bytes = Vec::with_capacity(EPOCH_DOMAIN.len() + 8 + HASH_SIZE);
+    bytes.extend_from_slice(EPOCH_DOMAIN);
+    bytes.extend_from_slice(&epoch.to_le_bytes());
+    bytes.extend_from_slice(&msg.h);
+    hash::long_hash(&bytes)
+}
+
+fn attach(magic: &[u8; 4], sign: &Signature, msg: &[u8]) -> Vec<u8> {
+    let mut header = [0u8; ATTACHED_HEADER_BYTES];
+    header[..4].copy_from_slice(magic);
+    LittleEndian::write_u64(array_mut_ref![header, 4, 8], msg.len() as u64);
+
+    let mut output = Vec::with_capacity(ATTACHED_HEADER_BYTES + SIGNATURE_BYTES + msg.len());
+    output.extend(header.iter());
+    sign.serialize(&mut output);
+    output.extend(msg.iter());
+    output
+}
+
+// Bytes of a vector in the format of the fixture files, see
+// Signature::from_hex_test_vector.
+pub(crate) fn decode_hex_vector(hex_str: &str) -> Result<Vec<u8>, hex::FromHexError> {
+    let digits: String = hex_str
+        .lines()
+        .filter(|l| !l.trim_start().starts_with('#'))
+        .flat_map(|l| l.split_whitespace())
+        .collect();
+    hex::decode(digits)
+}
+
+// The cached top-level tree, whose leaves are the roots of the subtrees of
+// layer 0, or the PORS public keys if GRAVITY_D = 0.
+fn gen_cache<P: prng::BlockPrng>(prng: &P) -> merkle::MerkleTree<P::Block> {
+    merkle::MerkleTree::from_leaves_with_tweak(&gen_cache_leaves(prng), top_tree_tweak())
+        .expect("2^GRAVITY_C leaves")
+}
+
+// The 2^GRAVITY_C leaves of the cache: the roots of the subtrees of layer 0, or
+// the PORS public keys if GRAVITY_D = 0.
+fn gen_cache_leaves<P: prng::BlockPrng>(prng: &P) -> Vec<P::Block> {
+    if GRAVITY_D == 0 {
+        let mut values = vec![P::Block::default(); PORS_T];
+        let mut buf = merkle::MerkleBuf::new(PORS_TAU);
+        return (0..1u64 << GRAVITY_C)
+            .map(|i| pors::genpk_with(prng, &address::Address::new(0, i), &mut values, &mut buf))
+            .collect();
+    }
+    let layer = 0u32;
+    let subtree_sk = subtree::SecKey::new(prng);
+    let mut scratch = subtree::Scratch::new();
+    (0..1usize << GRAVITY_C)
+        .map(|i| {
+            let address = address::Address::new(layer, (i << MERKLE_H) as u64);
+            subtree_sk.genpk_with(&address, &mut scratch).h
+        })
+        .collect()
+}
+
+// Same as gen_cache, the leaves being generated on the current rayon pool.
+#[cfg(feature = "parallel")]
+fn gen_cache_parallel(prng: &prng::Prng) -> merkle::MerkleTree {
+    merkle::MerkleTree::from_leaves_with_tweak(&gen_cache_leaves_parallel(prng), top_tree_tweak())
+        .expect("2^GRAVITY_C leaves")
+}
+
+#[cfg(feature = "parallel")]
+fn gen_cache_leaves_parallel(prng: &prng::Prng) -> Vec<Hash> {
+    if GRAVITY_D == 0 {
+        (0..1u64 << GRAVITY_C)
+            .into_par_iter()
+            .map_init(
+                || {
+                    (
+                        vec![Hash::default(); PORS_T],
+                        merkle::MerkleBuf::new(PORS_TAU),
+                    )
+                },
+                |(values, buf), i| {
+                    pors::genpk_with(prng, &address::Address::new(0, i), values, buf)
+                },
+            )
+            .collect()
+    } else {
+        let subtree_sk = subtree::SecKey::new(prng);
+        (0..1usize << GRAVITY_C)
+            .into_par_iter()
+            .map_init(subtree::Scratch::new, |scratch, i| {
+                let address = address::Address::new(0, (i << MERKLE_H) as u64);
+                subtree_sk.genpk_with(&address, scratch).h
+            })
+            .collect()
+    }
+}
+
+// SHA-256 of "signing-salt" || salt || index (u64 little-endian).
+fn derive_signing_salt(salt: &Hash, index: u64) -> Hash {
+    let mut bytes = Vec::with_capacity(SIGNING_SALT_PREFIX.len() + HASH_SIZE + 8);
+    bytes.extend_from_slice(SIGNING_SALT_PREFIX);
+    bytes.extend_from_slice(&salt.h);
+    bytes.extend_from_slice(&index.to_le_bytes());
+    hash::long_hash(&bytes)
+}
+
+fn sign_layers<P: prng::BlockPrng<Block = Hash>>(
+    prng: &P,
+    cache: &merkle::MerkleTree,
+    address: address::Address,
+    h: Hash,
+    pors_sign: pors::Signature,
+) -> Signature {
+    let mut sign = Signature {
+        pors_sign,
+        ..Default::default()
+    };
+    sign_layers_into(prng, cache, address, h, &mut sign);
+    sign
+}
+
+// Fills the subtree signatures and the top-level authentication path.
+fn sign_layers_into<P: prng::BlockPrng<Block = Hash>>(
+    prng: &P,
+    cache: &merkle::MerkleTree,
+    mut address: address::Address,
+    mut h: Hash,
+    sign: &mut Signature,
+) {
+    let subtree_sk = subtree::SecKey::new(prng);
+    for i in 0..GRAVITY_D {
+        address.next_layer();
+        let (root, subtree_sign) = subtree_sk.sign(&address, &h);
+        h = root;
+        sign.subtrees[i] = subtree_sign;
+        address.shift(MERKLE_H); // Update instance
+    }
+
+    // The instance of pors::sign is masked with GRAVITY_MASK, so that only the
+    // GRAVITY_C bits of the top tree are left; masking again keeps the path
+    // within the cache whatever the address.
+    let index = address.get_instance() & ((1 << GRAVITY_C) - 1);
+    cache.gen_auth(&mut sign.auth_c, index as usize);
+}
+
+// Leaf of the cached top-level tree above a PORS instance.
+fn top_leaf_index(instance: u64) -> Option<usize> {
+    if !address::SubtreeInstance(instance).is_valid() {
+        return None;
+    }
+    let leaf = instance
+        .checked_shr((MERKLE_H * GRAVITY_D) as u32)
+        .unwrap_or(0);
+    Some(leaf as usize)
+}
+
+impl AuditPath {
+    // Whether the path leads from leaf_hash to `root`, the public key.
+    pub fn verify(&self, root: &Hash) -> bool {
+        let Some(leaf) = top_leaf_index(self.index) else {
+            return false;
+        };
+        let tweak = top_tree_tweak();
+        merkle::root_from_auth(&self.leaf_hash, &self.merkle_path, leaf as u64, &tweak) == *root
+    }
+}
+
+impl InstanceProof {
+    // Verifies a signature against the root of a proof that was checked with
+    // PubKey::verify_instance: the signature must reach this root, at this
+    // instance. Its auth_c is ignored.
+    pub fn verify_hash(&self, sign: &Signature, msg: &Hash) -> Result<(), GravityError> {
+        let (h, index) = sign.recover_top_leaf(msg)?;
+        if index.0 as u64 == self.instance && h == self.root {
+            Ok(())
+        } else {
+            Err(GravityError::Verification)
+        }
+    }
+
+    pub fn serialize(&self, output: &mut Vec<u8>) {
+        output.extend_from_slice(&self.instance.to_le_bytes());
+        self.root.serialize(output);
+        for x in self.auth.iter() {
+            x.serialize(output);
+        }
+    }
+
+    pub fn deserialize<'a, I>(it: &mut I) -> Result<Self, DeserializeError>
+    where
+        I: Iterator<Item = &'a u8>,
+    {
+        let mut instance = [0u8; 8];
+        for x in instance.iter_mut() {
+            *x = *it.next().ok_or(DeserializeError::UnexpectedEnd)?;
+        }
+        let root = Hash::deserialize(it)?;
+        let mut auth = [Hash::default(); GRAVITY_C];
+        for x in auth.iter_mut() {
+            *x = Hash::deserialize(it)?;
+        }
+        Ok(InstanceProof {
+            instance: u64::from_le_bytes(instance),
+            root,
+            auth,
+        })
+    }
+}
+
+impl TryFrom<&[u8]> for InstanceProof {
+    type Error = DeserializeError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, DeserializeError> {
+        let mut it = bytes.iter();
+        let proof = InstanceProof::deserialize(&mut it)?;
+        if it.next().is_some() {
+            return Err(DeserializeError::TrailingBytes);
+        }
+        Ok(proof)
+    }
+}
+
+impl From<&InstanceProof> for Vec<u8> {
+    fn from(proof: &InstanceProof) -> Self {
+        let mut output = Vec::with_capacity(INSTANCE_PROOF_BYTES);
+        proof.serialize(&mut output);
+        output
+    }
+}
+
+// Instances outside of the hyper-tree are rejected, rather than silently
+// truncated by the index computations of the next layers.
+fn check_instance(address: &address::Address) -> Result<(), GravityError> {
+    if address.subtree_instance().is_valid() {
+        Ok(())
+    } else {
+        Err(GravityError::IndexOutOfRange)
+    }
+}
+
+impl Signature {
+    pub const SIZE: usize = SIGNATURE_BYTES;
+
+    pub fn pors_signature(&self) -> &pors::Signature {
+        &self.pors_sign
+    }
+
+    // Components in serialization order: PORS signature, subtree signatures from
+    // the bottom layer up, and the top-level authentication path.
+    pub fn into_parts(
+        self,
+    ) -> (
+        pors::Signature,
+        [subtree::Signature; GRAVITY_D],
+        [Hash; GRAVITY_C],
+    ) {
+        (self.pors_sign, self.subtrees, self.auth_c)
+    }
+
+    pub fn from_parts(
+        pors_sign: pors::Signature,
+        subtrees: [subtree::Signature; GRAVITY_D],
+        auth_c: [Hash; GRAVITY_C],
+    ) -> Self {
+        Signature {
+            pors_sign,
+            subtrees,
+            auth_c,
+        }
+    }
+
+    // Candidate public key for this signature and message. The signature is only
+    // valid if this root is equal to a trusted one (e.g. a PubKey, or a root
+    // committed elsewhere); an Ok result alone proves nothing.
+    pub fn recover_root(&self, msg: &Hash) -> Result<Hash, GravityError> {
+        let (h, index) = self.recover_top_leaf(msg)?;
+        merkle::check_auth(&self.auth_c, index.0 as u64)?;
+        Ok(self.auth_c_root(&h, index.0))
+    }
+
+    // Signing index (as in SigningRecord) of the signature of msg: the instance
+    // of the PORS key pair that the pepper selects, without checking the
+    // signature. None if the addresses of the layers above are out of range.
+    pub fn expected_leaf_index(&self, msg: &Hash) -> Option<u64> {
+        let mut address = self.pors_sign.address(msg);
+        let index = address.get_instance();
+        for _ in 0..GRAVITY_D {
+            address.next_layer();
+            check_instance(&address).ok()?;
+            address.shift(MERKLE_H);
+        }
+        address.leaf_index().ok()?;
+        Some(index)
+    }
+
+    // Root of the PORS signature for layer 0, then of subtree layer - 1, up to
+    // the root of the last subtree at GRAVITY_D, which auth_c then leads to the
+    // public key (see auth_c_root). None above GRAVITY_D, or if a layer up to
+    // `layer` cannot be computed.
+    pub fn layer_root(&self, layer: usize, msg: &Hash) -> Option<Hash> {
+        if layer > GRAVITY_D {
+            return None;
+        }
+        let (mut address, mut h) = self.pors_sign.extract(msg).ok()?;
+        for subtree_sign in self.subtrees[..layer].iter() {
+            address.next_layer();
+            check_instance(&address).ok()?;
+            h = subtree_sign.extract(&address, &h);
+            address.shift(MERKLE_H);
+        }
+        Some(h)
+    }
+
+    // Last step of recover_root: the root that auth_c leads to from the root of
+    // the last subtree (`leaf_hash`, at `index` in the cached tree). Only the low
+    // GRAVITY_C bits of the index are used; callers that take it from untrusted
+    // input should check it with LeafIndex::try_from.
+    pub fn auth_c_root(&self, leaf_hash: &Hash, index: usize) -> Hash {
+        merkle::root_from_auth(leaf_hash, &self.auth_c, index as u64, &top_tree_tweak())
+    }
+
+    // Root of the last subtree, and its index in the cached top-level tree.
+    fn recover_top_leaf(&self, msg: &Hash) -> Result<(Hash, address::LeafIndex), GravityError> {
+        let (mut address, mut h) = self.pors_sign.extract(msg)?;
+        for i in 0..GRAVITY_D {
+            address.next_layer();
+            check_instance(&address)?;
+            h = self.subtrees[i].extract(&address, &h);
+            address.shift(MERKLE_H);
+        }
+        let index = address
+            .leaf_index()
+            .map_err(|_| GravityError::IndexOutOfRange)?;
+        Ok((h, index))
+    }
+
+    // Checks only the top-level authentication path, from the root of the last
+    // subtree (`leaf`, at `index` in the cached tree) to `root`.
+    pub fn verify_auth_c_alone(&self, root: &Hash, leaf: &Hash, index: usize) -> bool {
+        merkle::check_auth(&self.auth_c, index as u64).is_ok()
+            && self.auth_c_root(leaf, index) == *root
+    }
+
+    pub fn size_breakdown(&self) -> SizeBreakdown {
+        let pors_octopus = HASH_SIZE * self.pors_sign.octopus_len();
+        SizeBreakdown {
+            pors_pepper: HASH_SIZE,
+            pors_values: HASH_SIZE * PORS_K,
+            pors_octopus,
+            pors_padding: PORS_SIGNATURE_BYTES - HASH_SIZE * (1 + PORS_K) - pors_octopus,
+            subtree_wots: [HASH_SIZE * subtree::Signature::wots_length(); GRAVITY_D],
+            subtree_auth: [HASH_SIZE * subtree::Signature::auth_path_length(); GRAVITY_D],
+            auth_c: HASH_SIZE * GRAVITY_C,
+        }
+    }
+
+    // Canonical encoding: the PORS signature (pepper, values, octopus nodes,
+    // zero padding up to the maximal octopus length, then the octopus length as
+    // a u32 little-endian in a zero-padded 16-byte block), the GRAVITY_D subtree
+    // signatures and the GRAVITY_C nodes of auth_c. All fields have a fixed size,
+    // and deserialize rejects non-zero padding and out-of-range lengths, so each
+    // signature has exactly one encoding.
+    pub fn serialize(&self, output: &mut Vec<u8>) {
+        self.write_to(output);
+    }
+
+    // Same encoding as serialize, written to a buffer of the caller.
+    pub fn serialize_into(&self, buf: &mut [u8; Signature::SIZE]) {
+        let mut sink = hash::SliceSink::new(buf);
+        self.write_to(&mut sink);
+        debug_assert_eq!(sink.position(), Signature::SIZE);
+    }
+
+    fn write_to<S: hash::ByteSink>(&self, output: &mut S) {
+        self.pors_sign.write_to(output);
+        for t in self.subtrees.iter() {
+            t.write_to(output);
+        }
+        for x in self.auth_c.iter() {
+            x.write_to(output);
+        }
+    }
+
+    // Structural checks only, kept for existing callers: parse_and_validate is
+    // the entry point for untrusted input.
+    #[deny(clippy::indexing_slicing)]
+    pub fn deserialize<'a, I>(it: &mut I) -> Result<Self, DeserializeError>
+    where
+        I: Iterator<Item = &'a u8>,
+    {
+        let mut sign = Signature::default();
+        sign.deserialize_into(it)?;
+        Ok(sign)
+    }
+
+    // Parses exactly one canonical signature, and also rejects all-zero nodes in
+    // the authentication paths of the subtrees and in auth_c, and all-ones nodes
+    // (the sentinel of Hash::all_ones) in auth_c. Honest signatures
+    // have one with probability 2^-256 per node, whereas zeroed or truncated
+    // buffers have many. The PORS signature stores no indices (they derive from
+    // the pepper and the message), and its octopus length is already checked by
+    // deserialize.
+    pub fn parse_and_validate(bytes: &[u8]) -> Result<Self, ParseError> {
+        let sign = Signature::try_from(bytes).map_err(ParseError::Deserialization)?;
+        for (layer, t) in sign.subtrees.iter().enumerate() {
+            if let Some(node) = t.auth_path().iter().position(|x| *x == Hash::default()) {
+                return Err(ParseError::ZeroSubtreeAuth { layer, node });
+            }
+        }
+        if let Some(index) = sign.auth_c.iter().position(|x| *x == Hash::default()) {
+            return Err(ParseError::ZeroAuthC(index));
+        }
+        if let Some(index) = sign.auth_c.iter().position(Hash::is_all_ones) {
+            return Err(ParseError::AllOnesAuthC(index));
+        }
+        Ok(sign)
+    }
+
+    // Same as try_from on the concatenation of `parts`, without copying them into
+    // one buffer.
+    pub fn from_multipart(parts: &[&[u8]]) -> Result<Self, ParseError> {
+        let mut it = ChunkedIterator::new(parts);
+        let sign = Signature::deserialize(&mut it).map_err(ParseError::Deserialization)?;
+        if it.next().is_some() {
+            return Err(ParseError::Deserialization(DeserializeError::TrailingBytes));
+        }
+        Ok(sign)
+    }
+
+    // Same as deserialize, building the signature in place on the heap.
+    pub fn deserialize_boxed<'a, I>(it: &mut I) -> Result<Box<Self>, DeserializeError>
+    where
+        I: Iterator<Item = &'a u8>,
+    {
+        let mut sign = Signature::new_boxed();
+        sign.deserialize_into(it)?;
+        Ok(sign)
+    }
+
+    fn deserialize_into<'a, I>(&mut self, it: &mut I) -> Result<(), DeserializeError>
+    where
+        I: Iterator<Item = &'a u8>,
+    {
+        self.pors_sign = pors::Signature::deserialize(it)?;
+        for t in self.subtrees.iter_mut() {
+            *t = subtree::Signature::deserialize(it)?;
+        }
+        for x in self.auth_c.iter_mut() {
+            *x = Hash::deserialize(it)?;
+        }
+        Ok(())
+    }
+
+    // Default signature, written field by field into the allocation: Box::new
+    // may build it on the stack first. size_of::<Signature>() is 1328 bytes for
+    // the S parameter set and grows with GRAVITY_D and GRAVITY_C; the WOTS
+    // chains and the octopus are already on the heap.
+    fn new_boxed() -> Box<Self> {
+        let mut sign = Box::<Self>::new_uninit();
+        let ptr = sign.as_mut_ptr();
+        // SAFETY: each field is initialized through raw pointers, without
+        // references to uninitialized memory, before assume_init.
+        unsafe {
+            ptr::addr_of_mut!((*ptr).pors_sign).write(Default::default());
+            let subtrees = ptr::addr_of_mut!((*ptr).subtrees) as *mut subtree::Signature;
+            for i in 0..GRAVITY_D {
+                subtrees.add(i).write(Default::default());
+            }
+            let auth_c = ptr::addr_of_mut!((*ptr).auth_c) as *mut Hash;
+            for i in 0..GRAVITY_C {
+                auth_c.add(i).write(Default::default());
+            }
+            sign.assume_init()
+        }
+    }
+
+    // Canonical encoding in lowercase hex, without separators: 2 * SIZE digits.
+    pub fn encode_hex(&self) -> String {
+        hex::encode(Vec::<u8>::from(self))
+    }
+
+    // Inverse of encode_hex; upper case digits are accepted.
+    pub fn decode_hex(s: &str) -> Result<Self, HexError> {
+        let bytes = hex::decode(s).map_err(|e| match e {
+            hex::FromHexError::InvalidHexCharacter { c, index } => {
+                HexError::InvalidCharacter { c, index }
+            }
+            hex::FromHexError::OddLength | hex::FromHexError::InvalidStringLength => {
+                HexError::OddLength
+            }
+        })?;
+        Signature::try_from(bytes.as_slice()).map_err(HexError::Deserialization)
+    }
+
+    // Canonical encoding in Base58 (Bitcoin alphabet, without checksum).
+    #[cfg(feature = "bs58")]
+    pub fn to_base58(&self) -> String {
+        bs58::encode(Vec::<u8>::from(self)).into_string()
+    }
+
+    #[cfg(feature = "bs58")]
+    pub fn from_base58(s: &str) -> Result<Self, Base58Error> {
+        let bytes = bs58::decode(s).into_vec().map_err(Base58Error::Decoding)?;
+        Signature::try_from(bytes.as_slice()).map_err(Base58Error::Deserialization)
+    }
+
+    // Canonical signature in hex, in the format of the fixture files: whitespace
+    // is ignored, as well as lines starting with '#'.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn from_hex_test_vector(hex_str: &str) -> Result<Signature, ParseError> {
+        let bytes = decode_hex_vector(hex_str).map_err(ParseError::InvalidHex)?;
+        Signature::try_from(bytes.as_slice()).map_err(ParseError::Deserialization)
+    }
+
+    // Components are compared by their serialization.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn diff(&self, other: &Signature) -> SignatureDiff {
+        let pors_bytes = |sign: &Signature| {
+            let mut output = Vec::new();
+            sign.pors_sign.serialize(&mut output);
+            output
+        };
+        let subtree_bytes = |t: &subtree::Signature| {
+            let mut output = Vec::new();
+            t.serialize(&mut output);
+            output
+        };
+        SignatureDiff {
+            pors_differs: pors_bytes(self) != pors_bytes(other),
+            differing_subtrees: (0..GRAVITY_D)
+                .filter(|&i| subtree_bytes(&self.subtrees[i]) != subtree_bytes(&other.subtrees[i]))
+                .collect(),
+            auth_c_differs: self.auth_c != other.auth_c,
+        }
+    }
+
+    // Copies of this signature with one component replaced, to build signatures
+    // that fail verification at a chosen stage. Panics if the index is out of
+    // range.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn clone_with_modified_auth_c(&self, index: usize, new_hash: Hash) -> Signature {
+        let mut sign = self.clone_parts();
+        sign.auth_c[index] = new_hash;
+        sign
+    }
+
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn clone_with_modified_subtree(
+        &self,
+        index: usize,
+        subtree: subtree::Signature,
+    ) -> Signature {
+        let mut sign = self.clone_parts();
+        sign.subtrees[index] = subtree;
+        sign
+    }
+
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn clone_with_modified_pors(&self, pors: pors::Signature) -> Signature {
+        Signature {
+            pors_sign: pors,
+            ..self.clone_parts()
+        }
+    }
+
+    #[cfg(any(test, feature = "test-utils"))]
+    fn clone_parts(&self) -> Signature {
+        Signature {
+            pors_sign: self.pors_sign.clone(),
+            subtrees: self.subtrees.clone(),
+            auth_c: self.auth_c,
+        }
+    }
+
+    // Number of bytes that differ between the encodings of the signatures, which
+    // both have SIGNATURE_BYTES bytes.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn byte_diff_count(&self, other: &Signature) -> usize {
+        let (a, b) = (Vec::<u8>::from(self), Vec::<u8>::from(other));
+        a.iter().zip(b.iter()).filter(|(x, y)| x != y).count()
+    }
+
+    // Offset of the first byte that differs between the encodings: the PORS
+    // signature, then the subtrees, then auth_c.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn first_diff_offset(&self, other: &Signature) -> Option<usize> {
+        let (a, b) = (Vec::<u8>::from(self), Vec::<u8>::from(other));
+        a.iter().zip(b.iter()).position(|(x, y)| x != y)
+    }
+
+    // Canonical encoding, to a file created with mode 0o600 on Unix. An existing
+    // file is truncated, and keeps its mode.
+    pub fn serialize_to_file(&self, path: &Path) -> io::Result<()> {
+        use std::io::Write;
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options.open(path)?.write_all(&Vec::<u8>::from(self))
+    }
+
+    // Fails with io::ErrorKind::InvalidData unless the file holds exactly one
+    // canonical signature.
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        Signature::try_from(bytes.as_slice())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // Canonical encoding, prefixed by its length for embedding in streams.
+    pub fn encode_framed(&self) -> Vec<u8> {
+        let mut output = Vec::with_capacity(FRAME_HEADER_BYTES + SIGNATURE_BYTES);
+        output.extend((SIGNATURE_BYTES as u32).to_le_bytes().iter());
+        self.serialize(&mut output);
+        output
+    }
+
+    // {"version":"gravity-1","pors":"..","subtrees":[..],"auth_c":[..]}, where
+    // each string is the standard base64 (with padding) of the canonical
+    // encoding of the PORS signature, of each of the GRAVITY_D subtree
+    // signatures and of each of the GRAVITY_C nodes of the top-level path.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> String {
+        use base64::Engine;
+        let encode = |serialize: &dyn Fn(&mut Vec<u8>)| {
+            let mut bytes = Vec::new();
+            serialize(&mut bytes);
+            base64::engine::general_purpose::STANDARD.encode(bytes)
+        };
+        let json = SignatureJson {
+            version: JSON_VERSION.to_string(),
+            pors: encode(&|out| self.pors_sign.serialize(out)),
+            subtrees: self
+                .subtrees
+                .iter()
+                .map(|t| encode(&|out| t.serialize(out)))
+                .collect(),
+            auth_c: self
+                .auth_c
+                .iter()
+                .map(|x| encode(&|out| x.serialize(out)))
+                .collect(),
+        };
+        serde_json::to_string(&json).expect("strings serialize to JSON")
+    }
+
+    // Strict: unknown fields, other versions, non-canonical base64 and
+    // encodings, and trailing bytes are rejected.
+    #[cfg(feature = "serde")]
+    pub fn from_json(s: &str) -> Result<Self, JsonError> {
+        let json: SignatureJson =
+            serde_json::from_str(s).map_err(|e| JsonError::Syntax(e.to_string()))?;
+        if json.version != JSON_VERSION {
+            return Err(JsonError::UnsupportedVersion(json.version));
+        }
+        if json.subtrees.len() != GRAVITY_D {
+            return Err(JsonError::InvalidLength("subtrees"));
+        }
+        if json.auth_c.len() != GRAVITY_C {
+            return Err(JsonError::InvalidLength("auth_c"));
+        }
+
+        let mut sign = Signature {
+            pors_sign: decode_json_field("pors", &json.pors, |it| {
+                pors::Signature::deserialize(it)
+            })?,
+            ..Default::default()
+        };
+        for (t, x) in sign.subtrees.iter_mut().zip(json.subtrees.iter()) {
+            *t = decode_json_field("subtrees", x, |it| subtree::Signature::deserialize(it))?;
+        }
+        for (h, x) in sign.auth_c.iter_mut().zip(json.auth_c.iter()) {
+            *h = decode_json_field("auth_c", x, |it| Hash::deserialize(it))?;
+        }
+        Ok(sign)
+    }
+
+    // Decodes the frame at the start of `buf`, and returns the bytes after it.
+    // Lengths above SIGNATURE_BYTES are rejected before reading the frame.
+    #[deny(clippy::indexing_slicing)]
+    pub fn decode_framed(buf: &[u8]) -> Result<(Signature, &[u8]), DeserializeError> {
+        let len = frame_length(buf)?.ok_or(DeserializeError::UnexpectedEnd)?;
+        let (frame, rest) = buf
+            .get(FRAME_HEADER_BYTES..)
+            .and_then(|body| body.split_at_checked(len))
+            .ok_or(DeserializeError::UnexpectedEnd)?;
+        Ok((Signature::try_from(frame)?, rest))
+    }
+
+    // Exactly `count` concatenated signatures, filling `buf`.
+    pub fn deserialize_many(buf: &[u8], count: usize) -> Result<Vec<Signature>, ParseError> {
+        let mut stream = SignatureStream::new(buf);
+        let mut signs = Vec::with_capacity(count.min(buf.len() / SIGNATURE_BYTES));
+        while signs.len() < count {
+            match stream.next() {
+                Some(sign) => signs.push(sign?),
+                None => return Err(stream.error(DeserializeError::UnexpectedEnd)),
+            }
+        }
+        if stream.offset < buf.len() {
+            return Err(stream.error(DeserializeError::TrailingBytes));
+        }
+        Ok(signs)
+    }
+
+    // Whether `bytes` parses and re-serializes to itself.
+    #[deny(clippy::indexing_slicing)]
+    pub fn is_canonical(bytes: &[u8]) -> bool {
+        match Signature::try_from(bytes) {
+            Ok(sign) => Vec::<u8>::from(&sign) == bytes,
+            Err(_) => false,
+        }
+    }
+
+    // Building block for blinded signatures: XORs the mask into the first node
+    // of auth_c. The masked signature doesn't verify until the mask is removed.
+    #[cfg(feature = "experimental")]
+    pub fn apply_mask(&mut self, mask: &Hash) {
+        for (x, m) in self.auth_c[0].h.iter_mut().zip(mask.h.iter()) {
+            *x ^= m;
+        }
+    }
+
+    #[cfg(feature = "experimental")]
+    pub fn remove_mask(&mut self, mask: &Hash) {
+        self.apply_mask(mask)
+    }
+
+    #[cfg(feature = "experimental")]
+    pub fn to_compact(&self) -> CompactSignature {
+        let mut bytes = Vec::new();
+        self.pors_sign.serialize_compact(&mut bytes);
+        for t in self.subtrees.iter() {
+            t.serialize(&mut bytes);
+        }
+        for x in self.auth_c.iter() {
+            x.serialize(&mut bytes);
+        }
+        CompactSignature { bytes }
+    }
+}
+
+impl SizeBreakdown {
+    pub fn total(&self) -> usize {
+        self.pors_pepper
+            + self.pors_values
+            + self.pors_octopus
+            + self.pors_padding
+            + self.subtree_wots.iter().sum::<usize>()
+            + self.subtree_auth.iter().sum::<usize>()
+            + self.auth_c
+    }
+}
+
+impl fmt::Display for SizeBreakdown {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<16} {:>8}", "pors pepper", self.pors_pepper)?;
+        writeln!(f, "{:<16} {:>8}", "pors values", self.pors_values)?;
+        writeln!(f, "{:<16} {:>8}", "pors octopus", self.pors_octopus)?;
+        writeln!(f, "{:<16} {:>8}", "pors padding", self.pors_padding)?;
+        for i in 0..GRAVITY_D {
+            writeln!(
+                f,
+                "{:<16} {:>8}",
+                format!("subtree {} wots", i),
+                self.subtree_wots[i]
+            )?;
+            writeln!(
+                f,
+                "{:<16} {:>8}",
+                format!("subtree {} auth", i),
+                self.subtree_auth[i]
+            )?;
+        }
+        writeln!(f, "{:<16} {:>8}", "auth c", self.auth_c)?;
+        write!(f, "{:<16} {:>8}", "total", self.total())
+    }
+}
+
+impl VerifyTrace {
+    pub fn is_valid(&self) -> bool {
+        self.failure.is_none()
+    }
+
+    // First stage whose hash differs from that of `reference`, e.g. the trace of
+    // the same signature by another implementation, or of a valid signature of
+    // the same message. A stage missing from one of the traces differs.
+    pub fn first_divergence(&self, reference: &VerifyTrace) -> Option<TraceStage> {
+        let stages = self.stages.len().max(reference.stages.len());
+        (0..stages).find_map(|i| {
+            let (ours, theirs) = (self.stages.get(i), reference.stages.get(i));
+            if ours == theirs {
+                None
+            } else {
+                ours.or(theirs).map(|(stage, _)| *stage)
+            }
+        })
+    }
+}
+
+impl fmt::Display for TraceStage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TraceStage::Pors => write!(f, "pors"),
+            TraceStage::Subtree(i) => write!(f, "subtree {}", i),
+            TraceStage::Top => write!(f, "top"),
+        }
+    }
+}
+
+impl fmt::Display for VerifyTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (stage, h) in self.stages.iter() {
+            writeln!(f, "{:<12} {}", stage.to_string(), hex::encode(h.h))?;
+        }
+        writeln!(f, "{:<12} {}", "expected", hex::encode(self.expected.h))?;
+        match self.failure {
+            None => write!(f, "{:<12} ok", "result"),
+            Some(stage) => write!(f, "{:<12} failed at {}", "result", stage),
+        }
+    }
+}
+
+#[deny(clippy::indexing_slicing)]
+impl TryFrom<&[u8]> for PubKey {
+    type Error = DeserializeError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, DeserializeError> {
+        let mut it = bytes.iter();
+        let h = Hash::deserialize(&mut it)?;
+        if it.next().is_some() {
+            return Err(DeserializeError::TrailingBytes);
+        }
+        Ok(PubKey { h })
+    }
+}
+
+impl From<&PubKey> for [u8; PUBLIC_KEY_BYTES] {
+    fn from(pk: &PubKey) -> Self {
+        pk.h.h
+    }
+}
+
+// Prefix of to_uncompressed_point.
+#[cfg(feature = "compat")]
+const POINT_PREFIX: u8 = 0x04;
+
+// Transport of the public key for interfaces that expect a one-byte tag and 32
+// bytes. This is NOT an elliptic curve point: the prefix is a convention for
+// length-tagged binary transport. As a SEC1 uncompressed point takes 65 bytes,
+// EC parsers reject this encoding rather than misinterpret it.
+#[cfg(feature = "compat")]
+impl PubKey {
+    pub fn to_uncompressed_point(&self) -> [u8; PUBLIC_KEY_BYTES + 1] {
+        let mut bytes = [0u8; PUBLIC_KEY_BYTES + 1];
+        bytes[0] = POINT_PREFIX;
+        bytes[1..].copy_from_slice(&self.h.h);
+        bytes
+    }
+
+    pub fn from_uncompressed_point(
+        bytes: &[u8; PUBLIC_KEY_BYTES + 1],
+    ) -> Result<PubKey, DeserializeError> {
+        let (prefix, h) = array_refs![bytes, 1, PUBLIC_KEY_BYTES];
+        if prefix[0] != POINT_PREFIX {
+            return Err(DeserializeError::InvalidHeader);
+        }
+        Ok(PubKey { h: Hash { h: *h } })
+    }
+}
+
+// Scheme identifier of to_compressed_bytes, for Gravity-SPHINCS at security
+// level 1. It is neither 0x02 nor 0x03, which tag SEC1 compressed points of
+// the same 33 bytes, so that EC parsers reject these keys.
+pub const COMPRESSED_KEY_TAG: u8 = 0x47;
+
+// Transport alongside other post-quantum schemes whose keys are tagged byte
+// arrays: 33 bytes, the scheme identifier then the 32-byte key.
+impl PubKey {
+    pub fn to_compressed_bytes(&self) -> [u8; PUBLIC_KEY_BYTES + 1] {
+        let mut bytes = [0u8; PUBLIC_KEY_BYTES + 1];
+        bytes[0] = COMPRESSED_KEY_TAG;
+        bytes[1..].copy_from_slice(&self.h.h);
+        bytes
+    }
+
+    pub fn from_compressed_bytes(
+        bytes: &[u8; PUBLIC_KEY_BYTES + 1],
+    ) -> Result<PubKey, CompressedKeyError> {
+        let (tag, h) = array_refs![bytes, 1, PUBLIC_KEY_BYTES];
+        if tag[0] != COMPRESSED_KEY_TAG {
+            return Err(CompressedKeyError::UnknownScheme(tag[0]));
+        }
+        Ok(PubKey { h: Hash { h: *h } })
+    }
+}
+
+// Algorithm identifier of as_der and VerificationKey::encode_spki (see
+// SPKI_PREFIX), in the experimental 1.3.9999 arc: a
+// placeholder until an OID is registered for Gravity-SPHINCS.
+#[cfg(feature = "der")]
+pub const DER_ALGORITHM_OID: spki::ObjectIdentifier =
+    spki::ObjectIdentifier::new_unwrap("1.3.9999.99.1");
+
+// SubjectPublicKeyInfo with the algorithm DER_ALGORITHM_OID, without
+// parameters, and the 32-byte key as the subject public key.
+#[cfg(feature = "der")]
+impl PubKey {
+    pub fn as_der(&self) -> Vec<u8> {
+        use spki::der::Encode;
+        let spki = spki::SubjectPublicKeyInfoRef {
+            algorithm: spki::AlgorithmIdentifierRef {
+                oid: GravitySignatureScheme::algorithm_id(),
+                parameters: None,
+            },
+            subject_public_key: spki::der::asn1::BitStringRef::from_bytes(&self.h.h)
+                .expect("32 bytes fit in a bit string"),
+        };
+        spki.to_der().expect("a 32-byte key always encodes")
+    }
+
+    pub fn from_der(bytes: &[u8]) -> Result<PubKey, DerError> {
+        use spki::der::Decode;
+        let spki = spki::SubjectPublicKeyInfoRef::from_der(bytes).map_err(DerError::Malformed)?;
+        if spki.algorithm.oid != GravitySignatureScheme::algorithm_id()
+            || spki.algorithm.parameters.is_some()
+        {
+            return Err(DerError::UnknownAlgorithm);
+        }
+        let key = &spki.subject_public_key;
+        let h = key
+            .as_bytes()
+            .and_then(|b| <[u8; PUBLIC_KEY_BYTES]>::try_from(b).ok())
+            .ok_or(DerError::InvalidKeyLength(key.bit_len()))?;
+        Ok(PubKey { h: Hash { h } })
+    }
+}
+
+#[deny(clippy::indexing_slicing)]
+impl TryFrom<&[u8]> for Signature {
+    type Error = DeserializeError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, DeserializeError> {
+        let mut it = bytes.iter();
+        let sign = Signature::deserialize(&mut it)?;
+        if it.next().is_some() {
+            return Err(DeserializeError::TrailingBytes);
+        }
+        Ok(sign)
+    }
+}
+
+impl From<&Signature> for Vec<u8> {
+    fn from(sign: &Signature) -> Self {
+        let mut output = Vec::with_capacity(SIGNATURE_BYTES);
+        sign.serialize(&mut output);
+        output
+    }
+}
+
+#[cfg(feature = "serde")]
+fn decode_json_field<T, F>(field: &'static str, s: &str, parse: F) -> Result<T, JsonError>
+where
+    F: FnOnce(&mut std::slice::Iter<u8>) -> Result<T, DeserializeError>,
+{
+    use base64::Engine;
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(s)
+        .map_err(|_| JsonError::InvalidBase64(field))?;
+    let mut it = bytes.iter();
+    let x = parse(&mut it).map_err(|e| JsonError::Deserialization(field, e))?;
+    if it.next().is_some() {
+        return Err(JsonError::Deserialization(
+            field,
+            DeserializeError::TrailingBytes,
+        ));
+    }
+    Ok(x)
+}
+
+// Length declared by the frame header at the start of `buf`, if complete.
+#[deny(clippy::indexing_slicing)]
+fn frame_length(buf: &[u8]) -> Result<Option<usize>, DeserializeError> {
+    let Some(header) = buf.first_chunk::<FRAME_HEADER_BYTES>() else {
+        return Ok(None);
+    };
+    let len = u32::from_le_bytes(*header) as usize;
+    if len > SIGNATURE_BYTES {
+        return Err(DeserializeError::FrameTooLong(len));
+    }
+    Ok(Some(len))
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn feed(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    // Bytes received but not yet decoded.
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    // Returns the next complete signature, or None until more data is fed. A
+    // malformed frame is consumed and reported; an oversize length is reported
+    // without consuming anything, as the stream cannot be resynchronized.
+    #[deny(clippy::indexing_slicing)]
+    pub fn next_signature(&mut self) -> Result<Option<Signature>, DeserializeError> {
+        let Some(len) = frame_length(&self.buf)? else {
+            return Ok(None);
+        };
+        let end = FRAME_HEADER_BYTES + len;
+        if self.buf.len() < end {
+            return Ok(None);
+        }
+        let frame: Vec<u8> = self.buf.drain(..end).skip(FRAME_HEADER_BYTES).collect();
+        Signature::try_from(frame.as_slice()).map(Some)
+    }
+}
+
+impl<'a> SignatureStream<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        SignatureStream {
+            buf,
+            offset: 0,
+            index: 0,
+            failed: false,
+        }
+    }
+
+    // Bytes of the signatures parsed so far.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    fn error(&self, error: DeserializeError) -> ParseError {
+        ParseError::Item {
+            index: self.index,
+            offset: self.offset,
+            error,
+        }
+    }
+}
+
+impl Iterator for SignatureStream<'_> {
+    type Item = Result<Signature, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = self.buf.get(self.offset..)?;
+        if self.failed || rest.is_empty() {
+            return None;
+        }
+        let mut it = rest.iter();
+        match Signature::deserialize(&mut it) {
+            Ok(sign) => {
+                self.offset += rest.len() - it.as_slice().len();
+                self.index += 1;
+                Some(Ok(sign))
+            }
+            Err(e) => {
+                self.failed = true;
+                Some(Err(self.error(e)))
+            }
+        }
+    }
+}
+
+impl<'a> ChunkedIterator<'a> {
+    pub fn new(parts: &'a [&'a [u8]]) -> Self {
+        ChunkedIterator {
+            parts,
+            current: [].iter(),
+        }
+    }
+}
+
+impl<'a> Iterator for ChunkedIterator<'a> {
+    type Item = &'a u8;
+
+    fn next(&mut self) -> Option<&'a u8> {
+        loop {
+            if let Some(x) = self.current.next() {
+                return Some(x);
+            }
+            let (first, rest) = self.parts.split_first()?;
+            self.current = first.iter();
+            self.parts = rest;
+        }
+    }
+}
+
+#[cfg(feature = "experimental")]
+impl CompactSignature {
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            bytes: bytes.to_vec(),
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    // Returns None if the compact encoding is malformed, or if the expanded
+    // signature does not verify under pk.
+    #[deny(clippy::indexing_slicing)]
+    pub fn expand(sign: &CompactSignature, pk: &PubKey, msg: &[u8]) -> Option<Signature> {
+        let h = hash::long_hash(msg);
+        let mut it = sign.bytes.iter();
+        let mut full = Signature {
+            pors_sign: pors::Signature::deserialize_compact(&mut it, &h).ok()?,
+            ..Default::default()
+        };
+        for t in full.subtrees.iter_mut() {
+            *t = subtree::Signature::deserialize(&mut it).ok()?;
+        }
+        for x in full.auth_c.iter_mut() {
+            *x = Hash::deserialize(&mut it).ok()?;
+        }
+        if it.next().is_some() {
+            return None;
+        }
+        pk.verify_hash(&full, &h).ok()?;
+        Some(full)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_verify() {
+        let mut random = [0u8; 64];
+        for i in 0..64 {
+            random[i] = i as u8;
+        }
+
+        let sk = SecKey::new(&random);
+        let pk = sk.genpk();
+        let msg = hash::tests::HASH_ELEMENT;
+        let sign = sk.sign_hash(&msg);
+        assert!(pk.verify_hash(&sign, &msg).is_ok());
+    }
+
+    #[test]
+    fn test_verify_errors() {
+        let random = [0u8; 64];
+        let sk = SecKey::from_slice(&random).unwrap();
+        let pk = sk.genpk();
+        let msg = hash::tests::HASH_ELEMENT;
+        let mut sign = sk.sign_hash(&msg);
+        sign.auth_c[0].h[0] ^= 1;
+        assert_eq!(pk.verify_hash(&sign, &msg), Err(GravityError::Verification));
+
+        let mut sign_bytes = Vec::<u8>::new();
+        sign.serialize(&mut sign_bytes);
+        sign_bytes.pop();
+        assert_eq!(
+            Signature::deserialize(&mut sign_bytes.iter()).err(),
+            Some(DeserializeError::UnexpectedEnd)
+        );
+    }
+
+    #[test]
+    fn test_sign_bytes_timed() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let msg = b"timed";
+        let (sign, elapsed) = sk.sign_bytes_timed(msg);
+        assert_eq!(Vec::<u8>::from(&sign), Vec::<u8>::from(&sk.sign_bytes(msg)));
+        assert!(elapsed > Duration::ZERO);
+        assert!(elapsed < Duration::from_secs(60));
+    }
+
+    // Timings are meaningless without optimizations.
+    #[cfg_attr(debug_assertions, ignore)]
+    #[test]
+    fn test_estimate_sign_time() {
+        use std::time::Instant;
+
+        let sk = SecKey::new(&[0u8; 64]);
+        sk.ensure_cache();
+        let msg = hash::tests::HASH_ELEMENT;
+        let iterations = 10;
+        let start = Instant::now();
+        for _ in 0..iterations {
+            black_box(sk.sign_hash(black_box(&msg)));
+        }
+        let actual = start.elapsed().as_nanos() as u64 / iterations;
+
+        let estimate = SecKey::estimate_sign_time_ns();
+        assert!(estimate <= 10 * actual, "{} vs. {}", estimate, actual);
+        assert!(actual <= 10 * estimate, "{} vs. {}", estimate, actual);
+    }
+
+    #[test]
+    fn test_attached() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.genpk();
+
+        let msg = b"Hello world";
+        let blob = sk.sign_attached(msg);
+        assert_eq!(
+            blob.len(),
+            ATTACHED_HEADER_BYTES + SIGNATURE_BYTES + msg.len()
+        );
+        assert_eq!(pk.open_attached(&blob), Ok(&msg[..]));
+
+        let blob = sk.sign_attached(&[]);
+        assert_eq!(pk.open_attached(&blob), Ok(&[][..]));
+
+        let msg = vec![0xab; 3 << 20];
+        let blob = sk.sign_attached(&msg);
+        assert_eq!(pk.open_attached(&blob), Ok(msg.as_slice()));
+    }
+
+    #[test]
+    fn test_diff() {
+        let sk0 = SecKey::new(&[0u8; 64]);
+        let sk1 = SecKey::new(&[1u8; 64]);
+        let msg = b"Hello world";
+        let sign0 = sk0.sign_bytes(msg);
+        let sign1 = sk1.sign_bytes(msg);
+
+        let diff = sign0.diff(&sign1);
+        assert!(diff.pors_differs);
+        assert_eq!(diff.differing_subtrees, (0..GRAVITY_D).collect::<Vec<_>>());
+        assert!(diff.auth_c_differs);
+        assert_eq!(sign1.diff(&sign0), diff);
+        assert_eq!(sign0.diff(&sk0.sign_bytes(msg)), SignatureDiff::default());
+
+        // Only the last node of auth_c.
+        let mut bytes = Vec::<u8>::from(&sign0);
+        *bytes.last_mut().unwrap() ^= 1;
+        let diff = sign0.diff(&Signature::try_from(bytes.as_slice()).unwrap());
+        assert_eq!(
+            diff,
+            SignatureDiff {
+                auth_c_differs: true,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_sign_bytes_no_cache() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.genpk();
+        let mut buf = [0xFFu8; Signature::SIZE];
+        for msg in [&b""[..], b"Hello world"] {
+            let bytes = sk.sign_bytes_no_cache(msg, &mut buf);
+            assert_eq!(bytes[..], Vec::<u8>::from(&sk.sign_bytes(msg))[..]);
+            let sign = Signature::try_from(&bytes[..]).unwrap();
+            assert!(pk.verify_bytes(&sign, msg).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_from_multipart() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.genpk();
+        let msg = b"Hello world";
+        let bytes = Vec::<u8>::from(&sk.sign_bytes(msg));
+        let (a, rest) = bytes.split_at(100);
+        let (b, c) = rest.split_at(rest.len() - 1);
+
+        let sign = Signature::from_multipart(&[a, b, c]).unwrap();
+        assert_eq!(Vec::<u8>::from(&sign), bytes);
+        assert!(pk.verify_bytes(&sign, msg).is_ok());
+        let sign = Signature::from_multipart(&[&[], a, &[], b, c, &[]]).unwrap();
+        assert_eq!(Vec::<u8>::from(&sign), bytes);
+        assert_eq!(ChunkedIterator::new(&[a, b, c]).count(), bytes.len());
+
+        assert_eq!(
+            Signature::from_multipart(&[a, b]).err(),
+            Some(ParseError::Deserialization(DeserializeError::UnexpectedEnd))
+        );
+        assert_eq!(
+            Signature::from_multipart(&[a, b, c, &[0]]).err(),
+            Some(ParseError::Deserialization(DeserializeError::TrailingBytes))
+        );
+        assert!(Signature::from_multipart(&[]).is_err());
+    }
+
+    #[test]
+    fn test_clone_with_modified() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.genpk();
+        let msg = hash::tests::HASH_ELEMENT;
+        let sign = sk.sign_hash(&msg);
+        let trace = pk.verify_hash_trace(&sign, &msg);
+        let (other_pors, other_subtrees, _) = SecKey::new(&[1u8; 64]).sign_hash(&msg).into_parts();
+
+        let bad = sign.clone_with_modified_auth_c(GRAVITY_C - 1, Hash::default());
+        assert_eq!(
+            sign.diff(&bad),
+            SignatureDiff {
+                auth_c_differs: true,
+                ..Default::default()
+            }
+        );
+        assert!(pk.verify_hash(&bad, &msg).is_err());
+        assert_eq!(
+            pk.verify_hash_trace(&bad, &msg).first_divergence(&trace),
+            Some(TraceStage::Top)
+        );
+
+        for (i, subtree) in other_subtrees.into_iter().enumerate() {
+            let bad = sign.clone_with_modified_subtree(i, subtree);
+            assert_eq!(sign.diff(&bad).differing_subtrees, vec![i]);
+            assert!(pk.verify_hash(&bad, &msg).is_err());
+            assert_eq!(
+                pk.verify_hash_trace(&bad, &msg).first_divergence(&trace),
+                Some(TraceStage::Subtree(i))
+            );
+        }
+
+        let bad = sign.clone_with_modified_pors(other_pors);
+        assert!(sign.diff(&bad).pors_differs);
+        assert!(pk.verify_hash(&bad, &msg).is_err());
+        assert_eq!(
+            pk.verify_hash_trace(&bad, &msg).first_divergence(&trace),
+            Some(TraceStage::Pors)
+        );
+
+        // The original is left untouched.
+        assert!(pk.verify_hash(&sign, &msg).is_ok());
+        assert_eq!(
+            sign.diff(&sign.clone_with_modified_auth_c(0, sign.auth_c[0])),
+            SignatureDiff::default()
+        );
+    }
+
+    #[test]
+    fn test_byte_diff_count() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let sign = sk.sign_bytes(b"Hello world");
+        assert_eq!(sign.byte_diff_count(&sign), 0);
+        assert_eq!(sign.first_diff_offset(&sign), None);
+
+        let mut bytes = Vec::<u8>::from(&sign);
+        let offset = SIGNATURE_BYTES - GRAVITY_C * HASH_SIZE + 3;
+        bytes[offset] ^= 0x80;
+        let bad = Signature::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(sign.byte_diff_count(&bad), 1);
+        assert_eq!(bad.byte_diff_count(&sign), 1);
+        assert_eq!(sign.first_diff_offset(&bad), Some(offset));
+
+        let other = sk.sign_bytes(b"Another message");
+        assert!(sign.byte_diff_count(&other) > 1);
+        assert!(sign.first_diff_offset(&other).is_some());
+    }
+
+    #[test]
+    fn test_file() -> io::Result<()> {
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.genpk();
+        let msg = b"Hello world";
+        let sign = sk.sign_bytes(msg);
+        let path = std::env::temp_dir().join(format!("gravity-test-{}.sig", std::process::id()));
+
+        sign.serialize_to_file(&path)?;
+        let read = Signature::from_file(&path)?;
+        assert_eq!(Vec::<u8>::from(&read), Vec::<u8>::from(&sign));
+        assert!(pk.verify_bytes(&read, msg).is_ok());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+        }
+
+        // Overwritten by a shorter file, then by a signature again.
+        fs::write(&path, &Vec::<u8>::from(&sign)[1..])?;
+        let err = Signature::from_file(&path).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        sk.sign_bytes(b"other").serialize_to_file(&path)?;
+        assert!(pk
+            .verify_bytes(&Signature::from_file(&path)?, b"other")
+            .is_ok());
+
+        fs::remove_file(&path)?;
+        assert_eq!(
+            Signature::from_file(&path).err().unwrap().kind(),
+            io::ErrorKind::NotFound
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_framed() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let signs = [sk.sign_bytes(b"Hello"), sk.sign_bytes(b"world")];
+        let frames: Vec<Vec<u8>> = signs.iter().map(|s| s.encode_framed()).collect();
+        assert_eq!(frames[0].len(), FRAME_HEADER_BYTES + SIGNATURE_BYTES);
+        assert_eq!(frames[0][FRAME_HEADER_BYTES..], Vec::<u8>::from(&signs[0]));
+
+        // Two frames back to back.
+        let stream = [frames[0].as_slice(), frames[1].as_slice(), &[0xab]].concat();
+        let (first, rest) = Signature::decode_framed(&stream).unwrap();
+        let (second, rest) = Signature::decode_framed(rest).unwrap();
+        assert_eq!(Vec::<u8>::from(&first), Vec::<u8>::from(&signs[0]));
+        assert_eq!(Vec::<u8>::from(&second), Vec::<u8>::from(&signs[1]));
+        assert_eq!(rest, [0xab]);
+        assert_eq!(
+            Signature::decode_framed(rest).err(),
+            Some(DeserializeError::UnexpectedEnd)
+        );
+        assert_eq!(
+            Signature::decode_framed(&frames[0][..frames[0].len() - 1]).err(),
+            Some(DeserializeError::UnexpectedEnd)
+        );
+
+        // A frame split across three feeds, followed by the start of another one.
+        let mut decoder = FrameDecoder::new();
+        for chunk in [&frames[0][..2], &frames[0][2..1000], &frames[0][1000..]].iter() {
+            assert!(decoder.next_signature().unwrap().is_none());
+            decoder.feed(chunk);
+        }
+        decoder.feed(&frames[1][..10]);
+        let sign = decoder.next_signature().unwrap().unwrap();
+        assert_eq!(Vec::<u8>::from(&sign), Vec::<u8>::from(&signs[0]));
+        assert!(decoder.next_signature().unwrap().is_none());
+        assert_eq!(decoder.buffered(), 10);
+        decoder.feed(&frames[1][10..]);
+        let sign = decoder.next_signature().unwrap().unwrap();
+        assert!(sk.genpk().verify_bytes(&sign, b"world").is_ok());
+        assert_eq!(decoder.buffered(), 0);
+    }
+
+    #[test]
+    fn test_framed_too_long() {
+        let mut frame = ((SIGNATURE_BYTES + 1) as u32).to_le_bytes().to_vec();
+        frame.resize(FRAME_HEADER_BYTES + SIGNATURE_BYTES + 1, 0);
+        let too_long = DeserializeError::FrameTooLong(SIGNATURE_BYTES + 1);
+        assert_eq!(Signature::decode_framed(&frame).err(), Some(too_long));
+
+        let mut decoder = FrameDecoder::new();
+        decoder.feed(&frame[..FRAME_HEADER_BYTES]);
+        assert_eq!(decoder.next_signature().err(), Some(too_long));
+        assert_eq!(decoder.buffered(), FRAME_HEADER_BYTES);
+
+        // Within the limit, but not a valid signature.
+        let mut frame = 3u32.to_le_bytes().to_vec();
+        frame.extend([0u8; 3].iter());
+        assert_eq!(
+            Signature::decode_framed(&frame).err(),
+            Some(DeserializeError::UnexpectedEnd)
+        );
+        let mut decoder = FrameDecoder::new();
+        decoder.feed(&frame);
+        assert_eq!(
+            decoder.next_signature().err(),
+            Some(DeserializeError::UnexpectedEnd)
+        );
+        assert_eq!(decoder.buffered(), 0);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_async() {
+        use crate::task::tests::block_on_all;
+
+        fn assert_send<T: Send>(x: T) -> T {
+            x
+        }
+
+        let keypair = block_on_all(vec![assert_send(GravityKeyPair::generate_async([0u8; 64]))])
+            .pop()
+            .unwrap();
+        let sk = Arc::new(keypair.secret_key);
+        let pk = keypair.public_key;
+
+        let msgs: Vec<Vec<u8>> = (0..4u8).map(|i| vec![i; 100]).collect();
+        let futures: Vec<_> = msgs
+            .iter()
+            .map(|msg| Box::pin(assert_send(sk.clone().sign_bytes_async(msg.clone()))))
+            .collect();
+        for (sign, msg) in block_on_all(futures).iter().zip(msgs.iter()) {
+            assert_eq!(pk.verify_bytes(sign, msg), Ok(()));
+            assert_eq!(Vec::<u8>::from(sign), Vec::<u8>::from(&sk.sign_bytes(msg)));
+        }
+
+        // A cancelled signature leaves the key usable.
+        drop(sk.clone().sign_bytes_async(b"cancelled".to_vec()));
+        let sign = block_on_all(vec![Box::pin(
+            sk.clone().sign_bytes_async(b"next".to_vec()),
+        )])
+        .pop()
+        .unwrap();
+        assert_eq!(pk.verify_bytes(&sign, b"next"), Ok(()));
+    }
+
+    #[test]
+    fn test_attached_tamper() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.genpk();
+        let blob = sk.sign_attached(b"Hello world");
+
+        let mut bad = blob.clone();
+        bad[0] ^= 1;
+        assert_eq!(
+            pk.open_attached(&bad),
+            Err(GravityError::Deserialization(
+                DeserializeError::InvalidHeader
+            ))
+        );
+
+        let mut bad = blob.clone();
+        bad[4] += 1;
+        assert_eq!(
+            pk.open_attached(&bad),
+            Err(GravityError::Deserialization(
+                DeserializeError::UnexpectedEnd
+            ))
+        );
+
+        let mut bad = blob.clone();
+        bad[4] -= 1;
+        assert_eq!(
+            pk.open_attached(&bad),
+            Err(GravityError::Deserialization(
+                DeserializeError::TrailingBytes
+            ))
+        );
+
+        let mut bad = blob.clone();
+        *bad.last_mut().unwrap() ^= 1;
+        assert_eq!(pk.open_attached(&bad), Err(GravityError::Verification));
+
+        let mut bad = blob.clone();
+        bad[ATTACHED_HEADER_BYTES + 1] ^= 1;
+        assert_eq!(pk.open_attached(&bad), Err(GravityError::Verification));
+
+        let mut bad = blob.clone();
+        bad.pop();
+        assert!(pk.open_attached(&bad).is_err());
+
+        let mut bad = blob.clone();
+        bad.push(0);
+        assert!(pk.open_attached(&bad).is_err());
+
+        assert!(pk.open_attached(&blob[..5]).is_err());
+    }
+
+    // Reader returning at most `chunk` bytes per call, and failing after `fail_at`.
+    struct ChunkReader<'a> {
+        data: &'a [u8],
+        chunk: usize,
+        fail_at: usize,
+    }
+
+    impl<'a> io::Read for ChunkReader<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.fail_at == 0 {
+                return Err(io::Error::new(io::ErrorKind::ConnectionReset, "reset"));
+            }
+            let n = buf
+                .len()
+                .min(self.chunk)
+                .min(self.data.len())
+                .min(self.fail_at);
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            self.fail_at -= n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_verify_streaming() {
+        let pk = SecKey::new(&[0u8; 64]).genpk();
+        let msg = hash::long_hash(&ZEROS_MSG);
+        let fixture = sign_zeros_fixture();
+
+        let reader = ChunkReader {
+            data: &fixture,
+            chunk: 16,
+            fail_at: usize::MAX,
+        };
+        assert_eq!(pk.verify_streaming(reader, &msg), Ok(()));
+
+        let reader = ChunkReader {
+            data: &fixture,
+            chunk: 16,
+            fail_at: PORS_SIGNATURE_BYTES + 100,
+        };
+        assert_eq!(
+            pk.verify_streaming(reader, &msg),
+            Err(GravityError::Deserialization(DeserializeError::Io(
+                io::ErrorKind::ConnectionReset
+            )))
+        );
+
+        assert_eq!(
+            pk.verify_streaming(&fixture[..fixture.len() - 1], &msg),
+            Err(GravityError::Deserialization(
+                DeserializeError::UnexpectedEnd
+            ))
+        );
+
+        let mut corrupted = fixture.clone();
+        corrupted[PORS_SIGNATURE_BYTES + 7] ^= 1;
+        assert_eq!(
+            pk.verify_streaming(corrupted.as_slice(), &msg),
+            Err(GravityError::Verification)
+        );
+
+        let mut corrupted = fixture.clone();
+        corrupted[PORS_SIGNATURE_BYTES - 1] ^= 1;
+        assert_eq!(
+            pk.verify_streaming(corrupted.as_slice(), &msg),
+            Err(GravityError::Deserialization(
+                DeserializeError::NonZeroPadding
+            ))
+        );
+    }
+
+    #[test]
+    fn test_verify_timing_safe() {
+        let pk = SecKey::new(&[0u8; 64]).genpk();
+        let fixture = sign_zeros_fixture();
+        let sign = Signature::try_from(fixture.as_slice()).unwrap();
+        assert!(pk.verify_timing_safe(&sign, &ZEROS_MSG));
+        // Another message selects another PORS subset, which the octopus does
+        // not match.
+        assert!(!pk.verify_timing_safe(&sign, b"another message"));
+
+        // One corruption in the pepper, the values, the octopus, the subtrees
+        // and auth_c.
+        let mut offsets = vec![0, HASH_SIZE, HASH_SIZE * (1 + PORS_K), SIGNATURE_BYTES - 1];
+        if GRAVITY_D != 0 {
+            offsets.extend([
+                PORS_SIGNATURE_BYTES,
+                PORS_SIGNATURE_BYTES + SUBTREE_SIGNATURE_BYTES - 1,
+            ]);
+        }
+        for offset in offsets.iter() {
+            let mut corrupted = fixture.clone();
+            corrupted[*offset] ^= 1;
+            let sign = Signature::try_from(corrupted.as_slice()).unwrap();
+            assert!(pk.verify_bytes(&sign, &ZEROS_MSG).is_err());
+            assert!(!pk.verify_timing_safe(&sign, &ZEROS_MSG));
+        }
+    }
+
+    #[cfg(feature = "experimental")]
+    #[test]
+    fn test_mask() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.genpk();
+        let mut sign = sk.sign_bytes(b"Hello world");
+        let expect = Vec::<u8>::from(&sign);
+        let mask = Hash { h: [0xa5; 32] };
+
+        sign.apply_mask(&mask);
+        assert_ne!(Vec::<u8>::from(&sign), expect);
+        assert!(pk.verify_bytes(&sign, b"Hello world").is_err());
+
+        sign.remove_mask(&mask);
+        assert_eq!(Vec::<u8>::from(&sign), expect);
+        assert!(pk.verify_bytes(&sign, b"Hello world").is_ok());
+    }
+
+    #[cfg(feature = "experimental")]
+    #[test]
+    fn test_compact() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.genpk();
+        let msg = b"Hello world";
+        let sign = sk.sign_bytes(msg);
+        let mut sign_bytes = Vec::<u8>::new();
+        sign.serialize(&mut sign_bytes);
+
+        let compact = sign.to_compact();
+        assert!(compact.as_bytes().len() < SIGNATURE_BYTES);
+
+        let compact = CompactSignature::from_bytes(compact.as_bytes());
+        let expanded = CompactSignature::expand(&compact, &pk, msg).unwrap();
+        let mut expanded_bytes = Vec::<u8>::new();
+        expanded.serialize(&mut expanded_bytes);
+        assert_eq!(expanded_bytes, sign_bytes);
+
+        assert!(CompactSignature::expand(&compact, &pk, b"Hello World").is_none());
+
+        let mut bytes = compact.as_bytes().to_vec();
+        bytes.push(0);
+        assert!(
+            CompactSignature::expand(&CompactSignature::from_bytes(&bytes), &pk, msg).is_none()
+        );
+        bytes.truncate(bytes.len() - 2);
+        assert!(
+            CompactSignature::expand(&CompactSignature::from_bytes(&bytes), &pk, msg).is_none()
+        );
+    }
+
+    #[test]
+    fn test_signer() {
+        let random = [0u8; 64];
+        let sk = SecKey::new(&random);
+        let pk = sk.genpk();
+        let signer = Signer::new(SecKey::new(&random), 2);
+        assert_eq!(signer.capacity(), 2);
+
+        let msg = b"Hello world";
+        let mut expect = Vec::<u8>::new();
+        sk.sign_bytes(msg).serialize(&mut expect);
+
+        let count = hash::tests::hash_count();
+        let sign = signer.sign_bytes(msg);
+        let miss = hash::tests::hash_count() - count;
+        let mut sign_bytes = Vec::<u8>::new();
+        sign.serialize(&mut sign_bytes);
+        assert_eq!(sign_bytes, expect);
+        assert_eq!(signer.cached_instances(), 1);
+
+        // Same message, same instance.
+        let count = hash::tests::hash_count();
+        let sign = signer.sign_bytes(msg);
+        let hit = hash::tests::hash_count() - count;
+        let mut sign_bytes = Vec::<u8>::new();
+        sign.serialize(&mut sign_bytes);
+        assert_eq!(sign_bytes, expect);
+        assert_eq!(miss - hit, PORS_T);
+        assert!(pk.verify_bytes(&sign, msg).is_ok());
+
+        for msg in [b"a", b"b", b"c"] {
+            let sign = signer.sign_bytes(msg);
+            assert!(pk.verify_bytes(&sign, msg).is_ok());
+        }
+        assert!(signer.cached_instances() <= 2);
+
+        let signer = Signer::new(SecKey::new(&random), 0);
+        let mut sign_bytes = Vec::<u8>::new();
+        signer.sign_bytes(msg).serialize(&mut sign_bytes);
+        assert_eq!(sign_bytes, expect);
+        assert_eq!(signer.cached_instances(), 0);
+    }
+
+    #[test]
+    fn test_size_breakdown() {
+        let fixture = sign_zeros_fixture();
+        let sign = Signature::deserialize(&mut fixture.iter()).unwrap();
+        let sizes = sign.size_breakdown();
+        assert_eq!(sizes.total(), fixture.len());
+        assert!(sizes.to_string().ends_with(&format!("{}", fixture.len())));
+
+        let sk = SecKey::new(&[0u8; 64]);
+        let breakdowns: Vec<SizeBreakdown> = (0u8..8)
+            .map(|i| sk.sign_bytes(&[i]).size_breakdown())
+            .collect();
+        for b in breakdowns.iter() {
+            assert_eq!(b.total(), SIGNATURE_BYTES);
+            assert_eq!(
+                SizeBreakdown {
+                    pors_octopus: sizes.pors_octopus,
+                    pors_padding: sizes.pors_padding,
+                    ..*b
+                },
+                sizes
+            );
+        }
+        assert!(breakdowns
+            .iter()
+            .any(|b| b.pors_octopus != breakdowns[0].pors_octopus));
+    }
+
+    // Smallest and largest octopus among a few forced selections.
+    #[test]
+    fn test_sign_hash_forced() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.genpk();
+        let msg = hash::long_hash(b"Hello world");
+        assert_eq!(
+            Vec::<u8>::from(&sk.sign_hash_forced(&msg, None)),
+            Vec::<u8>::from(&sk.sign_hash(&msg))
+        );
+
+        let mut selections: Vec<(usize, pors::Selection)> = (0u64..256)
+            .map(|i| {
+                let selection = pors::Selection {
+                    pepper: hash::long_hash(&i.to_le_bytes()),
+                };
+                let (_, mut subset) = selection.resolve(&msg);
+                let len = crate::octopus::octopus_length(&mut subset, PORS_TAU);
+                (len, selection)
+            })
+            .collect();
+        selections.sort_by_key(|(len, _)| *len);
+        let extremes = [selections[0], selections[selections.len() - 1]];
+        assert!(extremes[0].0 < extremes[1].0);
+
+        for (len, selection) in extremes.iter() {
+            let sign = sk.sign_hash_forced(&msg, Some(*selection));
+            assert_eq!(pk.verify_hash(&sign, &msg), Ok(()));
+
+            let sizes = sign.size_breakdown();
+            assert_eq!(sizes.pors_octopus, HASH_SIZE * len);
+            assert_eq!(
+                sizes.pors_padding,
+                HASH_SIZE * (OCTOPUS_MAX_NODES - len) + 16
+            );
+            assert_eq!(sizes.total(), SIGNATURE_BYTES);
+
+            // Padded encoding: same size whatever the octopus, zero padding.
+            let bytes = Vec::<u8>::from(&sign);
+            assert_eq!(bytes.len(), SIGNATURE_BYTES);
+            let padding = HASH_SIZE * (1 + PORS_K + len)..PORS_SIGNATURE_BYTES - 16;
+            assert!(bytes[padding].iter().all(|&b| b == 0));
+            let count = PORS_SIGNATURE_BYTES - 16;
+            assert_eq!(LittleEndian::read_u32(&bytes[count..]) as usize, *len);
+
+            #[cfg(feature = "experimental")]
+            {
+                let compact = sign.to_compact();
+                assert_eq!(
+                    compact.as_bytes().len(),
+                    SIGNATURE_BYTES - sizes.pors_padding
+                );
+                let expanded = CompactSignature::expand(&compact, &pk, b"Hello world").unwrap();
+                assert!(Vec::<u8>::from(&expanded) == bytes);
+            }
+        }
+    }
+
+    fn decode<'a, T: TryFrom<&'a [u8], Error = DeserializeError>>(
+        bytes: &'a [u8],
+    ) -> Result<T, DeserializeError> {
+        T::try_from(bytes)
+    }
+
+    #[test]
+    fn test_malleability() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.genpk();
+        let msg = b"Hello world";
+        let bytes = Vec::<u8>::from(&sk.sign_bytes(msg));
+        assert!(Signature::is_canonical(&bytes));
+        assert!(!Signature::is_canonical(&bytes[1..]));
+
+        for i in 0..bytes.len() {
+            let mut mutated = bytes.clone();
+            mutated[i] ^= 1 << (i % 8);
+            if let Ok(sign) = Signature::try_from(mutated.as_slice()) {
+                assert!(
+                    pk.verify_bytes(&sign, msg).is_err() || !Signature::is_canonical(&mutated),
+                    "mutating byte {} yields another valid encoding",
+                    i
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_deserialize_many() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let signs: Vec<Vec<u8>> = [&b"a"[..], b"b", b"c"]
+            .iter()
+            .map(|msg| Vec::<u8>::from(&sk.sign_bytes(msg)))
+            .collect();
+        let buf = signs.concat();
+
+        let parsed = Signature::deserialize_many(&buf, 3).unwrap();
+        for (sign, bytes) in parsed.iter().zip(signs.iter()) {
+            assert_eq!(&Vec::<u8>::from(sign), bytes);
+        }
+        let mut stream = SignatureStream::new(&buf);
+        assert_eq!(stream.by_ref().filter(|s| s.is_ok()).count(), 3);
+        assert_eq!(stream.offset(), buf.len());
+        assert!(SignatureStream::new(&[]).next().is_none());
+
+        // Out-of-range octopus length in the second signature, whatever the
+        // number of nodes: the second byte of the length is set.
+        let mut corrupted = buf.clone();
+        corrupted[SIGNATURE_BYTES + PORS_SIGNATURE_BYTES - 15] = 0xFF;
+        let error = ParseError::Item {
+            index: 1,
+            offset: SIGNATURE_BYTES,
+            error: DeserializeError::InvalidOctopusLength,
+        };
+        assert_eq!(
+            Signature::deserialize_many(&corrupted, 3).err(),
+            Some(error)
+        );
+        let items: Vec<_> = SignatureStream::new(&corrupted).collect();
+        assert_eq!(items.len(), 2);
+        assert!(items[0].is_ok());
+        assert_eq!(items[1].as_ref().err(), Some(&error));
+
+        let mut extended = buf.clone();
+        extended.extend_from_slice(&[0u8; 5]);
+        assert_eq!(
+            Signature::deserialize_many(&extended, 3).err(),
+            Some(ParseError::Item {
+                index: 3,
+                offset: buf.len(),
+                error: DeserializeError::TrailingBytes,
+            })
+        );
+        let last = SignatureStream::new(&extended).last().unwrap();
+        assert_eq!(
+            last.err(),
+            Some(ParseError::Item {
+                index: 3,
+                offset: buf.len(),
+                error: DeserializeError::UnexpectedEnd,
+            })
+        );
+        assert_eq!(
+            Signature::deserialize_many(&buf, 4).err(),
+            Some(ParseError::Item {
+                index: 3,
+                offset: buf.len(),
+                error: DeserializeError::UnexpectedEnd,
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_and_validate() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let bytes = Vec::<u8>::from(&sk.sign_bytes(b"Hello world"));
+        let sign = Signature::parse_and_validate(&bytes).unwrap();
+        assert_eq!(Vec::<u8>::from(&sign), bytes);
+
+        let zeroed = |offset: usize| {
+            let mut bad = bytes.clone();
+            bad[offset..offset + HASH_SIZE].fill(0);
+            assert!(Signature::try_from(bad.as_slice()).is_ok());
+            Signature::parse_and_validate(&bad).err()
+        };
+        let auth_c = SIGNATURE_BYTES - GRAVITY_C * HASH_SIZE;
+        assert_eq!(zeroed(auth_c), Some(ParseError::ZeroAuthC(0)));
+        assert_eq!(
+            zeroed(SIGNATURE_BYTES - HASH_SIZE),
+            Some(ParseError::ZeroAuthC(GRAVITY_C - 1))
+        );
+        let mut bad = bytes.clone();
+        bad[auth_c + HASH_SIZE..auth_c + 2 * HASH_SIZE].fill(0xFF);
+        assert_eq!(
+            Signature::parse_and_validate(&bad).err(),
+            Some(ParseError::AllOnesAuthC(1))
+        );
+        if let Some(layer) = GRAVITY_D.checked_sub(1) {
+            let auth =
+                PORS_SIGNATURE_BYTES + layer * SUBTREE_SIGNATURE_BYTES + WOTS_ELL * HASH_SIZE;
+            assert_eq!(
+                zeroed(auth + 2 * HASH_SIZE),
+                Some(ParseError::ZeroSubtreeAuth { layer, node: 2 })
+            );
+        }
+        // The structural checks of deserialize come first.
+        let mut bad = bytes.clone();
+        bad[PORS_SIGNATURE_BYTES - 15] = 0xFF;
+        assert_eq!(
+            Signature::parse_and_validate(&bad).err(),
+            Some(ParseError::Deserialization(
+                DeserializeError::InvalidOctopusLength
+            ))
+        );
+        assert_eq!(
+            Signature::parse_and_validate(&bytes[..bytes.len() - 1]).err(),
+            Some(ParseError::Deserialization(DeserializeError::UnexpectedEnd))
+        );
+        assert_eq!(
+            Signature::parse_and_validate(&[bytes.as_slice(), &[0]].concat()).err(),
+            Some(ParseError::Deserialization(DeserializeError::TrailingBytes))
+        );
+    }
+
+    #[test]
+    fn test_try_from() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.genpk();
+        let pk_bytes: [u8; PUBLIC_KEY_BYTES] = (&pk).into();
+        assert_eq!(decode::<PubKey>(&pk_bytes), Ok(pk));
+        assert_eq!(
+            decode::<PubKey>(&pk_bytes[1..]),
+            Err(DeserializeError::UnexpectedEnd)
+        );
+        let mut long = pk_bytes.to_vec();
+        long.push(0);
+        assert_eq!(
+            PubKey::try_from(long.as_slice()),
+            Err(DeserializeError::TrailingBytes)
+        );
+
+        let fixture = sign_zeros_fixture();
+        let sign: Signature = decode(&fixture).unwrap();
+        assert_eq!(Vec::<u8>::from(&sign), fixture);
+        assert!(pk.verify_bytes(&sign, &ZEROS_MSG).is_ok());
+        assert_eq!(
+            decode::<Signature>(&fixture[..fixture.len() - 1]).err(),
+            Some(DeserializeError::UnexpectedEnd)
+        );
+        let mut long = fixture.clone();
+        long.push(0);
+        assert_eq!(
+            Signature::try_from(long.as_slice()).err(),
+            Some(DeserializeError::TrailingBytes)
+        );
+    }
+
+    #[test]
+    fn test_debug_seckey() {
+        let mut random = [0u8; 64];
+        for (i, x) in random.iter_mut().enumerate() {
+            *x = 0xa0 ^ i as u8;
+        }
+        let sk = SecKey::new(&random);
+        let pk = sk.genpk().h.h;
+        let debug = format!("{:?}", sk);
+        assert!(debug.contains("seed: <redacted>"));
+        assert!(debug.contains("salt: <redacted>"));
+        assert!(debug.contains(&format!("cache_height: {}", GRAVITY_C)));
+        assert!(debug.contains(&format!(
+            "pubkey: {:02x}{:02x}…{:02x}{:02x}",
+            pk[0], pk[1], pk[30], pk[31]
+        )));
+        assert!(!debug.contains(&hex::encode(&random[..4])));
+        assert!(!debug.contains(&hex::encode(&random[32..36])));
+        assert!(!debug.contains(&hex::encode(&random[..2])));
+    }
+
+    fn oracle_sign_verify<O: SigningOracle>(oracle: &O, msg: &Hash) -> Result<bool, OracleError> {
+        let pk = oracle.public_key()?;
+        let sign = oracle.sign_hash(msg)?;
+        Ok(pk.verify_hash(&sign, msg).is_ok())
+    }
+
+    #[test]
+    fn test_signing_oracle() {
+        let msg = hash::tests::HASH_ELEMENT;
+        let sk = SecKey::new(&[0u8; 64]);
+        assert_eq!(oracle_sign_verify(&sk, &msg), Ok(true));
+
+        let mock = MockSigningOracle::new(SecKey::new(&[0u8; 64]));
+        assert_eq!(oracle_sign_verify(&mock, &msg), Ok(true));
+        assert_eq!(mock.signed_messages(), vec![msg]);
+        assert_eq!(mock.public_key_calls(), 1);
+
+        let mut expect = Vec::<u8>::new();
+        sk.sign_hash(&msg).serialize(&mut expect);
+        let oracle: &dyn SigningOracle = &mock;
+        assert_eq!(Vec::<u8>::from(&oracle.sign_hash(&msg).unwrap()), expect);
+        assert_eq!(mock.signed_messages(), vec![msg, msg]);
+
+        let mock = MockSigningOracle::failing(SecKey::new(&[0u8; 64]), OracleError::Unavailable);
+        assert_eq!(
+            oracle_sign_verify(&mock, &msg),
+            Err(OracleError::Unavailable)
+        );
+        assert!(mock.sign_hash(&msg).is_err());
+        assert_eq!(mock.signed_messages(), vec![msg]);
+        assert_eq!(mock.public_key_calls(), 1);
+    }
+
+    #[test]
+    fn test_sign_bound() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.genpk();
+        let other_pk = SecKey::new(&[1u8; 64]).genpk();
+        let msg = b"Hello world";
+
+        let bound = sk.sign_bytes_bound(msg);
+        // The public key is derived once, for all the bound signatures.
+        assert_eq!(sk.pk_cache.get(), Some(&pk));
+        let plain = sk.sign_bytes(msg);
+        assert_eq!(pk.verify_bytes_bound(&bound, msg), Ok(()));
+        assert_eq!(
+            pk.verify_bytes(&bound, msg),
+            Err(GravityError::Verification)
+        );
+        assert_eq!(
+            pk.verify_bytes_bound(&plain, msg),
+            Err(GravityError::Verification)
+        );
+        assert!(other_pk.verify_bytes_bound(&bound, msg).is_err());
+
+        // The header selects the digest, and must match the signature.
+        let blob = sk.sign_attached_bound(msg);
+        assert_eq!(&blob[..4], b"GRVb");
+        assert_eq!(pk.open_attached(&blob), Ok(&msg[..]));
+        let mut swapped = blob.clone();
+        swapped[..4].copy_from_slice(&ATTACHED_MAGIC);
+        assert_eq!(pk.open_attached(&swapped), Err(GravityError::Verification));
+        let mut swapped = sk.sign_attached(msg);
+        swapped[..4].copy_from_slice(&ATTACHED_BOUND_MAGIC);
+        assert_eq!(pk.open_attached(&swapped), Err(GravityError::Verification));
+    }
+
+    #[test]
+    fn test_tree_message_hash() {
+        assert_eq!(TREE_CHUNK_BYTES, 1 << 20);
+
+        // Two and a half chunks: the third chunk is carried up, then folded
+        // with the node of the first two.
+        let msg: Vec<u8> = (0..5 * TREE_CHUNK_BYTES / 2).map(|i| i as u8).collect();
+        let leaves: Vec<Hash> = msg.chunks(TREE_CHUNK_BYTES).map(hash::long_hash).collect();
+        assert_eq!(leaves.len(), 3);
+        let root =
+            hash::hash_2n_to_n_ret(&hash::hash_2n_to_n_ret(&leaves[0], &leaves[1]), &leaves[2]);
+        let mut bytes = b"gravity-tree".to_vec();
+        bytes.extend_from_slice(&(msg.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&root.h);
+        let expect = hash::long_hash(&bytes);
+        assert_eq!(tree_message_hash(&msg), expect);
+        assert_eq!(
+            hex::encode(expect.h),
+            "9c021d11c203d0d555ea710b33af95ed448a7434e5b2aeb50a34567438cdad0d"
+        );
+
+        // A message within one chunk is still hashed in tree mode.
+        let root = hash::long_hash(b"");
+        let mut bytes = b"gravity-tree".to_vec();
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+        bytes.extend_from_slice(&root.h);
+        assert_eq!(tree_message_hash(&[]), hash::long_hash(&bytes));
+        assert_ne!(tree_message_hash(b"abc"), hash::long_hash(b"abc"));
+        // The length is bound, not only the chunk digests.
+        assert_ne!(
+            tree_message_hash(&msg[..TREE_CHUNK_BYTES]),
+            tree_message_hash(&msg[..TREE_CHUNK_BYTES + 1])
+        );
+    }
+
+    #[test]
+    fn test_sign_bytes_tree() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.genpk();
+        let msg = vec![0xab; TREE_CHUNK_BYTES + 1];
+
+        let tree = sk.sign_bytes_tree(&msg);
+        let plain = sk.sign_bytes(&msg);
+        assert_eq!(pk.verify_bytes_tree(&tree, &msg), Ok(()));
+        assert_eq!(
+            pk.verify_bytes_tree(&tree, &msg[1..]),
+            Err(GravityError::Verification)
+        );
+        // Signatures of one mode do not verify in the other.
+        assert_eq!(
+            pk.verify_bytes(&tree, &msg),
+            Err(GravityError::Verification)
+        );
+        assert_eq!(
+            pk.verify_bytes_bound(&tree, &msg),
+            Err(GravityError::Verification)
+        );
+        assert_eq!(
+            pk.verify_bytes_tree(&plain, &msg),
+            Err(GravityError::Verification)
+        );
+
+        let blob = sk.sign_attached_tree(&msg);
+        assert_eq!(&blob[..4], b"GRVc");
+        assert_eq!(pk.open_attached(&blob), Ok(msg.as_slice()));
+        for magic in [ATTACHED_MAGIC, ATTACHED_BOUND_MAGIC] {
+            let mut swapped = blob.clone();
+            swapped[..4].copy_from_slice(&magic);
+            assert_eq!(pk.open_attached(&swapped), Err(GravityError::Verification));
+        }
+        let mut swapped = sk.sign_attached(&msg);
+        swapped[..4].copy_from_slice(&ATTACHED_TREE_MAGIC);
+        assert_eq!(pk.open_attached(&swapped), Err(GravityError::Verification));
+    }
+
+    #[test]
+    fn test_incremental_sign() -> io::Result<()> {
+        use std::io::Write;
+
+        let sk = SecKey::new(&[0u8; 64]);
+        let msg: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+        let expect = Vec::<u8>::from(&sk.sign_bytes(&msg));
+
+        let mut incremental = sk.incremental_sign();
+        incremental.write_all(&msg)?;
+        assert_eq!(Vec::<u8>::from(&incremental.finalize()), expect);
+
+        let mut incremental = sk.incremental_sign();
+        for chunk in msg.chunks(7) {
+            incremental.write_all(chunk)?;
+        }
+        incremental.flush()?;
+        assert_eq!(Vec::<u8>::from(&incremental.finalize()), expect);
+
+        let mut incremental = sk.incremental_sign();
+        assert_eq!(io::copy(&mut msg.as_slice(), &mut incremental)?, 3000);
+        assert_eq!(Vec::<u8>::from(&incremental.finalize()), expect);
+
+        let empty = sk.incremental_sign().finalize();
+        assert_eq!(
+            Vec::<u8>::from(&empty),
+            Vec::<u8>::from(&sk.sign_bytes(b""))
+        );
+
+        let sign = sk.sign_reader(msg.as_slice())?;
+        assert_eq!(Vec::<u8>::from(&sign), expect);
+        let sign = sk.sign_reader(io::Read::chain(&msg[..10], &msg[10..]))?;
+        assert_eq!(Vec::<u8>::from(&sign), expect);
+
+        struct Failing;
+        impl io::Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+        }
+        assert_eq!(
+            sk.sign_reader(Failing).err().map(|e| e.kind()),
+            Some(io::ErrorKind::BrokenPipe)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sign_hash_with_epoch() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.genpk();
+        let msg = hash::long_hash(b"Hello world");
+
+        let sign = sk.sign_hash_with_epoch(&msg, 1);
+        assert!(pk.verify_hash_with_epoch(&sign, &msg, 1));
+        assert!(!pk.verify_hash_with_epoch(&sign, &msg, 2));
+        assert!(!pk.verify_hash_with_epoch(&sign, &hash::long_hash(b"Hello"), 1));
+        assert_eq!(pk.verify_hash(&sign, &msg), Err(GravityError::Verification));
+        assert!(!pk.verify_hash_with_epoch(&sk.sign_hash(&msg), &msg, 1));
+
+        // The epoch moves the PORS instance.
+        let instance = |epoch| {
+            let h = epoch_message_hash(&msg, epoch);
+            sk.sign_hash_instance(&h).1
+        };
+        assert!((0..4).any(|epoch| instance(epoch) != instance(epoch + 1)));
+
+        let mut bytes = b"gravity-epoch".to_vec();
+        bytes.extend_from_slice(&[7, 0, 0, 0, 0, 0, 0, 0]);
+        bytes.extend_from_slice(&msg.h);
+        assert_eq!(epoch_message_hash(&msg, 7), hash::long_hash(&bytes));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_sign_parallel_batch() {
+        let sk = SecKey::new_lazy(&[0u8; 64]);
+        let pk = sk.genpk();
+        let msgs: Vec<Vec<u8>> = (0..8u8).map(|i| vec![i; i as usize]).collect();
+        let msgs: Vec<&[u8]> = msgs.iter().map(|m| m.as_slice()).collect();
+
+        let signs = sk.sign_parallel_batch(&msgs);
+        assert_eq!(signs.len(), msgs.len());
+        for (sign, msg) in signs.iter().zip(msgs.iter()) {
+            assert_eq!(pk.verify_bytes(sign, msg), Ok(()));
+            assert_eq!(Vec::<u8>::from(sign), Vec::<u8>::from(&sk.sign_bytes(msg)));
+        }
+        assert!(sk.sign_parallel_batch(&[]).is_empty());
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_with_thread_pool() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        let pool = Arc::new(pool);
+        let random = [0u8; 64];
+        let sk = SecKey::with_thread_pool(&random, pool.clone());
+        assert!(sk.is_cache_built());
+
+        let sequential = gen_cache(&prng::Prng::new(&sk.secrets.seed));
+        assert_eq!(sk.genpk().h, sequential.root());
+        assert_eq!(sk.genpk(), SecKey::new(&random).genpk());
+        assert_eq!(sk.cache().heap(), sequential.heap());
+
+        let msgs: [&[u8]; 3] = [b"a", b"b", b"c"];
+        for (sign, msg) in sk.sign_parallel_batch(&msgs).iter().zip(msgs) {
+            assert_eq!(sk.genpk().verify_bytes(sign, msg), Ok(()));
+        }
+        let fresh = sk.clone_with_fresh_salt(Hash::default());
+        assert!(fresh.pool.as_ref().is_some_and(|p| Arc::ptr_eq(p, &pool)));
+    }
+
+    #[cfg(feature = "keccak")]
+    #[test]
+    fn test_sign_bytes_keccak() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.genpk();
+        let msg = b"Hello world";
+
+        let sign = sk.sign_bytes_keccak(msg);
+        let h = Hash::keccak256(msg);
+        assert_eq!(pk.verify_bytes_keccak(&sign, msg), Ok(()));
+        assert_eq!(pk.verify_keccak_hash(&sign, &h), Ok(()));
+        assert_eq!(pk.verify_bytes(&sign, msg), Err(GravityError::Verification));
+        // Not a signature of the Keccak-256 hash as is, nor the converse.
+        assert_eq!(pk.verify_hash(&sign, &h), Err(GravityError::Verification));
+        assert_eq!(
+            pk.verify_keccak_hash(&sk.sign_hash(&h), &h),
+            Err(GravityError::Verification)
+        );
+        assert_eq!(
+            pk.verify_bytes_keccak(&sk.sign_bytes(msg), msg),
+            Err(GravityError::Verification)
+        );
+        assert!(pk.verify_bytes_keccak(&sign, b"another message").is_err());
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn test_sign_digest() {
+        use digest::Digest;
+
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.genpk();
+        let msg = b"Hello world";
+        let sha2 = || sha2_hkdf::Sha256::new_with_prefix(msg);
+        let sha3 = || sha3::Sha3_256::new_with_prefix(msg);
+
+        let sign = sk.sign_digest(sha2());
+        assert_eq!(pk.verify_digest(&sign, sha2()), Ok(()));
+        assert_eq!(
+            pk.verify_digest(&sign, sha3()),
+            Err(GravityError::Verification)
+        );
+        let sign3 = sk.sign_digest(sha3());
+        assert_eq!(pk.verify_digest(&sign3, sha3()), Ok(()));
+        let other_pk = SecKey::new(&[1u8; 64]).genpk();
+        assert!(other_pk.verify_digest(&sign, sha2()).is_err());
+
+        // Neither the message nor its digest as a message verify across modes.
+        let output = sha2().finalize();
+        assert_eq!(hash::long_hash(msg).h[..], output[..]);
+        for bytes in [&msg[..], &output[..]] {
+            assert_eq!(
+                pk.verify_bytes(&sign, bytes),
+                Err(GravityError::Verification)
+            );
+            let plain = sk.sign_bytes(bytes);
+            assert_eq!(
+                pk.verify_digest(&plain, sha2()),
+                Err(GravityError::Verification)
+            );
+        }
+        let hashed = sk.sign_hash(&Hash { h: output.into() });
+        assert_eq!(
+            pk.verify_digest(&hashed, sha2()),
+            Err(GravityError::Verification)
+        );
+    }
+
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac", feature = "tweaked")))]
+    #[test]
+    fn test_sign_bound_fixture() {
+        if get_config_type() != ConfigType::S {
+            return;
+        }
+        let sk = SecKey::new(&[0u8; 64]);
+        // SHA-256 of "gravity-bound" || pk || ZEROS_MSG.
+        let digest = bound_message_hash(&sk.genpk(), &ZEROS_MSG);
+        assert_eq!(
+            hex::encode(digest.h),
+            "2d325f54cb87b14253c00671c67409d5673b17b7ca50ce50b7e74d612dad67d5"
+        );
+        let sign = Vec::<u8>::from(&sk.sign_bytes_bound(&ZEROS_MSG));
+        assert_eq!(Vec::<u8>::from(&sk.sign_hash(&digest)), sign);
+        assert_eq!(
+            hex::encode(hash::long_hash(&sign).h),
+            "7045d16704d5eea1cc63edea51a5619036f2764d6246e26ae7006cf0143232dc"
+        );
+    }
+
+    #[test]
+    fn test_sign_with_metadata() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.genpk();
+        let msg = b"Hello world";
+        let meta_a = SigningMetadata {
+            timestamp: 1_700_000_000,
+            signer_id: [0xAB; 16],
+            version: 1,
+        };
+        let sign = sk.sign_bytes_with_metadata(msg, &meta_a);
+        assert_eq!(pk.verify_bytes_with_metadata(&sign, msg, &meta_a), Ok(()));
+
+        let metas_b = [
+            SigningMetadata {
+                timestamp: meta_a.timestamp + 1,
+                ..meta_a
+            },
+            SigningMetadata {
+                signer_id: [0xAC; 16],
+                ..meta_a
+            },
+            SigningMetadata {
+                version: 2,
+                ..meta_a
+            },
+        ];
+        for meta_b in metas_b.iter() {
+            assert_eq!(
+                pk.verify_bytes_with_metadata(&sign, msg, meta_b),
+                Err(GravityError::Verification)
+            );
+        }
+        assert!(pk.verify_bytes(&sign, msg).is_err());
+
+        // The metadata is a prefix of the signed bytes.
+        let mut bytes = meta_a.to_bytes().to_vec();
+        bytes.extend_from_slice(msg);
+        assert_eq!(pk.verify_bytes(&sign, &bytes), Ok(()));
+        assert_eq!(
+            hex::encode(meta_a.to_bytes()),
+            "00f1536500000000abababababababababababababababab01000000"
+        );
+    }
+
+    // Provider counting the blocks derived from it.
+    struct CountingProvider {
+        prng: prng::Prng,
+        derivations: std::cell::Cell<usize>,
+    }
+
+    impl prng::SeedProvider for CountingProvider {
+        fn derive(&self, address: &address::Address, counter: u32) -> Hash {
+            self.derivations.set(self.derivations.get() + 1);
+            self.prng.derive(address, counter)
+        }
+    }
+
+    #[test]
+    fn test_remote_seckey() {
+        let random: [u8; 64] = core::array::from_fn(|i| i as u8);
+        let sk = SecKey::new(&random);
+        let provider = CountingProvider {
+            prng: prng::Prng::new(&sk.secrets.seed),
+            derivations: Default::default(),
+        };
+        let remote = RemoteSecKey::new(provider, sk.secrets.salt);
+        assert_eq!(remote.genpk(), sk.genpk());
+        let keygen_blocks = if GRAVITY_D == 0 {
+            (1 << GRAVITY_C) * PORS_T
+        } else {
+            (1 << (GRAVITY_C + MERKLE_H)) * WOTS_ELL
+        };
+        assert_eq!(remote.provider().derivations.get(), keygen_blocks);
+
+        let msg = b"Hello world";
+        let sign = remote.sign_bytes(msg);
+        assert_eq!(sk.genpk().verify_bytes(&sign, msg), Ok(()));
+        assert_eq!(Vec::<u8>::from(&sign), Vec::<u8>::from(&sk.sign_bytes(msg)));
+        let sign_blocks = PORS_T + GRAVITY_D * (1 << MERKLE_H) * WOTS_ELL;
+        assert_eq!(
+            remote.provider().derivations.get(),
+            keygen_blocks + sign_blocks
+        );
+    }
+
+    #[test]
+    fn test_public_key_cached() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.public_key_cached();
+        assert_eq!(&pk.h, &sk.genpk().h);
+        assert!(std::ptr::eq(pk, sk.public_key_cached()));
+    }
+
+    #[test]
+    fn test_recover_root() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.genpk();
+        let msg = hash::tests::HASH_ELEMENT;
+        let mut sign = sk.sign_hash(&msg);
+        assert_eq!(sign.recover_root(&msg), Ok(pk.h));
+
+        let other = hash::hash_n_to_n_ret(&msg);
+        assert_ne!(sign.recover_root(&other).ok(), Some(pk.h));
+
+        sign.auth_c[GRAVITY_C - 1].h[0] ^= 1;
+        assert_ne!(sign.recover_root(&msg).ok(), Some(pk.h));
+    }
+
+    #[test]
+    fn test_cached_verifier() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.genpk();
+        let verifier = pk.verifier();
+        assert_eq!(verifier.pubkey(), &pk);
+        assert_eq!(verifier.capacity(), VERIFIER_CAPACITY);
+
+        let check = |sign: &Signature, msg: &[u8]| {
+            let result = pk.verify_bytes(sign, msg);
+            assert_eq!(verifier.verify_bytes(sign, msg), result);
+            result
+        };
+
+        let msg = b"Hello world";
+        let sign = sk.sign_bytes(msg);
+        assert_eq!(check(&sign, msg), Ok(()));
+        assert_eq!(verifier.cached_nodes(), GRAVITY_D + 1);
+
+        // The second time, only the PORS layer is extracted.
+        let count = hash::tests::hash_count();
+        assert_eq!(verifier.verify_bytes(&sign, msg), Ok(()));
+        let hit = hash::tests::hash_count() - count;
+        let count = hash::tests::hash_count();
+        assert_eq!(pk.verify_bytes(&sign, msg), Ok(()));
+        let miss = hash::tests::hash_count() - count;
+        assert!(hit < miss, "{} vs. {}", hit, miss);
+        assert_eq!(verifier.cached_nodes(), GRAVITY_D + 1);
+
+        assert!(check(&sign, b"Hello world!").is_err());
+
+        // Forgeries sharing a prefix of the path with the valid signature.
+        let mut forged = sk.sign_bytes(msg);
+        forged.auth_c[GRAVITY_C - 1].h[0] ^= 1;
+        assert!(check(&forged, msg).is_err());
+
+        if let Some(last) = GRAVITY_D.checked_sub(1) {
+            let mut forged = sk.sign_bytes(msg);
+            let mut bytes = Vec::<u8>::new();
+            forged.subtrees[last].serialize(&mut bytes);
+            bytes[0] ^= 1;
+            forged.subtrees[last] = subtree::Signature::deserialize(&mut bytes.iter()).unwrap();
+            assert!(check(&forged, msg).is_err());
+        }
+
+        let mut bytes = Vec::<u8>::new();
+        sign.serialize(&mut bytes);
+        bytes[HASH_SIZE] ^= 1; // First PORS value.
+        let forged = Signature::deserialize(&mut bytes.iter()).unwrap();
+        assert!(check(&forged, msg).is_err());
+
+        for msg in [b"a", b"b", b"c"] {
+            assert_eq!(check(&sk.sign_bytes(msg), msg), Ok(()));
+        }
+        assert_eq!(verifier.cached_nodes(), 4 * (GRAVITY_D + 1));
+
+        // Nothing is memoized without capacity.
+        let verifier = CachedVerifier::new(pk, 0);
+        assert_eq!(verifier.verify_bytes(&sign, msg), Ok(()));
+        assert_eq!(verifier.cached_nodes(), 0);
+    }
+
+    #[test]
+    fn test_verify_auth_c_alone() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.genpk();
+        let msg = hash::tests::HASH_ELEMENT;
+        let mut sign = sk.sign_hash(&msg);
+        let (leaf, index) = sign.recover_top_leaf(&msg).unwrap();
+        assert!(sign.verify_auth_c_alone(&pk.h, &leaf, index.0));
+        assert!(!sign.verify_auth_c_alone(&pk.h, &leaf, index.0 ^ 1));
+        assert!(!sign.verify_auth_c_alone(&pk.h, &leaf, address::LeafIndex::COUNT));
+        assert!(!sign.verify_auth_c_alone(&leaf, &leaf, index.0));
+
+        sign.auth_c[0].h[7] ^= 0x10;
+        assert!(!sign.verify_auth_c_alone(&pk.h, &leaf, index.0));
+    }
+
+    #[test]
+    fn test_auth_c_root() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.genpk();
+        for msg in [hash::tests::HASH_ELEMENT, Hash::default()] {
+            let sign = sk.sign_hash(&msg);
+            let (leaf, index) = sign.recover_top_leaf(&msg).unwrap();
+            assert_eq!(sign.auth_c_root(&leaf, index.0), pk.h);
+            assert_eq!(sign.recover_root(&msg), Ok(pk.h));
+            assert_ne!(sign.auth_c_root(&leaf, index.0 ^ 1), pk.h);
+            assert_ne!(sign.auth_c_root(&Hash::default(), index.0), pk.h);
+        }
+    }
+
+    // The corrupted stages are subtrees, which pure PORST doesn't have.
+    #[cfg(not(feature = "porst"))]
+    #[test]
+    fn test_verify_hash_trace() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.genpk();
+        let msg = hash::tests::HASH_ELEMENT;
+        let sign = sk.sign_hash(&msg);
+
+        let trace = pk.verify_hash_trace(&sign, &msg);
+        assert!(trace.is_valid());
+        assert_eq!(trace.stages.len(), GRAVITY_D + 2);
+        assert_eq!(trace.stages.last(), Some(&(TraceStage::Top, pk.h)));
+        assert_eq!(trace.first_divergence(&trace), None);
+        let display = trace.to_string();
+        assert!(display.starts_with("pors "));
+        assert!(display.ends_with("result       ok"));
+        assert!(display.contains(&format!("top          {}", hex::encode(pk.h.h))));
+
+        // Corrupting the authentication path of one subtree (the fourth one if
+        // there are enough layers) is localized to it.
+        let layer = if GRAVITY_D > 3 { 3 } else { GRAVITY_D - 1 };
+        let mut bytes = Vec::<u8>::from(&sign);
+        bytes[PORS_SIGNATURE_BYTES + layer * SUBTREE_SIGNATURE_BYTES + WOTS_ELL * HASH_SIZE] ^= 1;
+        let bad = Signature::try_from(bytes.as_slice()).unwrap();
+        let bad_trace = pk.verify_hash_trace(&bad, &msg);
+        assert_eq!(bad_trace.failure, Some(TraceStage::Top));
+        assert_eq!(
+            bad_trace.first_divergence(&trace),
+            Some(TraceStage::Subtree(layer))
+        );
+        assert_eq!(bad_trace.stages[..layer + 1], trace.stages[..layer + 1]);
+        assert!(bad_trace.to_string().ends_with("failed at top"));
+
+        // A trace that stops early.
+        let mut short = trace.clone();
+        short.stages.truncate(1);
+        assert_eq!(short.first_divergence(&trace), Some(TraceStage::Subtree(0)));
+        assert_eq!(
+            pk.verify_hash_trace(&sign, &Hash::default())
+                .first_divergence(&trace),
+            Some(TraceStage::Pors)
+        );
+    }
+
+    // Without subtrees, the PORS public key is a leaf of the cached tree.
+    #[cfg(feature = "porst")]
+    #[test]
+    fn test_porst() {
+        assert_eq!(GRAVITY_D, 0);
+        let sk = SecKey::new(&[1u8; 64]);
+        let pk = sk.genpk();
+        let msg = b"Hello world";
+        let sign = sk.sign_bytes(msg);
+        assert!(sign.subtrees.is_empty());
+        assert_eq!(
+            Vec::<u8>::from(&sign).len(),
+            PORS_SIGNATURE_BYTES + GRAVITY_C * HASH_SIZE
+        );
+        assert_eq!(pk.verify_bytes(&sign, msg), Ok(()));
+        assert!(pk.verify_bytes(&sign, b"Hello world!").is_err());
+
+        let h = hash::long_hash(msg);
+        let (leaf, index) = sign.recover_top_leaf(&h).unwrap();
+        let (address, pors_pk) = sign.pors_sign.extract(&h).unwrap();
+        assert_eq!(leaf, pors_pk);
+        assert_eq!(address.get_instance(), index.0 as u64);
+        let pors_sk = pors::SecKey::new(&prng::Prng::new(&sk.secrets.seed), &address);
+        assert_eq!(pors_sk.genpk().0, leaf);
+        assert_eq!(sign.auth_c_root(&leaf, index.0), pk.h);
+
+        let mut bytes = Vec::<u8>::from(&sign);
+        bytes[PORS_SIGNATURE_BYTES] ^= 1;
+        let forged = Signature::try_from(bytes.as_slice()).unwrap();
+        assert!(pk.verify_bytes(&forged, msg).is_err());
+    }
+
+    #[test]
+    fn test_index_out_of_range() {
+        assert_eq!(check_instance(&address::Address::new(0, 0)), Ok(()));
+        assert_eq!(
+            check_instance(&address::Address::new(0, GRAVITY_MASK)),
+            Ok(())
+        );
+        // All instances are valid when the hyper-tree spans 64 bits.
+        if GRAVITY_MASK != u64::MAX {
+            assert_eq!(
+                check_instance(&address::Address::new(0, GRAVITY_MASK + 1)),
+                Err(GravityError::IndexOutOfRange)
+            );
+        }
+
+        // Tampering with the subtrees cannot move the index, which only depends
+        // on the pepper and the message.
+        let sk = SecKey::new(&[0u8; 64]);
+        let msg = hash::tests::HASH_ELEMENT;
+        let mut sign = sk.sign_hash(&msg);
+        let (_, index) = sign.recover_top_leaf(&msg).unwrap();
+        if let Some(first) = sign.subtrees.first_mut() {
+            let mut bytes = Vec::<u8>::new();
+            first.serialize(&mut bytes);
+            bytes[SUBTREE_SIGNATURE_BYTES - 1] ^= 0x80;
+            *first = subtree::Signature::deserialize(&mut bytes.iter()).unwrap();
+            assert_eq!(sign.recover_top_leaf(&msg).unwrap().1, index);
+        }
+    }
+
+    #[test]
+    fn test_sign_hash_checked() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let msg = hash::long_hash(b"Hello world");
+        let sign = sk.sign_hash_checked(&msg).unwrap();
+        assert_eq!(Vec::<u8>::from(&sign), Vec::<u8>::from(&sk.sign_hash(&msg)));
+
+        // The fault is injected in a WOTS chain.
+        if GRAVITY_D != 0 {
+            hash::tests::inject_next_fault();
+            assert_eq!(sk.sign_hash_checked(&msg).err(), Some(FaultDetected));
+            assert!(sk.sign_hash_checked(&msg).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_sign_and_record() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let msg = b"Hello world";
+        let (sign, record) = sk.sign_bytes_and_record(msg);
+        assert_eq!(record.message_hash, hash::long_hash(msg));
+        assert_eq!(record.public_key_hash, sk.genpk().h);
+        assert!(record.leaf_index <= GRAVITY_MASK);
+        assert!(record.timestamp.is_some());
+
+        let expect = Vec::<u8>::from(&sign);
+        let sign = sk.sign_message_at_index(msg, record.leaf_index).unwrap();
+        assert_eq!(Vec::<u8>::from(&sign), expect);
+        assert_eq!(
+            sk.sign_message_at_index(msg, record.leaf_index ^ 1).err(),
+            Some(GravityError::InvalidParameter("leaf index"))
+        );
+        assert_eq!(
+            sk.sign_message_at_index(msg, GRAVITY_MASK + 1).err(),
+            Some(GravityError::IndexOutOfRange)
+        );
+
+        // The index from the signature alone, even once corrupted past the
+        // pepper.
+        let h = hash::long_hash(msg);
+        assert_eq!(sign.expected_leaf_index(&h), Some(record.leaf_index));
+        let mut bytes = Vec::<u8>::from(&sign);
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        bytes[HASH_SIZE] ^= 1;
+        let corrupted = Signature::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(corrupted.expected_leaf_index(&h), Some(record.leaf_index));
+        let other = sk.sign_hash(&hash::hash_n_to_n_ret(&h));
+        assert_eq!(
+            other.expected_leaf_index(&h),
+            Some(other.pors_sign.address(&h).get_instance())
+        );
+    }
+
+    #[cfg(feature = "memlock")]
+    #[test]
+    fn test_new_locked() {
+        use crate::memlock::{tests::inject_lock_failure, LockPolicy};
+        let random = [0u8; 64];
+        let pk = SecKey::new(&random).genpk();
+
+        inject_lock_failure();
+        assert_eq!(
+            SecKey::new_locked(&random, LockPolicy::Required).err(),
+            Some(MemlockError::LockFailed)
+        );
+        inject_lock_failure();
+        let sk = SecKey::new_locked(&random, LockPolicy::BestEffort).unwrap();
+        assert!(!sk.is_memory_locked());
+        assert_eq!(sk.genpk(), pk);
+        drop(sk);
+
+        // Depends on the limit of locked memory of the environment.
+        if let Ok(sk) = SecKey::new_locked(&random, LockPolicy::Required) {
+            assert!(sk.is_memory_locked());
+            assert_eq!(
+                sk.sign_bytes(b"msg").recover_root(&hash::long_hash(b"msg")),
+                Ok(pk.h)
+            );
+        }
+    }
+
+    #[test]
+    fn test_audit_log_path() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let root = sk.genpk().h;
+        let shift = MERKLE_H * GRAVITY_D;
+        for leaf in 0..(1u64 << GRAVITY_C) {
+            // Any instance below the leaf has the same path.
+            for instance in [leaf << shift, (leaf << shift) | ((1 << shift) - 1)] {
+                let path = sk.audit_log_path(instance).unwrap();
+                assert_eq!(path.index, instance);
+                assert_eq!(Some(&path.leaf_hash), sk.cache().node_at(0, leaf as usize));
+                assert!(path.verify(&root));
+            }
+        }
+
+        let msg = b"Hello world";
+        let (sign, record) = sk.sign_bytes_and_record(msg);
+        let path = sk.audit_log_path(record.leaf_index).unwrap();
+        assert_eq!(path.merkle_path, sign.auth_c);
+        let leaf = (record.leaf_index >> shift) as usize;
+        assert!(sign.verify_auth_c_alone(&root, &path.leaf_hash, leaf));
+
+        let mut bad = path;
+        bad.merkle_path[0].h[0] ^= 1;
+        assert!(!bad.verify(&root));
+        let mut bad = path;
+        bad.index ^= 1 << shift;
+        assert!(!bad.verify(&root));
+        assert!(!path.verify(&Hash::default()));
+
+        assert_eq!(
+            sk.audit_log_path(GRAVITY_MASK + 1).err(),
+            Some(GravityError::IndexOutOfRange)
+        );
+        bad = path;
+        bad.index = u64::MAX;
+        assert!(!bad.verify(&root));
+    }
+
+    #[test]
+    fn test_prove_instance() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.genpk();
+        let msg = hash::long_hash(b"Hello world");
+        let sign = sk.sign_hash(&msg);
+        let (leaf, index) = sign.recover_top_leaf(&msg).unwrap();
+
+        let proof = sk.prove_instance(index.0 as u64).unwrap();
+        assert_eq!(proof.root, leaf);
+        assert_eq!(proof.auth, sign.auth_c);
+        assert!(pk.verify_instance(&proof));
+        assert_eq!(proof.verify_hash(&sign, &msg), Ok(()));
+        assert_eq!(
+            proof.verify_hash(&sign, &hash::long_hash(b"Hello")),
+            Err(GravityError::Verification)
+        );
+        assert!(!SecKey::new(&[1u8; 64]).genpk().verify_instance(&proof));
+
+        // Relabeled as another instance, in or out of range.
+        let other = (index.0 as u64 + 1) % (1 << GRAVITY_C);
+        for instance in [other, 1 << GRAVITY_C, u64::MAX] {
+            let relabeled = InstanceProof { instance, ..proof };
+            assert!(!pk.verify_instance(&relabeled));
+            assert!(relabeled.verify_hash(&sign, &msg).is_err());
+        }
+        let other_proof = sk.prove_instance(other).unwrap();
+        assert!(pk.verify_instance(&other_proof));
+        assert!(other_proof.verify_hash(&sign, &msg).is_err());
+
+        let bytes = Vec::<u8>::from(&proof);
+        assert_eq!(bytes.len(), INSTANCE_PROOF_BYTES);
+        assert_eq!(bytes[..8], (index.0 as u64).to_le_bytes());
+        assert_eq!(InstanceProof::try_from(bytes.as_slice()), Ok(proof));
+        assert_eq!(
+            InstanceProof::try_from(&bytes[..bytes.len() - 1]),
+            Err(DeserializeError::UnexpectedEnd)
+        );
+        let mut long = bytes.clone();
+        long.push(0);
+        assert_eq!(
+            InstanceProof::try_from(long.as_slice()),
+            Err(DeserializeError::TrailingBytes)
+        );
+
+        for instance in [1 << GRAVITY_C, u64::MAX] {
+            assert_eq!(
+                sk.prove_instance(instance).err(),
+                Some(GravityError::IndexOutOfRange)
+            );
+        }
+    }
+
+    #[test]
+    fn test_into_parts() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let msg = b"Hello world";
+        let sign = sk.sign_bytes(msg);
+        let expect = Vec::<u8>::from(&sign);
+
+        let (pors_sign, subtrees, auth_c) = sign.into_parts();
+        let mut bytes = Vec::<u8>::new();
+        pors_sign.serialize(&mut bytes);
+        assert_eq!(bytes.len(), PORS_SIGNATURE_BYTES);
+        assert_eq!(&auth_c[..], &sk.sign_bytes(msg).auth_c[..]);
+
+        let sign = Signature::from_parts(pors_sign, subtrees, auth_c);
+        assert!(Vec::<u8>::from(&sign) == expect);
+        assert_eq!(sk.genpk().verify_bytes(&sign, msg), Ok(()));
+    }
+
+    #[test]
+    fn test_layer_root() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let pk = sk.genpk();
+        let msg = hash::tests::HASH_ELEMENT;
+        let sign = sk.sign_hash(&msg);
 
-        for (i, leaf) in sk.cache.leaves().iter_mut().enumerate() {
-            let address = address::Address::new(layer, (i << MERKLE_H) as u64);
-            let pk = subtree_sk.genpk(&address);
-            *leaf = pk.h;
+        let trace = pk.verify_hash_trace(&sign, &msg);
+        for layer in 0..=GRAVITY_D {
+            assert_eq!(sign.layer_root(layer, &msg), Some(trace.stages[layer].1));
         }
+        assert_eq!(sign.layer_root(GRAVITY_D + 1, &msg), None);
 
-        sk.cache.generate();
-        sk
+        let top = sign.layer_root(GRAVITY_D, &msg).unwrap();
+        let (leaf, index) = sign.recover_top_leaf(&msg).unwrap();
+        assert_eq!(top, leaf);
+        assert_eq!(sign.auth_c_root(&top, index.0), pk.h);
+
+        let other = hash::hash_n_to_n_ret(&msg);
+        assert_ne!(sign.layer_root(0, &other), sign.layer_root(0, &msg));
     }
 
-    pub fn genpk(&self) -> PubKey {
-        PubKey {
-            h: self.cache.root(),
+    #[test]
+    fn test_wots_part() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let msg = hash::tests::HASH_ELEMENT;
+        let sign = sk.sign_hash(&msg);
+        let prng = prng::Prng::new(&sk.secrets.seed);
+        let subtree_sk = subtree::SecKey::new(&prng);
+
+        let (mut address, mut h) = sign.pors_sign.extract(&msg).unwrap();
+        for subtree_sign in sign.subtrees.iter() {
+            address.next_layer();
+            let wots = subtree_sign.wots_part();
+            let pk = subtree_sk.derive_key_at(&address).public_hash();
+            assert!(wots.verify(&pk, &h, &address));
+            assert_eq!(wots.extract(&address, &h), pk);
+            assert!(!wots.verify(&pk, &Hash::default(), &address));
+            assert!(!wots.verify(&Hash::default(), &h, &address));
+
+            h = subtree_sign.extract(&address, &h);
+            address.shift(MERKLE_H);
         }
     }
 
-    pub fn sign_hash(&self, msg: &Hash) -> Signature {
-        let mut sign: Signature = Default::default();
+    #[test]
+    fn test_chained_signature() {
+        let root_sk = SecKey::new(&[1u8; 64]);
+        let root_pk = root_sk.genpk();
+        let delegation_sk = SecKey::new(&[2u8; 64]);
+        let msg = b"Hello world";
 
-        let prng = prng::Prng::new(&self.seed);
-        let (mut address, mut h, pors_sign) = pors::sign(&prng, &self.salt, msg);
-        sign.pors_sign = pors_sign;
+        let chained = ChainedSignature::new(&root_sk, &delegation_sk, msg);
+        assert_eq!(chained.delegation_pk, delegation_sk.genpk());
+        assert!(chained.verify(&root_pk, msg));
+        assert!(verify_chain_signature(
+            &root_pk,
+            &chained.delegation_sign,
+            &chained.delegation_pk,
+            &chained.msg_sign,
+            msg
+        ));
+        assert!(!chained.verify(&root_pk, b"another message"));
+        assert!(!chained.verify(&delegation_sk.genpk(), msg));
 
-        let subtree_sk = subtree::SecKey::new(&prng);
-        for i in 0..GRAVITY_D {
-            address.next_layer();
-            let (root, subtree_sign) = subtree_sk.sign(&address, &h);
-            h = root;
-            sign.subtrees[i] = subtree_sign;
-            address.shift(MERKLE_H); // Update instance
+        let tamper = |sign: &Signature| {
+            let mut bytes = Vec::<u8>::from(sign);
+            bytes[SIGNATURE_BYTES - 1] ^= 1;
+            Signature::try_from(bytes.as_slice()).unwrap()
+        };
+        let tampered = ChainedSignature {
+            delegation_sign: tamper(&chained.delegation_sign),
+            ..ChainedSignature::new(&root_sk, &delegation_sk, msg)
+        };
+        assert!(!tampered.verify(&root_pk, msg));
+        let tampered = ChainedSignature {
+            msg_sign: tamper(&chained.msg_sign),
+            ..ChainedSignature::new(&root_sk, &delegation_sk, msg)
+        };
+        assert!(!tampered.verify(&root_pk, msg));
+
+        // A delegation to another key.
+        let other_sk = SecKey::new(&[3u8; 64]);
+        let tampered = ChainedSignature {
+            delegation_pk: other_sk.genpk(),
+            msg_sign: other_sk.sign_bytes(msg),
+            ..ChainedSignature::new(&root_sk, &delegation_sk, msg)
+        };
+        assert!(!tampered.verify(&root_pk, msg));
+    }
+
+    #[test]
+    fn test_address_from_signing_index() {
+        let sk = SecKey::new(&[0u8; 64]);
+        for msg in [&b""[..], b"a", b"b", b"Hello world"].iter() {
+            let (sign, record) = sk.sign_bytes_and_record(msg);
+            let (address, _) = sign
+                .pors_signature()
+                .extract(&hash::long_hash(msg))
+                .unwrap();
+            assert_eq!(
+                address::Address::from_signing_index(record.leaf_index),
+                address
+            );
         }
+    }
 
-        let index = address.get_instance();
-        self.cache.gen_auth(&mut sign.auth_c, index);
+    #[cfg(feature = "bip39")]
+    #[test]
+    fn test_from_bip39_mnemonic() {
+        const ZEROS: &str = "abandon abandon abandon abandon abandon abandon \
+                             abandon abandon abandon abandon abandon about";
+        const SEVENS: &str = "legal winner thank year wave sausage \
+                              worth useful legal winner thank yellow";
 
-        sign
+        let pk = |words, passphrase| {
+            GravityKeyPair::from_bip39_mnemonic(words, passphrase)
+                .unwrap()
+                .public_key
+                .h
+        };
+        let zeros = pk(ZEROS, "");
+        assert_eq!(zeros, pk(ZEROS, ""));
+        assert_ne!(zeros, pk(SEVENS, ""));
+        assert_ne!(zeros, pk(ZEROS, "TREZOR"));
+
+        assert_eq!(
+            GravityKeyPair::from_bip39_mnemonic("abandon abandon abandon", "").err(),
+            Some(Bip39Error::InvalidMnemonic(bip39::Error::BadWordCount(3)))
+        );
     }
 
-    pub fn sign_bytes(&self, msg: &[u8]) -> Signature {
-        let h = hash::long_hash(msg);
-        self.sign_hash(&h)
+    #[test]
+    fn test_generate_from_os_rng() {
+        let keypair = GravityKeyPair::generate_from_os_rng().unwrap();
+        let sign = keypair.sign(b"Hello world");
+        assert!(keypair
+            .public_key
+            .verify_bytes(&sign, b"Hello world")
+            .is_ok());
+        let other = GravityKeyPair::generate_from_os_rng().unwrap();
+        assert_ne!(other.public_key, keypair.public_key);
     }
-}
 
-impl PubKey {
-    fn verify_hash(&self, sign: &Signature, msg: &Hash) -> bool {
-        if let Some(h) = sign.extract_hash(msg) {
-            self.h == h
-        } else {
-            false
-        }
+    #[test]
+    fn test_keypair() {
+        let keypair = GravityKeyPair::generate(&[0u8; 64]);
+        let sign = keypair.sign(b"Hello world");
+        assert!(keypair
+            .public_key
+            .verify_bytes(&sign, b"Hello world")
+            .is_ok());
+
+        let mut bytes = Vec::<u8>::new();
+        keypair.serialize(&mut bytes);
+        assert_eq!(bytes.len(), 4 + 3 + 3 * HASH_SIZE);
+        let decoded = GravityKeyPair::deserialize(&mut bytes.iter()).unwrap();
+        assert_eq!(decoded.public_key.h, keypair.public_key.h);
+        assert_eq!(decoded.secret_key.genpk().h, keypair.public_key.h);
+
+        let mut bad = bytes.clone();
+        bad[0] ^= 1;
+        assert_eq!(
+            GravityKeyPair::deserialize(&mut bad.iter()).err(),
+            Some(GravityError::Deserialization(
+                DeserializeError::InvalidHeader
+            ))
+        );
+
+        let mut bad = bytes.clone();
+        *bad.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            GravityKeyPair::deserialize(&mut bad.iter()).err(),
+            Some(GravityError::KeyGeneration(KeygenError::PublicKeyMismatch))
+        );
+
+        let mut bad = bytes.clone();
+        bad[4] += 1;
+        let found = GravityVersion {
+            major: GRAVITY_VERSION.major + 1,
+            ..GRAVITY_VERSION
+        };
+        let err = GravityKeyPair::deserialize(&mut bad.iter()).err();
+        assert_eq!(
+            err,
+            Some(GravityError::Deserialization(
+                DeserializeError::VersionMismatch {
+                    expected: GRAVITY_VERSION,
+                    found,
+                }
+            ))
+        );
+        assert_eq!(
+            err.unwrap().to_string(),
+            format!(
+                "deserialization failed: version mismatch: expected {}, found {}",
+                GRAVITY_VERSION, found
+            )
+        );
+
+        assert_eq!(
+            GravityKeyPair::deserialize(&mut bytes[..50].iter()).err(),
+            Some(GravityError::Deserialization(
+                DeserializeError::UnexpectedEnd
+            ))
+        );
     }
 
-    pub fn verify_bytes(&self, sign: &Signature, msg: &[u8]) -> bool {
-        let h = hash::long_hash(msg);
-        self.verify_hash(sign, &h)
+    #[test]
+    fn test_from_slice() {
+        assert_eq!(
+            SecKey::from_slice(&[0u8; 63]).err(),
+            Some(KeygenError::InvalidSeedLength(63))
+        );
     }
-}
 
-impl Signature {
-    fn extract_hash(&self, msg: &Hash) -> Option<Hash> {
-        if let Some((mut address, mut h)) = self.pors_sign.extract(msg) {
-            for i in 0..GRAVITY_D {
-                address.next_layer();
-                h = self.subtrees[i].extract(&address, &h);
-                address.shift(MERKLE_H);
-            }
+    #[test]
+    fn test_boxed() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let msg = hash::long_hash(b"Hello world");
+        let expect = Vec::<u8>::from(&sk.sign_hash(&msg));
 
-            let index = address.get_instance();
-            merkle::merkle_compress_auth(&mut h, &self.auth_c, GRAVITY_C, index);
-            Some(h)
-        } else {
-            None
-        }
+        let sign = std::thread::scope(|scope| {
+            std::thread::Builder::new()
+                .stack_size(64 * 1024)
+                .spawn_scoped(scope, || {
+                    let sign = sk.sign_hash_boxed(&msg);
+                    let bytes = Vec::<u8>::from(&*sign);
+                    Signature::deserialize_boxed(&mut bytes.iter()).unwrap()
+                })
+                .unwrap()
+                .join()
+                .unwrap()
+        });
+        assert!(Vec::<u8>::from(&*sign) == expect);
+        assert_eq!(sk.genpk().verify_hash(&sign, &msg), Ok(()));
+
+        assert_eq!(
+            Signature::deserialize_boxed(&mut expect[..expect.len() - 1].iter()).err(),
+            Some(DeserializeError::UnexpectedEnd)
+        );
     }
 
-    pub fn serialize(&self, output: &mut Vec<u8>) {
-        self.pors_sign.serialize(output);
-        for t in self.subtrees.iter() {
-            t.serialize(output);
-        }
-        for x in self.auth_c.iter() {
-            x.serialize(output);
-        }
+    #[test]
+    fn test_state_hash() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let copy = SecKey::from_slice(&[0u8; 64]).unwrap();
+        assert_eq!(sk.state_hash(), copy.state_hash());
+        assert_ne!(sk.state_hash(), sk.genpk().h);
+
+        let mut random = [0u8; 64];
+        random[32] = 1;
+        assert_ne!(SecKey::new(&random).state_hash(), sk.state_hash());
+        random[0] = 1;
+        assert_ne!(SecKey::new(&random).state_hash(), sk.state_hash());
     }
 
-    pub fn deserialize<'a, I>(it: &mut I) -> Option<Self>
-    where
-        I: Iterator<Item = &'a u8>,
-    {
-        let mut sign = Signature {
-            pors_sign: pors::Signature::deserialize(it)?,
-            ..Default::default()
-        };
-        for t in sign.subtrees.iter_mut() {
-            *t = subtree::Signature::deserialize(it)?;
-        }
-        for x in sign.auth_c.iter_mut() {
-            *x = Hash::deserialize(it)?;
+    #[cfg(feature = "dangerous-api")]
+    #[test]
+    fn test_expose_seed_and_salt() {
+        let mut random = [0u8; 64];
+        for (i, x) in random.iter_mut().enumerate() {
+            *x = i as u8;
         }
-        Some(sign)
+        let sk = SecKey::new(&random);
+        let (seed, salt) = (sk.expose_seed(), sk.expose_salt());
+        assert_eq!(seed[..], random[..32]);
+        assert_eq!(salt[..], random[32..]);
+
+        let imported = SecKey::new(&[seed, salt].concat().try_into().unwrap());
+        assert_eq!(imported.genpk(), sk.genpk());
+        let msg = b"Hello world";
+        assert!(Vec::<u8>::from(&imported.sign_bytes(msg)) == Vec::<u8>::from(&sk.sign_bytes(msg)));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_new_lazy() {
+        let random = [0x5a; 64];
+        let eager = SecKey::new(&random);
+        assert!(eager.is_cache_built());
+
+        let lazy = SecKey::new_lazy(&random);
+        assert!(!lazy.is_cache_built());
+        assert_eq!(lazy.to_seed_bytes(), random);
+        assert!(format!("{:?}", lazy).contains("pubkey: <not generated>"));
+        assert!(!lazy.is_cache_built());
+
+        let msg = b"Hello world";
+        let sign = lazy.sign_bytes(msg);
+        assert!(lazy.is_cache_built());
+        assert!(Vec::<u8>::from(&sign) == Vec::<u8>::from(&eager.sign_bytes(msg)));
+        assert_eq!(lazy.genpk(), eager.genpk());
+        assert_eq!(format!("{:?}", lazy), format!("{:?}", eager));
+
+        // The cache is built once, even with concurrent first uses.
+        let lazy = SecKey::new_lazy(&random);
+        let pks: Vec<PubKey> = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..4).map(|_| s.spawn(|| lazy.genpk())).collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert!(pks.iter().all(|pk| *pk == eager.genpk()));
+
+        let lazy = SecKey::new_lazy(&random);
+        lazy.ensure_cache();
+        assert!(lazy.is_cache_built());
+        assert_eq!(lazy.state_hash(), eager.state_hash());
+    }
 
     #[test]
-    fn test_sign_verify() {
+    fn test_verify_integrity() {
+        let mut sk = SecKey::new(&[0u8; 64]);
+        assert!(sk.verify_integrity());
+        assert!(SecKey::new_lazy(&[0u8; 64]).verify_integrity());
+
+        let cache = sk.cache.get_mut().unwrap();
+        cache.leaves()[3].h[0] ^= 1;
+        assert!(!sk.verify_integrity());
+        sk.cache.get_mut().unwrap().generate();
+        assert!(sk.verify_integrity());
+        assert_ne!(sk.genpk(), SecKey::new(&[0u8; 64]).genpk());
+    }
+
+    #[test]
+    fn test_verify_subtree_consistency() {
+        let mut sk = SecKey::new(&[0u8; 64]);
+        assert!(sk.verify_subtree_consistency().is_empty());
+        assert!(SecKey::new_lazy(&[0u8; 64])
+            .verify_subtree_consistency()
+            .is_empty());
+
+        let cache = sk.cache.get_mut().unwrap();
+        cache.leaves()[5].h[7] ^= 0x10;
+        assert_eq!(sk.verify_subtree_consistency(), vec![5]);
+        // Rebuilding the inner nodes hides the flip from verify_integrity, not
+        // from the regenerated leaves.
+        sk.cache.get_mut().unwrap().generate();
+        assert!(sk.verify_integrity());
+        assert_eq!(sk.verify_subtree_consistency(), vec![5]);
+        sk.cache.get_mut().unwrap().leaves()[(1 << GRAVITY_C) - 1].h[0] ^= 1;
+        assert_eq!(
+            sk.verify_subtree_consistency(),
+            vec![5, (1 << GRAVITY_C) - 1]
+        );
+    }
+
+    #[test]
+    fn test_derive_signing_salt() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let mut bytes = b"signing-salt".to_vec();
+        bytes.extend_from_slice(&sk.secrets.salt.h);
+        bytes.extend_from_slice(&5u64.to_le_bytes());
+        assert_eq!(sk.derive_signing_salt(5), hash::long_hash(&bytes));
+        assert_ne!(sk.derive_signing_salt(5), sk.derive_signing_salt(6));
+        assert_ne!(sk.derive_signing_salt(0), sk.secrets.salt);
+        let fresh = sk.clone_with_fresh_salt(Hash { h: [1; 32] });
+        assert_ne!(fresh.derive_signing_salt(5), sk.derive_signing_salt(5));
+    }
+
+    #[test]
+    fn test_clone_with_fresh_salt() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let fresh = sk.clone_with_fresh_salt(Hash { h: [1; 32] });
+        // The public key only depends on the seed.
+        assert_eq!(fresh.genpk(), sk.genpk());
         let mut random = [0u8; 64];
-        for i in 0..64 {
-            random[i] = i as u8;
-        }
+        random[32..].fill(1);
+        assert!(fresh.to_reference_bytes() == SecKey::new(&random).to_reference_bytes());
+        assert_ne!(fresh.state_hash(), sk.state_hash());
 
-        let sk = SecKey::new(&random);
+        let msg = b"Hello world";
+        let sign = fresh.sign_bytes(msg);
+        let bytes = Vec::<u8>::from(&sign);
+        assert!(bytes != Vec::<u8>::from(&sk.sign_bytes(msg)));
+        assert_eq!(sk.genpk().verify_bytes(&sign, msg), Ok(()));
+        let other = SecKey::new(&[1u8; 64]).genpk();
+        assert_eq!(
+            other.verify_bytes(&sign, msg),
+            Err(GravityError::Verification)
+        );
+
+        let same = sk.clone_with_fresh_salt(Hash::default());
+        assert_eq!(same.state_hash(), sk.state_hash());
+        assert!(Vec::<u8>::from(&same.sign_bytes(msg)) == Vec::<u8>::from(&sk.sign_bytes(msg)));
+    }
+
+    #[test]
+    fn test_rotate_salt() {
+        let mut sk = SecKey::new(&[0u8; 64]);
         let pk = sk.genpk();
-        let msg = hash::tests::HASH_ELEMENT;
-        let sign = sk.sign_hash(&msg);
-        assert!(pk.verify_hash(&sign, &msg));
+        let msg = b"Hello world";
+        let before = Vec::<u8>::from(&sk.sign_bytes(msg));
+
+        sk.rotate_salt(Hash { h: [1; 32] });
+        assert_eq!(sk.genpk(), pk);
+        assert_eq!(SecKey::new(&[0u8; 64]).genpk(), pk);
+        assert!(sk.state_hash() == sk.clone_with_fresh_salt(Hash { h: [1; 32] }).state_hash());
+
+        let sign = sk.sign_bytes(msg);
+        assert_eq!(pk.verify_bytes(&sign, msg), Ok(()));
+        assert!(Vec::<u8>::from(&sign) != before);
+    }
+
+    // Keygen, signing and verification on the 48-byte backend, down to the
+    // public key, the root of the top-level tree. Keygen takes seconds on
+    // SHA-384, so the larger M and L sets are skipped.
+    #[test]
+    fn test_sign_verify_hash384() {
+        use crate::hash::{Hash384, HashBackend};
+        use crate::prng::SeedablePrng;
+        if !matches!(get_config_type(), ConfigType::S | ConfigType::Porst) {
+            return;
+        }
+
+        type Signature384 = (
+            pors::Signature<Hash384>,
+            Vec<subtree::Signature<Hash384>>,
+            [Hash384; GRAVITY_C],
+        );
+
+        let prng = prng::HashPrng::new(&Hash384::long_hash(b"seed"));
+        let salt = Hash384::long_hash(b"salt");
+        let cache = gen_cache(&prng);
+        let pk = cache.root();
+
+        let sign = |msg: &Hash384| -> Signature384 {
+            let (mut address, mut h, pors_sign) = pors::sign(&prng, &salt, msg);
+            let subtree_sk = subtree::SecKey::new(&prng);
+            let mut subtree_signs = vec![];
+            for _ in 0..GRAVITY_D {
+                address.next_layer();
+                let (root, sign) = subtree_sk.sign(&address, &h);
+                h = root;
+                subtree_signs.push(sign);
+                address.shift(MERKLE_H);
+            }
+            let mut auth = [Hash384::default(); GRAVITY_C];
+            cache.gen_auth(&mut auth, address.leaf_index().unwrap().0);
+            (pors_sign, subtree_signs, auth)
+        };
+        let verify = |signature: &Signature384, msg: &Hash384| {
+            let (pors_sign, subtree_signs, auth) = signature;
+            let Ok((mut address, mut h)) = pors_sign.extract(msg) else {
+                return false;
+            };
+            for sign in subtree_signs.iter() {
+                address.next_layer();
+                h = sign.extract(&address, &h);
+                address.shift(MERKLE_H);
+            }
+            let index = address.leaf_index().unwrap().0 as u64;
+            merkle::root_from_auth(&h, auth, index, &top_tree_tweak()) == pk
+        };
+
+        let msg = Hash384::long_hash(b"Hello world");
+        let signature = sign(&msg);
+        assert!(verify(&signature, &msg));
+        assert!(!verify(&signature, &Hash384::long_hash(b"Hello world!")));
+        let mut corrupted = signature.clone();
+        corrupted.2[0].h[0] ^= 1;
+        assert!(!verify(&corrupted, &msg));
+    }
+
+    // The public key of the zero seed is checked against the reference in
+    // test_genkey_zeros; the bytes of the C key only in tests/c_ref.rs.
+    #[test]
+    fn test_reference_bytes() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let bytes = sk.to_reference_bytes();
+        assert_eq!(bytes.len(), REFERENCE_SECRET_KEY_BYTES);
+        assert_eq!(&bytes[..SECRET_SEED_BYTES], &[0u8; 64][..]);
+        let (root, nodes) = bytes[SECRET_SEED_BYTES..].split_at(HASH_SIZE);
+        assert_eq!(root, &sk.genpk().h.h[..]);
+        // The leaves of the top tree come last, from left to right.
+        let prng = prng::Prng::new(&sk.secrets.seed);
+        let leaves = nodes[nodes.len() - (HASH_SIZE << GRAVITY_C)..].chunks_exact(HASH_SIZE);
+        assert!(leaves.eq(gen_cache_leaves(&prng).iter().map(|leaf| &leaf.h[..])));
+
+        let imported = SecKey::from_reference_bytes(&bytes).unwrap();
+        assert_eq!(imported.genpk(), sk.genpk());
+        assert!(imported.to_reference_bytes() == bytes);
+
+        for len in [0, bytes.len() - 1, bytes.len() + 1] {
+            let mut bad = bytes.clone();
+            bad.resize(len, 0);
+            assert_eq!(
+                SecKey::from_reference_bytes(&bad).err(),
+                Some(ImportError::InvalidLength(len))
+            );
+        }
+        let mut bad = bytes.clone();
+        bad[SECRET_SEED_BYTES] ^= 1;
+        assert_eq!(
+            SecKey::from_reference_bytes(&bad).err(),
+            Some(ImportError::PublicKeyMismatch)
+        );
+        let mut bad = bytes.clone();
+        *bad.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            SecKey::from_reference_bytes(&bad).err(),
+            Some(ImportError::CacheMismatch((2 << GRAVITY_C) - 2))
+        );
+        // Another seed with the same cache.
+        let mut bad = bytes.clone();
+        bad[0] ^= 1;
+        assert_eq!(
+            SecKey::from_reference_bytes(&bad).err(),
+            Some(ImportError::PublicKeyMismatch)
+        );
     }
 
+    // Fixture of the specified AES PRNG and hashes.
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac", feature = "tweaked")))]
     #[test]
     fn test_genkey_zeros() {
         let random: [u8; 64] = [0u8; 64];
@@ -183,6 +5086,12 @@ mod tests {
                    \x84\x68\x1c\xa8\x03\x75\x4c\xc2\
                    \x1f\x50\x69\x68\x41\xc1\xb3\x03"
             }
+            ConfigType::Porst => {
+                *b"\xfb\xde\xf0\x83\x96\xd1\x35\x5c\
+                   \x4d\x9e\x96\x74\xcc\xac\xbb\xaf\
+                   \xba\xfd\xc2\xf3\xb4\xf0\x55\x39\
+                   \xdc\x9a\x2f\xde\x6a\xa5\xc7\x26"
+            }
             ConfigType::Unknown => unimplemented!(),
         };
 
@@ -191,44 +5100,146 @@ mod tests {
         assert_eq!(pk.h.h, pkh);
     }
 
+    const ZEROS_MSG: [u8; 32] = *b"\x00\x01\x02\x03\x04\x05\x06\x07\
+                                   \x08\x09\x0a\x0b\x0c\x0d\x0e\x0f\
+                                   \x10\x11\x12\x13\x14\x15\x16\x17\
+                                   \x18\x19\x1a\x1b\x1c\x1d\x1e\x1f";
+
+    // Expected signature of ZEROS_MSG under the all-zero secret key, in hex.
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac", feature = "tweaked")))]
+    fn sign_zeros_hex() -> &'static str {
+        match get_config_type() {
+            ConfigType::S => include_str!("../test_files/test_sign_zero_S.hex"),
+            ConfigType::M => include_str!("../test_files/test_sign_zero_M.hex"),
+            ConfigType::L => include_str!("../test_files/test_sign_zero_L.hex"),
+            ConfigType::Porst => include_str!("../test_files/test_sign_zero_P.hex"),
+            ConfigType::Unknown => unimplemented!(),
+        }
+    }
+
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac", feature = "tweaked")))]
+    fn sign_zeros_fixture() -> Vec<u8> {
+        crate::vectors::decode_hex_file(sign_zeros_hex())
+    }
+
+    // There are no such fixtures for the SHAKE256 and HMAC PRNGs, nor the tweaked
+    // hashes.
+    #[cfg(any(feature = "shake256", feature = "prng-hmac", feature = "tweaked"))]
+    fn sign_zeros_fixture() -> Vec<u8> {
+        Vec::<u8>::from(&SecKey::new(&[0u8; 64]).sign_bytes(&ZEROS_MSG))
+    }
+
+    // Fixture of the specified AES PRNG and hashes.
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac", feature = "tweaked")))]
     #[test]
     fn test_sign_zeros() {
-        use hex;
+        let expect = Signature::from_hex_test_vector(sign_zeros_hex()).unwrap();
 
-        let random: [u8; 64] = [0u8; 64];
-        let msg: [u8; 32] = *b"\x00\x01\x02\x03\x04\x05\x06\x07\
-                               \x08\x09\x0a\x0b\x0c\x0d\x0e\x0f\
-                               \x10\x11\x12\x13\x14\x15\x16\x17\
-                               \x18\x19\x1a\x1b\x1c\x1d\x1e\x1f";
-        let hex_file = match get_config_type() {
-            ConfigType::S => {
-                let hex_file = include_str!("../test_files/test_sign_zero_S.hex");
-                hex_file
-            }
-            ConfigType::M => {
-                let hex_file = include_str!("../test_files/test_sign_zero_M.hex");
-                hex_file
-            }
-            ConfigType::L => {
-                let hex_file = include_str!("../test_files/test_sign_zero_L.hex");
-                hex_file
-            }
-            ConfigType::Unknown => unimplemented!(),
-        };
+        let sign = SecKey::new(&[0u8; 64]).sign_bytes(&ZEROS_MSG);
+        let sign_bytes = Vec::<u8>::from(&sign);
+        assert_eq!(sign_bytes, Vec::<u8>::from(&expect));
+        assert_eq!(sign_bytes.len(), Signature::SIZE);
+        assert_eq!(sign_bytes.len(), crate::params().max_signature_bytes);
+    }
 
-        let mut hex: Vec<u8> = vec![];
-        for x in hex_file.split_whitespace() {
-            hex.extend(x.bytes())
-        }
-        let expect: Vec<u8> = hex::decode(hex).unwrap();
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac", feature = "tweaked")))]
+    #[test]
+    fn test_from_hex_test_vector() {
+        let hex_str = sign_zeros_hex();
+        let sign = Signature::from_hex_test_vector(hex_str).unwrap();
+        assert_eq!(Vec::<u8>::from(&sign), sign_zeros_fixture());
+        // Same vector, on one line and without the header.
+        let flat = hex::encode(sign_zeros_fixture());
+        assert!(Signature::from_hex_test_vector(&flat).is_ok());
 
-        let sk = SecKey::new(&random);
-        let sign = sk.sign_bytes(&msg);
-        let mut sign_bytes = Vec::<u8>::new();
-        sign.serialize(&mut sign_bytes);
-        assert_eq!(sign_bytes, expect);
+        let truncated = &flat[..flat.len() - 64];
+        assert_eq!(
+            Signature::from_hex_test_vector(truncated).err(),
+            Some(ParseError::Deserialization(DeserializeError::UnexpectedEnd))
+        );
+        assert_eq!(
+            Signature::from_hex_test_vector(&flat[1..]).err(),
+            Some(ParseError::InvalidHex(hex::FromHexError::OddLength))
+        );
+        assert!(matches!(
+            Signature::from_hex_test_vector(&flat.replacen('0', "g", 1)),
+            Err(ParseError::InvalidHex(_))
+        ));
+        let extended = format!("{}\n00", hex_str);
+        assert_eq!(
+            Signature::from_hex_test_vector(&extended).err(),
+            Some(ParseError::Deserialization(DeserializeError::TrailingBytes))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "bs58")]
+    fn test_base58() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let sign = sk.sign_bytes(b"Hello world");
+        let encoded = sign.to_base58();
+        // log(256) / log(58) = 1.3657 characters per byte.
+        assert!(encoded.len() <= Signature::SIZE * 13658 / 10000 + 1);
+        assert!(encoded.len() >= Signature::SIZE * 13656 / 10000);
+        let bytes = Vec::<u8>::from(&sign);
+        let decoded = Signature::from_base58(&encoded).unwrap();
+        assert!(Vec::<u8>::from(&decoded) == bytes);
+
+        let mut bad = encoded.clone();
+        bad.replace_range(10..11, "0");
+        assert_eq!(
+            Signature::from_base58(&bad).err(),
+            Some(Base58Error::Decoding(
+                bs58::decode::Error::InvalidCharacter {
+                    character: '0',
+                    index: 10
+                }
+            ))
+        );
+        assert_eq!(
+            Signature::from_base58(&bs58::encode(&bytes[..bytes.len() - 1]).into_string()).err(),
+            Some(Base58Error::Deserialization(
+                DeserializeError::UnexpectedEnd
+            ))
+        );
+    }
+
+    #[test]
+    fn test_encode_hex() {
+        let sk = SecKey::new(&[0u8; 64]);
+        let sign = sk.sign_bytes(b"Hello world");
+        let encoded = sign.encode_hex();
+        assert_eq!(encoded.len(), 2 * Signature::SIZE);
+        assert!(encoded
+            .bytes()
+            .all(|c| matches!(c, b'0'..=b'9' | b'a'..=b'f')));
+        let decoded = Signature::decode_hex(&encoded).unwrap();
+        assert!(Vec::<u8>::from(&decoded) == Vec::<u8>::from(&sign));
+        let upper = Signature::decode_hex(&encoded.to_uppercase()).unwrap();
+        assert!(Vec::<u8>::from(&upper) == Vec::<u8>::from(&sign));
+
+        assert_eq!(
+            Signature::decode_hex(&encoded[1..]).err(),
+            Some(HexError::OddLength)
+        );
+        let mut bad = encoded.clone();
+        bad.replace_range(10..11, "x");
+        assert_eq!(
+            Signature::decode_hex(&bad).err(),
+            Some(HexError::InvalidCharacter { c: 'x', index: 10 })
+        );
+        assert_eq!(
+            Signature::decode_hex(&encoded[..encoded.len() - 2]).err(),
+            Some(HexError::Deserialization(DeserializeError::UnexpectedEnd))
+        );
+        assert_eq!(
+            Signature::decode_hex(&format!("{}00", encoded)).err(),
+            Some(HexError::Deserialization(DeserializeError::TrailingBytes))
+        );
     }
 
+    // Fixture of the specified AES PRNG and hashes.
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac", feature = "tweaked")))]
     #[test]
     fn test_genkey_kat() {
         let random: [u8; 64] = *b"\x7C\x99\x35\xA0\xB0\x76\x94\xAA\
@@ -258,6 +5269,12 @@ mod tests {
                    \xF0\xF7\xC3\xC5\x3D\x0E\x21\xFE\
                    \xD8\xDF\x4C\xD6\xCB\x20\x69\xD9"
             }
+            ConfigType::Porst => {
+                *b"\x43\x19\x54\x95\xEE\xBB\xE2\x1A\
+                   \x46\x3E\xF5\xA8\xC0\xF0\x0A\xF9\
+                   \x20\xF9\xB6\xC5\xFD\x11\x8A\x38\
+                   \x0F\x26\x9F\x9B\x50\x8A\xBE\xC2"
+            }
             ConfigType::Unknown => unimplemented!(),
         };
 
@@ -266,6 +5283,8 @@ mod tests {
         assert_eq!(pk.h.h, pkh);
     }
 
+    // Fixture of the specified AES PRNG and hashes.
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac", feature = "tweaked")))]
     #[test]
     fn test_sign_kat() {
         use hex;
@@ -284,21 +5303,343 @@ mod tests {
             ConfigType::S => include_str!("../test_files/test_sign_kat_S.hex"),
             ConfigType::M => include_str!("../test_files/test_sign_kat_M.hex"),
             ConfigType::L => include_str!("../test_files/test_sign_kat_L.hex"),
+            ConfigType::Porst => include_str!("../test_files/test_sign_kat_P.hex"),
             ConfigType::Unknown => unimplemented!(),
         };
 
-        let mut hex: Vec<u8> = vec![];
-        for x in hex_file.split_whitespace() {
-            hex.extend(x.bytes())
-        }
-        let expect: Vec<u8> = hex::decode(hex).unwrap();
+        let expect = Signature::from_hex_test_vector(hex_file).unwrap();
 
         let sk = SecKey::new(&random);
         let sign = sk.sign_bytes(&msg);
-        let mut sign_bytes = Vec::<u8>::new();
-        sign.serialize(&mut sign_bytes);
+        assert_eq!(Vec::<u8>::from(&sign), Vec::<u8>::from(&expect));
+    }
 
-        assert_eq!(sign_bytes, expect);
+    // Keys and signatures of one hashing mode are rejected by the other, from the
+    // fixtures of both.
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac")))]
+    #[test]
+    fn test_tweaked_fixtures_cross_mode() {
+        if get_config_type() != ConfigType::S {
+            return;
+        }
+        let decode = crate::vectors::decode_hex_file;
+        let plain = (
+            decode(include_str!("../test_files/test_genpk_zero_S.hex")),
+            decode(include_str!("../test_files/test_sign_zero_S.hex")),
+        );
+        let tweaked = (
+            decode(include_str!("../test_files/test_genpk_zero_S_tweaked.hex")),
+            decode(include_str!("../test_files/test_sign_zero_S_tweaked.hex")),
+        );
+        let (own, other) = if cfg!(feature = "tweaked") {
+            (tweaked, plain)
+        } else {
+            (plain, tweaked)
+        };
+        assert_ne!(own.0, other.0);
+
+        let pk = SecKey::new(&[0u8; 64]).genpk();
+        assert_eq!(&pk.h.h[..], &own.0[..]);
+        let other_pk = PubKey::try_from(other.0.as_slice()).unwrap();
+        let own_sign = Signature::try_from(own.1.as_slice()).unwrap();
+        let other_sign = Signature::try_from(other.1.as_slice()).unwrap();
+        assert_eq!(pk.verify_bytes(&own_sign, &ZEROS_MSG), Ok(()));
+        assert!(other_pk.verify_bytes(&own_sign, &ZEROS_MSG).is_err());
+        assert!(other_pk.verify_bytes(&other_sign, &ZEROS_MSG).is_err());
+        assert!(pk.verify_bytes(&other_sign, &ZEROS_MSG).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json() {
+        let fixture = sign_zeros_fixture();
+        let sign = Signature::try_from(fixture.as_slice()).unwrap();
+        let json = sign.to_json();
+        assert!(json.starts_with("{\"version\":\"gravity-1\",\"pors\":\""));
+        let parsed = Signature::from_json(&json).unwrap();
+        assert_eq!(Vec::<u8>::from(&parsed), fixture);
+        assert_eq!(parsed.to_json(), json);
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let modified = |f: &dyn Fn(&mut serde_json::Value)| {
+            let mut value = value.clone();
+            f(&mut value);
+            Signature::from_json(&value.to_string()).err()
+        };
+        assert_eq!(
+            modified(&|v| v["version"] = "gravity-2".into()),
+            Some(JsonError::UnsupportedVersion("gravity-2".to_string()))
+        );
+
+        // Every string field: truncated, extended, and not base64.
+        fn field_mut<'a>(
+            v: &'a mut serde_json::Value,
+            field: &str,
+            index: Option<usize>,
+        ) -> &'a mut serde_json::Value {
+            match index {
+                Some(i) => &mut v[field][i],
+                None => &mut v[field],
+            }
+        }
+        let mut paths: Vec<(&str, Option<usize>)> = vec![("pors", None)];
+        paths.extend((0..GRAVITY_D).map(|i| ("subtrees", Some(i))));
+        paths.extend((0..GRAVITY_C).map(|i| ("auth_c", Some(i))));
+        for (field, index) in paths {
+            use base64::Engine;
+            let engine = base64::engine::general_purpose::STANDARD;
+            let edit = |g: &dyn Fn(&mut Vec<u8>)| {
+                modified(&|v| {
+                    let x = field_mut(v, field, index);
+                    let mut bytes = engine.decode(x.as_str().unwrap()).unwrap();
+                    g(&mut bytes);
+                    *x = engine.encode(bytes).into();
+                })
+            };
+            assert_eq!(
+                edit(&|x| {
+                    x.pop();
+                }),
+                Some(JsonError::Deserialization(
+                    field,
+                    DeserializeError::UnexpectedEnd
+                ))
+            );
+            assert_eq!(
+                edit(&|x| x.push(0)),
+                Some(JsonError::Deserialization(
+                    field,
+                    DeserializeError::TrailingBytes
+                ))
+            );
+            assert_eq!(
+                modified(&|v| *field_mut(v, field, index) = "!!!!".into()),
+                Some(JsonError::InvalidBase64(field))
+            );
+        }
+
+        assert_eq!(
+            modified(&|v| v["auth_c"].as_array_mut().unwrap().truncate(1)),
+            Some(JsonError::InvalidLength("auth_c"))
+        );
+        assert_eq!(
+            modified(&|v| v["subtrees"].as_array_mut().unwrap().push("".into())),
+            Some(JsonError::InvalidLength("subtrees"))
+        );
+        assert!(matches!(
+            modified(&|v| v["extra"] = 1.into()),
+            Some(JsonError::Syntax(_))
+        ));
+        assert!(matches!(
+            modified(&|v| {
+                v.as_object_mut().unwrap().remove("pors");
+            }),
+            Some(JsonError::Syntax(_))
+        ));
+        assert!(matches!(
+            Signature::from_json(&json[1..]),
+            Err(JsonError::Syntax(_))
+        ));
+    }
+
+    // Mirrors the fuzz target: arbitrary signatures round-trip and are rejected.
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn test_arbitrary() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        let pk = SecKey::new(&[0u8; 64]).genpk();
+        let msg = hash::long_hash(&ZEROS_MSG);
+        let mut data = Vec::new();
+        let mut state = msg;
+        while data.len() < 4 * SIGNATURE_BYTES {
+            state = hash::long_hash(&state.h);
+            data.extend_from_slice(&state.h);
+        }
+        let mut u = Unstructured::new(&data);
+        for _ in 0..3 {
+            let sign = Signature::arbitrary(&mut u).unwrap();
+            let bytes = Vec::<u8>::from(&sign);
+            assert_eq!(bytes.len(), SIGNATURE_BYTES);
+            let parsed = Signature::try_from(bytes.as_slice()).unwrap();
+            assert_eq!(Vec::<u8>::from(&parsed), bytes);
+            assert!(parsed.pors_signature().octopus_len() <= OCTOPUS_MAX_NODES);
+            assert!(pk.verify_hash(&parsed, &msg).is_err());
+        }
+
+        // Exhausted input still yields a signature of zeros, as arbitrary does.
+        let sign = Signature::arbitrary(&mut Unstructured::new(&[])).unwrap();
+        assert_eq!(sign.pors_signature().octopus_len(), 0);
+    }
+
+    #[cfg(feature = "bytemuck")]
+    #[test]
+    fn test_bytemuck_pubkey() {
+        assert_eq!(std::mem::size_of::<PubKey>(), PUBLIC_KEY_BYTES);
+        assert_eq!(std::mem::align_of::<PubKey>(), 1);
+
+        let pk = SecKey::new(&[0u8; 64]).genpk();
+        // Records at an odd offset, as in a mapped file with a 1-byte header.
+        let mut region = vec![0xFFu8];
+        region.extend_from_slice(&pk.h.h);
+        region.extend_from_slice(&[0u8; PUBLIC_KEY_BYTES]);
+        let mapped: &[PubKey] = bytemuck::cast_slice(&region[1..]);
+        assert_eq!(mapped.len(), 2);
+        assert_eq!(mapped[0], PubKey::try_from(&region[1..33]).unwrap());
+        assert_eq!(mapped[0], pk);
+        assert_eq!(mapped[1], bytemuck::Zeroable::zeroed());
+        assert_eq!(bytemuck::bytes_of(&pk), &pk.h.h);
+        assert!(bytemuck::try_cast_slice::<u8, PubKey>(&region).is_err());
+    }
+
+    #[cfg(feature = "compat")]
+    #[test]
+    fn test_uncompressed_point() {
+        let pk = SecKey::new(&[0u8; 64]).genpk();
+        let bytes = pk.to_uncompressed_point();
+        assert_eq!(bytes[0], 0x04);
+        assert_eq!(&bytes[1..], &pk.h.h[..]);
+        assert_eq!(PubKey::from_uncompressed_point(&bytes), Ok(pk));
+
+        for prefix in [0x00, 0x02, 0x03, 0x05] {
+            let mut bad = bytes;
+            bad[0] = prefix;
+            assert_eq!(
+                PubKey::from_uncompressed_point(&bad),
+                Err(DeserializeError::InvalidHeader)
+            );
+        }
+    }
+
+    #[test]
+    fn test_new_deterministic_from_master_secret() {
+        let master = [7u8; 32];
+        let sk = SecKey::new_deterministic_from_master_secret(&master, 0);
+        let again = SecKey::new_deterministic_from_master_secret(&master, 0);
+        assert_eq!(sk.to_seed_bytes(), again.to_seed_bytes());
+        assert_eq!(sk.genpk(), again.genpk());
+
+        let mut bytes = b"gravity-seed".to_vec();
+        bytes.extend_from_slice(&master);
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        assert_eq!(sk.secrets.seed, hash::long_hash(&bytes));
+        bytes[..12].copy_from_slice(b"gravity-salt");
+        assert_eq!(sk.secrets.salt, hash::long_hash(&bytes));
+
+        let next = SecKey::new_deterministic_from_master_secret(&master, 1);
+        assert_ne!(next.genpk(), sk.genpk());
+        assert_ne!(next.secrets.salt, sk.secrets.salt);
+        let other = SecKey::new_deterministic_from_master_secret(&[8u8; 32], 0);
+        assert_ne!(other.genpk(), sk.genpk());
+    }
+
+    #[test]
+    fn test_compressed_bytes() {
+        let pk = SecKey::new(&[0u8; 64]).genpk();
+        let bytes = pk.to_compressed_bytes();
+        assert_eq!(bytes.len(), 33);
+        assert_eq!(bytes[0], COMPRESSED_KEY_TAG);
+        assert_eq!(&bytes[1..], &pk.h.h[..]);
+        assert_eq!(PubKey::from_compressed_bytes(&bytes), Ok(pk));
+
+        for tag in [0x00, 0x02, 0x03, 0x04, 0xFF] {
+            let mut bad = bytes;
+            bad[0] = tag;
+            assert_eq!(
+                PubKey::from_compressed_bytes(&bad),
+                Err(CompressedKeyError::UnknownScheme(tag))
+            );
+        }
+    }
+
+    #[cfg(feature = "der")]
+    #[test]
+    fn test_der() {
+        use spki::der::{Decode, Encode};
+        let pk = SecKey::new(&[0u8; 64]).genpk();
+        let der = pk.as_der();
+        assert_eq!(PubKey::from_der(&der), Ok(pk));
+
+        let spki = spki::SubjectPublicKeyInfoOwned::from_der(&der).unwrap();
+        assert_eq!(spki.algorithm.oid, DER_ALGORITHM_OID);
+        assert!(spki.algorithm.parameters.is_none());
+        assert_eq!(spki.subject_public_key.as_bytes(), Some(&pk.h.h[..]));
+        assert_eq!(spki.to_der().unwrap(), der);
+
+        let other = spki::SubjectPublicKeyInfoOwned {
+            algorithm: spki::AlgorithmIdentifierOwned {
+                oid: spki::ObjectIdentifier::new_unwrap("1.3.101.112"),
+                parameters: None,
+            },
+            ..spki.clone()
+        };
+        assert_eq!(
+            PubKey::from_der(&other.to_der().unwrap()),
+            Err(DerError::UnknownAlgorithm)
+        );
+        let short = spki::SubjectPublicKeyInfoOwned {
+            subject_public_key: spki::der::asn1::BitString::from_bytes(&pk.h.h[1..]).unwrap(),
+            ..spki
+        };
+        assert_eq!(
+            PubKey::from_der(&short.to_der().unwrap()),
+            Err(DerError::InvalidKeyLength(248))
+        );
+        assert!(matches!(
+            PubKey::from_der(&der[..der.len() - 1]),
+            Err(DerError::Malformed(_))
+        ));
+        let mut long = der.clone();
+        long.push(0);
+        assert!(matches!(
+            PubKey::from_der(&long),
+            Err(DerError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_verification_key_revoked() {
+        let pk0 = PubKey {
+            h: hash::tests::HASH_ELEMENT,
+        };
+        let pk1 = PubKey {
+            h: hash::hash_n_to_n_ret(&pk0.h),
+        };
+        let vk = VerificationKey::new(pk0);
+        assert!(vk.is_revoked(&[pk1, pk0]));
+        assert!(!vk.is_revoked(&[pk1]));
+        assert!(!vk.is_revoked(&[]));
+    }
+
+    #[test]
+    fn test_verification_key_spki() {
+        let pk = PubKey {
+            h: hash::tests::HASH_ELEMENT,
+        };
+        let vk = VerificationKey::new(pk);
+        let spki = vk.encode_spki();
+        assert_eq!(spki.len(), 2 + spki[1] as usize);
+        assert_eq!(&spki[..14], &SPKI_PREFIX);
+        assert_eq!(&spki[14..], &pk.h.h);
+        assert_eq!(vk.algorithm_id(), "gravity-sphincs");
+    }
+
+    #[cfg(feature = "der")]
+    #[test]
+    fn test_verification_key_spki_der() {
+        let pk = SecKey::new(&[0u8; 64]).genpk();
+        let spki = VerificationKey::new(pk).encode_spki();
+        assert_eq!(&SPKI_PREFIX[6..11], DER_ALGORITHM_OID.as_bytes());
+        assert_eq!(spki, pk.as_der());
+        assert_eq!(PubKey::from_der(&spki), Ok(pk));
+    }
+
+    #[test]
+    fn test_verification_key_deserialize() {
+        let bytes = hash::tests::HASH_ELEMENT.h;
+        let vk = VerificationKey::deserialize(&mut bytes.iter()).unwrap();
+        assert_eq!(vk.pubkey().h, hash::tests::HASH_ELEMENT);
+        assert!(VerificationKey::deserialize(&mut bytes[1..].iter()).is_err());
     }
 
     use std::hint::black_box;
@@ -322,6 +5663,18 @@ mod tests {
         b.iter(|| sk.sign_hash(black_box(&msg)));
     }
 
+    // To compare with 16 times bench_sign: the speedup is close to the number
+    // of cores, up to 16.
+    #[cfg(feature = "parallel")]
+    #[bench]
+    fn bench_sign_parallel_batch_16(b: &mut Bencher) {
+        let seed = [0u8; 64];
+        let sk = SecKey::new(&seed);
+        let msgs: Vec<[u8; 4]> = (0..16u32).map(|i| i.to_le_bytes()).collect();
+        let msgs: Vec<&[u8]> = msgs.iter().map(|m| m.as_slice()).collect();
+        b.iter(|| sk.sign_parallel_batch(black_box(&msgs)));
+    }
+
     #[bench]
     fn bench_verify(b: &mut Bencher) {
         let seed = [0u8; 64];
@@ -329,6 +5682,6 @@ mod tests {
         let pk = sk.genpk();
         let msg = hash::tests::HASH_ELEMENT;
         let sign = sk.sign_hash(&msg);
-        b.iter(|| pk.verify_hash(black_box(&sign), black_box(&msg)));
+        b.iter(|| pk.verify_hash(black_box(&sign), black_box(&msg)).is_ok());
     }
 }
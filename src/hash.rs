@@ -1,16 +1,170 @@
+use crate::address;
 use crate::config;
+#[cfg(feature = "bip39")]
+use crate::error::Bip39Error;
+use crate::error::DeserializeError;
+use crate::error::EntropyError;
+use crate::error::HexError;
 use crate::primitives::haraka256;
 use crate::primitives::haraka512;
-use arrayref::array_ref;
+use crate::prng;
+use arrayref::{array_mut_ref, array_ref};
+#[cfg(feature = "tweaked")]
+use byteorder::{BigEndian, ByteOrder};
+#[cfg(any(test, feature = "experimental"))]
+use sha2::Sha384;
 use sha2::{Digest, Sha256};
 use std::fmt;
+use std::ptr;
 
-#[derive(Clone, Copy, Default, PartialEq, Eq)]
-pub struct Hash {
-    pub h: [u8; config::HASH_SIZE],
+// The default size is that of the specification; other sizes need their own
+// HashBackend implementation. The layout is that of [u8; N]: no padding and an
+// alignment of 1, so that a byte buffer of any alignment can be cast to hashes.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct Hash<const N: usize = { config::HASH_SIZE }> {
+    pub h: [u8; N],
 }
 
-impl fmt::Debug for Hash {
+// SAFETY: Hash is a transparent wrapper of a byte array, for which all bit
+// patterns are valid.
+#[cfg(feature = "bytemuck")]
+unsafe impl<const N: usize> bytemuck::Zeroable for Hash<N> {}
+#[cfg(feature = "bytemuck")]
+unsafe impl<const N: usize> bytemuck::Pod for Hash<N> {}
+
+#[cfg(feature = "arbitrary")]
+impl<'a, const N: usize> arbitrary::Arbitrary<'a> for Hash<N> {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Hash { h: u.arbitrary()? })
+    }
+
+    fn size_hint(_depth: usize) -> (usize, Option<usize>) {
+        (N, Some(N))
+    }
+}
+
+pub const TWEAK_BYTES: usize = 32;
+
+// Shortest input of Hash::from_entropy, i.e. 128 bits.
+pub const MIN_ENTROPY_BYTES: usize = 16;
+
+// Structure that a tweaked hash belongs to. WOTS chains and their L-tree share a
+// kind, as they differ by arity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TweakKind {
+    Message = 1,
+    Wots = 2,
+    Subtree = 3,
+    Pors = 4,
+    TopTree = 5,
+}
+
+// Context of a compression in the hyper-tree, mixed into the hash with the
+// tweaked feature: the kind and address of the structure, completed by the
+// position of the node in it (chain and step for WOTS chains, height and index
+// for tree nodes). Without the feature, tweaks are ignored and the hashes are
+// those of the specification. NONE is the plain hash in both modes, for data
+// outside of the hyper-tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tweak {
+    #[cfg_attr(not(feature = "tweaked"), allow(dead_code))]
+    prefix: Option<[u8; 16]>,
+}
+
+// Hash functions of an instantiation of the scheme, implemented by its hash
+// type, of at least 32 bytes. The merkle, ltree, wots, subtree, octopus and pors
+// modules are generic over it. Hash is Haraka with SHA-256 and the AES PRNG, as
+// specified.
+pub trait HashBackend: Copy + Default + Eq + fmt::Debug + AsRef<[u8]> + AsMut<[u8]> {
+    const SIZE: usize;
+    type Prng: prng::SeedablePrng<Block = Self>;
+    // The wots::ell::<Self>() chain values of a WOTS key or signature, in an
+    // array so that WOTS needs no allocation.
+    type Chains: Copy + AsRef<[Self]> + AsMut<[Self]>;
+
+    // Chain values all set to the default hash.
+    fn chains() -> Self::Chains;
+
+    fn hash_n_to_n(dst: &mut Self, src: &Self);
+    fn hash_2n_to_n(dst: &mut Self, src0: &Self, src1: &Self);
+    fn long_hash(src: &[u8]) -> Self;
+
+    // Functions keyed by a tweak block, which must not be related to the
+    // untweaked ones nor to each other.
+    #[cfg(feature = "tweaked")]
+    fn hash_n_to_n_tweaked(dst: &mut Self, src: &Self, tweak: &[u8; TWEAK_BYTES]);
+    #[cfg(feature = "tweaked")]
+    fn hash_2n_to_n_tweaked(dst: &mut Self, src0: &Self, src1: &Self, tweak: &[u8; TWEAK_BYTES]);
+
+    // Four independent hash_n_to_n, for backends with an interleaved
+    // implementation.
+    fn hash_n_to_n_x4(dst: &mut [Self; 4], src: &[Self; 4]) {
+        for (d, s) in dst.iter_mut().zip(src.iter()) {
+            Self::hash_n_to_n(d, s);
+        }
+    }
+
+    // Four independent hash_2n_to_n.
+    fn hash_2n_to_n_x4(dst: &mut [Self; 4], src0: &[Self; 4], src1: &[Self; 4]) {
+        for (d, (s0, s1)) in dst.iter_mut().zip(src0.iter().zip(src1.iter())) {
+            Self::hash_2n_to_n(d, s0, s1);
+        }
+    }
+}
+
+impl Tweak {
+    pub const NONE: Tweak = Tweak { prefix: None };
+
+    pub fn new(kind: TweakKind, address: &address::Address) -> Self {
+        Tweak {
+            prefix: Some(address.to_block(kind as u32)),
+        }
+    }
+
+    // The address block with the kind as counter, then the position (x, y) and
+    // the arity of the function, big-endian.
+    #[cfg(feature = "tweaked")]
+    fn block(&self, x: u32, y: u64, arity: u32) -> Option<[u8; TWEAK_BYTES]> {
+        let prefix = self.prefix?;
+        let mut block = [0u8; TWEAK_BYTES];
+        block[..16].copy_from_slice(&prefix);
+        BigEndian::write_u32(array_mut_ref![block, 16, 4], x);
+        BigEndian::write_u64(array_mut_ref![block, 20, 8], y);
+        BigEndian::write_u32(array_mut_ref![block, 28, 4], arity);
+        Some(block)
+    }
+}
+
+impl<const N: usize> Default for Hash<N> {
+    fn default() -> Self {
+        Hash { h: [0; N] }
+    }
+}
+
+impl<const N: usize> AsRef<[u8]> for Hash<N> {
+    fn as_ref(&self) -> &[u8] {
+        &self.h
+    }
+}
+
+impl<const N: usize> AsMut<[u8]> for Hash<N> {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.h
+    }
+}
+
+impl<const N: usize> Hash<N> {
+    pub fn as_bytes(&self) -> &[u8; N] {
+        &self.h
+    }
+
+    pub fn as_mut_bytes(&mut self) -> &mut [u8; N] {
+        &mut self.h
+    }
+}
+
+impl<const N: usize> fmt::Debug for Hash<N> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for x in self.h.iter() {
             write!(f, "{:02x}", x)?;
@@ -19,103 +173,893 @@ impl fmt::Debug for Hash {
     }
 }
 
+// Destination of the canonical encodings: a Vec, or a buffer of the caller
+// through SliceSink.
+pub(crate) trait ByteSink {
+    fn put(&mut self, bytes: &[u8]);
+}
+
+impl ByteSink for Vec<u8> {
+    fn put(&mut self, bytes: &[u8]) {
+        self.extend_from_slice(bytes);
+    }
+}
+
+// Writes at the start of `buf` onwards. Panics like slice indexing if `buf` is
+// too short.
+pub(crate) struct SliceSink<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> SliceSink<'a> {
+    pub(crate) fn new(buf: &'a mut [u8]) -> Self {
+        SliceSink { buf, pos: 0 }
+    }
+
+    pub(crate) fn position(&self) -> usize {
+        self.pos
+    }
+}
+
+impl ByteSink for SliceSink<'_> {
+    fn put(&mut self, bytes: &[u8]) {
+        let end = self.pos + bytes.len();
+        self.buf[self.pos..end].copy_from_slice(bytes);
+        self.pos = end;
+    }
+}
+
 impl Hash {
+    pub const fn len() -> usize {
+        config::HASH_SIZE
+    }
+
     pub fn serialize(&self, output: &mut Vec<u8>) {
-        output.extend(self.h.iter());
+        self.write_to(output);
     }
 
-    pub fn deserialize<'a, I>(it: &mut I) -> Option<Self>
+    pub(crate) fn write_to<S: ByteSink>(&self, output: &mut S) {
+        output.put(&self.h);
+    }
+
+    #[deny(clippy::indexing_slicing)]
+    pub fn deserialize<'a, I>(it: &mut I) -> Result<Self, DeserializeError>
     where
         I: Iterator<Item = &'a u8>,
     {
         let mut hash: Hash = Default::default();
         for x in hash.h.iter_mut() {
-            *x = *it.next()?;
+            *x = *it.next().ok_or(DeserializeError::UnexpectedEnd)?;
+        }
+        Ok(hash)
+    }
+
+    // Lowercase hex digits as ASCII bytes, without allocating.
+    pub fn to_hex_array(&self) -> [u8; 2 * config::HASH_SIZE] {
+        const DIGITS: &[u8; 16] = b"0123456789abcdef";
+        let mut output = [0u8; 2 * config::HASH_SIZE];
+        for (pair, x) in output.chunks_exact_mut(2).zip(self.h.iter()) {
+            pair[0] = DIGITS[(x >> 4) as usize];
+            pair[1] = DIGITS[(x & 0xF) as usize];
+        }
+        output
+    }
+
+    // Inverse of to_hex_array; upper case digits are accepted.
+    pub fn from_hex_array(arr: &[u8; 2 * config::HASH_SIZE]) -> Result<Hash, HexError> {
+        let digit = |index: usize| {
+            let c = arr[index];
+            (c as char)
+                .to_digit(16)
+                .map(|d| d as u8)
+                .ok_or(HexError::InvalidCharacter {
+                    c: c as char,
+                    index,
+                })
+        };
+        let mut hash = Hash::default();
+        for (i, x) in hash.h.iter_mut().enumerate() {
+            *x = (digit(2 * i)? << 4) | digit(2 * i + 1)?;
+        }
+        Ok(hash)
+    }
+
+    // SHA-256 of the concatenated hashes. This is not the Merkle tree
+    // compression: use pair_hash for that.
+    pub fn concat_and_hash(hashes: &[Hash]) -> Hash {
+        let mut bytes = Vec::with_capacity(hashes.len() * config::HASH_SIZE);
+        for x in hashes.iter() {
+            x.serialize(&mut bytes);
+        }
+        long_hash(&bytes)
+    }
+
+    // Parent of the `left` and `right` nodes in Merkle trees, octopuses and
+    // L-trees.
+    pub fn pair_hash(left: &Hash, right: &Hash) -> Hash {
+        hash_2n_to_n_ret(left, right)
+    }
+
+    // Hash of an index: hash_n_to_n of 24 zero bytes then n as u64
+    // little-endian.
+    pub fn from_u64(n: u64) -> Hash {
+        Self::from_index_block(0, n)
+    }
+
+    // Same as from_u64 with a first byte of 0x01 instead of 0x00, so that
+    // from_u32(n) differs from from_u64(n), including for n = 0.
+    pub fn from_u32(n: u32) -> Hash {
+        Self::from_index_block(1, n as u64)
+    }
+
+    fn from_index_block(tag: u8, n: u64) -> Hash {
+        let mut src = Hash::default();
+        src.h[0] = tag;
+        src.h[config::HASH_SIZE - 8..].copy_from_slice(&n.to_le_bytes());
+        let mut dst = Hash::default();
+        hash_n_to_n(&mut dst, &src);
+        dst
+    }
+
+    // The first HASH_SIZE bytes of slice, zero-padded if shorter. Not a hash of
+    // slice: for fixed labels in tests, e.g. from_slice_truncated(b"vector-1").
+    pub fn from_slice_truncated(slice: &[u8]) -> Hash {
+        let mut h = Hash::default();
+        let n = slice.len().min(config::HASH_SIZE);
+        h.h[..n].copy_from_slice(&slice[..n]);
+        h
+    }
+
+    // The i-th subkey is the SHA-256 of self || i as u64 little-endian.
+    pub fn derive_subkeys(&self, n: usize) -> Vec<Hash> {
+        let mut bytes = [0u8; config::HASH_SIZE + 8];
+        bytes[..config::HASH_SIZE].copy_from_slice(&self.h);
+        (0..n as u64)
+            .map(|i| {
+                bytes[config::HASH_SIZE..].copy_from_slice(&i.to_le_bytes());
+                long_hash(&bytes)
+            })
+            .collect()
+    }
+
+    // n bytes of output: the concatenated subkeys (SHA-256 of self || 0, of
+    // self || 1, etc.), truncated to n.
+    pub fn extend_to_vec(&self, n: usize) -> Vec<u8> {
+        self.derive_subkeys(n.div_ceil(config::HASH_SIZE))
+            .iter()
+            .flat_map(|key| key.h)
+            .take(n)
+            .collect()
+    }
+
+    // long_hash of a seed from the caller, rejected if shorter than
+    // MIN_ENTROPY_BYTES. Only the length is checked, not the randomness.
+    pub fn from_entropy(entropy: &[u8]) -> Result<Hash, EntropyError> {
+        if entropy.len() < MIN_ENTROPY_BYTES {
+            return Err(EntropyError::TooShort);
+        }
+        Ok(long_hash(entropy))
+    }
+
+    // The 24-word BIP39 mnemonic with the 32 bytes as entropy, to back up a
+    // seed on paper. Unlike GravityKeyPair::from_bip39_mnemonic, no key
+    // derivation is involved: the words encode the bytes themselves.
+    #[cfg(feature = "bip39")]
+    pub fn to_mnemonic(&self) -> String {
+        bip39::Mnemonic::from_entropy(&self.h)
+            .expect("256 bits is a valid BIP39 entropy length")
+            .to_string()
+    }
+
+    // Inverse of to_mnemonic. Valid mnemonics of other lengths are rejected
+    // with BadWordCount, as they do not encode 32 bytes.
+    #[cfg(feature = "bip39")]
+    pub fn from_mnemonic(words: &str) -> Result<Hash, Bip39Error> {
+        let mnemonic = bip39::Mnemonic::parse(words)?;
+        let entropy = mnemonic.to_entropy();
+        if entropy.len() != config::HASH_SIZE {
+            return Err(Bip39Error::InvalidMnemonic(bip39::Error::BadWordCount(
+                mnemonic.word_count(),
+            )));
+        }
+        let mut h = Hash::default();
+        h.h.copy_from_slice(&entropy);
+        Ok(h)
+    }
+
+    // HMAC-SHA256 (RFC 2104) of data under key.
+    #[cfg(feature = "hmac")]
+    pub fn hmac_sha256(key: &[u8], data: &[u8]) -> Hash {
+        hmac(key, data)
+    }
+
+    // SHA-256 and SHA3-256 of data, for interoperability tests against other
+    // implementations. These are NOT the hashes of the scheme (Haraka in the
+    // trees, or the tweaked or SHAKE variants), so they do not match any node
+    // of a key or signature.
+    #[cfg(feature = "sha2")]
+    pub fn from_sha256(data: &[u8]) -> Hash {
+        long_hash(data)
+    }
+
+    #[cfg(feature = "sha3")]
+    pub fn from_sha3_256(data: &[u8]) -> Hash {
+        Hash {
+            h: <sha3::Sha3_256 as sha3::Digest>::digest(data).into(),
+        }
+    }
+
+    // Keccak-256 of data, the hash of Ethereum (which differs from SHA3-256 by
+    // its padding), e.g. for messages whose hash a smart contract recomputes.
+    #[cfg(feature = "keccak")]
+    pub fn keccak256(data: &[u8]) -> Hash {
+        Hash {
+            h: <sha3::Keccak256 as sha3::Digest>::digest(data).into(),
+        }
+    }
+
+    // HKDF-SHA256 (RFC 5869) with self as input keying material and no salt,
+    // expanded to output_len bytes. Panics if output_len exceeds 255 * 32, the
+    // maximum of HKDF.
+    pub fn kdf(&self, info: &[u8], output_len: usize) -> Vec<u8> {
+        hkdf_sha256(&self.h, info, output_len)
+    }
+
+    // The first n bytes. Panics if n exceeds HASH_SIZE.
+    pub fn truncate_to_bytes(&self, n: usize) -> Vec<u8> {
+        assert!(
+            n <= config::HASH_SIZE,
+            "cannot truncate a hash to more than its size"
+        );
+        self.h[..n].to_vec()
+    }
+
+    // The first `bits` bits as a big-endian integer, in [0, 2^bits). Panics if
+    // bits exceeds the width of usize.
+    pub fn as_index(&self, bits: usize) -> usize {
+        assert!(bits <= usize::BITS as usize, "index wider than usize");
+        if bits == 0 {
+            return 0;
+        }
+        let first = u64::from_be_bytes(*array_ref![self.h, 0, 8]);
+        (first >> (64 - bits)) as usize
+    }
+
+    // Bit `pos` of the hash as a big-endian integer, from its most significant
+    // bit: bit 7 - pos % 8 of byte pos / 8. Panics if pos is at least 8 *
+    // HASH_SIZE.
+    pub fn bit_at(&self, pos: usize) -> bool {
+        self.h[pos / 8] >> (7 - pos % 8) & 1 == 1
+    }
+
+    // Bits start..end, as in bit_at, as a big-endian integer. Panics unless
+    // start <= end <= 8 * HASH_SIZE and end - start <= 64.
+    pub fn bits_in_range(&self, start: usize, end: usize) -> u64 {
+        assert!(
+            start <= end && end <= 8 * config::HASH_SIZE,
+            "bit range out of the hash"
+        );
+        assert!(end - start <= 64, "bit range wider than u64");
+        (start..end).fold(0, |acc, pos| acc << 1 | self.bit_at(pos) as u64)
+    }
+
+    // Sentinel for uninitialized slots, like Hash::default() (all-zero). Honest
+    // hashes take either value with probability 2^-256.
+    pub const fn all_ones() -> Hash {
+        Hash {
+            h: [0xFF; config::HASH_SIZE],
         }
-        Some(hash)
+    }
+
+    pub fn is_all_ones(&self) -> bool {
+        self.h.iter().all(|&x| x == 0xFF)
     }
 }
 
-pub fn long_hash(src: &[u8]) -> Hash {
-    let digest = Sha256::digest(src);
+// Behind Hash::hmac_sha256, and used by Hash::kdf without the hmac feature.
+fn hmac(key: &[u8], data: &[u8]) -> Hash {
+    const BLOCK_BYTES: usize = 64;
+
+    // Keys longer than a block are hashed first.
+    let mut block = [0u8; BLOCK_BYTES];
+    if key.len() > BLOCK_BYTES {
+        block[..config::HASH_SIZE].copy_from_slice(&long_hash(key).h);
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Sha256::default();
+    inner.input(&block.map(|x| x ^ 0x36));
+    inner.input(data);
+    let inner = inner.result();
+
+    let mut outer = Sha256::default();
+    outer.input(&block.map(|x| x ^ 0x5c));
+    outer.input(&inner);
+    let digest = outer.result();
     Hash {
         h: *array_ref![digest, 0, config::HASH_SIZE],
     }
 }
 
-pub fn hash_n_to_n(dst: &mut Hash, src: &Hash) {
-    haraka256::haraka256::<6>(&mut dst.h, &src.h)
+// A missing salt is HASH_SIZE zero bytes, which HMAC pads like an empty key.
+fn hkdf_sha256(ikm: &[u8], info: &[u8], output_len: usize) -> Vec<u8> {
+    assert!(
+        output_len <= 255 * config::HASH_SIZE,
+        "HKDF output is limited to 255 blocks"
+    );
+    let prk = hmac(&[], ikm);
+    let mut output = Vec::with_capacity(output_len);
+    let mut block: Vec<u8> = Vec::new();
+    for i in 1..=output_len.div_ceil(config::HASH_SIZE) {
+        block.extend_from_slice(info);
+        block.push(i as u8);
+        let t = hmac(&prk.h, &block);
+        let n = (output_len - output.len()).min(config::HASH_SIZE);
+        output.extend_from_slice(&t.h[..n]);
+        block.clear();
+        block.extend_from_slice(&t.h);
+    }
+    output
+}
+
+impl HashBackend for Hash {
+    const SIZE: usize = config::HASH_SIZE;
+    type Prng = prng::Prng;
+    type Chains = [Hash; config::WOTS_ELL];
+
+    fn chains() -> Self::Chains {
+        [Hash::default(); config::WOTS_ELL]
+    }
+
+    #[inline(always)]
+    fn hash_n_to_n(dst: &mut Self, src: &Self) {
+        #[cfg(test)]
+        HARAKA256_COUNT.with(|c| c.set(c.get() + 1));
+        haraka256::haraka256::<6>(&mut dst.h, &src.h)
+    }
+
+    fn hash_n_to_n_x4(dst: &mut [Self; 4], src: &[Self; 4]) {
+        #[cfg(test)]
+        HARAKA256_COUNT.with(|c| c.set(c.get() + 4));
+        let mut out = [[0; 32]; 4];
+        haraka256::haraka256_x4::<6>(&mut out, &src.map(|x| x.h));
+        *dst = out.map(|h| Hash { h });
+    }
+
+    // Haraka-512 of the tweak and the input. The 2n-to-n function compresses the
+    // tweak with the left input first, so that an output cannot be targeted
+    // without a preimage under the tweak.
+    #[cfg(feature = "tweaked")]
+    fn hash_n_to_n_tweaked(dst: &mut Self, src: &Self, tweak: &[u8; TWEAK_BYTES]) {
+        #[cfg(test)]
+        HARAKA512_COUNT.with(|c| c.set(c.get() + 1));
+        haraka512::haraka512::<6>(&mut dst.h, tweak, &src.h)
+    }
+
+    #[cfg(feature = "tweaked")]
+    fn hash_2n_to_n_tweaked(dst: &mut Self, src0: &Self, src1: &Self, tweak: &[u8; TWEAK_BYTES]) {
+        #[cfg(test)]
+        HARAKA512_COUNT.with(|c| c.set(c.get() + 2));
+        let mut tmp = [0u8; 32];
+        haraka512::haraka512::<6>(&mut tmp, tweak, &src0.h);
+        haraka512::haraka512::<6>(&mut dst.h, &tmp, &src1.h)
+    }
+
+    fn hash_2n_to_n(dst: &mut Self, src0: &Self, src1: &Self) {
+        #[cfg(test)]
+        HARAKA512_COUNT.with(|c| c.set(c.get() + 1));
+        haraka512::haraka512::<6>(&mut dst.h, &src0.h, &src1.h)
+    }
+
+    fn hash_2n_to_n_x4(dst: &mut [Self; 4], src0: &[Self; 4], src1: &[Self; 4]) {
+        #[cfg(test)]
+        HARAKA512_COUNT.with(|c| c.set(c.get() + 4));
+        let mut out = [[0; 32]; 4];
+        haraka512::haraka512_x4::<6>(&mut out, &src0.map(|x| x.h), &src1.map(|x| x.h));
+        *dst = out.map(|h| Hash { h });
+    }
+
+    fn long_hash(src: &[u8]) -> Self {
+        long_hash(src)
+    }
+}
+
+// 48-byte instantiation on SHA-384, with a domain separation byte for each
+// function. This exercises the genericity of the construction, and is not a
+// specified parameter set.
+#[cfg(any(test, feature = "experimental"))]
+pub type Hash384 = Hash<48>;
+
+#[cfg(any(test, feature = "experimental"))]
+impl HashBackend for Hash384 {
+    const SIZE: usize = 48;
+    type Prng = prng::HashPrng<Hash384>;
+    // wots::ell::<Hash384>() chains.
+    type Chains = [Hash384; 2 * 48 + config::WOTS_CHKSUM];
+
+    fn chains() -> Self::Chains {
+        [Hash384::default(); 2 * 48 + config::WOTS_CHKSUM]
+    }
+
+    fn hash_n_to_n(dst: &mut Self, src: &Self) {
+        *dst = sha384(0, &[&src.h]);
+    }
+
+    fn hash_2n_to_n(dst: &mut Self, src0: &Self, src1: &Self) {
+        *dst = sha384(1, &[&src0.h, &src1.h]);
+    }
+
+    fn long_hash(src: &[u8]) -> Self {
+        sha384(2, &[src])
+    }
+
+    #[cfg(feature = "tweaked")]
+    fn hash_n_to_n_tweaked(dst: &mut Self, src: &Self, tweak: &[u8; TWEAK_BYTES]) {
+        *dst = sha384(3, &[tweak, &src.h]);
+    }
+
+    #[cfg(feature = "tweaked")]
+    fn hash_2n_to_n_tweaked(dst: &mut Self, src0: &Self, src1: &Self, tweak: &[u8; TWEAK_BYTES]) {
+        *dst = sha384(4, &[tweak, &src0.h, &src1.h]);
+    }
+}
+
+#[cfg(any(test, feature = "experimental"))]
+fn sha384(domain: u8, src: &[&[u8]]) -> Hash384 {
+    let mut hasher = Sha384::default();
+    hasher.input(&[domain]);
+    for x in src.iter() {
+        hasher.input(x);
+    }
+    let digest = hasher.result();
+    Hash {
+        h: *array_ref![digest, 0, 48],
+    }
+}
+
+// Resets secret values (hashes, seed bytes) to their default, with volatile
+// writes so that the wipe is not optimized away.
+pub(crate) fn wipe<T: Copy + Default>(values: &mut [T]) {
+    for x in values.iter_mut() {
+        // SAFETY: x is a valid, aligned element of values, and T is Copy, so
+        // overwriting it drops nothing.
+        unsafe { ptr::write_volatile(x, T::default()) };
+    }
+}
+
+pub fn long_hash(src: &[u8]) -> Hash {
+    let mut state = HashState::new();
+    state.update(src);
+    state.finalize()
+}
+
+// long_hash of the concatenation of the inputs to update.
+#[derive(Clone, Default)]
+pub struct HashState(Sha256);
+
+impl HashState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.input(data);
+    }
+
+    pub fn finalize(self) -> Hash {
+        let digest = self.0.result();
+        Hash {
+            h: *array_ref![digest, 0, config::HASH_SIZE],
+        }
+    }
+}
+
+// SHAKE256 of msg, output_len bytes. Unrelated to long_hash, which is SHA-256:
+// their outputs differ even on the first 32 bytes.
+#[cfg(feature = "shake256")]
+pub fn xof_hash(msg: &[u8], output_len: usize) -> Vec<u8> {
+    use sha3::digest::ExtendableOutput;
+    let mut output = vec![0u8; output_len];
+    sha3::Shake256::digest_xof(msg, &mut output);
+    output
+}
+
+#[inline(always)]
+pub fn hash_n_to_n<H: HashBackend>(dst: &mut H, src: &H) {
+    H::hash_n_to_n(dst, src)
 }
 
 #[cfg(test)]
-pub fn hash_n_to_n_ret(src: &Hash) -> Hash {
+pub fn hash_n_to_n_ret<H: HashBackend>(src: &H) -> H {
     let mut dst = Default::default();
     hash_n_to_n(&mut dst, src);
     dst
 }
 
-pub fn hash_2n_to_n(dst: &mut Hash, src0: &Hash, src1: &Hash) {
-    haraka512::haraka512::<6>(&mut dst.h, &src0.h, &src1.h)
+pub fn hash_2n_to_n<H: HashBackend>(dst: &mut H, src0: &H, src1: &H) {
+    H::hash_2n_to_n(dst, src0, src1)
 }
 
 #[inline(always)]
-pub fn hash_2n_to_n_ret(src0: &Hash, src1: &Hash) -> Hash {
+pub fn hash_2n_to_n_ret<H: HashBackend>(src0: &H, src1: &H) -> H {
     let mut dst = Default::default();
     hash_2n_to_n(&mut dst, src0, src1);
     dst
 }
 
+// hash_n_to_n at position (x, y) of `tweak`.
+#[inline(always)]
+pub fn hash_n_to_n_at<H: HashBackend>(dst: &mut H, src: &H, tweak: &Tweak, x: u32, y: u64) {
+    #[cfg(feature = "tweaked")]
+    if let Some(block) = tweak.block(x, y, 1) {
+        H::hash_n_to_n_tweaked(dst, src, &block);
+        return;
+    }
+    #[cfg(not(feature = "tweaked"))]
+    let _ = (tweak, x, y);
+    hash_n_to_n(dst, src)
+}
+
+// hash_2n_to_n at position (x, y) of `tweak`.
 #[inline(always)]
-pub fn hash_n_to_n_chain(dst: &mut Hash, src: &Hash, count: usize) {
+pub fn hash_2n_to_n_at<H: HashBackend>(
+    dst: &mut H,
+    src0: &H,
+    src1: &H,
+    tweak: &Tweak,
+    x: u32,
+    y: u64,
+) {
+    #[cfg(feature = "tweaked")]
+    if let Some(block) = tweak.block(x, y, 2) {
+        H::hash_2n_to_n_tweaked(dst, src0, src1, &block);
+        return;
+    }
+    #[cfg(not(feature = "tweaked"))]
+    let _ = (tweak, x, y);
+    hash_2n_to_n(dst, src0, src1)
+}
+
+#[inline(always)]
+pub fn hash_2n_to_n_at_ret<H: HashBackend>(src0: &H, src1: &H, tweak: &Tweak, x: u32, y: u64) -> H {
+    let mut dst = Default::default();
+    hash_2n_to_n_at(&mut dst, src0, src1, tweak, x, y);
+    dst
+}
+
+#[inline(always)]
+pub fn hash_n_to_n_chain<H: HashBackend>(dst: &mut H, src: &H, count: usize) {
+    hash_n_to_n_chain_at(dst, src, count, &Tweak::NONE, 0);
+}
+
+// Chain number `chain` of `tweak`, from step 0.
+#[inline(always)]
+pub fn hash_n_to_n_chain_at<H: HashBackend>(
+    dst: &mut H,
+    src: &H,
+    count: usize,
+    tweak: &Tweak,
+    chain: usize,
+) {
     *dst = *src;
-    for _ in 0..count {
+    for step in 0..count {
         let tmp = *dst;
-        hash_n_to_n(dst, &tmp);
+        hash_n_to_n_at(dst, &tmp, tweak, chain as u32, step as u64);
     }
+    inject_fault(dst);
 }
 
 #[cfg(test)]
-pub fn hash_n_to_n_chain_ret(src: &Hash, count: usize) -> Hash {
+pub fn hash_n_to_n_chain_ret<H: HashBackend>(src: &H, count: usize) -> H {
     let mut dst = Default::default();
     hash_n_to_n_chain(&mut dst, src, count);
     dst
 }
 
+// Iterates over the successive values H(seed), H(H(seed)), ... of a hash chain.
+pub struct HashChain {
+    current: Hash,
+    remaining: usize,
+}
+
+impl HashChain {
+    pub fn new(seed: &Hash, steps: usize) -> Self {
+        Self {
+            current: *seed,
+            remaining: steps,
+        }
+    }
+
+    pub fn skip_to(seed: &Hash, steps: usize) -> Hash {
+        let mut dst = Default::default();
+        hash_n_to_n_chain(&mut dst, seed, steps);
+        dst
+    }
+}
+
+impl Iterator for HashChain {
+    type Item = Hash;
+
+    fn next(&mut self) -> Option<Hash> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let tmp = self.current;
+        hash_n_to_n(&mut self.current, &tmp);
+        Some(self.current)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for HashChain {}
+
+// Number of calls to each compression function on the current thread, to check
+// the work done in tests.
+#[cfg(test)]
+thread_local! {
+    static HARAKA256_COUNT: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    static HARAKA512_COUNT: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    static FAULT: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+// Flips a bit of the next chain output once tests::inject_next_fault was called.
 #[inline(always)]
-pub fn hash_parallel(dst: &mut [Hash], src: &[Hash], count: usize) {
+fn inject_fault<H: HashBackend>(_dst: &mut H) {
+    #[cfg(test)]
+    if FAULT.with(|f| f.replace(false)) {
+        _dst.as_mut()[0] ^= 1;
+    }
+}
+
+// Whether the hashes of `tweak` can go through the four-lane functions of the
+// backend, which are untweaked.
+#[inline(always)]
+fn batched(tweak: &Tweak) -> bool {
+    !cfg!(feature = "tweaked") || *tweak == Tweak::NONE
+}
+
+// The leaves of a tree, at height 0 of `tweak`.
+#[inline(always)]
+pub fn hash_parallel<H: HashBackend>(dst: &mut [H], src: &[H], count: usize, tweak: &Tweak) {
     for i in 0..count {
-        hash_n_to_n(&mut dst[i], &src[i]);
+        hash_n_to_n_at(&mut dst[i], &src[i], tweak, 0, i as u64);
     }
 }
 
 #[inline(always)]
-pub fn hash_parallel_all(dst: &mut [Hash], src: &[Hash]) {
+pub fn hash_parallel_all<H: HashBackend>(dst: &mut [H], src: &[H], tweak: &Tweak) {
     let count = dst.len();
-    hash_parallel(dst, src, count);
+    hash_parallel(dst, src, count, tweak);
 }
 
 #[inline(always)]
 #[allow(clippy::needless_range_loop)]
-fn hash_parallel_chains(dst: &mut [Hash], src: &[Hash], count: usize, chainlen: usize) {
+fn hash_parallel_chains<H: HashBackend>(
+    dst: &mut [H],
+    src: &[H],
+    count: usize,
+    chainlen: usize,
+    tweak: &Tweak,
+) {
     dst[..count].copy_from_slice(&src[..count]);
-    for _ in 0..chainlen {
-        for i in 0..count {
+    let mut first = 0;
+    if batched(tweak) {
+        first = count - count % 4;
+        for _ in 0..chainlen {
+            for lanes in dst[..first].chunks_exact_mut(4) {
+                let lanes: &mut [H; 4] = lanes.try_into().unwrap();
+                let tmp = *lanes;
+                H::hash_n_to_n_x4(lanes, &tmp);
+            }
+        }
+    }
+    for step in 0..chainlen {
+        for i in first..count {
             let tmp = dst[i];
-            hash_n_to_n(&mut dst[i], &tmp);
+            hash_n_to_n_at(&mut dst[i], &tmp, tweak, i as u32, step as u64);
         }
     }
 }
 
+// Chain i of `tweak` from step 0 for each src[i].
 #[inline(always)]
-pub fn hash_parallel_chains_all(dst: &mut [Hash], src: &[Hash], chainlen: usize) {
+pub fn hash_parallel_chains_all<H: HashBackend>(
+    dst: &mut [H],
+    src: &[H],
+    chainlen: usize,
+    tweak: &Tweak,
+) {
     let count = dst.len();
-    hash_parallel_chains(dst, src, count, chainlen);
+    hash_parallel_chains(dst, src, count, chainlen, tweak);
 }
 
+// Advances the independent chains states[i], chain first_chain + i of `tweak`,
+// from step steps[i] to `end`, leaving steps at `end`. Four chains are hashed at
+// once with hash_n_to_n_x4, taking the longest ones first so that few hashes are
+// left once fewer than four chains are running. Tweaked hashes are not batched.
+pub fn hash_parallel_chains_ragged<H: HashBackend>(
+    states: &mut [H],
+    steps: &mut [usize],
+    end: usize,
+    tweak: &Tweak,
+    first_chain: usize,
+) {
+    if batched(tweak) {
+        hash_chains_x4(states, steps, end);
+    }
+
+    for (i, (state, step)) in states.iter_mut().zip(steps.iter_mut()).enumerate() {
+        while *step < end {
+            let tmp = *state;
+            hash_n_to_n_at(state, &tmp, tweak, (first_chain + i) as u32, *step as u64);
+            *step += 1;
+        }
+    }
+}
+
+// Batched part of hash_parallel_chains_ragged, while at least four chains are
+// running.
+fn hash_chains_x4<H: HashBackend>(states: &mut [H], steps: &mut [usize], end: usize) {
+    loop {
+        let mut lanes = [0usize; 4];
+        let mut count = 0;
+        while count < 4 {
+            let longest = (0..steps.len())
+                .filter(|i| steps[*i] < end && !lanes[..count].contains(i))
+                .min_by_key(|i| steps[*i]);
+            match longest {
+                Some(i) => lanes[count] = i,
+                None => break,
+            }
+            count += 1;
+        }
+        if count < 4 {
+            break;
+        }
+
+        let src = lanes.map(|i| states[i]);
+        let mut dst = src;
+        H::hash_n_to_n_x4(&mut dst, &src);
+        for (&i, h) in lanes.iter().zip(dst.iter()) {
+            states[i] = *h;
+            steps[i] += 1;
+        }
+    }
+}
+
+// Parents at `height` of `tweak`, the first one at index `first`.
 #[inline(always)]
-pub fn hash_compress_pairs(dst: &mut [Hash], src: &[Hash], count: usize) {
-    for i in 0..count {
-        hash_2n_to_n(&mut dst[i], &src[2 * i], &src[2 * i + 1]);
+pub fn hash_compress_pairs<H: HashBackend>(
+    dst: &mut [H],
+    src: &[H],
+    count: usize,
+    tweak: &Tweak,
+    height: u32,
+    first: u64,
+) {
+    let mut batch = 0;
+    if batched(tweak) {
+        batch = count - count % 4;
+        for i in (0..batch).step_by(4) {
+            let src0 = [0, 2, 4, 6].map(|j| src[2 * i + j]);
+            let src1 = [1, 3, 5, 7].map(|j| src[2 * i + j]);
+            H::hash_2n_to_n_x4(array_mut_ref![dst, i, 4], &src0, &src1);
+        }
+    }
+    for i in batch..count {
+        hash_2n_to_n_at(
+            &mut dst[i],
+            &src[2 * i],
+            &src[2 * i + 1],
+            tweak,
+            height,
+            first + i as u64,
+        );
+    }
+}
+
+// The Haraka v2 permutations with truncated feed-forward (Kölbl et al., "Haraka
+// v2 - Efficient Short-Input Hashing for Post-Quantum Applications"). The
+// signatures use 6 rounds instead of the 5 of Haraka v2, so haraka256 and
+// haraka512 do not match the published test vectors; haraka256_v2 and
+// haraka512_v2 are the 5-round functions of the paper.
+pub mod primitives {
+    use crate::primitives::{haraka256, haraka512};
+    use arrayref::array_ref;
+
+    pub fn haraka256(input: &[u8; 32]) -> [u8; 32] {
+        let mut output = [0u8; 32];
+        haraka256::haraka256::<6>(&mut output, input);
+        output
+    }
+
+    pub fn haraka512(input: &[u8; 64]) -> [u8; 32] {
+        let mut output = [0u8; 32];
+        haraka512::haraka512::<6>(
+            &mut output,
+            array_ref![input, 0, 32],
+            array_ref![input, 32, 32],
+        );
+        output
+    }
+
+    pub fn haraka256_v2(input: &[u8; 32]) -> [u8; 32] {
+        let mut output = [0u8; 32];
+        haraka256::haraka256::<5>(&mut output, input);
+        output
+    }
+
+    pub fn haraka512_v2(input: &[u8; 64]) -> [u8; 32] {
+        let mut output = [0u8; 32];
+        haraka512::haraka512::<5>(
+            &mut output,
+            array_ref![input, 0, 32],
+            array_ref![input, 32, 32],
+        );
+        output
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::hash::{self, Hash};
+
+        fn counting<const N: usize>() -> [u8; N] {
+            let mut input = [0u8; N];
+            for (i, x) in input.iter_mut().enumerate() {
+                *x = i as u8;
+            }
+            input
+        }
+
+        // Test vectors of the Haraka v2 reference implementation.
+        #[test]
+        fn test_haraka_v2() {
+            assert_eq!(
+                hex::encode(haraka256_v2(&counting())),
+                "8027ccb87949774b78d0545fb72bf70c695c2a0923cbd47bba1159efbf2b2c1c"
+            );
+            assert_eq!(
+                hex::encode(haraka512_v2(&counting())),
+                "be7f723b4e80a99813b292287f306f625a6d57331cae5f34dd9277b0945be2aa"
+            );
+        }
+
+        #[test]
+        fn test_haraka_6round() {
+            let input = counting::<64>();
+            assert_eq!(
+                hex::encode(haraka256(array_ref![input, 0, 32])),
+                "dd90045b92993274fff8ccf46903d1c8184b404cc83735551c80a72b5fb32045"
+            );
+            assert_eq!(
+                hex::encode(haraka512(&input)),
+                "0e27514e8ab7b4ee153c9a5413fb1e984a914f5b6fea17228541ce1707fc4e64"
+            );
+        }
+
+        // The untweaked hashes of the signatures.
+        #[test]
+        fn test_hash_backend() {
+            let input = counting::<64>();
+            let src0 = Hash {
+                h: *array_ref![input, 0, 32],
+            };
+            let src1 = Hash {
+                h: *array_ref![input, 32, 32],
+            };
+            let mut dst = Hash::default();
+            hash::hash_n_to_n(&mut dst, &src0);
+            assert_eq!(dst.h, haraka256(&src0.h));
+            hash::hash_2n_to_n(&mut dst, &src0, &src1);
+            assert_eq!(dst.h, haraka512(&input));
+        }
     }
 }
 
@@ -131,6 +1075,61 @@ pub mod tests {
               \x18\x19\x1a\x1b\x1c\x1d\x1e\x1f",
     };
 
+    pub fn hash_count() -> usize {
+        haraka256_count() + haraka512_count()
+    }
+
+    pub fn haraka256_count() -> usize {
+        HARAKA256_COUNT.with(|c| c.get())
+    }
+
+    pub fn haraka512_count() -> usize {
+        HARAKA512_COUNT.with(|c| c.get())
+    }
+
+    pub fn inject_next_fault() {
+        FAULT.with(|f| f.set(true))
+    }
+
+    // Dudect-style threshold on Welch's t-statistic above which a timing leak is
+    // almost certain.
+    pub const DUDECT_THRESHOLD: f64 = 10.0;
+
+    // Times `f` on a fixed secret and on varying secrets, in a pseudo-random
+    // interleaving, and returns Welch's t-statistic between both distributions.
+    // The slowest 10% of measurements are discarded as interrupts and noise.
+    pub fn dudect_t_statistic<T, F>(iterations: usize, mut f: F) -> f64
+    where
+        F: FnMut(&Hash) -> T,
+    {
+        use std::time::Instant;
+
+        let fixed = HASH_ELEMENT;
+        let mut state = hash_n_to_n_ret(&fixed);
+        let mut samples: [Vec<f64>; 2] = [vec![], vec![]];
+        for _ in 0..iterations {
+            state = hash_n_to_n_ret(&state);
+            let class = (state.h[0] & 1) as usize;
+            let secret = if class == 0 { fixed } else { state };
+
+            let start = Instant::now();
+            std::hint::black_box(f(std::hint::black_box(&secret)));
+            samples[class].push(start.elapsed().as_nanos() as f64);
+        }
+
+        let mut stats = [(0.0, 0.0, 0.0); 2];
+        for (s, st) in samples.iter_mut().zip(stats.iter_mut()) {
+            s.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            s.truncate(s.len() * 9 / 10);
+            let n = s.len() as f64;
+            let mean = s.iter().sum::<f64>() / n;
+            let var = s.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / (n - 1.0);
+            *st = (n, mean, var);
+        }
+        let ((n0, m0, v0), (n1, m1, v1)) = (stats[0], stats[1]);
+        (m0 - m1) / (v0 / n0 + v1 / n1).sqrt()
+    }
+
     #[test]
     fn test_chain_0() {
         let src = HASH_ELEMENT;
@@ -164,15 +1163,304 @@ pub mod tests {
         assert_eq!(dst, expect);
     }
 
+    #[test]
+    fn test_hash_chain() {
+        let seed = HASH_ELEMENT;
+        assert_eq!(HashChain::skip_to(&seed, 0), seed);
+        assert_eq!(HashChain::new(&seed, 0).next(), None);
+
+        let chain: Vec<Hash> = HashChain::new(&seed, 5).collect();
+        assert_eq!(chain.len(), 5);
+        assert_eq!(chain[0], hash_n_to_n_ret(&seed));
+        for n in 1..=5 {
+            assert_eq!(HashChain::skip_to(&seed, n), chain[n - 1]);
+            assert_eq!(HashChain::new(&seed, 5).nth(n - 1), Some(chain[n - 1]));
+        }
+    }
+
+    #[test]
+    fn test_concat_and_hash() {
+        let a = HASH_ELEMENT;
+        let b = hash_n_to_n_ret(&a);
+        let mut bytes = a.h.to_vec();
+        bytes.extend(b.h.iter());
+        assert_eq!(Hash::concat_and_hash(&[a, b]), long_hash(&bytes));
+        assert_eq!(Hash::concat_and_hash(&[]), long_hash(&[]));
+        assert_ne!(
+            Hash::concat_and_hash(&[a, b]),
+            Hash::concat_and_hash(&[b, a])
+        );
+    }
+
+    // Against hashlib.sha256 and hashlib.sha3_256 in Python.
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn test_from_sha256() {
+        assert_eq!(
+            hex::encode(Hash::from_sha256(b"abc").h),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex::encode(Hash::from_sha256(b"").h),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_ne!(
+            Hash::from_sha256(&HASH_ELEMENT.h),
+            hash_n_to_n_ret(&HASH_ELEMENT)
+        );
+    }
+
+    #[cfg(feature = "sha3")]
+    #[test]
+    fn test_from_sha3_256() {
+        assert_eq!(
+            hex::encode(Hash::from_sha3_256(b"abc").h),
+            "3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532"
+        );
+        assert_eq!(
+            hex::encode(Hash::from_sha3_256(b"").h),
+            "a7ffc6f8bf1ed76651c14756a061d662f580ff4de43b49fa82d80a4b80f8434a"
+        );
+    }
+
+    // Ethereum: the empty hash, and the ERC-20 transfer function selector and
+    // Transfer event topic. SHA3-256 of "abc" differs by its padding.
+    #[cfg(feature = "keccak")]
+    #[test]
+    fn test_keccak256() {
+        assert_eq!(
+            hex::encode(Hash::keccak256(b"").h),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+        assert_eq!(
+            hex::encode(Hash::keccak256(b"transfer(address,uint256)").h),
+            "a9059cbb2ab09eb219583f4a59a5d0623ade346d962bcd4e46b11da047c9049b"
+        );
+        assert_eq!(
+            hex::encode(Hash::keccak256(b"Transfer(address,address,uint256)").h),
+            "ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"
+        );
+        assert_ne!(
+            hex::encode(Hash::keccak256(b"abc").h),
+            "3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532"
+        );
+    }
+
+    #[test]
+    fn test_hash_state() {
+        let msg: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        assert_eq!(long_hash(&msg).h[..], Sha256::digest(&msg)[..]);
+        for split in [0, 1, 64, 999, 1000] {
+            let mut hasher = HashState::new();
+            hasher.update(&msg[..split]);
+            hasher.update(&[]);
+            hasher.update(&msg[split..]);
+            assert_eq!(hasher.finalize(), long_hash(&msg));
+        }
+        assert_eq!(HashState::new().finalize(), long_hash(&[]));
+    }
+
+    #[test]
+    fn test_wipe() {
+        let mut bytes = [0xAAu8; 64];
+        wipe(&mut bytes);
+        assert_eq!(bytes, [0u8; 64]);
+        let mut hashes = [HASH_ELEMENT; 3];
+        wipe(&mut hashes[1..]);
+        assert_eq!(hashes, [HASH_ELEMENT, Hash::default(), Hash::default()]);
+    }
+
+    #[test]
+    fn test_derive_subkeys() {
+        let keys = HASH_ELEMENT.derive_subkeys(100);
+        assert_eq!(keys.len(), 100);
+        for i in 0..keys.len() {
+            for j in 0..i {
+                assert_ne!(keys[i], keys[j]);
+            }
+        }
+        assert_eq!(HASH_ELEMENT.derive_subkeys(100), keys);
+        assert_eq!(HASH_ELEMENT.derive_subkeys(10), keys[..10]);
+        assert!(HASH_ELEMENT.derive_subkeys(0).is_empty());
+
+        let mut bytes = HASH_ELEMENT.h.to_vec();
+        bytes.extend(0u64.to_le_bytes().iter());
+        assert_eq!(HASH_ELEMENT.derive_subkeys(1), [long_hash(&bytes)]);
+    }
+
+    #[test]
+    fn test_extend_to_vec() {
+        let block = |i: u64| {
+            let mut bytes = HASH_ELEMENT.h.to_vec();
+            bytes.extend(i.to_le_bytes().iter());
+            long_hash(&bytes)
+        };
+        let output = HASH_ELEMENT.extend_to_vec(64);
+        assert_eq!(output[..32], block(0).h);
+        assert_eq!(output[32..], block(1).h);
+
+        let long = HASH_ELEMENT.extend_to_vec(100);
+        assert_eq!(long[..64], output);
+        assert_eq!(long[96..], block(3).h[..4]);
+        assert_eq!(HASH_ELEMENT.extend_to_vec(5), output[..5]);
+        assert!(HASH_ELEMENT.extend_to_vec(0).is_empty());
+    }
+
+    #[test]
+    fn test_from_entropy() {
+        let entropy = [0x5a; 32];
+        assert_eq!(Hash::from_entropy(&[]), Err(EntropyError::TooShort));
+        assert_eq!(
+            Hash::from_entropy(&entropy[..15]),
+            Err(EntropyError::TooShort)
+        );
+        assert_eq!(
+            Hash::from_entropy(&entropy[..16]),
+            Ok(long_hash(&entropy[..16]))
+        );
+        assert_eq!(Hash::from_entropy(&entropy), Ok(long_hash(&entropy)));
+    }
+
+    #[test]
+    fn test_hex_array() {
+        let h = long_hash(b"abc");
+        let arr = h.to_hex_array();
+        assert_eq!(&arr[..], hex::encode(h.h).as_bytes());
+        assert_eq!(Hash::from_hex_array(&arr), Ok(h));
+        assert_eq!(
+            Hash::from_hex_array(&arr.to_ascii_uppercase().try_into().unwrap()),
+            Ok(h)
+        );
+        assert_eq!(
+            Hash::all_ones().to_hex_array(),
+            [b'f'; 2 * config::HASH_SIZE]
+        );
+
+        let mut bad = arr;
+        bad[9] = b'g';
+        assert_eq!(
+            Hash::from_hex_array(&bad),
+            Err(HexError::InvalidCharacter { c: 'g', index: 9 })
+        );
+        bad[9] = 0xC3;
+        assert_eq!(
+            Hash::from_hex_array(&bad),
+            Err(HexError::InvalidCharacter {
+                c: '\u{c3}',
+                index: 9
+            })
+        );
+    }
+
+    #[test]
+    fn test_from_slice_truncated() {
+        let h = Hash::from_slice_truncated(b"abcd");
+        assert_eq!(&h.h[..4], b"abcd");
+        assert!(h.h[4..].iter().all(|&x| x == 0));
+        assert_eq!(Hash::from_slice_truncated(&[]), Hash::default());
+
+        let long: Vec<u8> = (0..64).collect();
+        let h = Hash::from_slice_truncated(&long);
+        assert_eq!(&h.h[..], &long[..config::HASH_SIZE]);
+        assert_eq!(Hash::from_slice_truncated(&long[..config::HASH_SIZE]), h);
+    }
+
+    #[test]
+    fn test_from_u64() {
+        assert_ne!(Hash::from_u64(0), Hash::from_u64(1));
+        assert_ne!(Hash::from_u32(0), Hash::from_u32(1));
+        for n in [0, 1, 0xFFFF_FFFF] {
+            assert_ne!(Hash::from_u64(n as u64), Hash::from_u32(n));
+        }
+        assert_ne!(Hash::from_u64(1), Hash::from_u64(1 << 32));
+
+        let mut src = Hash::default();
+        src.h[24] = 0x2a;
+        assert_eq!(Hash::from_u64(0x2a), hash_n_to_n_ret(&src));
+        src.h[0] = 0x01;
+        assert_eq!(Hash::from_u32(0x2a), hash_n_to_n_ret(&src));
+    }
+
+    #[test]
+    fn test_bytes() {
+        let mut h = HASH_ELEMENT;
+        assert_eq!(Hash::len(), 32);
+        assert_eq!(h.as_bytes(), &HASH_ELEMENT.h);
+        assert_eq!(h.as_ref(), &HASH_ELEMENT.h[..]);
+        h.as_mut_bytes()[0] ^= 1;
+        h.as_mut()[1] ^= 1;
+        assert_eq!(
+            h.as_bytes()[..2],
+            [HASH_ELEMENT.h[0] ^ 1, HASH_ELEMENT.h[1] ^ 1]
+        );
+        assert_eq!(h.as_bytes()[2..], HASH_ELEMENT.h[2..]);
+    }
+
+    #[test]
+    fn test_pair_hash() {
+        let a = HASH_ELEMENT;
+        let b = hash_n_to_n_ret(&a);
+        assert_ne!(Hash::pair_hash(&a, &b), Hash::pair_hash(&b, &a));
+        assert_eq!(
+            Hash::pair_hash(&a, &b),
+            crate::merkle::merkle_compress_all_leaves(&[a, b], 1, &Tweak::NONE)
+        );
+        assert_eq!(
+            Hash::pair_hash(&a, &b),
+            crate::merkle::root_from_auth(&a, &[b], 0, &Tweak::NONE)
+        );
+    }
+
     #[test]
     fn test_parallel() {
         let src = [HASH_ELEMENT; 5];
         let expect = hash_n_to_n_ret(&HASH_ELEMENT);
         let mut dst = [Default::default(); 5];
-        hash_parallel_all(&mut dst, &src);
+        hash_parallel_all(&mut dst, &src, &Tweak::NONE);
         assert_eq!(dst, [expect; 5]);
     }
 
+    #[test]
+    fn test_parallel_chains_ragged() {
+        for lengths in [
+            vec![],
+            vec![3],
+            vec![0, 5, 1, 15, 15, 2, 0, 7, 9, 4],
+            vec![6; 9],
+        ] {
+            let src: Vec<Hash> = (0..lengths.len())
+                .map(|i| hash_n_to_n_chain_ret(&HASH_ELEMENT, i))
+                .collect();
+            let expect: Vec<Hash> = src
+                .iter()
+                .zip(lengths.iter())
+                .map(|(x, &length)| hash_n_to_n_chain_ret(x, length))
+                .collect();
+
+            let total: usize = lengths.iter().sum();
+            let mut states = src.clone();
+            let mut steps: Vec<usize> = lengths.iter().map(|x| 15 - x).collect();
+            let start = haraka256_count();
+            hash_parallel_chains_ragged(&mut states, &mut steps, 15, &Tweak::NONE, 0);
+            assert_eq!(haraka256_count() - start, total);
+            assert_eq!(states, expect);
+            assert!(steps.iter().all(|&x| x == 15));
+        }
+    }
+
+    #[test]
+    fn test_hash_n_to_n_x4() {
+        let src = [0, 1, 2, 3].map(|i| hash_n_to_n_chain_ret(&HASH_ELEMENT, i));
+        let mut dst = [Hash::default(); 4];
+        Hash::hash_n_to_n_x4(&mut dst, &src);
+        assert_eq!(dst, src.map(|x| hash_n_to_n_ret(&x)));
+
+        let src = [0, 1, 2, 3].map(|i| hash_n_to_n_chain_ret(&Hash384::default(), i));
+        let mut dst = [Hash384::default(); 4];
+        Hash384::hash_n_to_n_x4(&mut dst, &src);
+        assert_eq!(dst, src.map(|x| hash_n_to_n_ret(&x)));
+    }
+
     #[test]
     fn test_parallel_mix() {
         let h0 = HASH_ELEMENT;
@@ -185,7 +1473,7 @@ pub mod tests {
         let src = [h0, h1, h2, h3, h4];
         let expect = [h1, h2, h3, h4, h5];
         let mut dst = [Default::default(); 5];
-        hash_parallel_all(&mut dst, &src);
+        hash_parallel_all(&mut dst, &src, &Tweak::NONE);
         assert_eq!(dst, expect);
     }
 
@@ -193,7 +1481,7 @@ pub mod tests {
     fn test_parallel_chains_0() {
         let src = [HASH_ELEMENT; 5];
         let mut dst = [Default::default(); 5];
-        hash_parallel_chains_all(&mut dst, &src, 0);
+        hash_parallel_chains_all(&mut dst, &src, 0, &Tweak::NONE);
         assert_eq!(dst, src);
     }
 
@@ -202,7 +1490,7 @@ pub mod tests {
         let src = [HASH_ELEMENT; 5];
         let expect = hash_n_to_n_ret(&HASH_ELEMENT);
         let mut dst = [Default::default(); 5];
-        hash_parallel_chains_all(&mut dst, &src, 1);
+        hash_parallel_chains_all(&mut dst, &src, 1, &Tweak::NONE);
         assert_eq!(dst, [expect; 5]);
     }
 
@@ -211,7 +1499,7 @@ pub mod tests {
         let src = [HASH_ELEMENT; 5];
         let expect = hash_n_to_n_ret(&hash_n_to_n_ret(&hash_n_to_n_ret(&HASH_ELEMENT)));
         let mut dst = [Default::default(); 5];
-        hash_parallel_chains_all(&mut dst, &src, 3);
+        hash_parallel_chains_all(&mut dst, &src, 3, &Tweak::NONE);
         assert_eq!(dst, [expect; 5]);
     }
 
@@ -221,7 +1509,7 @@ pub mod tests {
         let expect = hash_2n_to_n_ret(&HASH_ELEMENT, &HASH_ELEMENT);
 
         let mut dst = [Default::default(); 1];
-        hash_compress_pairs(&mut dst, &src, 1);
+        hash_compress_pairs(&mut dst, &src, 1, &Tweak::NONE, 1, 0);
 
         assert_eq!(dst, [expect]);
     }
@@ -232,11 +1520,222 @@ pub mod tests {
         let expect = hash_2n_to_n_ret(&HASH_ELEMENT, &HASH_ELEMENT);
 
         let mut dst = [Default::default(); 2];
-        hash_compress_pairs(&mut dst, &src, 2);
+        hash_compress_pairs(&mut dst, &src, 2, &Tweak::NONE, 1, 0);
 
         assert_eq!(dst, [expect, expect]);
     }
 
+    // Test cases 1, 2, 4 and 6 of RFC 4231.
+    #[cfg(feature = "bip39")]
+    #[test]
+    fn test_mnemonic() {
+        // BIP39 test vector of the all-zero 256-bit entropy.
+        let zeros = Hash::default().to_mnemonic();
+        assert_eq!(zeros.split(' ').count(), 24);
+        assert!(zeros.starts_with("abandon abandon"));
+        assert!(zeros.ends_with("abandon art"));
+
+        for h in [Hash::default(), HASH_ELEMENT, Hash { h: [0xff; 32] }] {
+            assert_eq!(Hash::from_mnemonic(&h.to_mnemonic()), Ok(h));
+        }
+
+        let mut words: Vec<&str> = zeros.split(' ').collect();
+        words[5] = "abandonx";
+        words[9] = "gravity2";
+        assert_eq!(
+            Hash::from_mnemonic(&words.join(" ")),
+            Err(Bip39Error::UnknownWord(5))
+        );
+        assert_eq!(
+            Bip39Error::UnknownWord(5).to_string(),
+            "unknown word at index 5"
+        );
+
+        // A valid 12-word mnemonic encodes 16 bytes only.
+        assert_eq!(
+            Hash::from_mnemonic(
+                "abandon abandon abandon abandon abandon abandon \
+                 abandon abandon abandon abandon abandon about"
+            ),
+            Err(Bip39Error::InvalidMnemonic(bip39::Error::BadWordCount(12)))
+        );
+        let mut words: Vec<&str> = zeros.split(' ').collect();
+        words[23] = "abandon";
+        assert_eq!(
+            Hash::from_mnemonic(&words.join(" ")),
+            Err(Bip39Error::InvalidMnemonic(bip39::Error::InvalidChecksum))
+        );
+    }
+
+    #[cfg(feature = "hmac")]
+    #[test]
+    fn test_hmac_sha256() {
+        let cases: [(Vec<u8>, &[u8], &str); 4] = [
+            (
+                vec![0x0b; 20],
+                b"Hi There",
+                "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+            ),
+            (
+                b"Jefe".to_vec(),
+                b"what do ya want for nothing?",
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            ),
+            (
+                (1..=25).collect(),
+                &[0xcd; 50],
+                "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b",
+            ),
+            (
+                vec![0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First",
+                "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+            ),
+        ];
+        for (key, data, expect) in cases.iter() {
+            assert_eq!(hex::encode(Hash::hmac_sha256(key, data).h), *expect);
+        }
+    }
+
+    #[test]
+    fn test_kdf() {
+        // RFC 5869, test case 3 (no salt, empty info).
+        assert_eq!(
+            hex::encode(hkdf_sha256(&[0x0b; 22], &[], 42)),
+            "8da4e775a563c18f715f802a063c5a31b8a11f5c5ee1879ec3454e5f3c738d2d9d201395faa4b61a96c8"
+        );
+
+        let seed = HASH_ELEMENT;
+        for len in [0, 1, 32, 33, 100, 255 * 32].iter() {
+            assert_eq!(seed.kdf(b"info", *len).len(), *len);
+        }
+        let output = seed.kdf(b"info", 64);
+        assert_eq!(seed.kdf(b"info", 40), output[..40]);
+        assert_ne!(seed.kdf(b"other info", 64), output);
+        assert_ne!(seed.kdf(b"", 64), output);
+        assert_ne!(Hash::default().kdf(b"info", 64), output);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_kdf_too_long() {
+        HASH_ELEMENT.kdf(b"info", 255 * 32 + 1);
+    }
+
+    #[test]
+    fn test_truncate_to_bytes() {
+        for n in [0, 1, 16, 32] {
+            assert_eq!(HASH_ELEMENT.truncate_to_bytes(n), HASH_ELEMENT.h[..n]);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_truncate_to_bytes_too_long() {
+        HASH_ELEMENT.truncate_to_bytes(33);
+    }
+
+    #[test]
+    fn test_as_index() {
+        let mut h = Hash::default();
+        h.h[..3].copy_from_slice(&[0xAB, 0xCD, 0xEF]);
+        assert_eq!(h.as_index(0), 0);
+        assert_eq!(h.as_index(1), 1);
+        assert_eq!(h.as_index(8), 0xAB);
+        assert_eq!(h.as_index(12), 0xABC);
+        assert_eq!(h.as_index(24), 0xABCDEF);
+        assert_eq!(h.as_index(32), 0xABCDEF00);
+        for i in 0..=255u8 {
+            h.h[0] = i;
+            assert!(h.as_index(8) < 256);
+            assert_eq!(h.as_index(8), i as usize);
+        }
+        assert_eq!(
+            Hash { h: [0xFF; 32] }.as_index(usize::BITS as usize),
+            usize::MAX
+        );
+    }
+
+    #[test]
+    fn test_bits() {
+        let mut h = Hash::default();
+        h.h[..3].copy_from_slice(&[0xAB, 0xCD, 0xEF]);
+        // 0xAB = 0b10101011
+        let first: Vec<bool> = (0..8).map(|i| h.bit_at(i)).collect();
+        assert_eq!(first, [true, false, true, false, true, false, true, true]);
+        assert!(h.bit_at(8) && h.bit_at(15) && !h.bit_at(10));
+        assert!(!h.bit_at(255));
+
+        assert_eq!(h.bits_in_range(0, 8), h.h[0] as u64);
+        assert_eq!(h.bits_in_range(4, 12), 0xBC);
+        assert_eq!(h.bits_in_range(3, 6), 0b010);
+        assert_eq!(h.bits_in_range(5, 5), 0);
+        assert_eq!(h.bits_in_range(0, 64), 0xABCDEF << 40);
+        for bits in 0..=usize::BITS as usize {
+            assert_eq!(
+                HASH_ELEMENT.bits_in_range(0, bits),
+                HASH_ELEMENT.as_index(bits) as u64
+            );
+        }
+        let ones = Hash { h: [0xFF; 32] };
+        assert_eq!(ones.bits_in_range(192, 256), u64::MAX);
+        assert_eq!(ones.bits_in_range(250, 256), 0x3F);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_bits_in_range_too_wide() {
+        HASH_ELEMENT.bits_in_range(0, 65);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_bit_at_out_of_range() {
+        HASH_ELEMENT.bit_at(256);
+    }
+
+    #[test]
+    fn test_all_ones() {
+        let ones = Hash::all_ones();
+        assert_eq!(ones.h, [0xFF; 32]);
+        assert!(ones.is_all_ones());
+        assert!(!Hash::default().is_all_ones());
+        assert!(!HASH_ELEMENT.is_all_ones());
+        let mut almost = ones;
+        almost.h[31] = 0xFE;
+        assert!(!almost.is_all_ones());
+        assert_ne!(ones, Hash::default());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_as_index_too_wide() {
+        HASH_ELEMENT.as_index(usize::BITS as usize + 1);
+    }
+
+    #[cfg(feature = "shake256")]
+    #[test]
+    fn test_xof_hash() {
+        let seed = HASH_ELEMENT.h;
+        let output = xof_hash(&seed, 64);
+        assert_eq!(output.len(), 64);
+        assert_eq!(xof_hash(&seed, 32), output[..32]);
+        // SHAKE256 and SHA-256 are different functions.
+        assert_ne!(output[..32], long_hash(&seed).h);
+        assert!(xof_hash(&seed, 0).is_empty());
+
+        // FIPS 202 examples.
+        assert_eq!(
+            hex::encode(xof_hash(b"", 32)),
+            "46b9dd2b0ba88d13233b3feb743eeb243fcd52ea62b81b82b50c27646ed5762f"
+        );
+        assert_eq!(
+            hex::encode(xof_hash(b"abc", 64)),
+            "483366601360a8771c6863080cc4114d8db44530f8f1e1ee4f94ea37e78b5739\
+             d5a15bef186a5386c75744c0527e1faa9f8726e462a12a4feb06bd8801e751e4"
+        );
+    }
+
     use std::hint::black_box;
     use test::Bencher;
 
@@ -257,7 +1756,7 @@ pub mod tests {
         let src = [HASH_ELEMENT; 5];
         b.iter(|| {
             let mut dst = [Default::default(); 5];
-            hash_parallel_all(&mut dst, black_box(&src));
+            hash_parallel_all(&mut dst, black_box(&src), &Tweak::NONE);
             dst
         });
     }
@@ -267,7 +1766,7 @@ pub mod tests {
         let src = [HASH_ELEMENT; 5];
         b.iter(|| {
             let mut dst = [Default::default(); 5];
-            hash_parallel_chains_all(&mut dst, black_box(&src), 5);
+            hash_parallel_chains_all(&mut dst, black_box(&src), 5, &Tweak::NONE);
             dst
         });
     }
@@ -291,7 +1790,7 @@ pub mod tests {
             let mut dst = black_box(src);
             for _ in 0..5 {
                 let tmp = dst;
-                hash_parallel_all(&mut dst, &tmp);
+                hash_parallel_all(&mut dst, &tmp, &Tweak::NONE);
             }
             dst
         });
@@ -308,7 +1807,7 @@ pub mod tests {
         let src = [h0, h1, h2, h3, h4];
         b.iter(|| {
             let mut dst = [Default::default(); 5];
-            hash_parallel_all(&mut dst, black_box(&src));
+            hash_parallel_all(&mut dst, black_box(&src), &Tweak::NONE);
             dst
         });
     }
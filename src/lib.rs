@@ -1,21 +1,91 @@
+//! Gravity-SPHINCS post-quantum signatures.
+//!
+//! Quick start, on byte encodings: `keypair(seed)` returns the secret key (the
+//! 64-byte seed) and the public key, `sign(sk, msg)` returns a serialized
+//! signature, and `verify(pk, msg, sig)` checks it. The typed API is in the
+//! `gravity` module.
+//!
+//! ```ignore
+//! let (sk, pk) = gravity::keypair(&seed);
+//! let sig = gravity::sign(&sk, b"message")?;
+//! assert!(gravity::verify(&pk, b"message", &sig)?);
+//! ```
+//!
+//! Panics: key generation, signing, verification and parsing, as well as the
+//! `address` and `cost` modules, don't panic whatever their inputs, and report
+//! failures as errors (see `tests/no_panic.rs`): `CostModel::for_params`, for
+//! one, rejects a `ParamsInfo` beyond the bounds of the model. The lower-level
+//! functions of the `hash`, `merkle`, `pors` and `prng` modules work on
+//! caller-provided buffers, and panic like slice indexing if these are shorter
+//! than the parameters require.
+
 #![cfg_attr(test, feature(test, bench_black_box))]
+// The loops over the GRAVITY_D layers are empty with the porst feature, and
+// comparisons with GRAVITY_D constant.
+#![cfg_attr(
+    feature = "porst",
+    allow(clippy::reversed_empty_ranges, clippy::absurd_extreme_comparisons)
+)]
 
 #[cfg(test)]
 extern crate test;
 
-mod address;
+pub mod address;
 mod config;
-mod gravity;
-mod hash;
+pub mod cost;
+pub mod error;
+pub mod gravity;
+pub mod hash;
 mod ltree;
-mod merkle;
+#[cfg(feature = "memlock")]
+pub mod memlock;
+pub mod merkle;
 mod octopus;
-mod pors;
+pub mod pors;
+#[cfg(feature = "pqcrypto-compat")]
+pub mod pqcrypto;
 mod primitives;
-mod prng;
-mod subtree;
+pub mod prng;
+pub mod rotation;
+pub mod scheme;
+mod selftest;
+pub mod subtree;
+#[cfg(feature = "async")]
+mod task;
+#[cfg(any(test, feature = "vector-gen"))]
+pub mod vectors;
 mod wots;
 
+pub use config::{
+    params, validate_params, GravityConfig, GravityVersion, ParamsInfo, GRAVITY_VERSION,
+};
+use error::GravityError;
+pub use selftest::self_test;
+
+// Secret key bytes (the seed itself) and public key bytes.
+pub fn keypair(seed: &[u8; 64]) -> (Vec<u8>, Vec<u8>) {
+    let sk = gravity::SecKey::new(seed);
+    let pk = <[u8; config::PUBLIC_KEY_BYTES]>::from(&sk.genpk());
+    (seed.to_vec(), pk.to_vec())
+}
+
+pub fn sign(sk_bytes: &[u8], msg: &[u8]) -> Result<Vec<u8>, GravityError> {
+    let sk = gravity::SecKey::from_slice(sk_bytes)?;
+    Ok(Vec::<u8>::from(&sk.sign_bytes(msg)))
+}
+
+// Errors on malformed keys and signatures, and returns whether a well-formed
+// signature is valid.
+pub fn verify(pk_bytes: &[u8], msg: &[u8], sig_bytes: &[u8]) -> Result<bool, GravityError> {
+    let pk = gravity::PubKey::try_from(pk_bytes)?;
+    let sign = gravity::Signature::try_from(sig_bytes)?;
+    match pk.verify_bytes(&sign, msg) {
+        Ok(()) => Ok(true),
+        Err(GravityError::Verification) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
 pub fn gravity_genpk(public: &mut [u8; 32], secret: &[u8; 64]) {
     let sk = gravity::SecKey::new(secret);
     let pk = sk.genpk();
@@ -34,8 +104,8 @@ pub fn gravity_verify(public: &[u8; 32], msg: &[u8], sign_bytes: Vec<u8>) -> boo
     let pk = gravity::PubKey {
         h: hash::Hash { h: *public },
     };
-    if let Some(sign) = gravity::Signature::deserialize(&mut sign_bytes.iter()) {
-        pk.verify_bytes(&sign, msg)
+    if let Ok(sign) = gravity::Signature::deserialize(&mut sign_bytes.iter()) {
+        pk.verify_bytes(&sign, msg).is_ok()
     } else {
         false
     }
@@ -62,4 +132,30 @@ mod tests {
         let sign = gravity_sign(secret, msg);
         assert!(gravity_verify(&public, msg, sign));
     }
+
+    #[test]
+    fn test_bytes_api() {
+        let seed = [7u8; 64];
+        let msg = b"Hello world";
+        let (sk_bytes, pk_bytes) = keypair(&seed);
+        assert_eq!(sk_bytes, seed);
+
+        let sk = gravity::SecKey::new(&seed);
+        let pk = sk.genpk();
+        assert_eq!(gravity::PubKey::try_from(pk_bytes.as_slice()), Ok(pk));
+
+        // Bytes to typed.
+        let sig_bytes = sign(&sk_bytes, msg).unwrap();
+        let sig = gravity::Signature::try_from(sig_bytes.as_slice()).unwrap();
+        assert_eq!(pk.verify_bytes(&sig, msg), Ok(()));
+
+        // Typed to bytes.
+        let sig_bytes = Vec::<u8>::from(&sk.sign_bytes(msg));
+        assert_eq!(verify(&pk_bytes, msg, &sig_bytes), Ok(true));
+        assert_eq!(verify(&pk_bytes, b"another message", &sig_bytes), Ok(false));
+
+        assert!(sign(&sk_bytes[1..], msg).is_err());
+        assert!(verify(&pk_bytes[1..], msg, &sig_bytes).is_err());
+        assert!(verify(&pk_bytes, msg, &sig_bytes[1..]).is_err());
+    }
 }
@@ -1,51 +1,205 @@
 use crate::hash;
-use crate::hash::Hash;
-use std::mem;
+use crate::hash::{HashBackend, Tweak};
 
-fn ltree(root: &mut Hash, buf: &mut [Hash], mut count: usize) {
-    let (mut dst, mut src) = buf.split_at_mut(count);
-
-    while count > 1 {
-        mem::swap(&mut dst, &mut src);
+// L-tree built as leaves are pushed, without allocation. Two nodes are merged as
+// soon as they have the same height, so the stack holds at most one node per
+// height. The remaining nodes, of decreasing heights, are merged from the right:
+// this is the level-by-level construction, where an odd last node is carried up
+// unchanged until it has a sibling. A node of height h whose first leaf is s is
+// at (h, s >> h) in the level-by-level construction, which is the position of
+// its tweak.
+pub struct Ltree<H> {
+    stack: [(u32, H); 64],
+    len: usize,
+    count: u64,
+    tweak: Tweak,
+}
 
-        let mut newcount = count >> 1;
-        hash::hash_compress_pairs(dst, src, newcount);
-        if count & 1 != 0 {
-            dst[newcount] = src[count - 1];
-            newcount += 1;
+impl<H: HashBackend> Ltree<H> {
+    pub fn new(tweak: Tweak) -> Self {
+        Self {
+            stack: [(0, Default::default()); 64],
+            len: 0,
+            count: 0,
+            tweak,
         }
+    }
 
-        count = newcount;
+    pub fn push(&mut self, leaf: H) {
+        let (mut height, mut node) = (0, leaf);
+        while self.len > 0 && self.stack[self.len - 1].0 == height {
+            self.len -= 1;
+            height += 1;
+            node = hash::hash_2n_to_n_at_ret(
+                &self.stack[self.len].1,
+                &node,
+                &self.tweak,
+                height,
+                self.count >> height,
+            );
+        }
+        self.stack[self.len] = (height, node);
+        self.len += 1;
+        self.count += 1;
     }
 
-    *root = dst[0]
+    // The default hash if no leaf was pushed.
+    pub fn root(&self) -> H {
+        let mut nodes = self.stack[..self.len].iter().rev();
+        let mut root = nodes.next().map(|x| x.1).unwrap_or_default();
+        let mut start = self.count - self.stack[..self.len].last().map_or(0, |x| 1 << x.0);
+        for &(height, ref left) in nodes {
+            start -= 1 << height;
+            root = hash::hash_2n_to_n_at_ret(
+                left,
+                &root,
+                &self.tweak,
+                height + 1,
+                start >> (height + 1),
+            );
+        }
+        root
+    }
 }
 
-pub fn ltree_leaves(root: &mut Hash, leaves: &[Hash]) {
-    let count = leaves.len();
-    let mut buf = vec![Default::default(); 2 * count];
-
-    buf[..count].copy_from_slice(&leaves[..count]);
-
-    ltree(root, buf.as_mut_slice(), count)
+pub fn ltree_leaves<H: HashBackend>(root: &mut H, leaves: &[H], tweak: &Tweak) {
+    let mut ltree = Ltree::new(*tweak);
+    for leaf in leaves.iter() {
+        ltree.push(*leaf);
+    }
+    *root = ltree.root()
 }
 
-pub fn ltree_leaves_ret(leaves: &[Hash]) -> Hash {
+pub fn ltree_leaves_ret<H: HashBackend>(leaves: &[H], tweak: &Tweak) -> H {
     let mut root = Default::default();
-    ltree_leaves(&mut root, leaves);
+    ltree_leaves(&mut root, leaves, tweak);
     root
 }
 
+// Roots of the L-trees of consecutive chunks of `nodes`, one per tweak, which
+// are overwritten. The trees are compressed level by level all together, so
+// that hash_compress_pairs can batch the pairs of each level.
+pub fn ltree_batch<H: HashBackend>(nodes: &mut [H], tweaks: &[Tweak]) -> Vec<H> {
+    let mut roots = vec![H::default(); tweaks.len()];
+    let mut pairs = vec![H::default(); nodes.len() / tweaks.len().max(1) / 2];
+    ltree_batch_into(&mut roots, nodes, tweaks, &mut pairs);
+    roots
+}
+
+// Same as ltree_batch, into `roots`, with `pairs` as scratch space of at least
+// half the length of a chunk.
+pub fn ltree_batch_into<H: HashBackend>(
+    roots: &mut [H],
+    nodes: &mut [H],
+    tweaks: &[Tweak],
+    pairs: &mut [H],
+) {
+    let len = nodes.len() / tweaks.len().max(1);
+    if len == 0 {
+        roots.fill(H::default());
+        return;
+    }
+    let mut count = len;
+    let mut height = 0;
+
+    while count > 1 {
+        height += 1;
+        let half = count >> 1;
+        for (chunk, tweak) in nodes.chunks_exact_mut(len).zip(tweaks.iter()) {
+            hash::hash_compress_pairs(pairs, chunk, half, tweak, height, 0);
+            chunk[..half].copy_from_slice(&pairs[..half]);
+            // An odd last node is carried up unchanged.
+            if count & 1 != 0 {
+                chunk[half] = chunk[count - 1];
+            }
+        }
+        count = half + (count & 1);
+    }
+
+    for (root, chunk) in roots.iter_mut().zip(nodes.chunks_exact(len)) {
+        *root = chunk[0];
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::address::Address;
+    use crate::hash::{Hash, TweakKind};
+    use std::mem;
+
+    // Level-by-level construction, as in the specification.
+    fn ltree_levelwise(leaves: &[Hash], tweak: &Tweak) -> Hash {
+        let mut count = leaves.len();
+        let mut buf = vec![Default::default(); 2 * count];
+        buf[..count].copy_from_slice(leaves);
+        let (mut dst, mut src) = buf.split_at_mut(count);
+        let mut height = 0;
+
+        while count > 1 {
+            mem::swap(&mut dst, &mut src);
+            height += 1;
+
+            let mut newcount = count >> 1;
+            hash::hash_compress_pairs(dst, src, newcount, tweak, height, 0);
+            if count & 1 != 0 {
+                dst[newcount] = src[count - 1];
+                newcount += 1;
+            }
+
+            count = newcount;
+        }
+
+        dst[0]
+    }
+
+    #[test]
+    fn test_ltree_levelwise() {
+        let tweaks = [
+            Tweak::NONE,
+            Tweak::new(TweakKind::Wots, &Address::new(1, 2)),
+        ];
+        let mut leaves = vec![hash::tests::HASH_ELEMENT];
+        for count in 1..=300 {
+            for tweak in tweaks.iter() {
+                assert_eq!(
+                    ltree_leaves_ret(&leaves, tweak),
+                    ltree_levelwise(&leaves, tweak),
+                    "{}",
+                    count
+                );
+            }
+            let next = hash::hash_n_to_n_ret(&leaves[count - 1]);
+            leaves.push(next);
+        }
+    }
+
+    #[test]
+    fn test_ltree_batch() {
+        let tweaks: Vec<Tweak> = (0..5)
+            .map(|i| Tweak::new(TweakKind::Wots, &Address::new(1, i)))
+            .collect();
+        let mut leaves = vec![hash::tests::HASH_ELEMENT];
+        for _ in 1..5 * 70 {
+            let next = hash::hash_n_to_n_ret(&leaves[leaves.len() - 1]);
+            leaves.push(next);
+        }
+        for len in [0, 1, 2, 3, 8, 67, 70] {
+            let mut nodes = leaves[..5 * len].to_vec();
+            let roots = ltree_batch(&mut nodes, &tweaks);
+            for (i, (root, tweak)) in roots.iter().zip(tweaks.iter()).enumerate() {
+                let chunk = &leaves[i * len..(i + 1) * len];
+                assert_eq!(*root, ltree_leaves_ret(chunk, tweak), "{}", len);
+            }
+        }
+    }
 
     // Notation for these tests: H(h_i, h_j) = h_{2^i*3^j}
     #[test]
     fn test_ltree_1() {
         let h0 = hash::tests::HASH_ELEMENT;
 
-        let dst = ltree_leaves_ret(&[h0; 1]);
+        let dst = ltree_leaves_ret(&[h0; 1], &Tweak::NONE);
         assert_eq!(dst, h0);
     }
 
@@ -54,7 +208,7 @@ mod tests {
         let h0 = hash::tests::HASH_ELEMENT;
         let h1 = hash::hash_2n_to_n_ret(&h0, &h0);
 
-        let dst = ltree_leaves_ret(&[h0; 2]);
+        let dst = ltree_leaves_ret(&[h0; 2], &Tweak::NONE);
         assert_eq!(dst, h1);
     }
 
@@ -64,7 +218,7 @@ mod tests {
         let h1 = hash::hash_2n_to_n_ret(&h0, &h0);
         let h2 = hash::hash_2n_to_n_ret(&h1, &h0);
 
-        let dst = ltree_leaves_ret(&[h0; 3]);
+        let dst = ltree_leaves_ret(&[h0; 3], &Tweak::NONE);
         assert_eq!(dst, h2);
     }
 
@@ -74,7 +228,7 @@ mod tests {
         let h1 = hash::hash_2n_to_n_ret(&h0, &h0);
         let h6 = hash::hash_2n_to_n_ret(&h1, &h1);
 
-        let dst = ltree_leaves_ret(&[h0; 4]);
+        let dst = ltree_leaves_ret(&[h0; 4], &Tweak::NONE);
         assert_eq!(dst, h6);
     }
 
@@ -85,7 +239,7 @@ mod tests {
         let h6 = hash::hash_2n_to_n_ret(&h1, &h1);
         let h64 = hash::hash_2n_to_n_ret(&h6, &h0);
 
-        let dst = ltree_leaves_ret(&[h0; 5]);
+        let dst = ltree_leaves_ret(&[h0; 5], &Tweak::NONE);
         assert_eq!(dst, h64);
     }
 
@@ -96,7 +250,7 @@ mod tests {
         let h6 = hash::hash_2n_to_n_ret(&h1, &h1);
         let h192 = hash::hash_2n_to_n_ret(&h6, &h1);
 
-        let dst = ltree_leaves_ret(&[h0; 6]);
+        let dst = ltree_leaves_ret(&[h0; 6], &Tweak::NONE);
         assert_eq!(dst, h192);
     }
 
@@ -107,24 +261,24 @@ mod tests {
     #[bench]
     fn bench_ltree_1(b: &mut Bencher) {
         let h = [hash::tests::HASH_ELEMENT; 1];
-        b.iter(|| ltree_leaves_ret(black_box(&h)));
+        b.iter(|| ltree_leaves_ret(black_box(&h), &Tweak::NONE));
     }
 
     #[bench]
     fn bench_ltree_5(b: &mut Bencher) {
         let h = [hash::tests::HASH_ELEMENT; 5];
-        b.iter(|| ltree_leaves_ret(black_box(&h)));
+        b.iter(|| ltree_leaves_ret(black_box(&h), &Tweak::NONE));
     }
 
     #[bench]
     fn bench_ltree_20(b: &mut Bencher) {
         let h = [hash::tests::HASH_ELEMENT; 20];
-        b.iter(|| ltree_leaves_ret(black_box(&h)));
+        b.iter(|| ltree_leaves_ret(black_box(&h), &Tweak::NONE));
     }
 
     #[bench]
     fn bench_ltree_wots(b: &mut Bencher) {
         let h = [hash::tests::HASH_ELEMENT; config::WOTS_ELL];
-        b.iter(|| ltree_leaves_ret(black_box(&h)));
+        b.iter(|| ltree_leaves_ret(black_box(&h), &Tweak::NONE));
     }
 }
@@ -0,0 +1,223 @@
+// Memory locking (mlock, or VirtualLock on Windows) of the secrets of a key, so
+// that they are not written to swap. Locking is limited by RLIMIT_MEMLOCK on
+// Unix and by the working set size on Windows: with LockPolicy::BestEffort, a
+// failure leaves the memory unlocked, and with LockPolicy::Required it is an
+// error (see SecKey::new_locked). Locked memory is zeroized before being
+// unlocked and freed.
+use std::alloc::{self, Layout};
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+use std::slice;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockPolicy {
+    BestEffort,
+    Required,
+}
+
+// Allocations are aligned to pages, so that unlocking one does not unlock the
+// memory of another. On systems with larger pages, locking still covers the
+// whole page.
+const PAGE_SIZE: usize = 4096;
+
+// A value in its own page-aligned allocation, locked if possible.
+pub(crate) struct Locked<T: Copy> {
+    ptr: NonNull<T>,
+    layout: Layout,
+    locked: bool,
+}
+
+// SAFETY: Locked owns its allocation, as a Box does.
+unsafe impl<T: Copy + Send> Send for Locked<T> {}
+unsafe impl<T: Copy + Sync> Sync for Locked<T> {}
+
+impl<T: Copy> Locked<T> {
+    pub(crate) fn new(value: T) -> Self {
+        let layout = Layout::new::<T>()
+            .align_to(PAGE_SIZE)
+            .expect("page size is a power of two")
+            .pad_to_align();
+        // SAFETY: the layout has a non-zero size, as it is padded to a page.
+        let raw = unsafe { alloc::alloc(layout) } as *mut T;
+        let Some(ptr) = NonNull::new(raw) else {
+            alloc::handle_alloc_error(layout);
+        };
+        // SAFETY: the allocation is valid and aligned for T.
+        unsafe { ptr.as_ptr().write(value) };
+        let locked = lock(ptr.as_ptr() as *mut u8, layout.size());
+        Locked {
+            ptr,
+            layout,
+            locked,
+        }
+    }
+
+    pub(crate) fn is_locked(&self) -> bool {
+        self.locked
+    }
+}
+
+impl<T: Copy> Deref for Locked<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: the value was written in new and lives until drop.
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T: Copy> DerefMut for Locked<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: as for deref, and &mut self is exclusive.
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T: Copy> Drop for Locked<T> {
+    fn drop(&mut self) {
+        // SAFETY: the allocation is owned; T is Copy, so there is nothing to
+        // drop before freeing it.
+        unsafe { release(self.ptr.as_ptr() as *mut u8, self.layout, self.locked) };
+    }
+}
+
+// `len` values in their own page-aligned allocation, locked if possible: as
+// Locked, for buffers too large to pass by value (the PORS cache).
+pub(crate) struct LockedSlice<T: Copy> {
+    ptr: NonNull<T>,
+    len: usize,
+    layout: Layout,
+    locked: bool,
+}
+
+// SAFETY: LockedSlice owns its allocation, as a Box<[T]> does.
+unsafe impl<T: Copy + Send> Send for LockedSlice<T> {}
+unsafe impl<T: Copy + Sync> Sync for LockedSlice<T> {}
+
+impl<T: Copy + Default> LockedSlice<T> {
+    pub(crate) fn new(len: usize) -> Self {
+        let layout = Layout::array::<T>(len.max(1))
+            .and_then(|layout| layout.align_to(PAGE_SIZE))
+            .expect("slice size overflows")
+            .pad_to_align();
+        // SAFETY: the layout has a non-zero size, as it is padded to a page.
+        let raw = unsafe { alloc::alloc(layout) } as *mut T;
+        let Some(ptr) = NonNull::new(raw) else {
+            alloc::handle_alloc_error(layout);
+        };
+        for i in 0..len {
+            // SAFETY: the allocation holds len values of T, and is aligned for T.
+            unsafe { ptr.as_ptr().add(i).write(T::default()) };
+        }
+        let locked = lock(ptr.as_ptr() as *mut u8, layout.size());
+        LockedSlice {
+            ptr,
+            len,
+            layout,
+            locked,
+        }
+    }
+}
+
+impl<T: Copy> LockedSlice<T> {
+    #[cfg(test)]
+    pub(crate) fn is_locked(&self) -> bool {
+        self.locked
+    }
+}
+
+impl<T: Copy> Deref for LockedSlice<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        // SAFETY: the len values were written in new and live until drop.
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<T: Copy> DerefMut for LockedSlice<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        // SAFETY: as for deref, and &mut self is exclusive.
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<T: Copy> Drop for LockedSlice<T> {
+    fn drop(&mut self) {
+        // SAFETY: as for Locked.
+        unsafe { release(self.ptr.as_ptr() as *mut u8, self.layout, self.locked) };
+    }
+}
+
+// Zeroizes, unlocks and frees an allocation of Locked or LockedSlice. As these
+// own whole pages, unlocking leaves the pages of other allocations locked.
+//
+// SAFETY: bytes must be an owned allocation of `layout`, of values with nothing
+// to drop, and not used afterwards.
+unsafe fn release(bytes: *mut u8, layout: Layout, locked: bool) {
+    memsec::memzero(bytes, layout.size());
+    if locked {
+        memsec::munlock(bytes, layout.size());
+    }
+    alloc::dealloc(bytes, layout);
+}
+
+fn lock(addr: *mut u8, len: usize) -> bool {
+    #[cfg(test)]
+    if tests::FAIL_NEXT_LOCK.with(|f| f.replace(false)) {
+        return false;
+    }
+    if len == 0 {
+        return true;
+    }
+    // SAFETY: the callers pass the range of a live allocation.
+    unsafe { memsec::mlock(addr, len) }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    thread_local! {
+        pub static FAIL_NEXT_LOCK: Cell<bool> = const { Cell::new(false) };
+    }
+
+    // The next lock of the current thread fails, as with a low RLIMIT_MEMLOCK.
+    pub fn inject_lock_failure() {
+        FAIL_NEXT_LOCK.with(|f| f.set(true))
+    }
+
+    #[test]
+    fn test_locked() {
+        let mut x = Locked::new([7u64; 3]);
+        assert_eq!(*x, [7; 3]);
+        x[1] = 8;
+        assert_eq!(*x, [7, 8, 7]);
+        assert_eq!(x.ptr.as_ptr() as usize % PAGE_SIZE, 0);
+        assert_eq!(x.layout.size(), PAGE_SIZE);
+
+        inject_lock_failure();
+        let y = Locked::new(1u8);
+        assert!(!y.is_locked());
+        assert_eq!(*y, 1);
+    }
+
+    #[test]
+    fn test_locked_slice() {
+        let mut values = LockedSlice::<u64>::new(PAGE_SIZE / 8 + 1);
+        assert_eq!(values.len(), PAGE_SIZE / 8 + 1);
+        assert!(values.iter().all(|x| *x == 0));
+        values[PAGE_SIZE / 8] = 7;
+        assert_eq!(values.last(), Some(&7));
+        // Whole pages of their own.
+        assert_eq!(values.ptr.as_ptr() as usize % PAGE_SIZE, 0);
+        assert_eq!(values.layout.size(), 2 * PAGE_SIZE);
+
+        assert!(LockedSlice::<u64>::new(0).is_empty());
+        inject_lock_failure();
+        let values = LockedSlice::<u8>::new(3);
+        assert!(!values.is_locked());
+        assert_eq!(&values[..], &[0; 3]);
+    }
+}
@@ -1,13 +1,14 @@
+use crate::error::{GravityError, MerkleError};
 use crate::hash;
-use crate::hash::Hash;
+use crate::hash::{Hash, HashBackend, Tweak};
 use std::mem;
 
-pub struct MerkleBuf {
+pub struct MerkleBuf<H = Hash> {
     height: usize,
-    buf: Vec<Hash>,
+    buf: Vec<H>,
 }
 
-impl MerkleBuf {
+impl<H: HashBackend> MerkleBuf<H> {
     pub fn new(height: usize) -> Self {
         Self {
             height,
@@ -19,36 +20,90 @@ impl MerkleBuf {
         self.height
     }
 
-    pub fn split_half_mut(&mut self) -> (&mut [Hash], &mut [Hash]) {
+    pub fn split_half_mut(&mut self) -> (&mut [H], &mut [H]) {
         self.buf.as_mut_slice().split_at_mut(1 << self.height)
     }
 
-    pub fn slice_leaves_mut(&mut self) -> &mut [Hash] {
+    pub fn slice_leaves_mut(&mut self) -> &mut [H] {
         &mut self.buf[..(1 << self.height)]
     }
 
     #[cfg(test)]
-    pub fn fill_leaves(&mut self, leaves: &[Hash]) {
+    pub fn fill_leaves(&mut self, leaves: &[H]) {
         for i in 0..leaves.len() {
             self.buf[i] = leaves[i];
         }
     }
 }
 
-pub struct MerkleTree {
+// Compression of two sibling nodes into their parent, for the trees of
+// MerkleTree::new_with_hasher (e.g. a trivially invertible function in tests).
+pub trait MerkleHasher<H = Hash> {
+    fn hash_pair(left: &H, right: &H) -> H;
+}
+
+// The untweaked 2n-to-n hash of H, as in the trees of MerkleTree::new.
+pub struct DefaultMerkleHasher;
+
+impl<H: HashBackend> MerkleHasher<H> for DefaultMerkleHasher {
+    fn hash_pair(left: &H, right: &H) -> H {
+        hash::hash_2n_to_n_ret(left, right)
+    }
+}
+
+type PairHasher<H> = fn(&H, &H) -> H;
+
+#[derive(Clone)]
+pub struct MerkleTree<H = Hash> {
     height: usize,
-    nodes: Vec<Hash>,
+    nodes: Vec<H>,
+    tweak: Tweak,
+    // Replaces the hashes of H and the tweak if set.
+    hasher: Option<PairHasher<H>>,
 }
 
-impl MerkleTree {
+impl<H: HashBackend> MerkleTree<H> {
     pub fn new(height: usize) -> Self {
+        Self::with_tweak(height, Tweak::NONE)
+    }
+
+    pub fn with_tweak(height: usize, tweak: Tweak) -> Self {
         Self {
             height,
             nodes: vec![Default::default(); 1 << (height + 1)],
+            tweak,
+            hasher: None,
         }
     }
 
-    pub fn leaves(&mut self) -> &mut [Hash] {
+    // A tree whose parents are M::hash_pair of their children. Its
+    // authentication paths do not verify with root_from_auth, which uses the
+    // hashes of H.
+    pub fn new_with_hasher<M: MerkleHasher<H>>(height: usize) -> Self {
+        Self {
+            hasher: Some(M::hash_pair),
+            ..Self::new(height)
+        }
+    }
+
+    // The tree of `leaves`, whose number must be a power of two, generated.
+    pub fn from_leaves(leaves: &[H]) -> Result<Self, MerkleError> {
+        Self::from_leaves_with_tweak(leaves, Tweak::NONE)
+    }
+
+    pub fn from_leaves_with_tweak(leaves: &[H], tweak: Tweak) -> Result<Self, MerkleError> {
+        let height = leaf_height(leaves.len())?;
+        let mut tree = Self::with_tweak(height, tweak);
+        tree.leaves().copy_from_slice(leaves);
+        tree.generate();
+        Ok(tree)
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn leaves(&mut self) -> &mut [H] {
         let n = 1 << self.height;
         &mut self.nodes[n..(2 * n)]
     }
@@ -58,17 +113,115 @@ impl MerkleTree {
             let n = 1 << (self.height - 1 - i);
             {
                 let (dst, src) = self.nodes.split_at_mut(2 * n);
-                hash::hash_compress_pairs(&mut dst[n..(2 * n)], src, n);
+                compress_pairs(
+                    self.hasher,
+                    &mut dst[n..(2 * n)],
+                    src,
+                    n,
+                    &self.tweak,
+                    i as u32 + 1,
+                    0,
+                );
             }
         }
     }
 
-    pub fn root(&self) -> Hash {
+    pub fn root(&self) -> H {
         self.nodes[1]
     }
 
+    // All nodes from the root, level by level: the 2^(height + 1) - 1 nodes of
+    // a 0-based heap.
+    pub fn heap(&self) -> &[H] {
+        &self.nodes[1..]
+    }
+
+    // The nodes of a layer, from the leaves at layer 0 to the root at layer
+    // height; empty above the root.
+    pub fn nodes_at_layer(&self, layer: usize) -> &[H] {
+        if layer > self.height {
+            return &[];
+        }
+        let n = 1 << (self.height - layer);
+        &self.nodes[n..(2 * n)]
+    }
+
+    pub fn node_at(&self, layer: usize, index: usize) -> Option<&H> {
+        self.nodes_at_layer(layer).get(index)
+    }
+
+    // The other child of the parent of node (layer, index), i.e. the node at
+    // height `layer` of the authentication paths through it (see gen_auth).
+    // None for the root, which has no sibling, and for missing nodes.
+    pub fn sibling(&self, layer: usize, index: usize) -> Option<&H> {
+        self.node_at(layer, index)
+            .and(self.node_at(layer, index ^ 1))
+    }
+
+    // Replaces the given leaves and recomputes only their paths to the root,
+    // each modified node being hashed once. The tree is left untouched if an
+    // index is out of range.
+    pub fn batch_update(&mut self, updates: &[(usize, H)]) -> Result<H, GravityError> {
+        let n = 1 << self.height;
+        if updates.iter().any(|&(index, _)| index >= n) {
+            return Err(GravityError::InvalidParameter("leaf index"));
+        }
+        let mut indices: Vec<usize> = Vec::with_capacity(updates.len());
+        for &(index, leaf) in updates.iter() {
+            self.nodes[n + index] = leaf;
+            indices.push(n + index);
+        }
+
+        for _ in 0..self.height {
+            for x in indices.iter_mut() {
+                *x >>= 1;
+            }
+            indices.sort_unstable();
+            indices.dedup();
+            for &parent in indices.iter() {
+                // Heap position to height and index in the level.
+                let depth = parent.ilog2() as usize;
+                let (dst, src) = self.nodes.split_at_mut(2 * parent);
+                compress_pairs(
+                    self.hasher,
+                    &mut dst[parent..],
+                    &src[..2],
+                    1,
+                    &self.tweak,
+                    (self.height - depth) as u32,
+                    (parent - (1 << depth)) as u64,
+                );
+            }
+        }
+
+        Ok(self.root())
+    }
+
+    // Recomputes every parent from its two children and compares it with the
+    // stored node, to detect the corruption of a loaded tree: 2^height - 1
+    // hashes. A leaf changed without generate() fails the check of its parent.
+    pub fn verify_self_consistency(&self) -> bool {
+        let mut parents = vec![H::default(); (1 << self.height) >> 1];
+        for layer in 1..=self.height {
+            let n = 1 << (self.height - layer);
+            compress_pairs(
+                self.hasher,
+                &mut parents[..n],
+                &self.nodes[(2 * n)..(4 * n)],
+                n,
+                &self.tweak,
+                layer as u32,
+                0,
+            );
+            if parents[..n] != self.nodes[n..(2 * n)] {
+                return false;
+            }
+        }
+        true
+    }
+
     #[allow(clippy::needless_range_loop)]
-    pub fn gen_auth(&self, auth: &mut [Hash], mut index: usize) {
+    pub fn gen_auth(&self, auth: &mut [H], mut index: usize) {
         let mut n = 1 << self.height;
         for l in 0..self.height {
             // Copy auth path
@@ -80,22 +233,67 @@ impl MerkleTree {
     }
 }
 
-pub fn merkle_compress_all(root: &mut Hash, buf: &mut MerkleBuf) {
+// hash::hash_compress_pairs, or the hasher of a tree of new_with_hasher.
+fn compress_pairs<H: HashBackend>(
+    hasher: Option<PairHasher<H>>,
+    dst: &mut [H],
+    src: &[H],
+    count: usize,
+    tweak: &Tweak,
+    height: u32,
+    first: u64,
+) {
+    match hasher {
+        Some(hash_pair) => {
+            for (i, x) in dst[..count].iter_mut().enumerate() {
+                *x = hash_pair(&src[2 * i], &src[2 * i + 1]);
+            }
+        }
+        None => hash::hash_compress_pairs(dst, src, count, tweak, height, first),
+    }
+}
+
+fn leaf_height(count: usize) -> Result<usize, MerkleError> {
+    if count.is_power_of_two() {
+        Ok(count.trailing_zeros() as usize)
+    } else {
+        Err(MerkleError::InvalidLeafCount(count))
+    }
+}
+
+// Root of the tree of `leaves`, whose number must be a power of two, keeping
+// only one pending node per height.
+pub fn root_of<H: HashBackend>(leaves: &[H]) -> Result<H, MerkleError> {
+    let height = leaf_height(leaves.len())?;
+    let mut pending = [H::default(); usize::BITS as usize];
+    for (i, leaf) in leaves.iter().enumerate() {
+        let mut node = *leaf;
+        let mut level = 0;
+        while (i >> level) & 1 == 1 {
+            node = hash::hash_2n_to_n_ret(&pending[level], &node);
+            level += 1;
+        }
+        pending[level] = node;
+    }
+    Ok(pending[height])
+}
+
+pub fn merkle_compress_all<H: HashBackend>(root: &mut H, buf: &mut MerkleBuf<H>, tweak: &Tweak) {
     let height = buf.height();
     let mut n = 1 << height;
     let (mut dst, mut src) = buf.split_half_mut();
 
-    for _ in 0..height {
+    for l in 0..height {
         mem::swap(&mut dst, &mut src);
         n >>= 1;
-        hash::hash_compress_pairs(dst, src, n);
+        hash::hash_compress_pairs(dst, src, n, tweak, l as u32 + 1, 0);
     }
 
     *root = dst[0]
 }
 
 #[cfg(test)]
-pub fn merkle_compress_all_leaves(leaves: &[Hash], height: usize) -> Hash {
+pub fn merkle_compress_all_leaves<H: HashBackend>(leaves: &[H], height: usize, tweak: &Tweak) -> H {
     let count = leaves.len();
     assert_eq!(count, 1 << height);
 
@@ -103,12 +301,17 @@ pub fn merkle_compress_all_leaves(leaves: &[Hash], height: usize) -> Hash {
     buf.fill_leaves(leaves);
 
     let mut root = Default::default();
-    merkle_compress_all(&mut root, &mut buf);
+    merkle_compress_all(&mut root, &mut buf, tweak);
     root
 }
 
 #[allow(clippy::needless_range_loop)]
-pub fn merkle_gen_auth(auth: &mut [Hash], buf: &mut MerkleBuf, mut index: usize) -> Hash {
+pub fn merkle_gen_auth<H: HashBackend>(
+    auth: &mut [H],
+    buf: &mut MerkleBuf<H>,
+    mut index: usize,
+    tweak: &Tweak,
+) -> H {
     let height = buf.height();
     let mut n = 1 << height;
     let (mut dst, mut src) = buf.split_half_mut();
@@ -122,34 +325,72 @@ pub fn merkle_gen_auth(auth: &mut [Hash], buf: &mut MerkleBuf, mut index: usize)
         // Compute next layer
         mem::swap(&mut dst, &mut src);
         n >>= 1;
-        hash::hash_compress_pairs(dst, src, n);
+        hash::hash_compress_pairs(dst, src, n, tweak, l as u32 + 1, 0);
     }
 
     dst[0]
 }
 
-#[allow(clippy::needless_range_loop)]
-pub fn merkle_compress_auth(
-    node: &mut Hash,
-    auth: &[Hash],
-    height_diff: usize,
-    mut index: usize,
-) -> usize {
-    for l in 0..height_diff {
+// Computes the root reached from `leaf` at position `index` along the
+// authentication path `auth`. Bits of `index` above auth.len() are ignored, see
+// check_auth to reject them.
+pub fn root_from_auth<H: HashBackend>(leaf: &H, auth: &[H], index: u64, tweak: &Tweak) -> H {
+    climb(leaf, auth, 0, index, tweak)
+}
+
+// Same as root_from_auth, from a node at `height`.
+fn climb<H: HashBackend>(node: &H, auth: &[H], height: u32, mut index: u64, tweak: &Tweak) -> H {
+    let mut node = *node;
+    for (l, x) in auth.iter().enumerate() {
+        let parent = height + l as u32 + 1;
         if index & 1 == 0 {
-            *node = hash::hash_2n_to_n_ret(node, &auth[l])
+            node = hash::hash_2n_to_n_at_ret(&node, x, tweak, parent, index >> 1)
         } else {
-            *node = hash::hash_2n_to_n_ret(&auth[l], node)
+            node = hash::hash_2n_to_n_at_ret(x, &node, tweak, parent, index >> 1)
         }
         index >>= 1;
     }
+    node
+}
+
+pub fn check_auth(auth: &[Hash], index: u64) -> Result<(), GravityError> {
+    if auth.len() > 64 {
+        return Err(GravityError::InvalidParameter("auth"));
+    }
+    if auth.len() < 64 && index >> auth.len() != 0 {
+        return Err(GravityError::InvalidParameter("index"));
+    }
+    Ok(())
+}
 
-    index
+// Climbs `height_diff` levels of an authentication path: `node` is the node at
+// position `index` in its layer at `height` (e.g. a leaf hash at height 0), and
+// is replaced by its ancestor `height_diff` levels up, using the first
+// `height_diff` siblings of `auth` from the bottom. Returns the index of the
+// ancestor in its layer, so that a path can be consumed in several calls, as a
+// Gravity signature does across subtrees and the top-level tree.
+//
+// To verify an inclusion proof, check the path length and index with
+// check_auth, compress the leaf over the whole path (height_diff = auth.len(),
+// which returns 0) and compare the node with the trusted root. Panics if auth
+// has fewer than height_diff nodes.
+pub fn merkle_compress_auth<H: HashBackend>(
+    node: &mut H,
+    auth: &[H],
+    height_diff: usize,
+    index: u64,
+    height: u32,
+    tweak: &Tweak,
+) -> u64 {
+    *node = climb(node, &auth[..height_diff], height, index, tweak);
+    index.checked_shr(height_diff as u32).unwrap_or(0)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::address::Address;
+    use crate::hash::TweakKind;
 
     fn merkle_gen_auth_leaves(
         auth: &mut [Hash],
@@ -163,7 +404,248 @@ mod tests {
         let mut buf = MerkleBuf::new(height);
         buf.fill_leaves(leaves);
 
-        merkle_gen_auth(auth, &mut buf, index)
+        merkle_gen_auth(auth, &mut buf, index, &Tweak::NONE)
+    }
+
+    #[test]
+    fn test_from_leaves_root_of() {
+        let leaves: Vec<Hash> = (0..16)
+            .map(|i| hash::HashChain::skip_to(&hash::tests::HASH_ELEMENT, i))
+            .collect();
+        for height in 0..=4 {
+            let leaves = &leaves[..1 << height];
+            let mut expect = MerkleTree::new(height);
+            expect.leaves().copy_from_slice(leaves);
+            expect.generate();
+
+            let tree = MerkleTree::from_leaves(leaves).unwrap();
+            assert_eq!(tree.height(), height);
+            assert_eq!(tree.nodes, expect.nodes);
+            assert_eq!(root_of(leaves), Ok(expect.root()));
+        }
+
+        let tweak = Tweak::new(TweakKind::TopTree, &Address::new(0, 0));
+        let tree = MerkleTree::from_leaves_with_tweak(&leaves, tweak).unwrap();
+        assert_eq!(tree.root(), merkle_compress_all_leaves(&leaves, 4, &tweak));
+
+        for count in [0, 3, 5, 15] {
+            let error = Err(MerkleError::InvalidLeafCount(count));
+            assert_eq!(MerkleTree::from_leaves(&leaves[..count]).err(), error.err());
+            assert_eq!(root_of(&leaves[..count]), error);
+        }
+    }
+
+    #[test]
+    fn test_node_at() {
+        let leaves: Vec<Hash> = (0..8)
+            .map(|i| hash::HashChain::skip_to(&hash::tests::HASH_ELEMENT, i))
+            .collect();
+        let tree = MerkleTree::from_leaves(&leaves).unwrap();
+        assert_eq!(tree.node_at(3, 0), Some(&tree.root()));
+        assert_eq!(tree.node_at(3, 1), None);
+        assert_eq!(tree.node_at(4, 0), None);
+        for (i, leaf) in leaves.iter().enumerate() {
+            assert_eq!(tree.node_at(0, i), Some(leaf));
+        }
+        assert_eq!(tree.node_at(0, 8), None);
+
+        assert_eq!(tree.nodes_at_layer(0), &leaves[..]);
+        assert_eq!(
+            tree.nodes_at_layer(1)[3],
+            hash::hash_2n_to_n_ret(&leaves[6], &leaves[7])
+        );
+        assert_eq!(tree.nodes_at_layer(2).len(), 2);
+        assert!(tree.nodes_at_layer(4).is_empty());
+    }
+
+    #[test]
+    fn test_sibling() {
+        let leaves: Vec<Hash> = (0..8)
+            .map(|i| hash::HashChain::skip_to(&hash::tests::HASH_ELEMENT, i))
+            .collect();
+        let tree = MerkleTree::from_leaves(&leaves).unwrap();
+        assert_eq!(tree.sibling(0, 0), Some(&leaves[1]));
+        assert_eq!(tree.sibling(0, 1), Some(&leaves[0]));
+        assert_ne!(tree.sibling(0, 0), tree.sibling(0, 1));
+        assert_eq!(tree.sibling(2, 1), tree.node_at(2, 0));
+
+        let mut auth = [Hash::default(); 3];
+        for i in 0..leaves.len() {
+            tree.gen_auth(&mut auth, i);
+            for (layer, node) in auth.iter().enumerate() {
+                assert_eq!(tree.sibling(layer, i >> layer), Some(node));
+            }
+        }
+
+        assert_eq!(tree.sibling(3, 0), None);
+        assert_eq!(tree.sibling(0, 8), None);
+        assert_eq!(tree.sibling(1, 4), None);
+        assert_eq!(tree.sibling(4, 0), None);
+    }
+
+    // The XOR of the children: the root is the XOR of the leaves.
+    struct XorHasher;
+
+    impl MerkleHasher for XorHasher {
+        fn hash_pair(left: &Hash, right: &Hash) -> Hash {
+            let mut h = *left;
+            for (x, y) in h.h.iter_mut().zip(right.h.iter()) {
+                *x ^= y;
+            }
+            h
+        }
+    }
+
+    #[test]
+    fn test_new_with_hasher() {
+        let leaves: Vec<Hash> = (0..16)
+            .map(|i| hash::HashChain::skip_to(&hash::tests::HASH_ELEMENT, i))
+            .collect();
+        let expect = MerkleTree::from_leaves(&leaves).unwrap();
+        let mut tree = MerkleTree::new_with_hasher::<DefaultMerkleHasher>(4);
+        tree.leaves().copy_from_slice(&leaves);
+        tree.generate();
+        assert_eq!(tree.nodes, expect.nodes);
+        assert!(tree.verify_self_consistency());
+
+        let xor = |leaves: &[Hash]| {
+            leaves
+                .iter()
+                .fold(Hash::default(), |acc, x| XorHasher::hash_pair(&acc, x))
+        };
+        let mut tree = MerkleTree::new_with_hasher::<XorHasher>(4);
+        tree.leaves().copy_from_slice(&leaves);
+        tree.generate();
+        assert_eq!(tree.root(), xor(&leaves));
+        assert_eq!(tree.nodes_at_layer(2)[1], xor(&leaves[4..8]));
+        assert!(tree.verify_self_consistency());
+
+        let mut updated = leaves.clone();
+        updated[5] = Hash::default();
+        assert_eq!(
+            tree.batch_update(&[(5, Hash::default())]),
+            Ok(xor(&updated))
+        );
+        assert!(tree.verify_self_consistency());
+        // The nodes are not those of the hashes of H.
+        tree.hasher = None;
+        assert!(!tree.verify_self_consistency());
+    }
+
+    #[cfg(feature = "bytemuck")]
+    #[test]
+    fn test_bytemuck_leaves() {
+        use crate::config::HASH_SIZE;
+        assert_eq!(mem::size_of::<Hash>(), HASH_SIZE);
+        assert_eq!(mem::align_of::<Hash>(), 1);
+
+        let leaves: Vec<Hash> = (0..8)
+            .map(|i| hash::HashChain::skip_to(&hash::tests::HASH_ELEMENT, i))
+            .collect();
+        let mut region = vec![0u8; 3];
+        region.extend(leaves.iter().flat_map(|x| x.h));
+        let mapped: &[Hash] = bytemuck::cast_slice(&region[3..]);
+        assert_eq!(mapped, &leaves[..]);
+        let tree = MerkleTree::from_leaves(mapped).unwrap();
+        assert_eq!(
+            tree.root(),
+            MerkleTree::from_leaves(&leaves).unwrap().root()
+        );
+        assert_eq!(
+            bytemuck::cast_slice::<Hash, u8>(tree.heap()).len(),
+            15 * HASH_SIZE
+        );
+    }
+
+    #[test]
+    fn test_verify_self_consistency() {
+        const HEIGHT: usize = 4;
+        let n = 1 << HEIGHT;
+        let leaves: Vec<Hash> = (0..n)
+            .map(|i| hash::HashChain::skip_to(&hash::tests::HASH_ELEMENT, i))
+            .collect();
+        let tweak = Tweak::new(TweakKind::TopTree, &Address::new(0, 0));
+        let fresh = MerkleTree::from_leaves_with_tweak(&leaves, tweak).unwrap();
+        assert!(fresh.verify_self_consistency());
+        assert!(MerkleTree::<Hash>::new(0).verify_self_consistency());
+
+        // A leaf mutated without generate().
+        let mut tree = fresh.clone();
+        tree.leaves()[7].h[31] ^= 1;
+        assert!(!tree.verify_self_consistency());
+        tree.generate();
+        assert!(tree.verify_self_consistency());
+
+        // Every interior node, and the root.
+        for i in 1..n {
+            let mut tree = fresh.clone();
+            tree.nodes[i].h[0] ^= 0x80;
+            assert!(!tree.verify_self_consistency(), "node {}", i);
+        }
+    }
+
+    #[test]
+    fn test_batch_update() {
+        const HEIGHT: usize = 4;
+        let n = 1 << HEIGHT;
+        let mut tree = MerkleTree::new(HEIGHT);
+        for (i, leaf) in tree.leaves().iter_mut().enumerate() {
+            *leaf = hash::HashChain::skip_to(&hash::tests::HASH_ELEMENT, i);
+        }
+        tree.generate();
+
+        let mut expect = MerkleTree::new(HEIGHT);
+        expect.leaves().copy_from_slice(&tree.nodes[n..]);
+
+        let mut count = |tree: &mut MerkleTree, updates: &[(usize, Hash)]| {
+            let start = hash::tests::hash_count();
+            let root = tree.batch_update(updates).unwrap();
+            let hashes = hash::tests::hash_count() - start;
+
+            for &(i, leaf) in updates.iter() {
+                expect.leaves()[i] = leaf;
+            }
+            expect.generate();
+            assert_eq!(root, expect.root());
+            assert_eq!(tree.nodes, expect.nodes);
+            hashes
+        };
+
+        let leaf = hash::hash_n_to_n_ret(&hash::tests::HASH_ELEMENT);
+        assert_eq!(count(&mut tree, &[(5, leaf)]), HEIGHT);
+        assert_eq!(count(&mut tree, &[]), 0);
+
+        // Half of the leaves, sharing all their parents.
+        let updates: Vec<(usize, Hash)> = (0..n / 2).map(|i| (i, leaf)).collect();
+        assert_eq!(count(&mut tree, &updates), n / 2);
+
+        // Repeated and unsorted indices.
+        let updates = [(3, leaf), (12, leaf), (3, hash::tests::HASH_ELEMENT)];
+        assert_eq!(count(&mut tree, &updates), 2 * HEIGHT - 1);
+
+        let root = tree.root();
+        assert_eq!(
+            tree.batch_update(&[(0, hash::tests::HASH_ELEMENT), (n, leaf)]),
+            Err(GravityError::InvalidParameter("leaf index"))
+        );
+        assert_eq!(tree.root(), root);
+        assert_eq!(tree.nodes, expect.nodes);
+
+        // Positions of the updated nodes in a tweaked tree.
+        let tweak = Tweak::new(TweakKind::Subtree, &Address::new(1, 2));
+        let mut tweaked = MerkleTree::with_tweak(HEIGHT, tweak);
+        tweaked.leaves().copy_from_slice(&tree.nodes[n..]);
+        tweaked.generate();
+        let mut expect = MerkleTree::with_tweak(HEIGHT, tweak);
+        expect.leaves().copy_from_slice(&tree.nodes[n..]);
+        expect.leaves()[6] = leaf;
+        expect.leaves()[9] = leaf;
+        expect.generate();
+        assert_eq!(
+            tweaked.batch_update(&[(6, leaf), (9, leaf)]),
+            Ok(expect.root())
+        );
+        assert_eq!(tweaked.nodes, expect.nodes);
     }
 
     // Notation for these tests: H(h_i, h_i) = h_{i+1}
@@ -172,7 +654,7 @@ mod tests {
         let h0 = hash::tests::HASH_ELEMENT;
 
         let src = [h0; 1];
-        let dst = merkle_compress_all_leaves(&src, 0);
+        let dst = merkle_compress_all_leaves(&src, 0, &Tweak::NONE);
         assert_eq!(dst, h0);
     }
 
@@ -182,7 +664,7 @@ mod tests {
         let h1 = hash::hash_2n_to_n_ret(&h0, &h0);
 
         let src = [h0; 2];
-        let dst = merkle_compress_all_leaves(&src, 1);
+        let dst = merkle_compress_all_leaves(&src, 1, &Tweak::NONE);
         assert_eq!(dst, h1);
     }
 
@@ -193,7 +675,7 @@ mod tests {
         let h2 = hash::hash_2n_to_n_ret(&h1, &h1);
 
         let src = [h0; 4];
-        let dst = merkle_compress_all_leaves(&src, 2);
+        let dst = merkle_compress_all_leaves(&src, 2, &Tweak::NONE);
         assert_eq!(dst, h2);
     }
 
@@ -205,7 +687,7 @@ mod tests {
         let h3 = hash::hash_2n_to_n_ret(&h2, &h2);
 
         let src = [h0; 8];
-        let dst = merkle_compress_all_leaves(&src, 3);
+        let dst = merkle_compress_all_leaves(&src, 3, &Tweak::NONE);
         assert_eq!(dst, h3);
     }
 
@@ -222,7 +704,7 @@ mod tests {
         let h6 = hash::hash_2n_to_n_ret(&h4, &h5);
 
         let src = [h0, h1, h2, h3];
-        let dst = merkle_compress_all_leaves(&src, 2);
+        let dst = merkle_compress_all_leaves(&src, 2, &Tweak::NONE);
         assert_eq!(dst, h6);
     }
 
@@ -295,7 +777,7 @@ mod tests {
             let mut auth = [Default::default(); 2];
             let root = merkle_gen_auth_leaves(&mut auth, &src, 2, i);
             let mut node = src[i];
-            let index = merkle_compress_auth(&mut node, &auth, 2, i);
+            let index = merkle_compress_auth(&mut node, &auth, 2, i as u64, 0, &Tweak::NONE);
             assert_eq!(index, 0);
             assert_eq!(node, root);
         }
@@ -314,7 +796,7 @@ mod tests {
         let h3 = hash::hash_2n_to_n_ret(&h2, &a3);
 
         let mut node = h0;
-        let index = merkle_compress_auth(&mut node, &auth, 3, 2);
+        let index = merkle_compress_auth(&mut node, &auth, 3, 2, 0, &Tweak::NONE);
         assert_eq!(index, 0);
         assert_eq!(node, h3);
     }
@@ -331,11 +813,107 @@ mod tests {
         let h2 = hash::hash_2n_to_n_ret(&a2, &h1);
 
         let mut node = h0;
-        let index = merkle_compress_auth(&mut node, &auth, 2, 7);
+        let index = merkle_compress_auth(&mut node, &auth, 2, 7, 0, &Tweak::NONE);
         assert_eq!(index, 1);
         assert_eq!(node, h2);
     }
 
+    #[test]
+    fn test_merkle_compress_auth_inclusion_proof() {
+        const HEIGHT: usize = 4;
+        let tweaked = Tweak::new(TweakKind::Subtree, &Address::new(1, 2));
+        for tweak in [Tweak::NONE, tweaked].iter() {
+            let mut tree = MerkleTree::with_tweak(HEIGHT, *tweak);
+            for (i, leaf) in tree.leaves().iter_mut().enumerate() {
+                *leaf = hash::HashChain::skip_to(&hash::tests::HASH_ELEMENT, i);
+            }
+            tree.generate();
+            let root = tree.root();
+            let leaves = tree.nodes[1 << HEIGHT..].to_vec();
+            assert_eq!(merkle_compress_all_leaves(&leaves, HEIGHT, tweak), root);
+
+            let index = 11;
+            let leaf = leaves[index];
+            let mut auth = [Default::default(); HEIGHT];
+            tree.gen_auth(&mut auth, index);
+            let mut expect = [Default::default(); HEIGHT];
+            let mut buf = MerkleBuf::new(HEIGHT);
+            buf.fill_leaves(&leaves);
+            assert_eq!(merkle_gen_auth(&mut expect, &mut buf, index, tweak), root);
+            assert_eq!(auth, expect);
+
+            let verify = |leaf: &Hash, index: u64| -> bool {
+                if check_auth(&auth, index).is_err() {
+                    return false;
+                }
+                let mut node = *leaf;
+                assert_eq!(
+                    merkle_compress_auth(&mut node, &auth, HEIGHT, index, 0, tweak),
+                    0
+                );
+                node == root
+            };
+            assert!(verify(&leaf, index as u64));
+            assert!(!verify(&leaf, index as u64 ^ 1));
+            assert!(!verify(&leaf, index as u64 + (1 << HEIGHT)));
+            assert!(!verify(&hash::hash_n_to_n_ret(&leaf), index as u64));
+            assert_eq!(root_from_auth(&leaf, &auth, index as u64, tweak), root);
+
+            // The same path consumed in two steps.
+            let mut node = leaf;
+            let upper = merkle_compress_auth(&mut node, &auth, 1, index as u64, 0, tweak);
+            assert_eq!(upper, (index >> 1) as u64);
+            assert_eq!(
+                merkle_compress_auth(&mut node, &auth[1..], 3, upper, 1, tweak),
+                0
+            );
+            assert_eq!(node, root);
+        }
+    }
+
+    #[test]
+    fn test_root_from_auth() {
+        let h0 = hash::tests::HASH_ELEMENT;
+        let a1 = hash::hash_n_to_n_ret(&h0);
+        let a2 = hash::hash_n_to_n_ret(&a1);
+        let a3 = hash::hash_n_to_n_ret(&a2);
+        let auth = [a1, a2, a3];
+
+        let h1 = hash::hash_2n_to_n_ret(&h0, &a1);
+        let h2 = hash::hash_2n_to_n_ret(&a2, &h1);
+        let h3 = hash::hash_2n_to_n_ret(&h2, &a3);
+
+        assert_eq!(root_from_auth(&h0, &auth, 2, &Tweak::NONE), h3);
+        assert_eq!(root_from_auth(&h0, &[], 0, &Tweak::NONE), h0);
+        for index in 0..8 {
+            let mut node = h0;
+            merkle_compress_auth(&mut node, &auth, 3, index, 0, &Tweak::NONE);
+            assert_eq!(root_from_auth(&h0, &auth, index, &Tweak::NONE), node);
+        }
+    }
+
+    #[test]
+    fn test_check_auth() {
+        let auth = [hash::tests::HASH_ELEMENT; 3];
+        assert_eq!(check_auth(&auth, 7), Ok(()));
+        assert_eq!(
+            check_auth(&auth, 8),
+            Err(GravityError::InvalidParameter("index"))
+        );
+        assert_eq!(check_auth(&[], 0), Ok(()));
+        assert_eq!(
+            check_auth(&[], 1),
+            Err(GravityError::InvalidParameter("index"))
+        );
+        let auth = [hash::tests::HASH_ELEMENT; 64];
+        assert_eq!(check_auth(&auth, u64::MAX), Ok(()));
+        let auth = [hash::tests::HASH_ELEMENT; 65];
+        assert_eq!(
+            check_auth(&auth, 0),
+            Err(GravityError::InvalidParameter("auth"))
+        );
+    }
+
     #[test]
     fn test_merkle_tree_root() {
         let h0 = hash::tests::HASH_ELEMENT;
@@ -343,7 +921,7 @@ mod tests {
         let h2 = hash::hash_n_to_n_ret(&h1);
         let h3 = hash::hash_n_to_n_ret(&h2);
         let src = [h0, h1, h2, h3];
-        let expect = merkle_compress_all_leaves(&src, 2);
+        let expect = merkle_compress_all_leaves(&src, 2, &Tweak::NONE);
 
         let mut mt = MerkleTree::new(2);
         {
@@ -398,17 +976,17 @@ mod tests {
 
         b.iter(|| {
             let mut root = Default::default();
-            merkle_compress_all(&mut root, black_box(&mut buf));
+            merkle_compress_all(&mut root, black_box(&mut buf), &Tweak::NONE);
             root
         });
     }
 
     fn bench_merkle_gen_auth(b: &mut Bencher, height: usize, index: usize) {
-        let mut buf = MerkleBuf::new(height);
+        let mut buf: MerkleBuf = MerkleBuf::new(height);
         b.iter(|| {
             // TODO: use const generic height once it's available.
             let mut auth = vec![Default::default(); height];
-            let hash = merkle_gen_auth(&mut auth, black_box(&mut buf), index);
+            let hash = merkle_gen_auth(&mut auth, black_box(&mut buf), index, &Tweak::NONE);
             (hash, auth)
         });
     }
@@ -435,7 +1013,14 @@ mod tests {
         let auth = vec![hash::tests::HASH_ELEMENT; height];
         b.iter(|| {
             let mut node = black_box(hash::tests::HASH_ELEMENT);
-            merkle_compress_auth(&mut node, black_box(&auth), height, index);
+            merkle_compress_auth(
+                &mut node,
+                black_box(&auth),
+                height,
+                index as u64,
+                0,
+                &Tweak::NONE,
+            );
             node
         });
     }
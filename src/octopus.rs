@@ -1,34 +1,34 @@
 use crate::config::*;
+use crate::error::{DeserializeError, GravityError};
 use crate::hash;
-use crate::hash::Hash;
+use crate::hash::{ByteSink, Hash, HashBackend, Tweak};
 use crate::merkle;
 use arrayref::array_mut_ref;
 use byteorder::{ByteOrder, LittleEndian};
 use std::mem;
 
-#[derive(Default, Debug, PartialEq, Eq)]
-pub struct Octopus {
-    pub oct: Vec<Hash>,
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct Octopus<H = Hash> {
+    pub oct: Vec<H>,
 }
 
 impl Octopus {
-    pub fn serialize(&self, output: &mut Vec<u8>) {
+    pub(crate) fn write_to<S: ByteSink>(&self, output: &mut S) {
         for x in self.oct.iter() {
-            x.serialize(output);
+            x.write_to(output);
         }
-        // TODO: improve this!
-        let empty = Hash { h: [0; HASH_SIZE] };
         let count = self.oct.len();
         for _ in count..(PORS_K * PORS_TAU) {
-            empty.serialize(output);
+            output.put(&[0; HASH_SIZE]);
         }
 
         let mut block = [0u8; 16];
         LittleEndian::write_u32(array_mut_ref![&mut block, 0, 4], count as u32);
-        output.extend(block.iter());
+        output.put(&block);
     }
 
-    pub fn deserialize<'a, I>(it: &mut I) -> Option<Self>
+    #[deny(clippy::indexing_slicing)]
+    pub fn deserialize<'a, I>(it: &mut I) -> Result<Self, DeserializeError>
     where
         I: Iterator<Item = &'a u8>,
     {
@@ -39,42 +39,91 @@ impl Octopus {
 
         let mut block = [0u8; 4];
         for x in block.iter_mut() {
-            *x = *it.next()?;
+            *x = *it.next().ok_or(DeserializeError::UnexpectedEnd)?;
         }
         let count = LittleEndian::read_u32(&block) as usize;
 
         for _ in 0..12 {
-            if *it.next()? != 0 {
-                return None;
+            if *it.next().ok_or(DeserializeError::UnexpectedEnd)? != 0 {
+                return Err(DeserializeError::NonZeroPadding);
             }
         }
 
         if count > PORS_K * PORS_TAU {
-            return None;
+            return Err(DeserializeError::InvalidOctopusLength);
         }
         let empty = Hash { h: [0; HASH_SIZE] };
-        for i in count..(PORS_K * PORS_TAU) {
-            if octopus.oct[i] != empty {
-                return None;
-            }
+        if octopus.oct.iter().skip(count).any(|x| *x != empty) {
+            return Err(DeserializeError::NonZeroPadding);
         }
         octopus.oct.resize(count, empty);
 
-        Some(octopus)
+        Ok(octopus)
+    }
+
+    // Nodes only, without padding nor length.
+    #[cfg(feature = "experimental")]
+    pub fn serialize_compact(&self, output: &mut Vec<u8>) {
+        for x in self.oct.iter() {
+            x.serialize(output);
+        }
+    }
+
+    #[cfg(feature = "experimental")]
+    #[deny(clippy::indexing_slicing)]
+    pub fn deserialize_compact<'a, I>(it: &mut I, count: usize) -> Result<Self, DeserializeError>
+    where
+        I: Iterator<Item = &'a u8>,
+    {
+        let mut octopus: Octopus = Default::default();
+        for _ in 0..count {
+            octopus.oct.push(Hash::deserialize(it)?);
+        }
+        Ok(octopus)
     }
 }
 
-pub fn merkle_gen_octopus(
-    octopus: &mut Octopus,
-    buf: &mut merkle::MerkleBuf,
+// Number of octopus nodes for the given sorted indices, following the same walk
+// as merkle_gen_octopus without hashing.
+#[cfg(any(test, feature = "experimental"))]
+pub fn octopus_length(indices: &mut [usize], height: usize) -> usize {
+    let mut len = 0;
+    let mut count = indices.len();
+
+    for _ in 0..height {
+        let mut i = 0;
+        let mut j = 0;
+        while i < count {
+            let sibling = indices[i] ^ 1;
+            if i + 1 < count && indices[i + 1] == sibling {
+                i += 1;
+            } else {
+                len += 1;
+            }
+
+            indices[j] = indices[i] >> 1;
+
+            i += 1;
+            j += 1;
+        }
+        count = j;
+    }
+
+    len
+}
+
+pub fn merkle_gen_octopus<H: HashBackend>(
+    octopus: &mut Octopus<H>,
+    buf: &mut merkle::MerkleBuf<H>,
     indices: &mut [usize],
-) -> Hash {
+    tweak: &Tweak,
+) -> H {
     let height = buf.height();
     let mut n = 1 << height;
     let (mut dst, mut src) = buf.split_half_mut();
     let mut count = indices.len();
 
-    for _ in 0..height {
+    for l in 0..height {
         // Copy auth octopus
         let mut i = 0;
         let mut j = 0;
@@ -99,45 +148,51 @@ pub fn merkle_gen_octopus(
         // Compute next layer
         mem::swap(&mut dst, &mut src);
         n >>= 1;
-        hash::hash_compress_pairs(dst, src, n);
+        hash::hash_compress_pairs(dst, src, n, tweak, l as u32 + 1, 0);
     }
 
     dst[0]
 }
 
-pub fn merkle_compress_octopus(
-    nodes: &mut [Hash],
-    octopus: &Octopus,
+pub fn merkle_compress_octopus<H: HashBackend>(
+    nodes: &mut [H],
+    octopus: &Octopus<H>,
     height: usize,
     indices: &mut [usize],
-) -> Option<Hash> {
+    tweak: &Tweak,
+) -> Result<H, GravityError> {
     let octolen = octopus.oct.len();
     let mut len = 0;
     let mut count = indices.len();
 
-    for _ in 0..height {
+    for l in 0..height {
+        let parent = l as u32 + 1;
         let mut i = 0;
         let mut j = 0;
         while i < count {
             let index = indices[i];
+            let at = (index >> 1) as u64;
 
             if index & 1 == 0 {
                 let sibling = index ^ 1;
                 if i + 1 < count && indices[i + 1] == sibling {
-                    nodes[j] = hash::hash_2n_to_n_ret(&nodes[i], &nodes[i + 1]);
+                    nodes[j] =
+                        hash::hash_2n_to_n_at_ret(&nodes[i], &nodes[i + 1], tweak, parent, at);
                     i += 1;
                 } else {
                     if len == octolen {
-                        return None;
+                        return Err(GravityError::Verification);
                     }
-                    nodes[j] = hash::hash_2n_to_n_ret(&nodes[i], &octopus.oct[len]);
+                    nodes[j] =
+                        hash::hash_2n_to_n_at_ret(&nodes[i], &octopus.oct[len], tweak, parent, at);
                     len += 1;
                 }
             } else {
                 if len == octolen {
-                    return None;
+                    return Err(GravityError::Verification);
                 }
-                nodes[j] = hash::hash_2n_to_n_ret(&octopus.oct[len], &nodes[i]);
+                nodes[j] =
+                    hash::hash_2n_to_n_at_ret(&octopus.oct[len], &nodes[i], tweak, parent, at);
                 len += 1;
             }
 
@@ -150,15 +205,69 @@ pub fn merkle_compress_octopus(
     }
 
     if len == octolen {
-        Some(nodes[0])
+        Ok(nodes[0])
     } else {
-        None
+        Err(GravityError::Verification)
+    }
+}
+
+// Authentication path of the leaf at position `leaf` of the sorted `indices`,
+// as if it had been signed alone. Siblings that are not octopus nodes are
+// recomputed from the other leaves. This follows the same walk as
+// merkle_compress_octopus, but keeps the indices of the nodes.
+pub fn octopus_auth_path<H: HashBackend>(
+    leaves: &[H],
+    octopus: &Octopus<H>,
+    height: usize,
+    indices: &[usize],
+    leaf: usize,
+    tweak: &Tweak,
+) -> Result<Vec<H>, GravityError> {
+    let mut nodes: Vec<(usize, H)> = indices
+        .iter()
+        .copied()
+        .zip(leaves.iter().copied())
+        .collect();
+    let mut oct = octopus.oct.iter();
+    let mut index = indices[leaf];
+    let mut auth = Vec::with_capacity(height);
+    for l in 0..height {
+        let mut parents = Vec::with_capacity(nodes.len());
+        let mut i = 0;
+        while i < nodes.len() {
+            let (x, h) = nodes[i];
+            let sibling = if i + 1 < nodes.len() && nodes[i + 1].0 == x ^ 1 {
+                i += 1;
+                nodes[i].1
+            } else {
+                *oct.next().ok_or(GravityError::Verification)?
+            };
+            let (left, right) = if x & 1 == 0 {
+                (h, sibling)
+            } else {
+                (sibling, h)
+            };
+            if x >> 1 == index >> 1 {
+                auth.push(if index & 1 == 0 { right } else { left });
+            }
+            let parent =
+                hash::hash_2n_to_n_at_ret(&left, &right, tweak, l as u32 + 1, (x >> 1) as u64);
+            parents.push((x >> 1, parent));
+            i += 1;
+        }
+        nodes = parents;
+        index >>= 1;
     }
+    if oct.next().is_some() {
+        return Err(GravityError::Verification);
+    }
+    Ok(auth)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hash::TweakKind;
     use arrayref::array_ref;
 
     fn merkle_gen_octopus_leaves(
@@ -166,6 +275,7 @@ mod tests {
         leaves: &[Hash],
         height: usize,
         indices: &mut [usize],
+        tweak: &Tweak,
     ) -> Hash {
         let count = leaves.len();
         assert_eq!(count, 1 << height);
@@ -173,7 +283,7 @@ mod tests {
         let mut buf = merkle::MerkleBuf::new(height);
         buf.fill_leaves(leaves);
 
-        merkle_gen_octopus(octopus, &mut buf, indices)
+        merkle_gen_octopus(octopus, &mut buf, indices, tweak)
     }
 
     #[test]
@@ -199,7 +309,8 @@ mod tests {
 
         let src = [h0, h1, h2, h3, h4, h5, h6, h7];
         let mut octopus = Default::default();
-        let root = merkle_gen_octopus_leaves(&mut octopus, &src, 3, &mut [0, 2, 3, 6]);
+        let root =
+            merkle_gen_octopus_leaves(&mut octopus, &src, 3, &mut [0, 2, 3, 6], &Tweak::NONE);
         assert_eq!(
             octopus,
             Octopus {
@@ -209,6 +320,22 @@ mod tests {
         assert_eq!(root, h14);
     }
 
+    #[cfg(feature = "experimental")]
+    #[test]
+    fn test_octopus_length() {
+        let src = vec![hash::tests::HASH_ELEMENT; PORS_T];
+        let mut buf = merkle::MerkleBuf::new(PORS_TAU);
+        hash::hash_parallel(buf.slice_leaves_mut(), &src, PORS_T, &Tweak::NONE);
+
+        let mut subset = fake_pors_subset();
+        let mut octopus: Octopus = Default::default();
+        merkle_gen_octopus(&mut octopus, &mut buf, &mut subset.clone(), &Tweak::NONE);
+        assert_eq!(octopus_length(&mut subset, PORS_TAU), octopus.oct.len());
+
+        let mut indices = [0, 2, 3];
+        assert_eq!(octopus_length(&mut indices, 3), 2);
+    }
+
     #[test]
     fn test_merkle_gen_compress_octopus() {
         let h0 = hash::tests::HASH_ELEMENT;
@@ -220,17 +347,37 @@ mod tests {
         let h6 = hash::hash_n_to_n_ret(&h5);
         let h7 = hash::hash_n_to_n_ret(&h6);
         let src = [h0, h1, h2, h3, h4, h5, h6, h7];
+        let tweaks = [
+            Tweak::NONE,
+            Tweak::new(TweakKind::Pors, &address::Address::new(1, 2)),
+        ];
 
         // Test all combinations of 3 indices
-        for i in 0..6 {
-            for j in (i + 1)..7 {
-                for k in (j + 1)..8 {
-                    let mut octopus = Default::default();
-                    let root = merkle_gen_octopus_leaves(&mut octopus, &src, 3, &mut [i, j, k]);
-                    let mut nodes = [src[i], src[j], src[k]];
-                    let compressed =
-                        merkle_compress_octopus(&mut nodes, &octopus, 3, &mut [i, j, k]);
-                    assert_eq!(compressed, Some(root));
+        for tweak in tweaks.iter() {
+            let expect = merkle::merkle_compress_all_leaves(&src, 3, tweak);
+            for i in 0..6 {
+                for j in (i + 1)..7 {
+                    for k in (j + 1)..8 {
+                        let mut octopus = Default::default();
+                        let root =
+                            merkle_gen_octopus_leaves(&mut octopus, &src, 3, &mut [i, j, k], tweak);
+                        assert_eq!(root, expect);
+                        let mut nodes = [src[i], src[j], src[k]];
+                        let compressed =
+                            merkle_compress_octopus(&mut nodes, &octopus, 3, &mut [i, j, k], tweak);
+                        assert_eq!(compressed, Ok(root));
+
+                        let leaves = [src[i], src[j], src[k]];
+                        for (t, &index) in [i, j, k].iter().enumerate() {
+                            let auth =
+                                octopus_auth_path(&leaves, &octopus, 3, &[i, j, k], t, tweak)
+                                    .unwrap();
+                            assert_eq!(
+                                merkle::root_from_auth(&src[index], &auth, index as u64, tweak),
+                                root
+                            );
+                        }
+                    }
                 }
             }
         }
@@ -248,8 +395,13 @@ mod tests {
         let mut indices = [0, 2, 3, 6];
         b.iter(|| {
             let mut octopus = Default::default();
-            let hash =
-                merkle_gen_octopus_leaves(&mut octopus, black_box(&src), HEIGHT, &mut indices);
+            let hash = merkle_gen_octopus_leaves(
+                &mut octopus,
+                black_box(&src),
+                HEIGHT,
+                &mut indices,
+                &Tweak::NONE,
+            );
             (hash, octopus)
         });
     }
@@ -258,12 +410,13 @@ mod tests {
     fn bench_merkle_gen_octopus_pors(b: &mut Bencher) {
         let src = vec![hash::tests::HASH_ELEMENT; PORS_T];
         let mut buf = merkle::MerkleBuf::new(PORS_TAU);
-        hash::hash_parallel(buf.slice_leaves_mut(), &src, PORS_T);
+        hash::hash_parallel(buf.slice_leaves_mut(), &src, PORS_T, &Tweak::NONE);
 
         let mut subset = fake_pors_subset();
         b.iter(|| {
             let mut octopus = Default::default();
-            let hash = merkle_gen_octopus(&mut octopus, black_box(&mut buf), &mut subset);
+            let hash =
+                merkle_gen_octopus(&mut octopus, black_box(&mut buf), &mut subset, &Tweak::NONE);
             (hash, octopus)
         });
     }
@@ -275,7 +428,13 @@ mod tests {
         let mut indices = [0, 2, 3];
 
         let mut octopus = Default::default();
-        let _ = merkle_gen_octopus_leaves(&mut octopus, &src, HEIGHT, &mut indices.clone());
+        let _ = merkle_gen_octopus_leaves(
+            &mut octopus,
+            &src,
+            HEIGHT,
+            &mut indices.clone(),
+            &Tweak::NONE,
+        );
 
         let mut nodes = indices.map(|i| src[i]);
         b.iter(|| {
@@ -284,6 +443,7 @@ mod tests {
                 black_box(&octopus),
                 HEIGHT,
                 &mut indices,
+                &Tweak::NONE,
             )
         })
     }
@@ -292,11 +452,11 @@ mod tests {
     fn bench_merkle_compress_octopus_pors(b: &mut Bencher) {
         let src = vec![hash::tests::HASH_ELEMENT; PORS_T];
         let mut buf = merkle::MerkleBuf::new(PORS_TAU);
-        hash::hash_parallel(buf.slice_leaves_mut(), &src, PORS_T);
+        hash::hash_parallel(buf.slice_leaves_mut(), &src, PORS_T, &Tweak::NONE);
 
         let mut subset = fake_pors_subset();
         let mut octopus = Default::default();
-        merkle_gen_octopus(&mut octopus, &mut buf, &mut subset.clone());
+        merkle_gen_octopus(&mut octopus, &mut buf, &mut subset.clone(), &Tweak::NONE);
 
         let mut nodes = subset.map(|i| src[i]);
         b.iter(|| {
@@ -305,6 +465,7 @@ mod tests {
                 black_box(&octopus),
                 PORS_TAU,
                 &mut subset,
+                &Tweak::NONE,
             )
         });
     }
@@ -1,65 +1,255 @@
 use crate::address;
 use crate::config::*;
+use crate::error::{DeserializeError, GravityError};
 use crate::hash;
-use crate::hash::Hash;
+use crate::hash::{ByteSink, Hash, HashBackend, Tweak, TweakKind};
 use crate::merkle;
 use crate::octopus;
 use crate::prng;
+use crate::prng::{BlockPrng, SeedablePrng};
 use arrayref::array_ref;
 use byteorder::{BigEndian, ByteOrder};
+use std::sync::Mutex;
 
-pub struct SecKey {
-    values: Vec<Hash>,
+pub struct SecKey<H = Hash> {
+    values: Vec<H>,
+    tweak: Tweak,
 }
 #[cfg(test)]
-pub struct PubKey(Hash);
-#[derive(Default)]
-pub struct Signature {
-    pepper: Hash,
-    values: [Hash; PORS_K],
-    octopus: octopus::Octopus,
+pub struct PubKey<H = Hash>(pub H);
+#[derive(Clone, Default)]
+pub struct Signature<H = Hash> {
+    pepper: H,
+    values: [H; PORS_K],
+    octopus: octopus::Octopus<H>,
 }
 
-impl SecKey {
-    pub fn new(prng: &prng::Prng, address: &address::Address) -> Self {
+// Forced PORS selection of sign_with_selection. The pepper normally derives from
+// the secret salt and the message; forcing it lets the caller pick the instance
+// and subset (by searching peppers), hence reuse a PORS instance at will. This is
+// insecure, for tests and research only. The pepper is part of the signature, so
+// verification is unchanged.
+#[cfg(any(test, feature = "test-utils"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Selection<H = Hash> {
+    pub pepper: H,
+}
+
+// Bounded LRU cache of PORS secret values and leaves, keyed by instance. Each
+// entry holds 2 * PORS_T hashes. Entries are wiped when evicted or dropped.
+// With the memlock feature, the secret values are locked in memory if possible
+// (PORS_T hashes per entry may exceed the limit of locked memory).
+pub struct Cache {
+    capacity: usize,
+    entries: Mutex<Vec<CacheEntry>>,
+}
+
+// With the memlock feature, the secret values of an entry are in pages of their
+// own, so that unlocking them on eviction leaves other secrets locked.
+#[cfg(feature = "memlock")]
+type CachedValues = crate::memlock::LockedSlice<Hash>;
+#[cfg(not(feature = "memlock"))]
+type CachedValues = Vec<Hash>;
+
+struct CacheEntry {
+    instance: address::SubtreeInstance,
+    values: CachedValues,
+    tweak: Tweak,
+    leaves: Vec<Hash>,
+}
+
+impl<H: HashBackend> SecKey<H> {
+    pub fn new<P: BlockPrng<Block = H>>(prng: &P, address: &address::Address) -> Self {
         let mut sk = SecKey {
             values: vec![Default::default(); PORS_T],
+            tweak: Tweak::new(TweakKind::Pors, address),
         };
         prng.genblocks(sk.values.as_mut_slice(), address);
         sk
     }
 
     #[cfg(test)]
-    pub fn genpk(&self) -> PubKey {
+    pub fn genpk(&self) -> PubKey<H> {
         let mut buf = vec![Default::default(); PORS_T];
-        hash::hash_parallel_all(buf.as_mut_slice(), self.values.as_slice());
-        PubKey(merkle::merkle_compress_all_leaves(buf.as_slice(), PORS_TAU))
+        hash::hash_parallel_all(buf.as_mut_slice(), self.values.as_slice(), &self.tweak);
+        PubKey(merkle::merkle_compress_all_leaves(
+            buf.as_slice(),
+            PORS_TAU,
+            &self.tweak,
+        ))
     }
 
-    #[allow(clippy::needless_range_loop)]
-    pub fn sign_subset(&self, pepper: Hash, mut subset: [usize; PORS_K]) -> (Hash, Signature) {
-        let mut sign = Signature {
-            pepper,
-            values: [Default::default(); PORS_K],
-            octopus: Default::default(),
-        };
+    pub fn sign_subset(&self, pepper: H, subset: [usize; PORS_K]) -> (H, Signature<H>) {
+        let mut buf = merkle::MerkleBuf::new(PORS_TAU);
+        hash::hash_parallel(
+            buf.slice_leaves_mut(),
+            self.values.as_slice(),
+            PORS_T,
+            &self.tweak,
+        );
+        self.sign_subset_buf(&mut buf, pepper, subset)
+    }
+
+    // Same as sign_subset, with the leaves already hashed.
+    pub fn sign_subset_leaves(
+        &self,
+        leaves: &[H],
+        pepper: H,
+        subset: [usize; PORS_K],
+    ) -> (H, Signature<H>) {
+        sign_subset_leaves(&self.values, &self.tweak, leaves, pepper, subset)
+    }
 
-        for i in 0..PORS_K {
-            sign.values[i] = self.values[subset[i]];
+    fn sign_subset_buf(
+        &self,
+        buf: &mut merkle::MerkleBuf<H>,
+        pepper: H,
+        subset: [usize; PORS_K],
+    ) -> (H, Signature<H>) {
+        sign_subset_buf(&self.values, &self.tweak, buf, pepper, subset)
+    }
+}
+
+// SecKey::sign_subset_leaves for the secret values of a key, which need not be
+// in a SecKey (see CachedValues).
+fn sign_subset_leaves<H: HashBackend>(
+    values: &[H],
+    tweak: &Tweak,
+    leaves: &[H],
+    pepper: H,
+    subset: [usize; PORS_K],
+) -> (H, Signature<H>) {
+    let mut buf = merkle::MerkleBuf::new(PORS_TAU);
+    buf.slice_leaves_mut().copy_from_slice(leaves);
+    sign_subset_buf(values, tweak, &mut buf, pepper, subset)
+}
+
+#[allow(clippy::needless_range_loop)]
+fn sign_subset_buf<H: HashBackend>(
+    values: &[H],
+    tweak: &Tweak,
+    buf: &mut merkle::MerkleBuf<H>,
+    pepper: H,
+    mut subset: [usize; PORS_K],
+) -> (H, Signature<H>) {
+    let mut sign = Signature {
+        pepper,
+        values: [Default::default(); PORS_K],
+        octopus: Default::default(),
+    };
+
+    for i in 0..PORS_K {
+        sign.values[i] = values[subset[i]];
+    }
+
+    let root = octopus::merkle_gen_octopus(&mut sign.octopus, buf, &mut subset, tweak);
+
+    (root, sign)
+}
+
+impl Cache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(Vec::with_capacity(capacity)),
         }
+    }
 
-        let mut buf = merkle::MerkleBuf::new(PORS_TAU);
-        hash::hash_parallel(buf.slice_leaves_mut(), self.values.as_slice(), PORS_T);
-        let root = octopus::merkle_gen_octopus(&mut sign.octopus, &mut buf, &mut subset);
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn count(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    // Signs with the cached entry for the instance, generating it on a miss.
+    // Entries are kept most-recently-used first.
+    pub fn sign_subset(
+        &self,
+        prng: &prng::Prng,
+        address: &address::Address,
+        pepper: Hash,
+        subset: [usize; PORS_K],
+    ) -> (Hash, Signature) {
+        if self.capacity == 0 {
+            return SecKey::new(prng, address).sign_subset(pepper, subset);
+        }
+
+        let instance = address.subtree_instance();
+        let mut entries = self.entries.lock().unwrap();
+        match entries.iter().position(|e| e.instance == instance) {
+            Some(i) => {
+                let entry = entries.remove(i);
+                entries.insert(0, entry);
+            }
+            None => {
+                // As SecKey::new, into the values of the entry.
+                #[cfg(feature = "memlock")]
+                let mut values = CachedValues::new(PORS_T);
+                #[cfg(not(feature = "memlock"))]
+                let mut values = vec![Default::default(); PORS_T];
+                prng.genblocks(&mut values, address);
+                let tweak = Tweak::new(TweakKind::Pors, address);
+                let mut leaves = vec![Default::default(); PORS_T];
+                hash::hash_parallel_all(leaves.as_mut_slice(), &values, &tweak);
+                entries.truncate(self.capacity - 1);
+                entries.insert(
+                    0,
+                    CacheEntry {
+                        instance,
+                        values,
+                        tweak,
+                        leaves,
+                    },
+                );
+            }
+        }
+
+        let entry = &entries[0];
+        sign_subset_leaves(&entry.values, &entry.tweak, &entry.leaves, pepper, subset)
+    }
+}
+
+// With the memlock feature, the values are also wiped and unlocked when the
+// LockedSlice is dropped.
+impl Drop for CacheEntry {
+    fn drop(&mut self) {
+        hash::wipe(&mut self.values);
+        hash::wipe(&mut self.leaves);
+    }
+}
+
+// Random values and octopus nodes, with at most OCTOPUS_MAX_NODES nodes so that
+// the signature serializes, but of any count in that range, consistent with the
+// subset or not.
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Signature {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let pepper = u.arbitrary()?;
+        let values = u.arbitrary()?;
+        let count = u.int_in_range(0..=OCTOPUS_MAX_NODES)?;
+        let oct = (0..count)
+            .map(|_| u.arbitrary())
+            .collect::<arbitrary::Result<Vec<Hash>>>()?;
+        Ok(Signature {
+            pepper,
+            values,
+            octopus: octopus::Octopus { oct },
+        })
+    }
 
-        (root, sign)
+    fn size_hint(_depth: usize) -> (usize, Option<usize>) {
+        let min = HASH_SIZE * (1 + PORS_K);
+        let max = min + std::mem::size_of::<usize>() + HASH_SIZE * OCTOPUS_MAX_NODES;
+        (min, Some(max))
     }
 }
 
 #[cfg(test)]
-impl PubKey {
-    pub fn verify(&self, sign: &Signature, msg: &Hash) -> bool {
-        if let Some((_, h)) = sign.extract(msg) {
+impl<H: HashBackend> PubKey<H> {
+    pub fn verify(&self, sign: &Signature<H>, msg: &H) -> bool {
+        if let Ok((_, h)) = sign.extract(msg) {
             self.0 == h
         } else {
             false
@@ -67,25 +257,160 @@ impl PubKey {
     }
 }
 
-impl Signature {
-    pub fn extract(&self, msg: &Hash) -> Option<(address::Address, Hash)> {
+impl<H: HashBackend> Signature<H> {
+    // Input of choose_indices, with the message.
+    pub fn pepper(&self) -> &H {
+        &self.pepper
+    }
+
+    pub fn octopus_len(&self) -> usize {
+        self.octopus.oct.len()
+    }
+
+    // Introspection for audit tools. The PORS_K revealed values are leaves of a
+    // single tree of height PORS_TAU, whose authentication paths are merged into
+    // the octopus; each of them is exposed here as its own one-leaf tree. The
+    // leaf indices and paths depend on the subset, hence on the message.
+    pub fn num_trees(&self) -> usize {
+        PORS_K
+    }
+
+    // One-time secret value; the leaf of the tree is its hash, tweaked at the leaf
+    // index with the tweaked feature. These methods fail
+    // if t is not below num_trees().
+    pub fn tree_leaf_value(&self, t: usize) -> Result<H, GravityError> {
+        self.values
+            .get(t)
+            .copied()
+            .ok_or(GravityError::InvalidParameter("tree"))
+    }
+
+    pub fn tree_leaf_index(&self, t: usize, msg: &H) -> Result<usize, GravityError> {
+        let (_, subset) = obtain_address_subset(&self.pepper, msg);
+        subset
+            .get(t)
+            .copied()
+            .ok_or(GravityError::InvalidParameter("tree"))
+    }
+
+    // Also fails if the octopus does not have the expected length for the
+    // message.
+    pub fn tree_auth_path(&self, t: usize, msg: &H) -> Result<Vec<H>, GravityError> {
+        if t >= PORS_K {
+            return Err(GravityError::InvalidParameter("tree"));
+        }
+        let (address, subset) = obtain_address_subset(&self.pepper, msg);
+        let tweak = Tweak::new(TweakKind::Pors, &address);
+        let mut leaves = [Default::default(); PORS_K];
+        hash_subset_leaves(&mut leaves, &self.values, &subset, &tweak);
+        octopus::octopus_auth_path(&leaves, &self.octopus, PORS_TAU, &subset, t, &tweak)
+    }
+
+    // Root of tree t from its leaf and tree_auth_path. The trees share the PORS
+    // tree, so that the roots of a valid signature are all the PORS public key.
+    pub fn tree_root(&self, t: usize, msg: &H) -> Result<H, GravityError> {
+        if t >= PORS_K {
+            return Err(GravityError::InvalidParameter("tree"));
+        }
+        let (address, subset) = obtain_address_subset(&self.pepper, msg);
+        let tweak = Tweak::new(TweakKind::Pors, &address);
+        let mut leaves = [Default::default(); PORS_K];
+        hash_subset_leaves(&mut leaves, &self.values, &subset, &tweak);
+        self.root_from_tree(&leaves, &subset, t, &tweak)
+    }
+
+    pub fn all_tree_roots(&self, msg: &H) -> Result<[H; PORS_K], GravityError> {
+        let (address, subset) = obtain_address_subset(&self.pepper, msg);
+        let tweak = Tweak::new(TweakKind::Pors, &address);
+        let mut leaves = [Default::default(); PORS_K];
+        hash_subset_leaves(&mut leaves, &self.values, &subset, &tweak);
+        let mut roots = [Default::default(); PORS_K];
+        for (t, root) in roots.iter_mut().enumerate() {
+            *root = self.root_from_tree(&leaves, &subset, t, &tweak)?;
+        }
+        Ok(roots)
+    }
+
+    fn root_from_tree(
+        &self,
+        leaves: &[H; PORS_K],
+        subset: &[usize; PORS_K],
+        t: usize,
+        tweak: &Tweak,
+    ) -> Result<H, GravityError> {
+        let auth = octopus::octopus_auth_path(leaves, &self.octopus, PORS_TAU, subset, t, tweak)?;
+        Ok(merkle::root_from_auth(
+            &leaves[t],
+            &auth,
+            subset[t] as u64,
+            tweak,
+        ))
+    }
+
+    // Address of the key pair that signs msg with this pepper, as in extract,
+    // without checking the signature.
+    pub fn address(&self, msg: &H) -> address::Address {
+        obtain_address_subset(&self.pepper, msg).0
+    }
+
+    pub fn extract(&self, msg: &H) -> Result<(address::Address, H), GravityError> {
         let (address, mut subset) = obtain_address_subset(&self.pepper, msg);
+        let tweak = Tweak::new(TweakKind::Pors, &address);
         let mut nodes = [Default::default(); PORS_K];
-        hash::hash_parallel_all(&mut nodes, &self.values);
-        let root =
-            octopus::merkle_compress_octopus(&mut nodes, &self.octopus, PORS_TAU, &mut subset);
+        hash_subset_leaves(&mut nodes, &self.values, &subset, &tweak);
+        let root = octopus::merkle_compress_octopus(
+            &mut nodes,
+            &self.octopus,
+            PORS_TAU,
+            &mut subset,
+            &tweak,
+        );
         root.map(|h| (address, h))
     }
+}
 
+impl Signature {
     pub fn serialize(&self, output: &mut Vec<u8>) {
+        self.write_to(output);
+    }
+
+    pub(crate) fn write_to<S: ByteSink>(&self, output: &mut S) {
+        self.pepper.write_to(output);
+        for x in self.values.iter() {
+            x.write_to(output);
+        }
+        self.octopus.write_to(output);
+    }
+
+    #[deny(clippy::indexing_slicing)]
+    pub fn deserialize<'a, I>(it: &mut I) -> Result<Self, DeserializeError>
+    where
+        I: Iterator<Item = &'a u8>,
+    {
+        let mut sign = Signature {
+            pepper: Hash::deserialize(it)?,
+            ..Default::default()
+        };
+        for x in sign.values.iter_mut() {
+            *x = Hash::deserialize(it)?;
+        }
+        sign.octopus = octopus::Octopus::deserialize(it)?;
+        Ok(sign)
+    }
+
+    #[cfg(feature = "experimental")]
+    pub fn serialize_compact(&self, output: &mut Vec<u8>) {
         self.pepper.serialize(output);
         for x in self.values.iter() {
             x.serialize(output);
         }
-        self.octopus.serialize(output);
+        self.octopus.serialize_compact(output);
     }
 
-    pub fn deserialize<'a, I>(it: &mut I) -> Option<Self>
+    // The octopus length is recomputed from the subset selected by the message.
+    #[cfg(feature = "experimental")]
+    #[deny(clippy::indexing_slicing)]
+    pub fn deserialize_compact<'a, I>(it: &mut I, msg: &Hash) -> Result<Self, DeserializeError>
     where
         I: Iterator<Item = &'a u8>,
     {
@@ -96,23 +421,143 @@ impl Signature {
         for x in sign.values.iter_mut() {
             *x = Hash::deserialize(it)?;
         }
-        sign.octopus = octopus::Octopus::deserialize(it)?;
-        Some(sign)
+        let (_, mut subset) = obtain_address_subset(&sign.pepper, msg);
+        let count = octopus::octopus_length(&mut subset, PORS_TAU);
+        sign.octopus = octopus::Octopus::deserialize_compact(it, count)?;
+        Ok(sign)
+    }
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+impl<H: HashBackend> Selection<H> {
+    // Instance and sorted subset selected for msg.
+    pub fn resolve(&self, msg: &H) -> (address::Address, [usize; PORS_K]) {
+        obtain_address_subset(&self.pepper, msg)
+    }
+}
+
+// Tweak of the hashes of the message: the pepper, at (0, 0), and the seed of the
+// subset, at (1, 0).
+fn message_tweak() -> Tweak {
+    Tweak::new(TweakKind::Message, &address::Address::new(0, 0))
+}
+
+// The leaves of the revealed values, at their index in the PORS tree.
+fn hash_subset_leaves<H: HashBackend>(
+    dst: &mut [H; PORS_K],
+    values: &[H; PORS_K],
+    subset: &[usize; PORS_K],
+    tweak: &Tweak,
+) {
+    for ((dst, src), &index) in dst.iter_mut().zip(values.iter()).zip(subset.iter()) {
+        hash::hash_n_to_n_at(dst, src, tweak, 0, index as u64);
+    }
+}
+
+// Public key of the PORS key pair at `address`, as SecKey::new and genpk, in
+// workspaces of PORS_T values and a MerkleBuf of height PORS_TAU reused across
+// calls. Pure PORST keygen derives 2^GRAVITY_C of them.
+pub fn genpk_with<P: BlockPrng<Block = H>, H: HashBackend>(
+    prng: &P,
+    address: &address::Address,
+    values: &mut [H],
+    buf: &mut merkle::MerkleBuf<H>,
+) -> H {
+    let tweak = Tweak::new(TweakKind::Pors, address);
+    prng.genblocks(values, address);
+    hash::hash_parallel_all(buf.slice_leaves_mut(), values, &tweak);
+    let mut root = Default::default();
+    merkle::merkle_compress_all(&mut root, buf, &tweak);
+    root
+}
+
+fn derive_pepper<H: HashBackend>(salt: &H, msg: &H) -> H {
+    hash::hash_2n_to_n_at_ret(salt, msg, &message_tweak(), 0, 0)
+}
+
+pub fn sign<P: BlockPrng>(
+    prng: &P,
+    salt: &P::Block,
+    msg: &P::Block,
+) -> (address::Address, P::Block, Signature<P::Block>) {
+    let pepper = derive_pepper(salt, msg);
+    let (address, root, sign, _) = sign_pepper(prng, pepper, msg);
+    (address, root, sign)
+}
+
+// Same as sign, also returning the PORS_K leaf indices revealed, in increasing
+// order, for audit logs: choose_indices of the pepper of the signature and msg.
+pub fn sign_with_indices<P: BlockPrng>(
+    prng: &P,
+    salt: &P::Block,
+    msg: &P::Block,
+) -> (address::Address, P::Block, Signature<P::Block>, Vec<usize>) {
+    let pepper = derive_pepper(salt, msg);
+    let (address, root, sign, subset) = sign_pepper(prng, pepper, msg);
+    (address, root, sign, subset.to_vec())
+}
+
+// Same as sign, with the pepper of `forced` instead of the one derived from the
+// salt. Insecure, see Selection.
+#[cfg(any(test, feature = "test-utils"))]
+pub fn sign_with_selection<P: BlockPrng>(
+    prng: &P,
+    salt: &P::Block,
+    msg: &P::Block,
+    forced: Option<Selection<P::Block>>,
+) -> (address::Address, P::Block, Signature<P::Block>) {
+    match forced {
+        Some(selection) => {
+            let (address, root, sign, _) = sign_pepper(prng, selection.pepper, msg);
+            (address, root, sign)
+        }
+        None => sign(prng, salt, msg),
     }
 }
 
-pub fn sign(prng: &prng::Prng, salt: &Hash, msg: &Hash) -> (address::Address, Hash, Signature) {
-    let pepper = hash::hash_2n_to_n_ret(salt, msg);
+fn sign_pepper<P: BlockPrng>(
+    prng: &P,
+    pepper: P::Block,
+    msg: &P::Block,
+) -> (
+    address::Address,
+    P::Block,
+    Signature<P::Block>,
+    [usize; PORS_K],
+) {
     let (address, subset) = obtain_address_subset(&pepper, msg);
 
     let sk = SecKey::new(prng, &address);
     let (root, sign) = sk.sign_subset(pepper, subset);
+    (address, root, sign, subset)
+}
+
+pub fn sign_cached(
+    prng: &prng::Prng,
+    cache: &Cache,
+    salt: &Hash,
+    msg: &Hash,
+) -> (address::Address, Hash, Signature) {
+    let pepper = derive_pepper(salt, msg);
+    let (address, subset) = obtain_address_subset(&pepper, msg);
+
+    let (root, sign) = cache.sign_subset(prng, &address, pepper, subset);
     (address, root, sign)
 }
 
+// The PORS_K distinct leaf indices in [0, PORS_T) revealed for msg, in
+// increasing order. They derive from the pepper of the signature (see
+// Signature::pepper) and the message, not from the message alone.
+pub fn choose_indices(pepper: &Hash, msg: &Hash) -> Vec<usize> {
+    obtain_address_subset(pepper, msg).1.to_vec()
+}
+
 #[allow(clippy::needless_range_loop)]
 #[allow(clippy::assertions_on_constants)]
-fn obtain_address_subset(pepper: &Hash, msg: &Hash) -> (address::Address, [usize; PORS_K]) {
+fn obtain_address_subset<H: HashBackend>(
+    pepper: &H,
+    msg: &H,
+) -> (address::Address, [usize; PORS_K]) {
     // TODO: use some kind of static_assert instead
     assert!(PORS_K > 0, "PORS is only implemented for PORS_K > 0");
     assert!(
@@ -120,13 +565,13 @@ fn obtain_address_subset(pepper: &Hash, msg: &Hash) -> (address::Address, [usize
         "PORS is only implemented for PORS_K <= PORS_T"
     );
 
-    let seed = hash::hash_2n_to_n_ret(pepper, msg);
-    let prng = prng::Prng::new(&seed);
+    let seed = hash::hash_2n_to_n_at_ret(pepper, msg, &message_tweak(), 1, 0);
+    let prng = H::Prng::new(&seed);
     let address = address::Address::new(0, 0);
 
-    let mut block = Default::default();
+    let mut block: H = Default::default();
     prng.genblock(&mut block, &address, 0);
-    let instance: u64 = BigEndian::read_u64(array_ref![block.h, 24, 8]);
+    let instance: u64 = BigEndian::read_u64(array_ref![block.as_ref(), 24, 8]);
     let instance = instance & GRAVITY_MASK;
 
     let mut subset: [usize; PORS_K] = [0; PORS_K];
@@ -136,7 +581,7 @@ fn obtain_address_subset(pepper: &Hash, msg: &Hash) -> (address::Address, [usize
     'outer: while count < PORS_K {
         prng.genblock(&mut block, &address, counter);
         'inner: for i in 0..8 {
-            let x = BigEndian::read_u32(array_ref![block.h, 4 * i, 4]) as usize;
+            let x = BigEndian::read_u32(array_ref![block.as_ref(), 4 * i, 4]) as usize;
             let x = x % PORS_T;
 
             for i in 0..count {
@@ -169,7 +614,7 @@ mod tests {
         let msg = hash::tests::HASH_ELEMENT;
 
         let prng = prng::Prng::new(&seed);
-        let pepper = hash::hash_2n_to_n_ret(&salt, &msg);
+        let pepper = derive_pepper(&salt, &msg);
         let (address, subset) = obtain_address_subset(&pepper, &msg);
 
         let sk = SecKey::new(&prng, &address);
@@ -179,6 +624,174 @@ mod tests {
         assert!(pk.verify(&sign, &msg));
     }
 
+    #[test]
+    fn test_cache() {
+        let prng = prng::Prng::new(&hash::tests::HASH_ELEMENT);
+        let msg = hash::tests::HASH_ELEMENT;
+        let cache = Cache::new(2);
+        for salt in [[1u8; 32], [2u8; 32], [1u8; 32], [3u8; 32]] {
+            let pepper = derive_pepper(&Hash { h: salt }, &msg);
+            let (address, subset) = obtain_address_subset(&pepper, &msg);
+            let sk = SecKey::new(&prng, &address);
+            let (root, sign) = cache.sign_subset(&prng, &address, pepper, subset);
+            let (expect_root, expect) = sk.sign_subset(pepper, subset);
+            assert_eq!(root, expect_root);
+            assert_eq!(sign.values, expect.values);
+
+            let entries = cache.entries.lock().unwrap();
+            assert!(entries[0].values[..] == sk.values[..]);
+            // Each entry has pages of its own, see CachedValues.
+            #[cfg(feature = "memlock")]
+            assert_eq!(entries[0].values.as_ptr() as usize % 4096, 0);
+        }
+        assert_eq!(cache.count(), 2);
+    }
+
+    #[test]
+    fn test_genpk_with() {
+        let prng = prng::Prng::new(&hash::tests::HASH_ELEMENT);
+        let mut values = vec![Hash::default(); PORS_T];
+        let mut buf = merkle::MerkleBuf::new(PORS_TAU);
+        for instance in [0, 1, 5] {
+            let address = address::Address::new(GRAVITY_D as u32, instance);
+            let pk = SecKey::new(&prng, &address).genpk();
+            assert_eq!(genpk_with(&prng, &address, &mut values, &mut buf), pk.0);
+        }
+    }
+
+    #[test]
+    fn test_sign_with_selection() {
+        let prng = prng::Prng::new(&hash::tests::HASH_ELEMENT);
+        let salt = hash::tests::HASH_ELEMENT;
+        let msg = hash::hash_n_to_n_ret(&hash::tests::HASH_ELEMENT);
+
+        let (address, root, sign) = sign(&prng, &salt, &msg);
+        let (forced_address, forced_root, _) = sign_with_selection(&prng, &salt, &msg, None);
+        assert_eq!(forced_address, address);
+        assert_eq!(forced_root, root);
+
+        let selection = Selection { pepper: msg };
+        let (forced_address, forced_root, forced) =
+            sign_with_selection(&prng, &salt, &msg, Some(selection));
+        assert_eq!(forced.pepper, msg);
+        assert_eq!(forced_address, selection.resolve(&msg).0);
+        assert_ne!(forced_address, address);
+        let (extracted_address, extracted_root) = forced.extract(&msg).unwrap();
+        assert_eq!(extracted_address, forced_address);
+        assert_eq!(extracted_root, forced_root);
+        assert!(SecKey::new(&prng, &forced_address)
+            .genpk()
+            .verify(&forced, &msg));
+        assert_ne!(forced.pepper, sign.pepper);
+    }
+
+    #[test]
+    fn test_tree_introspection() {
+        let prng = prng::Prng::new(&hash::tests::HASH_ELEMENT);
+        let salt = hash::tests::HASH_ELEMENT;
+        let msg = hash::hash_n_to_n_ret(&hash::tests::HASH_ELEMENT);
+
+        let (address, root, mut sign) = sign(&prng, &salt, &msg);
+        assert_eq!(sign.extract(&msg).unwrap().1, root);
+        assert_eq!(sign.address(&msg), address);
+        assert_eq!(sign.num_trees(), PORS_K);
+        let tweak = Tweak::new(TweakKind::Pors, &address);
+
+        // PORS verification from the introspection methods alone.
+        let verify = |sign: &Signature| -> Option<Hash> {
+            let mut roots = (0..sign.num_trees()).map(|t| {
+                let index = sign.tree_leaf_index(t, &msg).ok()? as u64;
+                let mut leaf = Hash::default();
+                hash::hash_n_to_n_at(&mut leaf, &sign.tree_leaf_value(t).ok()?, &tweak, 0, index);
+                let auth = sign.tree_auth_path(t, &msg).ok()?;
+                assert_eq!(auth.len(), PORS_TAU);
+                Some(merkle::root_from_auth(&leaf, &auth, index, &tweak))
+            });
+            let first = roots.next()??;
+            for r in roots {
+                if r? != first {
+                    return None;
+                }
+            }
+            Some(first)
+        };
+        assert_eq!(verify(&sign), Some(root));
+        let pk = SecKey::new(&prng, &address).genpk();
+        assert_eq!(pk.0, root);
+        for t in [0, PORS_K / 2, PORS_K - 1] {
+            assert_eq!(sign.tree_root(t, &msg), Ok(pk.0));
+        }
+        assert_eq!(sign.all_tree_roots(&msg), Ok([pk.0; PORS_K]));
+
+        let invalid = GravityError::InvalidParameter("tree");
+        assert_eq!(sign.tree_leaf_value(PORS_K), Err(invalid));
+        assert_eq!(sign.tree_leaf_index(PORS_K, &msg), Err(invalid));
+        assert_eq!(sign.tree_auth_path(PORS_K, &msg), Err(invalid));
+        assert_eq!(sign.tree_root(PORS_K, &msg), Err(invalid));
+
+        sign.values[0].h[0] ^= 1;
+        let extracted = sign.extract(&msg).unwrap().1;
+        assert_ne!(extracted, root);
+        assert_eq!(verify(&sign), Some(extracted));
+        assert_eq!(sign.tree_root(0, &msg), Ok(extracted));
+
+        sign.values[0].h[0] ^= 1;
+        sign.octopus.oct.pop();
+        assert!(sign.extract(&msg).is_err());
+        assert!(sign.tree_auth_path(0, &msg).is_err());
+        assert!(sign.all_tree_roots(&msg).is_err());
+    }
+
+    #[test]
+    fn test_choose_indices() {
+        let prng = prng::Prng::new(&hash::tests::HASH_ELEMENT);
+        let mut msg = hash::tests::HASH_ELEMENT;
+        for i in 0..16u8 {
+            msg.h[0] = i;
+            let pepper = derive_pepper(&hash::tests::HASH_ELEMENT, &msg);
+            let indices = choose_indices(&pepper, &msg);
+            assert_eq!(indices.len(), PORS_K);
+            assert!(indices.iter().all(|&x| x < PORS_T));
+            assert!(indices.windows(2).all(|w| w[0] < w[1]));
+
+            // Same subset as signing.
+            let (_, _, sign) = sign(&prng, &hash::tests::HASH_ELEMENT, &msg);
+            assert_eq!(*sign.pepper(), pepper);
+            let revealed: Vec<usize> = (0..PORS_K)
+                .map(|t| sign.tree_leaf_index(t, &msg).unwrap())
+                .collect();
+            assert_eq!(revealed, indices);
+        }
+        let pepper = hash::tests::HASH_ELEMENT;
+        assert_ne!(
+            choose_indices(&pepper, &msg),
+            choose_indices(&Hash::default(), &msg)
+        );
+    }
+
+    #[test]
+    fn test_sign_with_indices() {
+        let prng = prng::Prng::new(&hash::tests::HASH_ELEMENT);
+        let salt = hash::tests::HASH_ELEMENT;
+        let mut msg = hash::tests::HASH_ELEMENT;
+        for i in 0..4u8 {
+            msg.h[0] = i;
+            let (address, root, sign, indices) = sign_with_indices(&prng, &salt, &msg);
+            assert_eq!(indices, choose_indices(sign.pepper(), &msg));
+            assert_eq!(indices.len(), PORS_K);
+
+            let (expect_address, expect_root, expect_sign) = super::sign(&prng, &salt, &msg);
+            assert_eq!(address, expect_address);
+            assert_eq!(root, expect_root);
+            let bytes = |s: &Signature| {
+                let mut output = vec![];
+                s.serialize(&mut output);
+                output
+            };
+            assert_eq!(bytes(&sign), bytes(&expect_sign));
+        }
+    }
+
     use std::hint::black_box;
     use test::Bencher;
 
@@ -187,7 +800,7 @@ mod tests {
         let salt = hash::tests::HASH_ELEMENT;
         let msg = hash::tests::HASH_ELEMENT;
 
-        let pepper = hash::hash_2n_to_n_ret(&salt, &msg);
+        let pepper = derive_pepper(&salt, &msg);
         b.iter(|| obtain_address_subset(black_box(&pepper), black_box(&msg)));
     }
 
@@ -199,7 +812,7 @@ mod tests {
 
         let prng = prng::Prng::new(&seed);
         b.iter(|| {
-            let pepper = hash::hash_2n_to_n_ret(black_box(&salt), black_box(&msg));
+            let pepper = derive_pepper(black_box(&salt), black_box(&msg));
             let (address, _) = obtain_address_subset(&pepper, &msg);
 
             let sk = SecKey::new(black_box(&prng), &address);
@@ -215,7 +828,7 @@ mod tests {
 
         let prng = prng::Prng::new(&seed);
         b.iter(|| {
-            let pepper = hash::hash_2n_to_n_ret(black_box(&salt), black_box(&msg));
+            let pepper = derive_pepper(black_box(&salt), black_box(&msg));
             let (address, _) = obtain_address_subset(&pepper, &msg);
 
             SecKey::new(black_box(&prng), &address)
@@ -229,7 +842,7 @@ mod tests {
         let msg = hash::tests::HASH_ELEMENT;
 
         let prng = prng::Prng::new(&seed);
-        let pepper = hash::hash_2n_to_n_ret(&salt, &msg);
+        let pepper = derive_pepper(&salt, &msg);
         let (address, _) = obtain_address_subset(&pepper, &msg);
 
         let sk = SecKey::new(&prng, &address);
@@ -243,7 +856,7 @@ mod tests {
         let msg = hash::tests::HASH_ELEMENT;
 
         let prng = prng::Prng::new(&seed);
-        let pepper = hash::hash_2n_to_n_ret(&salt, &msg);
+        let pepper = derive_pepper(&salt, &msg);
         let (address, subset) = obtain_address_subset(&pepper, &msg);
 
         let sk = SecKey::new(&prng, &address);
@@ -257,7 +870,7 @@ mod tests {
         let msg = hash::tests::HASH_ELEMENT;
 
         let prng = prng::Prng::new(&seed);
-        let pepper = hash::hash_2n_to_n_ret(&salt, &msg);
+        let pepper = derive_pepper(&salt, &msg);
         let (address, subset) = obtain_address_subset(&pepper, &msg);
 
         let sk = SecKey::new(&prng, &address);
@@ -0,0 +1,281 @@
+// Implementations of the pqcrypto-traits sign traits, so that frameworks built
+// on the pqcrypto crates can use Gravity-SPHINCS as a backend. The functions
+// mirror those of a pqcrypto scheme module, except keypair, which takes the
+// randomness as there is no RNG in this crate.
+//
+// from_bytes only checks lengths, as the traits have no other error: parsing
+// and verification of signatures happen in verify_detached_signature and open,
+// which report malformed signatures as InvalidSignature.
+use crate::config::{PUBLIC_KEY_BYTES, SECRET_SEED_BYTES, SIGNATURE_BYTES};
+use crate::gravity::{PubKey, SecKey, Signature, ATTACHED_HEADER_BYTES};
+use crate::hash::Hash;
+use pqcrypto_traits::sign::{self, VerificationError};
+use pqcrypto_traits::{Error, Result};
+use std::fmt;
+use std::sync::OnceLock;
+
+// The 64-byte seed, from which the key is generated on first use.
+pub struct SecretKey {
+    seed: [u8; SECRET_SEED_BYTES],
+    sk: OnceLock<SecKey>,
+}
+
+// Canonical signature encoding.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DetachedSignature(Vec<u8>);
+
+// Blob of SecKey::sign_attached.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignedMessage(Vec<u8>);
+
+pub fn public_key_bytes() -> usize {
+    PUBLIC_KEY_BYTES
+}
+
+pub fn secret_key_bytes() -> usize {
+    SECRET_SEED_BYTES
+}
+
+pub fn signature_bytes() -> usize {
+    SIGNATURE_BYTES
+}
+
+fn check_length(name: &'static str, bytes: &[u8], expected: usize) -> Result<()> {
+    if bytes.len() != expected {
+        return Err(Error::BadLength {
+            name,
+            actual: bytes.len(),
+            expected,
+        });
+    }
+    Ok(())
+}
+
+impl SecretKey {
+    fn key(&self) -> &SecKey {
+        self.sk.get_or_init(|| SecKey::new(&self.seed))
+    }
+}
+
+// The clone regenerates the key on first use, as SecKey is not Clone.
+impl Clone for SecretKey {
+    fn clone(&self) -> Self {
+        SecretKey {
+            seed: self.seed,
+            sk: OnceLock::new(),
+        }
+    }
+}
+
+// Never shows the seed.
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecretKey").finish_non_exhaustive()
+    }
+}
+
+impl sign::PublicKey for PubKey {
+    fn as_bytes(&self) -> &[u8] {
+        &self.h.h
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        check_length("PubKey", bytes, PUBLIC_KEY_BYTES)?;
+        let mut h = Hash::default();
+        h.h.copy_from_slice(bytes);
+        Ok(PubKey { h })
+    }
+}
+
+impl sign::SecretKey for SecretKey {
+    fn as_bytes(&self) -> &[u8] {
+        &self.seed
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        check_length("SecretKey", bytes, SECRET_SEED_BYTES)?;
+        let mut seed = [0u8; SECRET_SEED_BYTES];
+        seed.copy_from_slice(bytes);
+        Ok(SecretKey {
+            seed,
+            sk: OnceLock::new(),
+        })
+    }
+}
+
+impl sign::DetachedSignature for DetachedSignature {
+    fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        check_length("DetachedSignature", bytes, SIGNATURE_BYTES)?;
+        Ok(DetachedSignature(bytes.to_vec()))
+    }
+}
+
+impl sign::SignedMessage for SignedMessage {
+    fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    // Only the minimal length is checked, the message may be of any length.
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let min = ATTACHED_HEADER_BYTES + SIGNATURE_BYTES;
+        if bytes.len() < min {
+            return Err(Error::BadLength {
+                name: "SignedMessage",
+                actual: bytes.len(),
+                expected: min,
+            });
+        }
+        Ok(SignedMessage(bytes.to_vec()))
+    }
+}
+
+impl From<&Signature> for DetachedSignature {
+    fn from(sign: &Signature) -> Self {
+        DetachedSignature(Vec::<u8>::from(sign))
+    }
+}
+
+pub fn keypair(random: &[u8; SECRET_SEED_BYTES]) -> (PubKey, SecretKey) {
+    let sk = SecretKey {
+        seed: *random,
+        sk: OnceLock::new(),
+    };
+    let pk = sk.key().genpk();
+    (pk, sk)
+}
+
+pub fn detached_sign(msg: &[u8], sk: &SecretKey) -> DetachedSignature {
+    DetachedSignature::from(&sk.key().sign_bytes(msg))
+}
+
+pub fn verify_detached_signature(
+    sig: &DetachedSignature,
+    msg: &[u8],
+    pk: &PubKey,
+) -> std::result::Result<(), VerificationError> {
+    let sign =
+        Signature::try_from(sig.0.as_slice()).map_err(|_| VerificationError::InvalidSignature)?;
+    pk.verify_bytes(&sign, msg)
+        .map_err(|_| VerificationError::InvalidSignature)
+}
+
+pub fn sign(msg: &[u8], sk: &SecretKey) -> SignedMessage {
+    SignedMessage(sk.key().sign_attached(msg))
+}
+
+pub fn open(sm: &SignedMessage, pk: &PubKey) -> std::result::Result<Vec<u8>, VerificationError> {
+    pk.open_attached(&sm.0)
+        .map(|msg| msg.to_vec())
+        .map_err(|_| VerificationError::InvalidSignature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pqcrypto_traits::sign::{
+        DetachedSignature as _, PublicKey as _, SecretKey as _, SignedMessage as _,
+    };
+
+    // Signature scheme as seen by a framework that only knows the traits.
+    struct Scheme<PK, SK, DS, SM> {
+        keypair: fn(&[u8; 64]) -> (PK, SK),
+        detached_sign: fn(&[u8], &SK) -> DS,
+        verify_detached_signature:
+            fn(&DS, &[u8], &PK) -> std::result::Result<(), VerificationError>,
+        sign: fn(&[u8], &SK) -> SM,
+        open: fn(&SM, &PK) -> std::result::Result<Vec<u8>, VerificationError>,
+    }
+
+    fn reparse<T, F: Fn(&[u8]) -> Result<T>>(bytes: &[u8], from_bytes: F) -> T {
+        from_bytes(bytes).unwrap()
+    }
+
+    fn exercise<PK, SK, DS, SM>(scheme: &Scheme<PK, SK, DS, SM>)
+    where
+        PK: sign::PublicKey,
+        SK: sign::SecretKey,
+        DS: sign::DetachedSignature,
+        SM: sign::SignedMessage,
+    {
+        let (pk, sk) = (scheme.keypair)(&[0x5a; 64]);
+        // Keys and signatures only travel as bytes.
+        let pk: PK = reparse(pk.as_bytes(), PK::from_bytes);
+        let sk: SK = reparse(sk.as_bytes(), SK::from_bytes);
+        let msg = b"Hello world";
+
+        let sig = (scheme.detached_sign)(msg, &sk);
+        let sig: DS = reparse(sig.as_bytes(), DS::from_bytes);
+        assert!((scheme.verify_detached_signature)(&sig, msg, &pk).is_ok());
+        assert!(matches!(
+            (scheme.verify_detached_signature)(&sig, b"another message", &pk),
+            Err(VerificationError::InvalidSignature)
+        ));
+        let mut bad = sig.as_bytes().to_vec();
+        bad[0] ^= 1;
+        let bad: DS = reparse(&bad, DS::from_bytes);
+        assert!((scheme.verify_detached_signature)(&bad, msg, &pk).is_err());
+
+        let sm = (scheme.sign)(msg, &sk);
+        let sm: SM = reparse(sm.as_bytes(), SM::from_bytes);
+        assert_eq!((scheme.open)(&sm, &pk).unwrap(), msg);
+        let mut bad = sm.as_bytes().to_vec();
+        *bad.last_mut().unwrap() ^= 1;
+        let bad: SM = reparse(&bad, SM::from_bytes);
+        assert!(matches!(
+            (scheme.open)(&bad, &pk),
+            Err(VerificationError::InvalidSignature)
+        ));
+
+        assert!(PK::from_bytes(&[]).is_err());
+        assert!(SK::from_bytes(&[0; 63]).is_err());
+        assert!(DS::from_bytes(&sig.as_bytes()[1..]).is_err());
+        assert!(SM::from_bytes(&sm.as_bytes()[..10]).is_err());
+    }
+
+    #[test]
+    fn test_traits() {
+        exercise(&Scheme {
+            keypair,
+            detached_sign,
+            verify_detached_signature,
+            sign,
+            open,
+        });
+    }
+
+    #[test]
+    fn test_bad_length() {
+        match PubKey::from_bytes(&[0; 33]) {
+            Err(Error::BadLength {
+                name,
+                actual,
+                expected,
+            }) => {
+                assert_eq!(name, "PubKey");
+                assert_eq!(actual, 33);
+                assert_eq!(expected, PUBLIC_KEY_BYTES);
+            }
+            _ => panic!("expected BadLength"),
+        }
+        assert!(
+            SignedMessage::from_bytes(&vec![0; ATTACHED_HEADER_BYTES + SIGNATURE_BYTES]).is_ok()
+        );
+        assert!(DetachedSignature::from_bytes(&vec![0; SIGNATURE_BYTES + 1]).is_err());
+    }
+
+    // Matches the byte API of the crate root.
+    #[test]
+    fn test_encodings() {
+        let seed = [0x5a; 64];
+        let (pk, sk) = keypair(&seed);
+        let (sk_bytes, pk_bytes) = crate::keypair(&seed);
+        assert_eq!(pk.as_bytes(), pk_bytes.as_slice());
+        assert_eq!(sk.as_bytes(), sk_bytes.as_slice());
+        let sig = detached_sign(b"msg", &sk);
+        assert!(crate::verify(&pk_bytes, b"msg", sig.as_bytes()).unwrap());
+    }
+}
@@ -27,6 +27,8 @@ fn assist256_2(mut a: Simd128, b: &mut Simd128) {
     Simd128::pxor(b, &a);
 }
 
+// Constant time: the key schedule only uses AES-NI and SSE2 instructions, and
+// the round constants are compile-time immediates.
 #[inline(always)]
 fn expand256(key: &[u8; 32], rkeys: &mut [Simd128; 15]) {
     let mut key0_xmm = Simd128::read(array_ref![key, 0, 16]);
@@ -77,6 +79,7 @@ fn expand256(key: &[u8; 32], rkeys: &mut [Simd128; 15]) {
     rkeys[14] = key0_xmm;
 }
 
+#[cfg(test)]
 pub fn expand256_slice(key: &[u8; 32], rkeys: &mut [[u8; 16]; 15]) {
     let mut rkeys_xmm = [Simd128::from(0); 15];
     expand256(key, &mut rkeys_xmm);
@@ -98,6 +101,7 @@ fn aes256_rkeys_xmm(dst: &mut [u8; 16], src: &[u8; 16], rkeys: &[Simd128; 15]) {
     state_xmm.write(dst);
 }
 
+#[cfg(test)]
 pub fn aes256_rkeys_slice(dst: &mut [u8; 16], src: &[u8; 16], rkeys: &[[u8; 16]; 15]) {
     let mut rkeys_xmm = [Simd128::from(0); 15];
     for i in 0..15 {
@@ -107,6 +111,23 @@ pub fn aes256_rkeys_slice(dst: &mut [u8; 16], src: &[u8; 16], rkeys: &[[u8; 16];
     aes256_rkeys_xmm(dst, src, &rkeys_xmm);
 }
 
+// AES-256 key schedule, expanded once and kept in SIMD registers format.
+#[derive(Clone, Copy)]
+pub struct RoundKeys([Simd128; 15]);
+
+impl RoundKeys {
+    pub fn new(key: &[u8; 32]) -> Self {
+        let mut rkeys = [Simd128::from(0); 15];
+        expand256(key, &mut rkeys);
+        Self(rkeys)
+    }
+
+    #[inline]
+    pub fn encrypt(&self, dst: &mut [u8; 16], src: &[u8; 16]) {
+        aes256_rkeys_xmm(dst, src, &self.0);
+    }
+}
+
 #[cfg(test)]
 pub fn aes256_ret(src: &[u8; 16], key: &[u8; 32]) -> [u8; 16] {
     let mut rkeys = [Simd128::from(0); 15];
@@ -362,6 +383,13 @@ mod tests {
         let mut rkeys_bis = [[0u8; 16]; 15];
         expand256_bis(&key, &mut rkeys_bis);
         assert_eq!(rkeys, rkeys_bis);
+
+        let src = [0x5a; 16];
+        let mut dst = [0u8; 16];
+        let mut dst_bis = [0u8; 16];
+        RoundKeys::new(&key).encrypt(&mut dst, &src);
+        aes256_rkeys_slice(&mut dst_bis, &src, &rkeys);
+        assert_eq!(dst, dst_bis);
     }
 
     use std::hint::black_box;
@@ -52,6 +52,7 @@ pub(crate) static HARAKA_CONSTANTS: &[Simd128; 48] = &[
 ];
 
 #[cfg(test)]
+#[cfg(not(feature = "shake256"))]
 pub static AES_RCON: [u8; 7] = [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40];
 
 #[cfg(test)]
@@ -1,3 +1,4 @@
+#[cfg(not(any(feature = "shake256", feature = "prng-hmac")))]
 pub mod aes256;
 mod constants;
 pub mod haraka256;
@@ -1,14 +1,26 @@
 #[cfg(target_arch = "x86")]
 use std::arch::x86::{
-    __m128i, _mm_aesenc_si128, _mm_aesenclast_si128, _mm_aeskeygenassist_si128, _mm_loadu_si128,
-    _mm_shuffle_epi32, _mm_slli_si128, _mm_storeu_si128, _mm_unpackhi_epi32, _mm_unpackhi_epi64,
-    _mm_unpacklo_epi32, _mm_unpacklo_epi64, _mm_xor_si128,
+    __m128i, _mm_aesenc_si128, _mm_loadu_si128, _mm_storeu_si128, _mm_unpackhi_epi32,
+    _mm_unpackhi_epi64, _mm_unpacklo_epi32, _mm_unpacklo_epi64, _mm_xor_si128,
+};
+#[cfg(all(
+    target_arch = "x86",
+    not(any(feature = "shake256", feature = "prng-hmac"))
+))]
+use std::arch::x86::{
+    _mm_aesenclast_si128, _mm_aeskeygenassist_si128, _mm_shuffle_epi32, _mm_slli_si128,
 };
 #[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::{
-    __m128i, _mm_aesenc_si128, _mm_aesenclast_si128, _mm_aeskeygenassist_si128, _mm_loadu_si128,
-    _mm_shuffle_epi32, _mm_slli_si128, _mm_storeu_si128, _mm_unpackhi_epi32, _mm_unpackhi_epi64,
-    _mm_unpacklo_epi32, _mm_unpacklo_epi64, _mm_xor_si128,
+    __m128i, _mm_aesenc_si128, _mm_loadu_si128, _mm_storeu_si128, _mm_unpackhi_epi32,
+    _mm_unpackhi_epi64, _mm_unpacklo_epi32, _mm_unpacklo_epi64, _mm_xor_si128,
+};
+#[cfg(all(
+    target_arch = "x86_64",
+    not(any(feature = "shake256", feature = "prng-hmac"))
+))]
+use std::arch::x86_64::{
+    _mm_aesenclast_si128, _mm_aeskeygenassist_si128, _mm_shuffle_epi32, _mm_slli_si128,
 };
 use std::mem::transmute;
 
@@ -44,6 +56,8 @@ impl Simd128 {
         }
     }
 
+    // Only used by AES-256.
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac")))]
     #[inline(always)]
     pub(crate) fn aesenclast(block: &mut Self, key: &Self) {
         unsafe {
@@ -51,6 +65,8 @@ impl Simd128 {
         }
     }
 
+    // Only used by AES-256.
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac")))]
     #[inline(always)]
     pub(crate) fn aeskeygenassist<const ROUND_CONSTANT: i32>(block: &Self) -> Self {
         unsafe { Self(_mm_aeskeygenassist_si128::<ROUND_CONSTANT>(block.0)) }
@@ -63,11 +79,15 @@ impl Simd128 {
         }
     }
 
+    // Only used by AES-256.
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac")))]
     #[inline(always)]
     pub(crate) fn pslldq<const SHIFT: i32>(block: &mut Self) {
         unsafe { block.0 = _mm_slli_si128::<SHIFT>(block.0) }
     }
 
+    // Only used by AES-256.
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac")))]
     #[inline(always)]
     pub(crate) fn pshufd<const CONTROL: i32>(block: &Self) -> Self {
         unsafe { Self(_mm_shuffle_epi32::<CONTROL>(block.0)) }
@@ -120,6 +140,7 @@ pub(crate) mod tests {
         assert_eq!(dst, expect);
     }
 
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac")))]
     pub fn aesenclast_slice(block: &mut [u8; 16], rkey: &[u8; 16]) {
         let mut block_xmm = Simd128::read(block);
         let rkey_xmm = Simd128::read(rkey);
@@ -127,6 +148,7 @@ pub(crate) mod tests {
         block_xmm.write(block);
     }
 
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac")))]
     #[test]
     fn test_aesenclast() {
         use super::super::constants;
@@ -1,47 +1,361 @@
 use crate::address;
-use crate::hash::Hash;
+use crate::config;
+use crate::hash::{Hash, HashBackend};
+#[cfg(not(any(feature = "shake256", feature = "prng-hmac")))]
 use crate::primitives::aes256;
+#[cfg(not(any(feature = "shake256", feature = "prng-hmac")))]
 use arrayref::array_mut_ref;
+#[cfg(feature = "shake256")]
+use sha3::digest::ExtendableOutput;
+use std::fmt;
 
-#[derive(Default)]
+#[cfg(all(feature = "shake256", feature = "prng-hmac"))]
+compile_error!("the shake256 and prng-hmac features are exclusive");
+
+// AES-256 in counter mode keyed by the seed, as specified, SHAKE256 of the seed
+// and block with the shake256 feature, or HMAC-SHA256 of the block keyed by the
+// seed with the prng-hmac feature.
 pub struct Prng {
-    rkeys: [[u8; 16]; 15],
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac")))]
+    rkeys: aes256::RoundKeys,
+    #[cfg(any(feature = "shake256", feature = "prng-hmac"))]
+    seed: Hash,
+}
+
+// The blocks of a Prng at an address, for counters 0, 1, 2..., as a byte stream
+// (see Prng::to_rng). This is a DETERMINISTIC expansion of the seed, exactly
+// that of the secret keys, and not an entropy source: the same seed and
+// address always give the same bytes, so it must never stand for a system RNG,
+// nor be seeded with the seed of a signing key in production. The stream ends
+// after 2^31 blocks, the counters that genblock keeps distinct, and panics
+// beyond.
+#[cfg(feature = "rand-core")]
+pub struct PrngStream<'a> {
+    prng: &'a Prng,
+    address: &'a address::Address,
+    counter: u32,
+    block: Hash,
+    // Consumed bytes of block.
+    used: usize,
+}
+
+// Expansion of a secret seed into blocks of key material, indexed by address and
+// counter. The generic modules derive their secret keys through it.
+pub trait BlockPrng {
+    type Block: HashBackend;
+
+    fn genblock(&self, dst: &mut Self::Block, address: &address::Address, counter: u32);
+
+    fn genblocks(&self, dst: &mut [Self::Block], address: &address::Address) {
+        for (i, x) in dst.iter_mut().enumerate() {
+            self.genblock(x, address, i as u32);
+        }
+    }
+}
+
+// A BlockPrng keyed by a seed held in memory.
+pub trait SeedablePrng: BlockPrng {
+    fn new(seed: &Self::Block) -> Self;
+}
+
+// Block i is the long hash of seed || address.to_block(i), for backends without
+// a block cipher.
+pub struct HashPrng<H> {
+    seed: H,
+}
+
+// Source of the secret blocks of a key whose seed is kept elsewhere, e.g. in an
+// HSM or a secure element: only the block at (address, counter), as
+// BlockPrng::genblock would compute it, is released to the process.
+pub trait SeedProvider {
+    fn derive(&self, address: &address::Address, counter: u32) -> Hash;
 }
 
+// Derives the blocks of the generic modules through a SeedProvider.
+pub(crate) struct ProviderPrng<'a, S>(pub &'a S);
+
+// HKDF info prefix of Prng::from_seed_and_counter.
+const STREAM_INFO: &[u8] = b"gravity-prng-stream";
+
+impl fmt::Debug for Prng {
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac")))]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Prng {{ rkeys: <redacted> }}")
+    }
+
+    #[cfg(any(feature = "shake256", feature = "prng-hmac"))]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Prng {{ seed: <redacted> }}")
+    }
+}
+
+// Timing: the seed and round keys are only processed by AES-NI instructions
+// (key expansion and encryption), which run in constant time, or by the
+// constant-time Keccak and SHA-256 of the other variants. The address and
+// counter select the output block and are not secret.
 impl Prng {
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac")))]
     pub fn new(seed: &Hash) -> Self {
-        let mut prng: Prng = Default::default();
-        aes256::expand256_slice(&seed.h, &mut prng.rkeys);
-        prng
+        Self {
+            rkeys: aes256::RoundKeys::new(&seed.h),
+        }
     }
 
+    #[cfg(any(feature = "shake256", feature = "prng-hmac"))]
+    pub fn new(seed: &Hash) -> Self {
+        Self { seed: *seed }
+    }
+
+    // Independent stream seeded with seed.kdf(STREAM_INFO || counter), counter
+    // being in little-endian. Even for counter = 0 this differs from
+    // Prng::new(seed), which is the first-level PRNG of a secret key.
+    pub fn from_seed_and_counter(seed: &Hash, counter: u64) -> Self {
+        let mut info = STREAM_INFO.to_vec();
+        info.extend_from_slice(&counter.to_le_bytes());
+        let mut stream_seed = Hash::default();
+        stream_seed
+            .h
+            .copy_from_slice(&seed.kdf(&info, config::HASH_SIZE));
+        Self::new(&stream_seed)
+    }
+
+    // The streams of counters 0..n, e.g. one per thread. This takes the seed
+    // rather than a Prng, which may only keep the AES round keys of its seed.
+    pub fn split(seed: &Hash, n: usize) -> Vec<Self> {
+        (0..n as u64)
+            .map(|i| Self::from_seed_and_counter(seed, i))
+            .collect()
+    }
+
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac")))]
     pub fn genblock(&self, dst: &mut Hash, address: &address::Address, counter: u32) {
         let h = &mut dst.h;
-        aes256::aes256_rkeys_slice(
-            array_mut_ref![h, 0, 16],
-            &address.to_block(2 * counter),
-            &self.rkeys,
-        );
-        aes256::aes256_rkeys_slice(
+        self.rkeys
+            .encrypt(array_mut_ref![h, 0, 16], &address.to_block(2 * counter));
+        self.rkeys.encrypt(
             array_mut_ref![h, 16, 16],
             &address.to_block(2 * counter + 1),
-            &self.rkeys,
         );
     }
 
+    // SHAKE256 of seed || address.to_block(counter).
+    #[cfg(feature = "shake256")]
+    pub fn genblock(&self, dst: &mut Hash, address: &address::Address, counter: u32) {
+        let mut bytes = [0u8; 48];
+        bytes[..32].copy_from_slice(&self.seed.h);
+        bytes[32..].copy_from_slice(&address.to_block(counter));
+        sha3::Shake256::digest_xof(bytes, &mut dst.h);
+    }
+
+    // HMAC-SHA256 of address.to_block(counter), keyed by the seed.
+    #[cfg(feature = "prng-hmac")]
+    pub fn genblock(&self, dst: &mut Hash, address: &address::Address, counter: u32) {
+        *dst = Hash::hmac_sha256(&self.seed.h, &address.to_block(counter));
+    }
+
     pub fn genblocks(&self, dst: &mut [Hash], address: &address::Address) {
         for (i, x) in dst.iter_mut().enumerate() {
             self.genblock(x, address, i as u32);
         }
     }
+
+    #[cfg(feature = "rand-core")]
+    pub fn to_rng<'a>(&'a self, domain: &'a address::Address) -> PrngStream<'a> {
+        PrngStream {
+            prng: self,
+            address: domain,
+            counter: 0,
+            block: Hash::default(),
+            used: config::HASH_SIZE,
+        }
+    }
+}
+
+#[cfg(feature = "rand-core")]
+impl fmt::Debug for PrngStream<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "PrngStream {{ address: {:?}, counter: {}, block: <redacted> }}",
+            self.address, self.counter
+        )
+    }
+}
+
+#[cfg(feature = "rand-core")]
+impl rand_core::RngCore for PrngStream<'_> {
+    fn next_u32(&mut self) -> u32 {
+        rand_core::impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand_core::impls::next_u64_via_fill(self)
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        let mut filled = 0;
+        while filled < dst.len() {
+            if self.used == config::HASH_SIZE {
+                assert!(self.counter < 1 << 31, "PrngStream exhausted");
+                self.prng
+                    .genblock(&mut self.block, self.address, self.counter);
+                self.counter += 1;
+                self.used = 0;
+            }
+            let n = (dst.len() - filled).min(config::HASH_SIZE - self.used);
+            dst[filled..(filled + n)].copy_from_slice(&self.block.h[self.used..(self.used + n)]);
+            filled += n;
+            self.used += n;
+        }
+    }
+}
+
+#[cfg(feature = "rand-core")]
+impl rand_core::CryptoRng for PrngStream<'_> {}
+
+impl BlockPrng for Prng {
+    type Block = Hash;
+
+    fn genblock(&self, dst: &mut Hash, address: &address::Address, counter: u32) {
+        Prng::genblock(self, dst, address, counter)
+    }
+}
+
+impl SeedablePrng for Prng {
+    fn new(seed: &Hash) -> Self {
+        Prng::new(seed)
+    }
+}
+
+impl SeedProvider for Prng {
+    fn derive(&self, address: &address::Address, counter: u32) -> Hash {
+        let mut block = Hash::default();
+        self.genblock(&mut block, address, counter);
+        block
+    }
+}
+
+impl<S: SeedProvider> BlockPrng for ProviderPrng<'_, S> {
+    type Block = Hash;
+
+    fn genblock(&self, dst: &mut Hash, address: &address::Address, counter: u32) {
+        *dst = self.0.derive(address, counter);
+    }
+}
+
+impl<H> fmt::Debug for HashPrng<H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HashPrng {{ seed: <redacted> }}")
+    }
+}
+
+impl<H: HashBackend> SeedablePrng for HashPrng<H> {
+    fn new(seed: &H) -> Self {
+        Self { seed: *seed }
+    }
+}
+
+impl<H: HashBackend> BlockPrng for HashPrng<H> {
+    type Block = H;
+
+    fn genblock(&self, dst: &mut H, address: &address::Address, counter: u32) {
+        let mut bytes = self.seed.as_ref().to_vec();
+        bytes.extend(address.to_block(counter).iter());
+        *dst = H::long_hash(&bytes);
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::super::hash;
     use super::*;
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac")))]
     use arrayref::array_ref;
 
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac")))]
+    #[test]
+    fn test_debug() {
+        let prng = Prng::new(&hash::tests::HASH_ELEMENT);
+        assert_eq!(format!("{:?}", prng), "Prng { rkeys: <redacted> }");
+    }
+
+    fn first_block(prng: &Prng) -> Hash {
+        let mut block = Default::default();
+        prng.genblock(&mut block, &address::Address::new(0, 0), 0);
+        block
+    }
+
+    #[test]
+    fn test_from_seed_and_counter() {
+        let seed = hash::tests::HASH_ELEMENT;
+        let blocks: Vec<Hash> = (0..4)
+            .map(|counter| first_block(&Prng::from_seed_and_counter(&seed, counter)))
+            .collect();
+        for i in 0..blocks.len() {
+            for j in 0..i {
+                assert_ne!(blocks[i], blocks[j]);
+            }
+        }
+
+        let mut info = b"gravity-prng-stream".to_vec();
+        info.extend([0u8; 8].iter());
+        let mut stream_seed = Hash::default();
+        stream_seed.h.copy_from_slice(&seed.kdf(&info, 32));
+        assert_eq!(blocks[0], first_block(&Prng::new(&stream_seed)));
+        assert_ne!(blocks[0], first_block(&Prng::new(&seed)));
+        assert_eq!(
+            first_block(&Prng::from_seed_and_counter(&seed, 2)),
+            blocks[2]
+        );
+    }
+
+    #[test]
+    fn test_split() {
+        let seed = hash::tests::HASH_ELEMENT;
+        let children = Prng::split(&seed, 2);
+        assert_eq!(children.len(), 2);
+        assert_ne!(first_block(&children[0]), first_block(&children[1]));
+        assert_eq!(
+            first_block(&Prng::split(&seed, 1)[0]),
+            first_block(&Prng::from_seed_and_counter(&seed, 0))
+        );
+        assert_eq!(
+            first_block(&children[1]),
+            first_block(&Prng::from_seed_and_counter(&seed, 1))
+        );
+        assert!(Prng::split(&seed, 0).is_empty());
+    }
+
+    #[cfg(feature = "prng-hmac")]
+    #[test]
+    fn test_genblock_hmac() {
+        let prng = Prng::new(&hash::tests::HASH_ELEMENT);
+        assert_eq!(format!("{:?}", prng), "Prng { seed: <redacted> }");
+        let address = address::Address::new(0x01020304, 0x05060708090a0b0c);
+        let mut dst = [Default::default(); 2];
+        prng.genblocks(&mut dst, &address);
+        assert_eq!(
+            dst[1],
+            Hash::hmac_sha256(&hash::tests::HASH_ELEMENT.h, &address.to_block(1))
+        );
+        assert_ne!(dst[0], dst[1]);
+    }
+
+    #[cfg(feature = "shake256")]
+    #[test]
+    fn test_genblock_shake256() {
+        let prng = Prng::new(&hash::tests::HASH_ELEMENT);
+        assert_eq!(format!("{:?}", prng), "Prng { seed: <redacted> }");
+        let address = address::Address::new(0, 0);
+        let mut bytes = hash::tests::HASH_ELEMENT.h.to_vec();
+        bytes.extend(address.to_block(1).iter());
+        let mut dst = [Default::default(); 2];
+        prng.genblocks(&mut dst, &address);
+        assert_eq!(dst[1].h[..], hash::xof_hash(&bytes, 32)[..]);
+        assert_ne!(dst[0], dst[1]);
+    }
+
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac")))]
     #[test]
     fn test_genblock_zero() {
         let prng = Prng::new(&hash::tests::HASH_ELEMENT);
@@ -64,6 +378,7 @@ mod tests {
         );
     }
 
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac")))]
     #[test]
     fn test_genblocks() {
         let prng = Prng::new(&hash::tests::HASH_ELEMENT);
@@ -90,6 +405,7 @@ mod tests {
         }
     }
 
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac")))]
     #[test]
     fn test_kat() {
         use hex;
@@ -113,6 +429,86 @@ mod tests {
         assert_eq!(dst[2].h, *array_ref![expect, 64, 32]);
     }
 
+    #[cfg(feature = "rand-core")]
+    #[test]
+    fn test_to_rng() {
+        use rand_core::RngCore;
+
+        let prng = Prng::new(&Hash::default());
+        let address = address::Address::new(0x01020304, 0x05060708090a0b0c);
+        let mut expect = vec![0u8; 96];
+        prng.to_rng(&address).fill_bytes(&mut expect);
+        let mut blocks = [Hash::default(); 3];
+        prng.genblocks(&mut blocks, &address);
+        assert_eq!(expect, blocks.map(|x| x.h).concat());
+
+        // Requests that are not multiples of a block.
+        let mut rng = prng.to_rng(&address);
+        let mut bytes = vec![0u8; 96];
+        for range in [0..1, 1..1, 1..33, 33..40, 40..96] {
+            rng.fill_bytes(&mut bytes[range]);
+        }
+        assert_eq!(bytes, expect);
+
+        let mut rng = prng.to_rng(&address);
+        assert_eq!(rng.next_u32().to_le_bytes(), expect[..4]);
+        assert_eq!(rng.next_u64().to_le_bytes(), expect[4..12]);
+        let other = address::Address::new(0x01020304, 0);
+        assert_ne!(prng.to_rng(&other).next_u64().to_le_bytes(), expect[..8]);
+        assert_eq!(
+            format!("{:?}", rng),
+            "PrngStream { address: { instance: 0x5060708090a0b0c, layer: 16909060 }, counter: 1, block: <redacted> }"
+        );
+    }
+
+    #[cfg(feature = "rand-core")]
+    #[test]
+    #[should_panic(expected = "PrngStream exhausted")]
+    fn test_to_rng_exhausted() {
+        use rand_core::RngCore;
+
+        let prng = Prng::new(&Hash::default());
+        let address = address::Address::new(0, 0);
+        let mut rng = prng.to_rng(&address);
+        rng.counter = (1 << 31) - 1;
+        rng.fill_bytes(&mut [0u8; 32]);
+        rng.fill_bytes(&mut [0u8; 1]);
+    }
+
+    // Pins the stream of the specified AES PRNG.
+    #[cfg(all(
+        feature = "rand-core",
+        not(any(feature = "shake256", feature = "prng-hmac"))
+    ))]
+    #[test]
+    fn test_to_rng_kat() {
+        use rand_core::RngCore;
+
+        let prng = Prng::new(&Hash::default());
+        let address = address::Address::new(0x01020304, 0x05060708090a0b0c);
+        let mut bytes = [0u8; 96];
+        prng.to_rng(&address).fill_bytes(&mut bytes);
+        assert_eq!(
+            hex::encode(&bytes[..]),
+            "218f7b222dfa085cbf2b0794c78f365f504b13e11362edfa8385a996c8f9ee45\
+             e9e40faf683e37776f444a1fb1701b8f85163cecc399ac10a680d4205aea83b1\
+             3e9998ac9d02e6e7d5db0b3721e632125241e6a82526b9e5f07ce2829ca193a2"
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn test_constant_time_genblocks() {
+        let address = address::Address::new(0, 0);
+        let t = hash::tests::dudect_t_statistic(100_000, |seed| {
+            let prng = Prng::new(seed);
+            let mut dst = [Default::default(); config::WOTS_ELL];
+            prng.genblocks(&mut dst, &address);
+            dst
+        });
+        assert!(t.abs() < hash::tests::DUDECT_THRESHOLD, "t = {}", t);
+    }
+
     use super::super::config;
     use test::Bencher;
 
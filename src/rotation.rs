@@ -0,0 +1,275 @@
+// Key rotation: the old key signs a transition certificate endorsing the new
+// public key, so that verifiers that trust the old key can move to the new one
+// once its signature budget runs low.
+use crate::config::{GravityVersion, GRAVITY_VERSION};
+use crate::error::{DeserializeError, GravityError};
+use crate::gravity::{PubKey, SecKey, Signature};
+use crate::hash::Hash;
+
+const CERT_MAGIC: [u8; 4] = *b"GRVt";
+
+// Prefix of the signed statement, so that a certificate signature cannot be
+// replayed as the signature of another message, nor the converse.
+const ROTATION_DOMAIN: &[u8] = b"gravity-rotation";
+
+// The sequence number orders the rotations of a chain (see verify_chain), and
+// the note is free-form (e.g. a validity period), possibly empty.
+pub struct TransitionCert {
+    pub new_pk: PubKey,
+    pub sequence: u64,
+    pub note: Vec<u8>,
+    sign: Signature,
+}
+
+pub fn rotate(old: &SecKey, new_pk: &PubKey) -> TransitionCert {
+    rotate_with(old, new_pk, 0, &[])
+}
+
+pub fn rotate_with(old: &SecKey, new_pk: &PubKey, sequence: u64, note: &[u8]) -> TransitionCert {
+    TransitionCert {
+        new_pk: *new_pk,
+        sequence,
+        note: note.to_vec(),
+        sign: old.sign_bytes(&statement(new_pk, sequence, note)),
+    }
+}
+
+// Follows the certificates from `root`, each signed by the key endorsed by the
+// previous one, with increasing sequence numbers. Returns the last key.
+pub fn verify_chain(root: &PubKey, certs: &[TransitionCert]) -> Result<PubKey, GravityError> {
+    let mut pk = *root;
+    let mut last: Option<u64> = None;
+    for cert in certs.iter() {
+        if last.is_some_and(|s| cert.sequence <= s) {
+            return Err(GravityError::InvalidParameter("sequence"));
+        }
+        pk = cert.verify(&pk)?;
+        last = Some(cert.sequence);
+    }
+    Ok(pk)
+}
+
+// ROTATION_DOMAIN || version (3 bytes) || new_pk || sequence (u64 little-endian)
+// || note length (u64 little-endian) || note.
+fn statement(new_pk: &PubKey, sequence: u64, note: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(ROTATION_DOMAIN.len() + 3 + 32 + 16 + note.len());
+    bytes.extend_from_slice(ROTATION_DOMAIN);
+    bytes.extend_from_slice(&GRAVITY_VERSION.to_bytes());
+    new_pk.h.serialize(&mut bytes);
+    bytes.extend_from_slice(&sequence.to_le_bytes());
+    bytes.extend_from_slice(&(note.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(note);
+    bytes
+}
+
+fn read_u64<'a, I>(it: &mut I) -> Result<u64, DeserializeError>
+where
+    I: Iterator<Item = &'a u8>,
+{
+    let mut block = [0u8; 8];
+    for x in block.iter_mut() {
+        *x = *it.next().ok_or(DeserializeError::UnexpectedEnd)?;
+    }
+    Ok(u64::from_le_bytes(block))
+}
+
+impl TransitionCert {
+    // Returns the endorsed key if `old_pk` signed this certificate.
+    pub fn verify(&self, old_pk: &PubKey) -> Result<PubKey, GravityError> {
+        let statement = statement(&self.new_pk, self.sequence, &self.note);
+        old_pk.verify_bytes(&self.sign, &statement)?;
+        Ok(self.new_pk)
+    }
+
+    pub fn signature(&self) -> &Signature {
+        &self.sign
+    }
+
+    // CERT_MAGIC || version (3 bytes) || new_pk || sequence || note length ||
+    // note || canonical signature, with integers in little-endian.
+    pub fn serialize(&self, output: &mut Vec<u8>) {
+        output.extend(CERT_MAGIC.iter());
+        output.extend(GRAVITY_VERSION.to_bytes().iter());
+        self.new_pk.h.serialize(output);
+        output.extend_from_slice(&self.sequence.to_le_bytes());
+        output.extend_from_slice(&(self.note.len() as u64).to_le_bytes());
+        output.extend_from_slice(&self.note);
+        self.sign.serialize(output);
+    }
+
+    pub fn deserialize<'a, I>(it: &mut I) -> Result<Self, DeserializeError>
+    where
+        I: Iterator<Item = &'a u8>,
+    {
+        for x in CERT_MAGIC.iter() {
+            if it.next().ok_or(DeserializeError::UnexpectedEnd)? != x {
+                return Err(DeserializeError::InvalidHeader);
+            }
+        }
+        let mut version = [0u8; 3];
+        for x in version.iter_mut() {
+            *x = *it.next().ok_or(DeserializeError::UnexpectedEnd)?;
+        }
+        let version = GravityVersion::from_bytes(&version);
+        if version != GRAVITY_VERSION {
+            return Err(DeserializeError::VersionMismatch {
+                expected: GRAVITY_VERSION,
+                found: version,
+            });
+        }
+        let new_pk = PubKey {
+            h: Hash::deserialize(it)?,
+        };
+        let sequence = read_u64(it)?;
+        // The note is read byte by byte, so that a forged length does not
+        // allocate more than the input.
+        let note_len = read_u64(it)?;
+        let mut note = Vec::new();
+        for _ in 0..note_len {
+            note.push(*it.next().ok_or(DeserializeError::UnexpectedEnd)?);
+        }
+        let sign = Signature::deserialize(it)?;
+        Ok(TransitionCert {
+            new_pk,
+            sequence,
+            note,
+            sign,
+        })
+    }
+}
+
+impl TryFrom<&[u8]> for TransitionCert {
+    type Error = DeserializeError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, DeserializeError> {
+        let mut it = bytes.iter();
+        let cert = TransitionCert::deserialize(&mut it)?;
+        if it.next().is_some() {
+            return Err(DeserializeError::TrailingBytes);
+        }
+        Ok(cert)
+    }
+}
+
+impl From<&TransitionCert> for Vec<u8> {
+    fn from(cert: &TransitionCert) -> Self {
+        let mut output = Vec::new();
+        cert.serialize(&mut output);
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(n: u8) -> Vec<(SecKey, PubKey)> {
+        (1..=n)
+            .map(|i| {
+                let sk = SecKey::new(&[i; 64]);
+                let pk = sk.genpk();
+                (sk, pk)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rotate() {
+        let keys = keys(2);
+        let (old_sk, old_pk) = &keys[0];
+        let new_pk = &keys[1].1;
+
+        let cert = rotate_with(old_sk, new_pk, 7, b"valid until 2030");
+        assert_eq!(cert.verify(old_pk), Ok(*new_pk));
+        assert_eq!(cert.verify(new_pk), Err(GravityError::Verification));
+
+        let bytes = Vec::<u8>::from(&cert);
+        assert_eq!(&bytes[..4], b"GRVt");
+        let parsed = TransitionCert::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(parsed.verify(old_pk), Ok(*new_pk));
+        assert_eq!(parsed.sequence, 7);
+        assert_eq!(parsed.note, b"valid until 2030");
+        assert_eq!(Vec::<u8>::from(&parsed), bytes);
+
+        // Tampering with the endorsed key, the sequence number or the note.
+        let tampered = |offset: usize| {
+            let mut bad = bytes.clone();
+            bad[offset] ^= 1;
+            TransitionCert::try_from(bad.as_slice())
+                .unwrap()
+                .verify(old_pk)
+        };
+        for offset in [7, 7 + 31, 7 + 32, 7 + 32 + 7, 7 + 48] {
+            assert_eq!(tampered(offset), Err(GravityError::Verification));
+        }
+        let mut bad = TransitionCert::try_from(bytes.as_slice()).unwrap();
+        bad.note.clear();
+        assert_eq!(bad.verify(old_pk), Err(GravityError::Verification));
+
+        // A signature of the bare public key is not a certificate.
+        let plain = TransitionCert {
+            new_pk: *new_pk,
+            sequence: 0,
+            note: vec![],
+            sign: old_sk.sign_bytes(&new_pk.h.h),
+        };
+        assert_eq!(plain.verify(old_pk), Err(GravityError::Verification));
+        assert_eq!(rotate(old_sk, new_pk).verify(old_pk), Ok(*new_pk));
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let keys = keys(1);
+        let cert = rotate_with(&keys[0].0, &keys[0].1, 1, b"note");
+        let bytes = Vec::<u8>::from(&cert);
+        let parse = |b: &[u8]| TransitionCert::try_from(b).err();
+
+        let mut bad = bytes.clone();
+        bad[0] ^= 1;
+        assert_eq!(parse(&bad), Some(DeserializeError::InvalidHeader));
+        let mut bad = bytes.clone();
+        bad[4] ^= 1;
+        assert!(matches!(
+            parse(&bad),
+            Some(DeserializeError::VersionMismatch { .. })
+        ));
+        assert_eq!(
+            parse(&bytes[..bytes.len() - 1]),
+            Some(DeserializeError::UnexpectedEnd)
+        );
+        let mut long = bytes.clone();
+        long.push(0);
+        assert_eq!(parse(&long), Some(DeserializeError::TrailingBytes));
+        // Note length beyond the input.
+        let mut bad = bytes.clone();
+        bad[7 + 32 + 8 + 7] = 0xFF;
+        assert_eq!(parse(&bad), Some(DeserializeError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn test_chain() {
+        let keys = keys(3);
+        let first = rotate_with(&keys[0].0, &keys[1].1, 1, &[]);
+        let second = rotate_with(&keys[1].0, &keys[2].1, 2, &[]);
+        assert_eq!(first.verify(&keys[0].1), Ok(keys[1].1));
+        assert_eq!(second.verify(&keys[1].1), Ok(keys[2].1));
+        assert_eq!(second.verify(&keys[0].1), Err(GravityError::Verification));
+
+        let chain = [first, second];
+        assert_eq!(verify_chain(&keys[0].1, &chain), Ok(keys[2].1));
+        assert_eq!(verify_chain(&keys[0].1, &chain[..1]), Ok(keys[1].1));
+        assert_eq!(verify_chain(&keys[0].1, &[]), Ok(keys[0].1));
+        assert_eq!(
+            verify_chain(&keys[1].1, &chain),
+            Err(GravityError::Verification)
+        );
+
+        // Sequence numbers must increase along the chain.
+        let replay = rotate_with(&keys[1].0, &keys[2].1, 1, &[]);
+        let [first, _] = chain;
+        assert_eq!(
+            verify_chain(&keys[0].1, &[first, replay]),
+            Err(GravityError::InvalidParameter("sequence"))
+        );
+    }
+}
@@ -0,0 +1,131 @@
+// Generic interface to signature algorithms, for code that abstracts over them,
+// and its implementation for Gravity-SPHINCS.
+use crate::config::{params, SECRET_SEED_BYTES};
+use crate::gravity::{PubKey, SecKey, Signature};
+use crate::hash;
+
+pub trait SignatureScheme {
+    type SecretKey;
+    type PublicKey;
+    type Signature;
+
+    fn keygen(seed: &[u8]) -> (Self::SecretKey, Self::PublicKey);
+    fn sign(sk: &Self::SecretKey, msg: &[u8]) -> Self::Signature;
+    fn verify(pk: &Self::PublicKey, msg: &[u8], sig: &Self::Signature) -> bool;
+}
+
+pub struct GravitySignatureScheme;
+
+impl GravitySignatureScheme {
+    // Identifier of the compiled parameter set for protocol negotiation, e.g.
+    // "gravity-haraka-d1-c10-h5" (see ParamsInfo::id).
+    pub fn name() -> &'static str {
+        &params().id
+    }
+
+    // Algorithm identifier of PubKey::as_der. No OID is registered for
+    // Gravity-SPHINCS, so this is the placeholder DER_ALGORITHM_OID.
+    #[cfg(feature = "der")]
+    pub fn algorithm_id() -> spki::ObjectIdentifier {
+        crate::gravity::DER_ALGORITHM_OID
+    }
+}
+
+// Prefix of the expansion of seeds that are not SECRET_SEED_BYTES long.
+const KEYGEN_DOMAIN: &[u8] = b"gravity-keygen";
+
+// A seed of SECRET_SEED_BYTES is used as is, so that keys match SecKey::new.
+// Other seeds are expanded to SECRET_SEED_BYTES, each half being the SHA-256 of
+// KEYGEN_DOMAIN || half index || seed length (u64 little-endian) || seed, as
+// keygen cannot fail. Short seeds are no more secure for it.
+fn expand_seed(seed: &[u8]) -> [u8; SECRET_SEED_BYTES] {
+    let mut random = [0u8; SECRET_SEED_BYTES];
+    if seed.len() == SECRET_SEED_BYTES {
+        random.copy_from_slice(seed);
+        return random;
+    }
+    for (i, half) in random.chunks_exact_mut(SECRET_SEED_BYTES / 2).enumerate() {
+        let mut bytes = Vec::with_capacity(KEYGEN_DOMAIN.len() + 9 + seed.len());
+        bytes.extend_from_slice(KEYGEN_DOMAIN);
+        bytes.push(i as u8);
+        bytes.extend_from_slice(&(seed.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(seed);
+        half.copy_from_slice(&hash::long_hash(&bytes).h);
+    }
+    random
+}
+
+impl SignatureScheme for GravitySignatureScheme {
+    type SecretKey = SecKey;
+    type PublicKey = PubKey;
+    type Signature = Signature;
+
+    fn keygen(seed: &[u8]) -> (SecKey, PubKey) {
+        let sk = SecKey::new(&expand_seed(seed));
+        let pk = sk.genpk();
+        (sk, pk)
+    }
+
+    fn sign(sk: &SecKey, msg: &[u8]) -> Signature {
+        sk.sign_bytes(msg)
+    }
+
+    fn verify(pk: &PubKey, msg: &[u8], sig: &Signature) -> bool {
+        pk.verify_bytes(sig, msg).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sign_and_verify<S: SignatureScheme>(seed: &[u8]) -> S::PublicKey {
+        let (sk, pk) = S::keygen(seed);
+        let msg = b"Hello world";
+        let sig = S::sign(&sk, msg);
+        assert!(S::verify(&pk, msg, &sig));
+        assert!(!S::verify(&pk, b"another message", &sig));
+        pk
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let seed = [0x2a; SECRET_SEED_BYTES];
+        let pk = sign_and_verify::<GravitySignatureScheme>(&seed);
+        assert_eq!(pk, SecKey::new(&seed).genpk());
+        let pk = sign_and_verify::<GravitySignatureScheme>(b"short seed");
+        assert_ne!(pk, SecKey::new(&seed).genpk());
+    }
+
+    #[test]
+    fn test_name() {
+        assert_eq!(GravitySignatureScheme::name(), params().id);
+        assert!(GravitySignatureScheme::name().starts_with("gravity-haraka-"));
+    }
+
+    #[cfg(feature = "der")]
+    #[test]
+    fn test_algorithm_id() {
+        use spki::der::Encode;
+        let oid = GravitySignatureScheme::algorithm_id();
+        assert_eq!(oid.to_string(), "1.3.9999.99.1");
+        // Tag, length, then 40 * 1 + 3 and 9999, 99, 1 in base 128.
+        assert_eq!(
+            oid.to_der().unwrap(),
+            [0x06, 0x05, 0x2B, 0xCE, 0x0F, 0x63, 0x01]
+        );
+        let der = SecKey::new(&[0u8; 64]).genpk().as_der();
+        assert!(der.windows(7).any(|w| w == oid.to_der().unwrap()));
+    }
+
+    #[test]
+    fn test_expand_seed() {
+        let seed = [0x2a; SECRET_SEED_BYTES];
+        assert_eq!(expand_seed(&seed), seed);
+        // The length is bound, so that prefixes of each other differ.
+        assert_ne!(expand_seed(&[]), expand_seed(&[0]));
+        assert_ne!(expand_seed(&[0; 65]), expand_seed(&[0; 63]));
+        let random = expand_seed(b"short seed");
+        assert_ne!(random[..32], random[32..]);
+    }
+}
@@ -0,0 +1,182 @@
+// Power-on self-test, for deployments that must check a crypto module before
+// using it: known answers of the hash functions of the signatures and of a
+// Merkle authentication path, and the verification of an embedded signature.
+// Nothing is generated nor signed, so that it completes in milliseconds.
+use crate::config::{GRAVITY_C, GRAVITY_D, MERKLE_H, PORS_K, PORS_TAU};
+use crate::error::SelfTestError;
+use crate::gravity::{self, PubKey, Signature};
+use crate::hash::{self, Hash};
+use crate::merkle;
+
+// Outputs of hash_n_to_n on the bytes 0, 1, ..., 31, and of hash_2n_to_n on the
+// bytes 0, 1, ..., 63 (Haraka with 6 rounds, as in the signatures).
+const HARAKA256_KAT: &str = "dd90045b92993274fff8ccf46903d1c8184b404cc83735551c80a72b5fb32045";
+const HARAKA512_KAT: &str = "0e27514e8ab7b4ee153c9a5413fb1e984a914f5b6fea17228541ce1707fc4e64";
+
+// Root of the untweaked tree of the 8 leaves [i; 32], for i = 0, ..., 7, and the
+// leaf whose authentication path is checked.
+const MERKLE_ROOT_KAT: &str = "0a3c09b1c54e28e862dac75e73f13a82e89f58a25ab99d218d157f1f1f0a5319";
+const MERKLE_LEAF: usize = 5;
+const MERKLE_LEAVES: usize = 8;
+
+// Public key and signature of the bytes 0, 1, ..., 31 under the all-zero secret
+// key, for the S, M, L and P parameters; only the fixture of the parameters
+// built is embedded. There are no such fixtures for the SHAKE256 and HMAC
+// PRNGs, which the subset selection of verification uses, nor for the tweaked
+// hashes beyond S: the stage is skipped for them, as for other parameters.
+#[cfg(not(any(feature = "shake256", feature = "prng-hmac", feature = "tweaked")))]
+const SIGNATURE_FIXTURE: Option<(&str, &str)> =
+    match (PORS_TAU, PORS_K, MERKLE_H, GRAVITY_D, GRAVITY_C) {
+        (16, 24, 5, 1, 10) => Some((
+            include_str!("../test_files/test_genpk_zero_S.hex"),
+            include_str!("../test_files/test_sign_zero_S.hex"),
+        )),
+        (16, 32, 5, 7, 15) => Some((
+            include_str!("../test_files/test_genpk_zero_M.hex"),
+            include_str!("../test_files/test_sign_zero_M.hex"),
+        )),
+        (16, 28, 5, 10, 14) => Some((
+            include_str!("../test_files/test_genpk_zero_L.hex"),
+            include_str!("../test_files/test_sign_zero_L.hex"),
+        )),
+        (16, 24, 5, 0, 6) => Some((
+            include_str!("../test_files/test_genpk_zero_P.hex"),
+            include_str!("../test_files/test_sign_zero_P.hex"),
+        )),
+        _ => None,
+    };
+#[cfg(all(
+    feature = "tweaked",
+    not(any(feature = "shake256", feature = "prng-hmac"))
+))]
+const SIGNATURE_FIXTURE: Option<(&str, &str)> =
+    match (PORS_TAU, PORS_K, MERKLE_H, GRAVITY_D, GRAVITY_C) {
+        (16, 24, 5, 1, 10) => Some((
+            include_str!("../test_files/test_genpk_zero_S_tweaked.hex"),
+            include_str!("../test_files/test_sign_zero_S_tweaked.hex"),
+        )),
+        _ => None,
+    };
+#[cfg(any(feature = "shake256", feature = "prng-hmac"))]
+const SIGNATURE_FIXTURE: Option<(&str, &str)> = None;
+
+// Runs the stages in order, and reports the first that fails.
+pub fn self_test() -> Result<(), SelfTestError> {
+    let input: [u8; 64] = std::array::from_fn(|i| i as u8);
+    let mut src0: Hash = Hash::default();
+    src0.h.copy_from_slice(&input[..32]);
+    let mut src1: Hash = Hash::default();
+    src1.h.copy_from_slice(&input[32..]);
+
+    let mut dst: Hash = Hash::default();
+    hash::hash_n_to_n(&mut dst, &src0);
+    check(SelfTestError::Haraka256, &dst.h, HARAKA256_KAT)?;
+    hash::hash_2n_to_n(&mut dst, &src0, &src1);
+    check(SelfTestError::Haraka512, &dst.h, HARAKA512_KAT)?;
+
+    let leaves: Vec<Hash> = (0..MERKLE_LEAVES)
+        .map(|i| Hash { h: [i as u8; 32] })
+        .collect();
+    let tree =
+        merkle::MerkleTree::from_leaves(&leaves).map_err(|_| SelfTestError::MerkleAuthPath)?;
+    let mut auth: [Hash; 3] = Default::default();
+    tree.gen_auth(&mut auth, MERKLE_LEAF);
+    let root = merkle::root_from_auth(
+        &leaves[MERKLE_LEAF],
+        &auth,
+        MERKLE_LEAF as u64,
+        &hash::Tweak::NONE,
+    );
+    check(SelfTestError::MerkleAuthPath, &root.h, MERKLE_ROOT_KAT)?;
+    check(
+        SelfTestError::MerkleAuthPath,
+        &tree.root().h,
+        MERKLE_ROOT_KAT,
+    )?;
+
+    if let Some((pk_hex, sign_hex)) = SIGNATURE_FIXTURE {
+        let stage = SelfTestError::SignatureVerification;
+        let pk = PubKey::try_from(known_answer(stage, pk_hex).as_slice()).map_err(|_| stage)?;
+        let sign =
+            Signature::try_from(known_answer(stage, sign_hex).as_slice()).map_err(|_| stage)?;
+        pk.verify_bytes(&sign, &input[..32]).map_err(|_| stage)?;
+    }
+    Ok(())
+}
+
+fn check(stage: SelfTestError, output: &[u8], expect: &str) -> Result<(), SelfTestError> {
+    if output == known_answer(stage, expect).as_slice() {
+        Ok(())
+    } else {
+        Err(stage)
+    }
+}
+
+// Decodes an embedded hex vector, as Signature::from_hex_test_vector does. A
+// malformed vector decodes to nothing, so that its stage fails.
+fn known_answer(stage: SelfTestError, hex: &str) -> Vec<u8> {
+    #[allow(unused_mut)]
+    let mut bytes = gravity::decode_hex_vector(hex).unwrap_or_default();
+    #[cfg(test)]
+    if tests::CORRUPT_NEXT.with(|c| c.get()) == Some(stage) {
+        tests::CORRUPT_NEXT.with(|c| c.set(None));
+        if let Some(b) = bytes.last_mut() {
+            *b ^= 1;
+        }
+    }
+    #[cfg(not(test))]
+    let _ = stage;
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    thread_local! {
+        pub static CORRUPT_NEXT: Cell<Option<SelfTestError>> = const { Cell::new(None) };
+    }
+
+    // Corrupts the next embedded vector decoded for `stage`.
+    pub fn corrupt_next(stage: SelfTestError) {
+        CORRUPT_NEXT.with(|c| c.set(Some(stage)));
+    }
+
+    #[test]
+    fn test_self_test() {
+        let start = std::time::Instant::now();
+        assert_eq!(self_test(), Ok(()));
+        // Only optimized builds are held to the power-on budget.
+        if !cfg!(debug_assertions) {
+            assert!(start.elapsed() < std::time::Duration::from_millis(100));
+        }
+    }
+
+    #[test]
+    fn test_known_answer() {
+        let stage = SelfTestError::Haraka256;
+        assert_eq!(
+            known_answer(stage, "  # comment\nab cd\n0e"),
+            [0xab, 0xcd, 0x0e]
+        );
+        assert!(known_answer(stage, "abc").is_empty());
+    }
+
+    #[test]
+    fn test_corrupted_vector() {
+        let mut stages = vec![
+            SelfTestError::Haraka256,
+            SelfTestError::Haraka512,
+            SelfTestError::MerkleAuthPath,
+        ];
+        if SIGNATURE_FIXTURE.is_some() {
+            stages.push(SelfTestError::SignatureVerification);
+        }
+        for stage in stages {
+            corrupt_next(stage);
+            assert_eq!(self_test(), Err(stage));
+            assert_eq!(self_test(), Ok(()));
+        }
+    }
+}
@@ -1,88 +1,360 @@
 use crate::address;
 use crate::config::*;
-use crate::hash::Hash;
+use crate::error::DeserializeError;
+use crate::hash::{ByteSink, Hash, HashBackend, Tweak, TweakKind};
 use crate::merkle;
 use crate::prng;
 use crate::wots;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Deref;
 
-pub struct SecKey<'a> {
-    prng: &'a prng::Prng,
+// The WOTS part of a subtree signature, see Signature::wots_part.
+pub use crate::wots::Signature as WotsSignature;
+
+pub struct SecKey<'a, P = prng::Prng> {
+    prng: PrngRef<'a, P>,
+}
+
+// The PRNG of a SecKey: that of the caller, or its own for new_from_hash.
+enum PrngRef<'a, P> {
+    Borrowed(&'a P),
+    Owned(P),
+}
+
+impl<P> Deref for PrngRef<'_, P> {
+    type Target = P;
+
+    fn deref(&self) -> &P {
+        match self {
+            PrngRef::Borrowed(prng) => prng,
+            PrngRef::Owned(prng) => prng,
+        }
+    }
+}
+
+impl<P: fmt::Debug> fmt::Debug for SecKey<'_, P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SecKey {{ prng: {:?} }}", *self.prng)
+    }
+}
+pub struct PubKey<H = Hash> {
+    pub h: H,
+}
+// The WOTS key at one leaf of a subtree.
+pub struct WotsKey<H: HashBackend = Hash> {
+    sk: wots::SecKey<H>,
 }
-pub struct PubKey {
-    pub h: Hash,
+// Buffers of SecKey::genpk_with, sized for one subtree: the WOTS secret values
+// and chain ends of its 2^MERKLE_H leaves, their tweaks, the pairs of an L-tree
+// level, and the Merkle tree.
+pub struct Scratch<H = Hash> {
+    secrets: Vec<H>,
+    nodes: Vec<H>,
+    tweaks: Vec<Tweak>,
+    pairs: Vec<H>,
+    buf: merkle::MerkleBuf<H>,
 }
-#[derive(Default)]
-pub struct Signature {
-    wots_sign: wots::Signature,
-    auth: [Hash; MERKLE_H],
+#[derive(Clone)]
+pub struct Signature<H: HashBackend = Hash> {
+    wots_sign: wots::Signature<H>,
+    auth: [H; MERKLE_H],
 }
 
-impl<'a> SecKey<'a> {
-    pub fn new(prng: &'a prng::Prng) -> Self {
-        Self { prng }
+impl<H: HashBackend> Default for Signature<H> {
+    fn default() -> Self {
+        Signature {
+            wots_sign: Default::default(),
+            auth: [Default::default(); MERKLE_H],
+        }
     }
+}
 
-    pub fn genpk(&self, address: &address::Address) -> PubKey {
-        let mut buf = merkle::MerkleBuf::new(MERKLE_H);
-        let (mut address, _) = address.normalize_index(MERKLE_H_MASK as u64);
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Signature {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Signature {
+            wots_sign: u.arbitrary()?,
+            auth: u.arbitrary()?,
+        })
+    }
 
-        for leaf in buf.slice_leaves_mut() {
-            let sk = wots::SecKey::new(self.prng, &address);
-            let pk = sk.genpk();
-            *leaf = pk.h;
+    fn size_hint(depth: usize) -> (usize, Option<usize>) {
+        arbitrary::size_hint::and(
+            <wots::Signature as arbitrary::Arbitrary>::size_hint(depth),
+            <[Hash; MERKLE_H] as arbitrary::Arbitrary>::size_hint(depth),
+        )
+    }
+}
+
+impl SecKey<'static> {
+    // Same key as new(&Prng::new(h)), owning its PRNG: for key material derived
+    // elsewhere (e.g. by an HSM), which should be specific to this use. This is
+    // also the subtree key of a gravity::SecKey whose seed is h.
+    pub fn new_from_hash(h: &Hash) -> Self {
+        Self {
+            prng: PrngRef::Owned(prng::Prng::new(h)),
+        }
+    }
+}
+
+impl<'a, P: prng::BlockPrng> SecKey<'a, P> {
+    pub fn new(prng: &'a P) -> Self {
+        Self {
+            prng: PrngRef::Borrowed(prng),
+        }
+    }
+
+    // The address is that of the leaf, as derived by genpk and sign.
+    pub fn derive_key_at(&self, address: &address::Address) -> WotsKey<P::Block> {
+        WotsKey {
+            sk: wots::SecKey::new(&*self.prng, address),
+        }
+    }
+
+    // Same as derive_key_at, for a leaf address of the hyper-tree: layer l below
+    // GRAVITY_D, whose subtrees have GRAVITY_C + (l + 1) * MERKLE_H bits of leaf
+    // instances. None otherwise.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn key_for_address(&self, address: &address::Address) -> Option<WotsKey<P::Block>> {
+        let layer = address.get_layer() as usize;
+        if layer >= GRAVITY_D {
+            return None;
+        }
+        let bits = GRAVITY_C + (layer + 1) * MERKLE_H;
+        if bits < 64 && address.get_instance() >> bits != 0 {
+            return None;
+        }
+        Some(self.derive_key_at(address))
+    }
+
+    pub fn genpk(&self, address: &address::Address) -> PubKey<P::Block> {
+        self.genpk_with(address, &mut Scratch::new())
+    }
+
+    // Same as genpk, in the buffers of `scratch`, so that the public keys of
+    // many subtrees are derived without allocation (see gravity::gen_cache).
+    pub fn genpk_with(
+        &self,
+        address: &address::Address,
+        scratch: &mut Scratch<P::Block>,
+    ) -> PubKey<P::Block> {
+        let (mut address, _) = address.normalize_index(MERKLE_H_MASK as u64);
+        let tweak = Tweak::new(TweakKind::Subtree, &address);
+        let ell = wots::ell::<P::Block>();
+        for (secret, wots_tweak) in scratch
+            .secrets
+            .chunks_exact_mut(ell)
+            .zip(scratch.tweaks.iter_mut())
+        {
+            self.prng.genblocks(secret, &address);
+            *wots_tweak = Tweak::new(TweakKind::Wots, &address);
             address.incr_instance();
         }
+        wots::genpk_batch_into(
+            scratch.buf.slice_leaves_mut(),
+            &scratch.secrets,
+            &scratch.tweaks,
+            &mut scratch.nodes,
+            &mut scratch.pairs,
+        );
 
         let mut dst = Default::default();
-        merkle::merkle_compress_all(&mut dst, &mut buf);
+        merkle::merkle_compress_all(&mut dst, &mut scratch.buf, &tweak);
         PubKey { h: dst }
     }
 
-    pub fn sign(&self, address: &address::Address, msg: &Hash) -> (Hash, Signature) {
-        let mut sign: Signature = Default::default();
+    pub fn sign(
+        &self,
+        address: &address::Address,
+        msg: &P::Block,
+    ) -> (P::Block, Signature<P::Block>) {
+        let mut sign: Signature<P::Block> = Default::default();
 
         let mut buf = merkle::MerkleBuf::new(MERKLE_H);
-        let (mut address, index) = address.normalize_index(MERKLE_H_MASK as u64);
-
-        for (i, leaf) in buf.slice_leaves_mut().iter_mut().enumerate() {
-            let sk = wots::SecKey::new(self.prng, &address);
-            let pk = sk.genpk();
-            *leaf = pk.h;
-            if i == index {
-                sign.wots_sign = sk.sign(msg);
-            }
+        let (address, index) = address.normalize_index(MERKLE_H_MASK as u64);
+        let index = usize::try_from(index).expect("index must be below 1 << MERKLE_H");
+        let tweak = Tweak::new(TweakKind::Subtree, &address);
+        let (keys, leaves) = self.derive_leaves(address);
+        buf.slice_leaves_mut().copy_from_slice(&leaves);
+        sign.wots_sign = keys[index].sk.sign(msg);
+
+        let root = merkle::merkle_gen_auth(&mut sign.auth, &mut buf, index, &tweak);
+        (root, sign)
+    }
+
+    // Signs each hashes[i] at addresses[i], returning the results in order, as
+    // sign would. The WOTS keys and the Merkle tree of each distinct subtree are
+    // derived once, for all the signatures that fall in it.
+    pub fn batch_sign_all(
+        &self,
+        addresses: &[address::Address],
+        hashes: &[P::Block],
+    ) -> Vec<(P::Block, Signature<P::Block>)> {
+        assert_eq!(addresses.len(), hashes.len(), "one hash per address");
+
+        let mut subtrees = BTreeMap::new();
+        for address in addresses.iter() {
+            let (base, _) = address.normalize_index(MERKLE_H_MASK as u64);
+            subtrees
+                .entry(base)
+                .or_insert_with_key(|base| self.derive_subtree(base));
+        }
+
+        addresses
+            .iter()
+            .zip(hashes.iter())
+            .map(|(address, msg)| {
+                let (base, index) = address.normalize_index(MERKLE_H_MASK as u64);
+                let index = usize::try_from(index).expect("index must be below 1 << MERKLE_H");
+                let (keys, tree) = &subtrees[&base];
+                let mut sign = Signature {
+                    wots_sign: keys[index].sk.sign(msg),
+                    auth: [Default::default(); MERKLE_H],
+                };
+                tree.gen_auth(&mut sign.auth, index);
+                (tree.root(), sign)
+            })
+            .collect()
+    }
+
+    // The WOTS keys of all leaves of the subtree at `address`, and its tree.
+    fn derive_subtree(
+        &self,
+        address: &address::Address,
+    ) -> (Vec<WotsKey<P::Block>>, merkle::MerkleTree<P::Block>) {
+        let (address, _) = address.normalize_index(MERKLE_H_MASK as u64);
+        let tweak = Tweak::new(TweakKind::Subtree, &address);
+        let mut tree = merkle::MerkleTree::with_tweak(MERKLE_H, tweak);
+        let (keys, leaves) = self.derive_leaves(address);
+        tree.leaves().copy_from_slice(&leaves);
+        tree.generate();
+        (keys, tree)
+    }
+
+    // The WOTS keys of the 2^MERKLE_H leaves from `address` on, and their
+    // public hashes, computed together by wots::genpk_batch.
+    fn derive_leaves(
+        &self,
+        mut address: address::Address,
+    ) -> (Vec<WotsKey<P::Block>>, Vec<P::Block>) {
+        let mut keys = Vec::with_capacity(1 << MERKLE_H);
+        for _ in 0..1 << MERKLE_H {
+            keys.push(wots::SecKey::new(&*self.prng, &address));
             address.incr_instance();
         }
+        let leaves = wots::genpk_batch(&keys);
+        (keys.into_iter().map(|sk| WotsKey { sk }).collect(), leaves)
+    }
+}
 
-        let root = merkle::merkle_gen_auth(&mut sign.auth, &mut buf, index);
-        (root, sign)
+impl<H: HashBackend> Scratch<H> {
+    pub fn new() -> Self {
+        let ell = wots::ell::<H>();
+        Self {
+            secrets: vec![H::default(); ell << MERKLE_H],
+            nodes: vec![H::default(); ell << MERKLE_H],
+            tweaks: vec![Tweak::NONE; 1 << MERKLE_H],
+            pairs: vec![H::default(); ell / 2],
+            buf: merkle::MerkleBuf::new(MERKLE_H),
+        }
+    }
+}
+
+impl<H: HashBackend> Default for Scratch<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H: HashBackend> WotsKey<H> {
+    // The compressed WOTS public key, i.e. the leaf of the Merkle tree.
+    pub fn public_hash(&self) -> H {
+        self.sk.genpk().h
+    }
+
+    // The WOTS_ELL secret values, i.e. the starts of the chains.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn private_values(&self) -> &[H] {
+        self.sk.values()
     }
 }
 
-impl PubKey {
+impl<H: HashBackend> PubKey<H> {
     #[cfg(test)]
-    pub fn verify(&self, address: &address::Address, sign: &Signature, msg: &Hash) -> bool {
+    pub fn verify(&self, address: &address::Address, sign: &Signature<H>, msg: &H) -> bool {
         let h = sign.extract(&address, msg);
         self.h == h
     }
 }
 
+// Verification steps: extract is root_from_wots_pk of extract_wots_pk. The
+// leaves of a subtree are the compressed WOTS public keys, so root_from_leaf and
+// root_from_wots_pk are the same step.
+impl<H: HashBackend> Signature<H> {
+    pub fn extract(&self, address: &address::Address, msg: &H) -> H {
+        self.root_from_wots_pk(&self.extract_wots_pk(address, msg), address)
+    }
+
+    // The compressed WOTS public key that the signature of msg yields.
+    pub fn extract_wots_pk(&self, address: &address::Address, msg: &H) -> H {
+        self.wots_sign.extract(address, msg)
+    }
+
+    // The root of the subtree from the leaf at `address`, with the
+    // authentication path of the signature.
+    pub fn root_from_leaf(&self, leaf: &H, address: &address::Address) -> H {
+        let (base, index) = address.normalize_index(MERKLE_H_MASK as u64);
+        let tweak = Tweak::new(TweakKind::Subtree, &base);
+        merkle::root_from_auth(leaf, &self.auth, index, &tweak)
+    }
+
+    pub fn root_from_wots_pk(&self, wots_pk: &H, address: &address::Address) -> H {
+        self.root_from_leaf(wots_pk, address)
+    }
+
+    pub fn auth_path(&self) -> &[H; MERKLE_H] {
+        &self.auth
+    }
+
+    pub fn wots_part(&self) -> &WotsSignature<H> {
+        &self.wots_sign
+    }
+
+    // Number of hashes from the secret value to the signed value of each WOTS
+    // chain, in [0, WOTS_W - 1]: the base-WOTS_W digits of msg, then those of
+    // the checksum. The signature only holds the chain values, hence the signed
+    // message (the root of the layer below) is needed.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn wots_chain_index(&self, msg: &H) -> Vec<u8> {
+        wots::chain_lengths(msg)
+    }
+}
+
 impl Signature {
-    pub fn extract(&self, address: &address::Address, msg: &Hash) -> Hash {
-        let (_, index) = address.normalize_index(MERKLE_H_MASK as u64);
-        let mut h = self.wots_sign.extract(msg);
-        merkle::merkle_compress_auth(&mut h, &self.auth, MERKLE_H, index);
-        h
+    pub const SIZE: usize = HASH_SIZE * (Self::wots_length() + Self::auth_path_length());
+
+    pub const fn auth_path_length() -> usize {
+        MERKLE_H
+    }
+
+    pub const fn wots_length() -> usize {
+        WOTS_ELL
     }
 
     pub fn serialize(&self, output: &mut Vec<u8>) {
-        self.wots_sign.serialize(output);
+        self.write_to(output);
+    }
+
+    pub(crate) fn write_to<S: ByteSink>(&self, output: &mut S) {
+        self.wots_sign.write_to(output);
         for x in self.auth.iter() {
-            x.serialize(output);
+            x.write_to(output);
         }
     }
 
-    pub fn deserialize<'a, I>(it: &mut I) -> Option<Self>
+    #[deny(clippy::indexing_slicing)]
+    pub fn deserialize<'a, I>(it: &mut I) -> Result<Self, DeserializeError>
     where
         I: Iterator<Item = &'a u8>,
     {
@@ -93,7 +365,7 @@ impl Signature {
         for x in sign.auth.iter_mut() {
             *x = Hash::deserialize(it)?;
         }
-        Some(sign)
+        Ok(sign)
     }
 }
 
@@ -102,6 +374,39 @@ mod tests {
     use super::super::hash;
     use super::*;
 
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac")))]
+    #[test]
+    fn test_debug() {
+        let prng = prng::Prng::new(&hash::tests::HASH_ELEMENT);
+        let sk = SecKey::new(&prng);
+        assert_eq!(
+            format!("{:?}", sk),
+            "SecKey { prng: Prng { rkeys: <redacted> } }"
+        );
+    }
+
+    #[test]
+    fn test_new_from_hash() {
+        let seed = hash::tests::HASH_ELEMENT;
+        let prng = prng::Prng::new(&seed);
+        let borrowed = SecKey::new(&prng);
+        let owned = SecKey::new_from_hash(&seed);
+        let address = address::Address::new(1, 0x45);
+
+        assert_eq!(owned.genpk(&address).h, borrowed.genpk(&address).h);
+        assert_eq!(
+            owned.derive_key_at(&address).public_hash(),
+            borrowed.derive_key_at(&address).public_hash()
+        );
+        let msg = hash::tests::HASH_ELEMENT;
+        let (root, sign) = owned.sign(&address, &msg);
+        assert_eq!(root, borrowed.genpk(&address).h);
+        assert!(borrowed.genpk(&address).verify(&address, &sign, &msg));
+
+        let other = SecKey::new_from_hash(&hash::hash_n_to_n_ret(&seed));
+        assert_ne!(other.genpk(&address).h, root);
+    }
+
     #[test]
     fn test_sign_verify() {
         let seed = hash::tests::HASH_ELEMENT;
@@ -119,6 +424,194 @@ mod tests {
         assert!(pk.verify(&address, &sign, &msg));
     }
 
+    #[test]
+    fn test_genpk_with() {
+        let prng = prng::Prng::new(&hash::tests::HASH_ELEMENT);
+        let sk = SecKey::new(&prng);
+        let mut scratch = Scratch::new();
+        for instance in [0, 1 << MERKLE_H, 7 << MERKLE_H, 0] {
+            let address = address::Address::new(2, instance);
+            let (root, _) = sk.sign(&address, &hash::tests::HASH_ELEMENT);
+            assert_eq!(sk.genpk_with(&address, &mut scratch).h, root);
+        }
+    }
+
+    #[test]
+    fn test_sign_verify_large_instance() {
+        let prng = prng::Prng::new(&hash::tests::HASH_ELEMENT);
+        let sk = SecKey::new(&prng);
+        let msg = hash::tests::HASH_ELEMENT;
+        let instances = [
+            u32::MAX as u64 + 1,
+            (u32::MAX as u64) << MERKLE_H | 3,
+            u64::MAX,
+        ];
+        for &instance in instances.iter() {
+            let address = address::Address::new(0, instance);
+            let pk = sk.genpk(&address);
+            let (root, sign) = sk.sign(&address, &msg);
+            assert_eq!(root, pk.h);
+            assert!(pk.verify(&address, &sign, &msg));
+
+            // The high bits select a different subtree.
+            let other = address::Address::new(0, instance ^ 1 << 32);
+            assert_ne!(sk.genpk(&other).h, pk.h);
+        }
+    }
+
+    #[test]
+    fn test_derive_key_at() {
+        let prng = prng::Prng::new(&hash::tests::HASH_ELEMENT);
+        let sk = SecKey::new(&prng);
+        let msg = hash::tests::HASH_ELEMENT;
+        let instance: u64 = 0x05060708090a0b0c;
+        let address = address::Address::new(0x01020304, instance);
+
+        let key = sk.derive_key_at(&address);
+        let (root, sign) = sk.sign(&address, &msg);
+        assert_eq!(sign.wots_sign.extract(&address, &msg), key.public_hash());
+        assert_eq!(root, sk.genpk(&address).h);
+
+        let other = address::Address::new(0x01020304, instance ^ 1);
+        assert_ne!(sk.derive_key_at(&other).public_hash(), key.public_hash());
+        let other = address::Address::new(0x01020305, instance);
+        assert_ne!(sk.derive_key_at(&other).public_hash(), key.public_hash());
+    }
+
+    #[test]
+    fn test_key_for_address() {
+        let prng = prng::Prng::new(&hash::tests::HASH_ELEMENT);
+        let sk = SecKey::new(&prng);
+        let msg = hash::tests::HASH_ELEMENT;
+        for layer in 0..GRAVITY_D as u32 {
+            let base = 3 << MERKLE_H;
+            let (keys, leaves) = sk.derive_leaves(address::Address::new(layer, base));
+            for i in [0, 5, (1 << MERKLE_H) - 1] {
+                let address = address::Address::new(layer, base | i as u64);
+                let key = sk.key_for_address(&address).unwrap();
+                assert_eq!(key.public_hash(), leaves[i]);
+                assert_eq!(key.private_values(), keys[i].private_values());
+                assert_eq!(key.private_values().len(), WOTS_ELL);
+                let (root, sign) = sk.sign(&address, &msg);
+                assert_eq!(sign.root_from_leaf(&key.public_hash(), &address), root);
+            }
+        }
+
+        // Beyond the subtree layers, or the leaves of layer 0.
+        let out_of_range = [
+            address::Address::new(GRAVITY_D as u32, 0),
+            address::Address::new(u32::MAX, 0),
+            address::Address::new(0, 1 << (GRAVITY_C + MERKLE_H)),
+            address::Address::new(0, u64::MAX),
+        ];
+        for address in out_of_range.iter() {
+            assert!(sk.key_for_address(address).is_none());
+        }
+    }
+
+    #[test]
+    fn test_wots_chain_index() {
+        let prng = prng::Prng::new(&hash::tests::HASH_ELEMENT);
+        let sk = SecKey::new(&prng);
+        let msg = hash::tests::HASH_ELEMENT;
+        let address = address::Address::new(0, 5);
+        let (_, sign) = sk.sign(&address, &msg);
+
+        let lengths = sign.wots_chain_index(&msg);
+        assert_eq!(lengths.len(), WOTS_ELL);
+        assert!(lengths.iter().all(|&x| (x as usize) < WOTS_W));
+        let (digits, checksum) = lengths.split_at(2 * HASH_SIZE);
+        for (byte, pair) in msg.h.iter().zip(digits.chunks_exact(2)) {
+            assert_eq!(*byte, pair[0] << 4 | pair[1]);
+        }
+        let expected: usize = digits.iter().map(|&x| WOTS_W - 1 - x as usize).sum();
+        let checksum = checksum
+            .iter()
+            .enumerate()
+            .map(|(i, &x)| (x as usize) << (4 * i))
+            .sum::<usize>();
+        assert_eq!(checksum, expected);
+        assert_ne!(sign.wots_chain_index(&Hash::default()), lengths);
+    }
+
+    #[test]
+    fn test_root_from_leaf() {
+        let prng = prng::Prng::new(&hash::tests::HASH_ELEMENT);
+        let sk = SecKey::new(&prng);
+        let msg = hash::tests::HASH_ELEMENT;
+        let address = address::Address::new(1, 0x40 | 5);
+
+        let (root, sign) = sk.sign(&address, &msg);
+        let wots_pk = sign.extract_wots_pk(&address, &msg);
+        assert_eq!(wots_pk, sk.derive_key_at(&address).public_hash());
+        assert_eq!(sign.root_from_leaf(&wots_pk, &address), root);
+        assert_eq!(sign.root_from_wots_pk(&wots_pk, &address), root);
+        assert_eq!(sign.extract(&address, &msg), root);
+
+        // Another leaf, or the same leaf at another index, gives another root.
+        let other = hash::hash_n_to_n_ret(&wots_pk);
+        assert_ne!(sign.root_from_leaf(&other, &address), root);
+        let moved = address::Address::new(1, 0x40 | 4);
+        assert_ne!(sign.root_from_leaf(&wots_pk, &moved), root);
+    }
+
+    #[test]
+    fn test_batch_sign_all() {
+        let prng = prng::Prng::new(&hash::tests::HASH_ELEMENT);
+        let sk = SecKey::new(&prng);
+        // Several leaves of one subtree, a repeated address, and other subtrees
+        // and layers.
+        let addresses = [
+            address::Address::new(0, 0x40 | 3),
+            address::Address::new(0, 0x40),
+            address::Address::new(1, 0x40 | 3),
+            address::Address::new(0, u64::MAX),
+            address::Address::new(0, 0x40 | 3),
+            address::Address::new(0, 0x40 | MERKLE_H_MASK as u64),
+        ];
+        let hashes: Vec<Hash> = (0..addresses.len())
+            .map(|i| hash::hash_n_to_n_chain_ret(&hash::tests::HASH_ELEMENT, i))
+            .collect();
+
+        let batch = sk.batch_sign_all(&addresses, &hashes);
+        assert_eq!(batch.len(), addresses.len());
+        for ((address, msg), (root, sign)) in addresses.iter().zip(hashes.iter()).zip(batch.iter())
+        {
+            let pk = sk.genpk(address);
+            assert_eq!(*root, pk.h);
+            assert!(pk.verify(address, sign, msg));
+
+            let (expect_root, expect) = sk.sign(address, msg);
+            assert_eq!(*root, expect_root);
+            let mut bytes = Vec::<u8>::new();
+            sign.serialize(&mut bytes);
+            let mut expect_bytes = Vec::<u8>::new();
+            expect.serialize(&mut expect_bytes);
+            assert_eq!(bytes, expect_bytes);
+        }
+
+        assert!(sk.batch_sign_all(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn test_lengths() {
+        let prng = prng::Prng::new(&hash::tests::HASH_ELEMENT);
+        let address = address::Address::new(0, 0);
+        let (_, sign) = SecKey::new(&prng).sign(&address, &hash::tests::HASH_ELEMENT);
+
+        let mut wots_bytes = Vec::<u8>::new();
+        sign.wots_sign.serialize(&mut wots_bytes);
+        assert_eq!(wots_bytes.len() / HASH_SIZE, Signature::wots_length());
+
+        let mut sign_bytes = Vec::<u8>::new();
+        sign.serialize(&mut sign_bytes);
+        assert_eq!(sign_bytes.len(), Signature::SIZE);
+        assert_eq!(
+            (sign_bytes.len() - wots_bytes.len()) / HASH_SIZE,
+            Signature::auth_path_length()
+        );
+    }
+
     use std::hint::black_box;
     use test::Bencher;
 
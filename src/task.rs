@@ -0,0 +1,109 @@
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+// Future of a computation running on its own thread, so that CPU-heavy work
+// doesn't stall an async executor. Dropping the future detaches the thread, whose
+// result is then discarded; a panic is resumed in the task polling the future.
+pub struct Blocking<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+struct Shared<T> {
+    result: Option<thread::Result<T>>,
+    waker: Option<Waker>,
+}
+
+pub fn spawn_blocking<T, F>(f: F) -> Blocking<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let shared = Arc::new(Mutex::new(Shared {
+        result: None,
+        waker: None,
+    }));
+    let thread_shared = shared.clone();
+    thread::spawn(move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(f));
+        let waker = {
+            let mut shared = thread_shared.lock().unwrap();
+            shared.result = Some(result);
+            shared.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    });
+    Blocking { shared }
+}
+
+impl<T> Future for Blocking<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        let result = {
+            let mut shared = self.shared.lock().unwrap();
+            if shared.result.is_none() {
+                shared.waker = Some(cx.waker().clone());
+            }
+            shared.result.take()
+        };
+        match result {
+            Some(Ok(x)) => Poll::Ready(x),
+            Some(Err(e)) => panic::resume_unwind(e),
+            None => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::task::Wake;
+
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    // Minimal executor: polls all the futures on the current thread until they
+    // complete, parking in between.
+    pub fn block_on_all<F: Future + Unpin>(mut futures: Vec<F>) -> Vec<F::Output> {
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut outputs: Vec<Option<F::Output>> = futures.iter().map(|_| None).collect();
+        while outputs.iter().any(|x| x.is_none()) {
+            for (f, output) in futures.iter_mut().zip(outputs.iter_mut()) {
+                if output.is_none() {
+                    if let Poll::Ready(x) = Pin::new(f).poll(&mut cx) {
+                        *output = Some(x);
+                    }
+                }
+            }
+            if outputs.iter().any(|x| x.is_none()) {
+                thread::park();
+            }
+        }
+        outputs.into_iter().map(Option::unwrap).collect()
+    }
+
+    #[test]
+    fn test_spawn_blocking() {
+        let futures: Vec<_> = (0..4u64).map(|i| spawn_blocking(move || i * i)).collect();
+        assert_eq!(block_on_all(futures), [0, 1, 4, 9]);
+    }
+
+    #[test]
+    fn test_panic() {
+        let future = spawn_blocking(|| -> u64 { panic!("in the blocking task") });
+        let result = panic::catch_unwind(AssertUnwindSafe(|| block_on_all(vec![future])));
+        assert!(result.is_err());
+    }
+}
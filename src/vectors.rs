@@ -0,0 +1,157 @@
+use crate::config::*;
+use crate::gravity::{SecKey, Signature};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const ZERO_SEED: [u8; 64] = [0u8; 64];
+const ZERO_MSG: [u8; 32] = *b"\x00\x01\x02\x03\x04\x05\x06\x07\
+                              \x08\x09\x0a\x0b\x0c\x0d\x0e\x0f\
+                              \x10\x11\x12\x13\x14\x15\x16\x17\
+                              \x18\x19\x1a\x1b\x1c\x1d\x1e\x1f";
+
+const KAT_SEED: [u8; 64] = *b"\x7C\x99\x35\xA0\xB0\x76\x94\xAA\
+                              \x0C\x6D\x10\xE4\xDB\x6B\x1A\xDD\
+                              \x2F\xD8\x1A\x25\xCC\xB1\x48\x03\
+                              \x2D\xCD\x73\x99\x36\x73\x7F\x2D\
+                              \x86\x26\xED\x79\xD4\x51\x14\x08\
+                              \x00\xE0\x3B\x59\xB9\x56\xF8\x21\
+                              \x0E\x55\x60\x67\x40\x7D\x13\xDC\
+                              \x90\xFA\x9E\x8B\x87\x2B\xFB\x8F";
+const KAT_MSG: [u8; 33] = *b"\xD8\x1C\x4D\x8D\x73\x4F\xCB\xFB\
+                             \xEA\xDE\x3D\x3F\x8A\x03\x9F\xAA\
+                             \x2A\x2C\x99\x57\xE8\x35\xAD\x55\
+                             \xB2\x2E\x75\xBF\x57\xBB\x55\x6A\
+                             \xC8";
+
+// Suffix of the fixture file names of the tweaked hashes, which differ from
+// the specification. There is no reference for them: their fixtures, of the
+// zero key only, guard against unintended changes.
+#[cfg(feature = "tweaked")]
+const MODE_SUFFIX: &str = "_tweaked";
+#[cfg(not(feature = "tweaked"))]
+const MODE_SUFFIX: &str = "";
+
+// Name of the parameter set in fixture file names, None for custom parameters.
+// The pure PORST parameters of the porst feature are P.
+pub fn params_name() -> Option<&'static str> {
+    match GRAVITY_VERSION.minor {
+        0 => Some("S"),
+        1 => Some("M"),
+        2 => Some("L"),
+        _ if cfg!(feature = "porst") => Some("P"),
+        _ => None,
+    }
+}
+
+// Hex, 32 bytes per line, after a header comment. KAT files use upper case, as
+// in the NIST format.
+fn encode_hex_file(bytes: &[u8], upper: bool) -> String {
+    let mut output = format!(
+        "# {} {}, {} (tau={}, k={})\n",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        params().id,
+        PORS_TAU,
+        PORS_K
+    );
+    for line in bytes.chunks(HASH_SIZE) {
+        if upper {
+            output.push_str(&hex::encode_upper(line));
+        } else {
+            output.push_str(&hex::encode(line));
+        }
+        output.push('\n');
+    }
+    output
+}
+
+// Inverse of encode_hex_file: comment lines are skipped.
+pub fn decode_hex_file(contents: &str) -> Vec<u8> {
+    crate::gravity::decode_hex_vector(contents).unwrap()
+}
+
+fn serialize(sign: &Signature) -> Vec<u8> {
+    let mut sign_bytes = Vec::<u8>::new();
+    sign.serialize(&mut sign_bytes);
+    sign_bytes
+}
+
+// File names and contents of all fixtures for the current parameter set.
+pub fn fixtures() -> Vec<(String, String)> {
+    let name = params_name().expect("fixtures exist only for the S, M, L and P parameter sets");
+
+    let zero_sk = SecKey::new(&ZERO_SEED);
+    let mut fixtures = vec![
+        (
+            format!("test_genpk_zero_{}{}.hex", name, MODE_SUFFIX),
+            encode_hex_file(&zero_sk.genpk().h.h, false),
+        ),
+        (
+            format!("test_sign_zero_{}{}.hex", name, MODE_SUFFIX),
+            encode_hex_file(&serialize(&zero_sk.sign_bytes(&ZERO_MSG)), false),
+        ),
+    ];
+    if cfg!(feature = "tweaked") {
+        return fixtures;
+    }
+
+    let kat_sk = SecKey::new(&KAT_SEED);
+    fixtures.extend([(
+        format!("test_sign_kat_{}.hex", name),
+        encode_hex_file(&serialize(&kat_sk.sign_bytes(&KAT_MSG)), true),
+    )]);
+    fixtures
+}
+
+pub fn write_fixtures(dir: &Path) -> io::Result<Vec<String>> {
+    let mut names = vec![];
+    for (name, contents) in fixtures() {
+        fs::write(dir.join(&name), contents)?;
+        names.push(name);
+    }
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_file() {
+        let bytes: Vec<u8> = (0..80).collect();
+        for upper in [false, true] {
+            let contents = encode_hex_file(&bytes, upper);
+            assert!(contents.starts_with("# gravity "));
+            assert_eq!(contents.lines().count(), 1 + 3);
+            assert_eq!(decode_hex_file(&contents), bytes);
+        }
+    }
+
+    // Fixtures of the AES PRNG, with the specified or the tweaked hashes.
+    #[cfg(not(any(feature = "shake256", feature = "prng-hmac")))]
+    #[test]
+    fn test_fixtures_up_to_date() {
+        // The tweaked fixtures are only checked in for S.
+        if cfg!(feature = "tweaked") && params_name() != Some("S") {
+            return;
+        }
+        let features = if cfg!(feature = "tweaked") {
+            "vector-gen,tweaked"
+        } else if cfg!(feature = "porst") {
+            "vector-gen,porst"
+        } else {
+            "vector-gen"
+        };
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("test_files");
+        for (name, contents) in fixtures() {
+            let expect = fs::read_to_string(dir.join(&name)).unwrap();
+            assert!(
+                expect == contents,
+                "{} is out of date, run `cargo run --release --features {} --bin gen-vectors`",
+                name,
+                features
+            );
+        }
+    }
+}
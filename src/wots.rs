@@ -1,108 +1,209 @@
 use crate::address;
 use crate::config::*;
+use crate::error::DeserializeError;
 use crate::hash;
-use crate::hash::Hash;
-use crate::ltree::ltree_leaves_ret;
+use crate::hash::{ByteSink, Hash, HashBackend, Tweak, TweakKind};
+use crate::ltree::{ltree_batch, ltree_batch_into, ltree_leaves_ret, Ltree};
 use crate::prng;
-use std::default;
 
-pub struct SecKey([Hash; WOTS_ELL]);
-pub struct PubKey {
-    pub h: Hash,
+pub struct SecKey<H: HashBackend = Hash>(H::Chains, Tweak);
+pub struct PubKey<H = Hash> {
+    pub h: H,
 }
-pub struct Signature([Hash; WOTS_ELL]);
+#[derive(Clone)]
+pub struct Signature<H: HashBackend = Hash>(H::Chains);
 
-impl default::Default for Signature {
+impl<H: HashBackend> Default for Signature<H> {
     fn default() -> Self {
-        Signature([Default::default(); WOTS_ELL])
+        Signature(H::chains())
     }
 }
 
-// Split a message into a list of Winternitz indices (with checksum)
-#[allow(clippy::needless_range_loop)]
-fn split_msg(msg: &Hash) -> [usize; WOTS_ELL] {
+// WOTS_ELL random chain values.
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Signature {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let values: [Hash; WOTS_ELL] = u.arbitrary()?;
+        Ok(Signature(values))
+    }
+
+    fn size_hint(depth: usize) -> (usize, Option<usize>) {
+        <[Hash; WOTS_ELL] as arbitrary::Arbitrary>::size_hint(depth)
+    }
+}
+
+// Timing: the message, and therefore every chain length, is public. Secret
+// chain values are only processed by the hash function and copied, without
+// secret-dependent branches or memory accesses.
+
+// Number of chains for the hash size: two message digits per byte, and
+// WOTS_CHKSUM checksum digits, which is enough up to 136-byte hashes. This is
+// WOTS_ELL for Hash.
+pub const fn ell<H: HashBackend>() -> usize {
+    2 * H::SIZE + WOTS_CHKSUM
+}
+
+// Enough chains to keep the four lanes of hash_n_to_n_x4 busy for most of the
+// hashes.
+const CHAIN_WINDOW: usize = 16;
+
+// Split a message into Winternitz indices (with checksum), without allocation.
+fn split_msg<H: HashBackend>(msg: &H) -> impl Iterator<Item = usize> + '_ {
     // TODO: use some kind of static_assert instead
     assert_eq!(
         WOTS_W, 16,
         "Winternitz OTS is only implemented for WOTS_W = 16"
     );
 
-    let mut result = [0; WOTS_ELL];
-    let mut checksum: usize = 0;
-
-    for j in 0..HASH_SIZE {
-        let v = msg.h[j];
-        let a = (v >> 4) as usize;
-        let b = (v & 0xF) as usize;
-        checksum += (WOTS_W - 1 - a) + (WOTS_W - 1 - b);
-
-        result[2 * j] = a;
-        result[2 * j + 1] = b;
-    }
+    let digits = msg
+        .as_ref()
+        .iter()
+        .flat_map(|&v| [(v >> 4) as usize, (v & 0xF) as usize]);
+    let checksum: usize = digits.clone().map(|x| WOTS_W - 1 - x).sum();
 
-    for i in WOTS_ELL1..WOTS_ELL {
-        result[i] = checksum & 0xF;
-        checksum >>= 4;
-    }
+    digits.chain((0..WOTS_CHKSUM).map(move |i| (checksum >> (4 * i)) & 0xF))
+}
 
-    result
+// The digits of split_msg, for tests and debugging.
+#[cfg(any(test, feature = "test-utils"))]
+pub fn chain_lengths<H: HashBackend>(msg: &H) -> Vec<u8> {
+    split_msg(msg).map(|x| x as u8).collect()
 }
 
-impl SecKey {
-    pub fn new(prng: &prng::Prng, address: &address::Address) -> Self {
-        let mut sk = SecKey([Default::default(); WOTS_ELL]);
-        prng.genblocks(&mut sk.0, address);
+impl<H: HashBackend> SecKey<H> {
+    pub fn new<P: prng::BlockPrng<Block = H>>(prng: &P, address: &address::Address) -> Self {
+        let mut sk = SecKey(H::chains(), Tweak::new(TweakKind::Wots, address));
+        prng.genblocks(AsMut::<[H]>::as_mut(&mut sk.0), address);
         sk
     }
 
-    pub fn genpk(&self) -> PubKey {
-        let mut buf = [Default::default(); WOTS_ELL];
-        hash::hash_parallel_chains_all(&mut buf, &self.0, WOTS_W - 1);
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn values(&self) -> &[H] {
+        self.0.as_ref()
+    }
+
+    pub fn genpk(&self) -> PubKey<H> {
+        let mut buf = H::chains();
+        hash::hash_parallel_chains_all(buf.as_mut(), self.0.as_ref(), WOTS_W - 1, &self.1);
         PubKey {
-            h: ltree_leaves_ret(&buf),
+            h: ltree_leaves_ret(buf.as_ref(), &self.1),
         }
     }
 
-    #[allow(clippy::needless_range_loop)]
-    pub fn sign(&self, msg: &Hash) -> Signature {
-        let mut sign = Signature([Default::default(); WOTS_ELL]);
-        let lengths = split_msg(msg);
-
-        for i in 0..WOTS_ELL {
-            hash::hash_n_to_n_chain(&mut sign.0[i], &self.0[i], lengths[i]);
+    pub fn sign(&self, msg: &H) -> Signature<H> {
+        let mut sign: Signature<H> = Default::default();
+
+        let chains = sign
+            .0
+            .as_mut()
+            .iter_mut()
+            .zip(self.0.as_ref())
+            .zip(split_msg(msg));
+        for (i, ((dst, src), length)) in chains.enumerate() {
+            hash::hash_n_to_n_chain_at(dst, src, length, &self.1, i);
         }
 
         sign
     }
 }
 
-impl PubKey {
+// The compressed public keys of `keys`, as genpk would return them. The chain
+// ends of all keys are computed into one buffer, and their L-trees compressed
+// level by level all together.
+pub fn genpk_batch<H: HashBackend>(keys: &[SecKey<H>]) -> Vec<H> {
+    let ell = ell::<H>();
+    let mut nodes = vec![H::default(); keys.len() * ell];
+    for (chunk, key) in nodes.chunks_exact_mut(ell).zip(keys.iter()) {
+        hash::hash_parallel_chains_all(chunk, key.0.as_ref(), WOTS_W - 1, &key.1);
+    }
+    let tweaks: Vec<Tweak> = keys.iter().map(|key| key.1).collect();
+    ltree_batch(&mut nodes, &tweaks)
+}
+
+// Same as genpk_batch, into `roots`, for the keys whose secret values are the
+// consecutive chunks of `secrets`, chunk i with tweaks[i]. The chain ends are
+// computed into `nodes`, of the length of `secrets`, and `pairs` is the scratch
+// space of ltree_batch_into.
+pub fn genpk_batch_into<H: HashBackend>(
+    roots: &mut [H],
+    secrets: &[H],
+    tweaks: &[Tweak],
+    nodes: &mut [H],
+    pairs: &mut [H],
+) {
+    let ell = ell::<H>();
+    for ((chunk, secret), tweak) in nodes
+        .chunks_exact_mut(ell)
+        .zip(secrets.chunks_exact(ell))
+        .zip(tweaks.iter())
+    {
+        hash::hash_parallel_chains_all(chunk, secret, WOTS_W - 1, tweak);
+    }
+    ltree_batch_into(roots, nodes, tweaks, pairs);
+}
+
+impl<H: HashBackend> PubKey<H> {
     #[cfg(test)]
-    pub fn verify(&self, sign: &Signature, msg: &Hash) -> bool {
-        let h = sign.extract(msg);
+    pub fn verify(&self, sign: &Signature<H>, address: &address::Address, msg: &H) -> bool {
+        let h = sign.extract(address, msg);
         self.h == h
     }
 }
 
-impl Signature {
-    pub fn extract(&self, msg: &Hash) -> Hash {
-        let mut buf = [Default::default(); WOTS_ELL];
-        let lengths = split_msg(msg);
-
-        for i in 0..WOTS_ELL {
-            hash::hash_n_to_n_chain(&mut buf[i], &self.0[i], WOTS_W - 1 - lengths[i]);
+impl<H: HashBackend> Signature<H> {
+    // The signature must have ell::<H>() values, as those of SecKey::sign and
+    // deserialize do. No allocation, as this is on the verification path.
+    // The chains are hashed CHAIN_WINDOW at a time, to batch their hashes.
+    pub fn extract(&self, address: &address::Address, msg: &H) -> H {
+        let tweak = Tweak::new(TweakKind::Wots, address);
+        let mut ltree = Ltree::new(tweak);
+        let mut lengths = split_msg(msg);
+        for (w, window) in self.0.as_ref().chunks(CHAIN_WINDOW).enumerate() {
+            let mut states = [H::default(); CHAIN_WINDOW];
+            // Chains past the end of the window are already at the end.
+            let mut steps = [WOTS_W - 1; CHAIN_WINDOW];
+            for ((state, step), (src, length)) in states
+                .iter_mut()
+                .zip(steps.iter_mut())
+                .zip(window.iter().zip(lengths.by_ref()))
+            {
+                *state = *src;
+                *step = length;
+            }
+            hash::hash_parallel_chains_ragged(
+                &mut states,
+                &mut steps,
+                WOTS_W - 1,
+                &tweak,
+                w * CHAIN_WINDOW,
+            );
+            for state in states[..window.len()].iter() {
+                ltree.push(*state);
+            }
         }
+        ltree.root()
+    }
 
-        ltree_leaves_ret(&buf)
+    // Whether this signs msg for the compressed public key pk of the leaf at
+    // address. Both are public, so the comparison need not be constant-time.
+    pub fn verify(&self, pk: &H, msg: &H, address: &address::Address) -> bool {
+        self.extract(address, msg) == *pk
     }
+}
 
+impl Signature {
     pub fn serialize(&self, output: &mut Vec<u8>) {
+        self.write_to(output);
+    }
+
+    pub(crate) fn write_to<S: ByteSink>(&self, output: &mut S) {
         for x in self.0.iter() {
-            x.serialize(output);
+            x.write_to(output);
         }
     }
 
-    pub fn deserialize<'a, I>(it: &mut I) -> Option<Self>
+    #[deny(clippy::indexing_slicing)]
+    pub fn deserialize<'a, I>(it: &mut I) -> Result<Self, DeserializeError>
     where
         I: Iterator<Item = &'a u8>,
     {
@@ -110,7 +211,7 @@ impl Signature {
         for x in sign.0.iter_mut() {
             *x = Hash::deserialize(it)?;
         }
-        Some(sign)
+        Ok(sign)
     }
 }
 
@@ -130,14 +231,57 @@ mod tests {
         let pk = sk.genpk();
         let msg = hash::tests::HASH_ELEMENT;
         let sign = sk.sign(&msg);
-        assert!(pk.verify(&sign, &msg));
+        assert!(pk.verify(&sign, &address, &msg));
+    }
+
+    #[test]
+    fn test_chains() {
+        use crate::hash::Hash384;
+        assert_eq!(<Hash as HashBackend>::chains().as_ref().len(), WOTS_ELL);
+        assert_eq!(Hash384::chains().as_ref().len(), ell::<Hash384>());
+    }
+
+    #[test]
+    fn test_genpk_batch() {
+        let prng = prng::Prng::new(&hash::tests::HASH_ELEMENT);
+        let keys: Vec<SecKey> = (0..5)
+            .map(|i| SecKey::new(&prng, &address::Address::new(1, i)))
+            .collect();
+        let roots = genpk_batch(&keys);
+        for (key, root) in keys.iter().zip(roots.iter()) {
+            assert_eq!(key.genpk().h, *root);
+        }
+        assert!(genpk_batch::<Hash>(&[]).is_empty());
+    }
+
+    #[test]
+    #[ignore]
+    fn test_constant_time_genpk() {
+        let address = address::Address::new(0, 0);
+        let t = hash::tests::dudect_t_statistic(20_000, |seed| {
+            let prng = prng::Prng::new(seed);
+            SecKey::new(&prng, &address).genpk().h
+        });
+        assert!(t.abs() < hash::tests::DUDECT_THRESHOLD, "t = {}", t);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_constant_time_sign() {
+        let address = address::Address::new(0, 0);
+        let msg = hash::tests::HASH_ELEMENT;
+        let t = hash::tests::dudect_t_statistic(20_000, |seed| {
+            let prng = prng::Prng::new(seed);
+            SecKey::new(&prng, &address).sign(&msg).0
+        });
+        assert!(t.abs() < hash::tests::DUDECT_THRESHOLD, "t = {}", t);
     }
 
     #[test]
     fn test_split_msg_0() {
         let msg = Hash { h: [0; HASH_SIZE] };
-        let lengths = split_msg(&msg);
-        let mut expect: [usize; WOTS_ELL] = [0; WOTS_ELL];
+        let lengths: Vec<usize> = split_msg(&msg).collect();
+        let mut expect = [0; WOTS_ELL];
         expect[64] = 0x0;
         expect[65] = 0xC;
         expect[66] = 0x3;
@@ -145,8 +289,7 @@ mod tests {
             0x0 + 0xC * WOTS_W + 0x3 * WOTS_W * WOTS_W,
             WOTS_ELL1 * (WOTS_W - 1)
         );
-        // TODO: cannot use assert_eq for [usize; 67]
-        assert_eq!(&lengths as &[usize], &expect as &[usize]);
+        assert_eq!(lengths, expect);
     }
 
     #[test]
@@ -157,7 +300,7 @@ mod tests {
                   \x10\x11\x12\x13\x14\x15\x16\x17\
                   \x18\x19\x1a\x1b\x1c\x1d\x1e\x1f",
         };
-        let lengths = split_msg(&msg);
+        let lengths: Vec<usize> = split_msg(&msg).collect();
         let expect: [usize; WOTS_ELL] = [
             0, 0, 0, 1, 0, 2, 0, 3, 0, 4, 0, 5, 0, 6, 0, 7, 0, 8, 0, 9, 0, 10, 0, 11, 0, 12, 0, 13,
             0, 14, 0, 15, 1, 0, 1, 1, 1, 2, 1, 3, 1, 4, 1, 5, 1, 6, 1, 7, 1, 8, 1, 9, 1, 10, 1, 11,
@@ -170,8 +313,7 @@ mod tests {
             expect[64] + expect[65] * WOTS_W + expect[66] * WOTS_W * WOTS_W,
             checksum
         );
-        // TODO: cannot use assert_eq for [usize; 67]
-        assert_eq!(&lengths as &[usize], &expect as &[usize]);
+        assert_eq!(lengths, expect);
     }
 
     use std::hint::black_box;
@@ -241,13 +383,13 @@ mod tests {
         let pk = sk.genpk();
         let msg = hash::tests::HASH_ELEMENT;
         let sign = sk.sign(&msg);
-        b.iter(|| pk.verify(black_box(&sign), black_box(&msg)));
+        b.iter(|| pk.verify(black_box(&sign), &address, black_box(&msg)));
     }
 
     #[bench]
     fn bench_split_msg(b: &mut Bencher) {
         let msg = Hash { h: [0; HASH_SIZE] };
-        b.iter(|| split_msg(black_box(&msg)));
+        b.iter(|| split_msg(black_box(&msg)).sum::<usize>());
     }
 
     // TODO: test vectors
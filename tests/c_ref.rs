@@ -0,0 +1,204 @@
+// Cross-checks against the reference C implementation, see build.rs:
+//     GRAVITY_C_REF_DIR=... cargo test --release --features c-ref-tests --test c_ref
+#![cfg(feature = "c-ref-tests")]
+
+use gravity::gravity::{PubKey, SecKey, Signature};
+use gravity::hash::Hash;
+use std::os::raw::{c_int, c_uchar, c_ulonglong};
+
+extern "C" {
+    fn gravity_ref_public_key_bytes() -> c_ulonglong;
+    fn gravity_ref_signature_bytes() -> c_ulonglong;
+    fn gravity_ref_secret_key_bytes() -> c_ulonglong;
+    fn gravity_ref_genpk(pk: *mut c_uchar, seed: *const c_uchar) -> c_int;
+    fn gravity_ref_secret_key(sk: *mut c_uchar, seed: *const c_uchar) -> c_int;
+    fn gravity_ref_sign(
+        sig: *mut c_uchar,
+        siglen: *mut c_ulonglong,
+        m: *const c_uchar,
+        mlen: c_ulonglong,
+        seed: *const c_uchar,
+    ) -> c_int;
+    fn gravity_ref_verify(
+        pk: *const c_uchar,
+        sig: *const c_uchar,
+        siglen: c_ulonglong,
+        m: *const c_uchar,
+        mlen: c_ulonglong,
+    ) -> c_int;
+}
+
+fn seeds() -> Vec<[u8; 64]> {
+    let mut counting = [0u8; 64];
+    for (i, x) in counting.iter_mut().enumerate() {
+        *x = i as u8;
+    }
+    vec![[0u8; 64], counting]
+}
+
+const MESSAGES: [&[u8]; 3] = [b"", b"Hello world", &[0xab; 1000]];
+
+fn c_genpk(seed: &[u8; 64]) -> Vec<u8> {
+    let mut pk = vec![0u8; unsafe { gravity_ref_public_key_bytes() } as usize];
+    let ret = unsafe { gravity_ref_genpk(pk.as_mut_ptr(), seed.as_ptr()) };
+    assert_eq!(ret, 0, "reference key generation failed");
+    pk
+}
+
+fn c_secret_key(seed: &[u8; 64]) -> Vec<u8> {
+    let mut sk = vec![0u8; unsafe { gravity_ref_secret_key_bytes() } as usize];
+    let ret = unsafe { gravity_ref_secret_key(sk.as_mut_ptr(), seed.as_ptr()) };
+    assert_eq!(ret, 0, "reference key generation failed");
+    sk
+}
+
+fn c_sign(seed: &[u8; 64], msg: &[u8]) -> Vec<u8> {
+    let mut sig = vec![0u8; unsafe { gravity_ref_signature_bytes() } as usize];
+    let mut siglen = 0;
+    let ret = unsafe {
+        gravity_ref_sign(
+            sig.as_mut_ptr(),
+            &mut siglen,
+            msg.as_ptr(),
+            msg.len() as c_ulonglong,
+            seed.as_ptr(),
+        )
+    };
+    assert_eq!(ret, 0, "reference signing failed");
+    sig.truncate(siglen as usize);
+    sig
+}
+
+fn c_verify(pk: &[u8], sig: &[u8], msg: &[u8]) -> bool {
+    let ret = unsafe {
+        gravity_ref_verify(
+            pk.as_ptr(),
+            sig.as_ptr(),
+            sig.len() as c_ulonglong,
+            msg.as_ptr(),
+            msg.len() as c_ulonglong,
+        )
+    };
+    ret == 0
+}
+
+fn rust_sign(sk: &SecKey, msg: &[u8]) -> (Signature, Vec<u8>) {
+    let sign = sk.sign_bytes(msg);
+    let bytes = Vec::<u8>::from(&sign);
+    (sign, bytes)
+}
+
+// Names and lengths of the serialized components, in serialization order.
+fn components(sign: &Signature) -> Vec<(String, usize)> {
+    let sizes = sign.size_breakdown();
+    let mut components = vec![
+        ("pors pepper".to_string(), sizes.pors_pepper),
+        ("pors values".to_string(), sizes.pors_values),
+        (
+            "pors octopus".to_string(),
+            sizes.pors_octopus + sizes.pors_padding,
+        ),
+    ];
+    for i in 0..sizes.subtree_wots.len() {
+        components.push((format!("subtree {} wots", i), sizes.subtree_wots[i]));
+        components.push((format!("subtree {} auth", i), sizes.subtree_auth[i]));
+    }
+    components.push(("auth c".to_string(), sizes.auth_c));
+    components
+}
+
+// Panics with a hex dump of the first component that differs.
+fn assert_same_signature(context: &str, sign: &Signature, expect: &[u8], actual: &[u8]) {
+    let mut offset = 0;
+    for (name, len) in components(sign) {
+        let end = offset + len;
+        let e = &expect[offset.min(expect.len())..end.min(expect.len())];
+        let a = &actual[offset.min(actual.len())..end.min(actual.len())];
+        if e != a {
+            panic!(
+                "{}: first difference in {} (bytes {}..{})\nrust: {}\nC:    {}",
+                context,
+                name,
+                offset,
+                end,
+                hex::encode(e),
+                hex::encode(a)
+            );
+        }
+        offset = end;
+    }
+    assert_eq!(expect.len(), actual.len(), "{}: lengths differ", context);
+}
+
+#[test]
+fn test_genpk() {
+    for seed in seeds() {
+        let pk = SecKey::new(&seed).genpk();
+        let c_pk = c_genpk(&seed);
+        assert_eq!(
+            hex::encode(pk.h.h),
+            hex::encode(&c_pk),
+            "public keys differ for seed {}",
+            hex::encode(&seed[..])
+        );
+    }
+}
+
+#[test]
+fn test_reference_secret_key() {
+    for seed in seeds() {
+        let c_sk = c_secret_key(&seed);
+        let sk = SecKey::new(&seed);
+        assert!(
+            sk.to_reference_bytes() == c_sk,
+            "secret keys differ for seed {}",
+            hex::encode(&seed[..])
+        );
+        let imported = SecKey::from_reference_bytes(&c_sk).unwrap();
+        assert_eq!(imported.genpk(), sk.genpk());
+    }
+}
+
+#[test]
+fn test_c_sign_rust_verify() {
+    for seed in seeds() {
+        let sk = SecKey::new(&seed);
+        let pk = sk.genpk();
+        for msg in MESSAGES.iter() {
+            let c_sig = c_sign(&seed, msg);
+            let (sign, bytes) = rust_sign(&sk, msg);
+            assert_same_signature(
+                &format!("message {}", hex::encode(msg)),
+                &sign,
+                &bytes,
+                &c_sig,
+            );
+
+            let c_sign = Signature::try_from(c_sig.as_slice()).unwrap();
+            assert!(pk.verify_bytes(&c_sign, msg).is_ok());
+        }
+    }
+}
+
+#[test]
+fn test_rust_sign_c_verify() {
+    for seed in seeds() {
+        let sk = SecKey::new(&seed);
+        let pk: [u8; 32] = (&sk.genpk()).into();
+        for msg in MESSAGES.iter() {
+            let (_, bytes) = rust_sign(&sk, msg);
+            assert!(c_verify(&pk, &bytes, msg), "C rejected the Rust signature");
+
+            let mut tampered = bytes.clone();
+            tampered[0] ^= 1;
+            assert!(!c_verify(&pk, &tampered, msg));
+        }
+    }
+
+    let other = PubKey {
+        h: Hash { h: [0x55; 32] },
+    };
+    let (_, bytes) = rust_sign(&SecKey::new(&seeds()[0]), b"Hello world");
+    let other: [u8; 32] = (&other).into();
+    assert!(!c_verify(&other, &bytes, b"Hello world"));
+}
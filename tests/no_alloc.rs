@@ -0,0 +1,94 @@
+// Counts the heap allocations of verification and of serialization to a caller
+// buffer, which must make none, and of key generation, which must not allocate
+// per leaf. Run it in release mode, as it generates key pairs:
+//     cargo test --release --test no_alloc
+use gravity::gravity::{PubKey, SecKey, Signature};
+use gravity::hash;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct CountingAllocator;
+
+thread_local! {
+    // Only the allocations of the current thread are counted, so that other
+    // tests running in parallel do not interfere.
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count_allocation() {
+    let _ = ALLOCATIONS.try_with(|c| c.set(c.get() + 1));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation();
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations<T, F: FnOnce() -> T>(f: F) -> (T, usize) {
+    let start = ALLOCATIONS.with(|c| c.get());
+    let result = f();
+    (result, ALLOCATIONS.with(|c| c.get()) - start)
+}
+
+#[test]
+fn test_verify_no_alloc() {
+    let sk = SecKey::new(&[0u8; 64]);
+    let pk: PubKey = sk.genpk();
+    for msg in [&b""[..], b"Hello world", &[0xab; 1000]].iter() {
+        let sign = sk.sign_bytes(msg);
+        let h = hash::long_hash(msg);
+
+        let (result, count) = allocations(|| pk.verify_bytes(&sign, msg));
+        assert_eq!(result, Ok(()));
+        assert_eq!(count, 0, "verify_bytes allocated");
+
+        let (root, count) = allocations(|| sign.recover_root(&h));
+        assert_eq!(root, Ok(pk.h));
+        assert_eq!(count, 0, "recover_root allocated");
+
+        let (result, count) = allocations(|| pk.verify_bytes(&sign, b"another message"));
+        assert!(result.is_err());
+        assert_eq!(count, 0, "failed verification allocated");
+
+        let mut buf = [0u8; Signature::SIZE];
+        let ((), count) = allocations(|| sign.serialize_into(&mut buf));
+        assert_eq!(count, 0, "serialize_into allocated");
+        assert_eq!(buf[..], Vec::<u8>::from(&sign)[..]);
+    }
+
+    // Sanity check of the allocator itself.
+    let (_, count) = allocations(|| vec![0u8; 16]);
+    assert_eq!(count, 1);
+}
+
+// The subtrees of the top-level tree share one scratch workspace, so that the
+// count does not grow with their 2^GRAVITY_C roots (it was about 38 per
+// subtree before). With the parallel feature, the leaves are generated on the
+// rayon threads, with one scratch per job, and the first key also starts the
+// global pool (about 20 allocations), which is done before counting.
+#[test]
+fn test_keygen_constant_alloc() {
+    #[cfg(feature = "parallel")]
+    SecKey::new(&[1u8; 64]);
+    let (_, count) = allocations(|| SecKey::new(&[0u8; 64]));
+    assert!(count <= 16, "SecKey::new made {} allocations", count);
+}
@@ -0,0 +1,257 @@
+// Calls the public API with empty, maximal and corrupted inputs, and checks that
+// no panic escapes. Run it in release mode, as it generates a key pair:
+//     cargo test --release --test no_panic
+use gravity::address::{self, Address, LeafIndex};
+use gravity::cost::{CostModel, Count};
+use gravity::error::GravityError;
+use gravity::gravity::{
+    FrameDecoder, GravityKeyPair, PubKey, SecKey, Signature, SignatureStream, VerificationKey,
+};
+use gravity::hash::{self, Hash};
+use gravity::merkle;
+use gravity::rotation::{self, TransitionCert};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::OnceLock;
+
+fn no_panic_ret<T, F: FnOnce() -> T>(f: F) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(x) => x,
+        Err(_) => panic!("a panic escaped from the public API"),
+    }
+}
+
+fn no_panic<T, F: FnOnce() -> T>(f: F) {
+    no_panic_ret(f);
+}
+
+struct Fixture {
+    sk: SecKey,
+    pk: PubKey,
+    msg: &'static [u8],
+    sign_bytes: Vec<u8>,
+    keypair_bytes: Vec<u8>,
+}
+
+fn fixture() -> &'static Fixture {
+    static FIXTURE: OnceLock<Fixture> = OnceLock::new();
+    FIXTURE.get_or_init(|| {
+        let keypair = GravityKeyPair::generate(&[0x42; 64]);
+        let msg = b"Hello world";
+        let sign_bytes = Vec::<u8>::from(&keypair.sign(msg));
+        let mut keypair_bytes = Vec::<u8>::new();
+        keypair.serialize(&mut keypair_bytes);
+        Fixture {
+            pk: keypair.public_key,
+            sk: keypair.secret_key,
+            msg,
+            sign_bytes,
+            keypair_bytes,
+        }
+    })
+}
+
+// Deterministic corruptions of `bytes`: flipped bytes, truncations and
+// extensions, at pseudo-random positions.
+fn corruptions(bytes: &[u8], count: usize) -> Vec<Vec<u8>> {
+    let mut state = hash::long_hash(bytes);
+    let mut next = || {
+        state = hash::long_hash(&state.h);
+        u64::from_le_bytes(state.h[..8].try_into().unwrap()) as usize
+    };
+    let mut result = vec![
+        vec![],
+        vec![0xFF; bytes.len()],
+        vec![0xFF; 2 * bytes.len() + 1],
+    ];
+    for _ in 0..count {
+        let mut x = bytes.to_vec();
+        match next() % 3 {
+            0 => {
+                let i = next() % x.len().max(1);
+                if let Some(b) = x.get_mut(i) {
+                    *b ^= 1 << (next() % 8);
+                }
+            }
+            1 => x.truncate(next() % (x.len() + 1)),
+            _ => x.extend(std::iter::repeat_n(next() as u8, next() % 64 + 1)),
+        }
+        result.push(x);
+    }
+    result
+}
+
+#[test]
+fn test_parsing() {
+    let f = fixture();
+    for bytes in corruptions(&f.sign_bytes, 64).iter() {
+        let sign = no_panic_ret(|| Signature::try_from(bytes.as_slice()));
+        no_panic(|| Signature::is_canonical(bytes));
+        no_panic(|| Signature::deserialize(&mut bytes.iter()));
+        no_panic(|| Signature::deserialize_many(bytes, usize::MAX));
+        no_panic(|| Signature::parse_and_validate(bytes));
+        no_panic(|| {
+            Signature::from_multipart(&[&bytes[..bytes.len() / 2], &bytes[bytes.len() / 2..]])
+        });
+        no_panic(|| Signature::decode_hex(&String::from_utf8_lossy(bytes)));
+        no_panic(|| SignatureStream::new(bytes).count());
+        no_panic(|| {
+            f.pk.verify_streaming(bytes.as_slice(), &hash::long_hash(f.msg))
+        });
+        if let Ok(sign) = sign {
+            no_panic(|| f.pk.verify_bytes(&sign, f.msg));
+            no_panic(|| f.pk.verify_timing_safe(&sign, f.msg));
+            no_panic(|| f.pk.verifier().verify_bytes(&sign, f.msg));
+            no_panic(|| sign.recover_root(&hash::long_hash(f.msg)));
+            no_panic(|| sign.size_breakdown().total());
+        }
+        let mut public = [0u8; 32];
+        public.copy_from_slice(&f.pk.h.h);
+        no_panic(|| gravity::gravity_verify(&public, f.msg, bytes.clone()));
+    }
+
+    let sign = Signature::try_from(f.sign_bytes.as_slice()).unwrap();
+    for bytes in corruptions(&sign.encode_framed(), 64).iter() {
+        no_panic(|| Signature::decode_framed(bytes));
+        let mut decoder = FrameDecoder::new();
+        for chunk in bytes.chunks(1000) {
+            decoder.feed(chunk);
+            no_panic(|| decoder.next_signature());
+        }
+    }
+
+    let mut attached = f.sk.sign_attached(f.msg);
+    for bytes in corruptions(&attached, 64).iter() {
+        no_panic(|| f.pk.open_attached(bytes));
+    }
+    attached.truncate(12);
+    assert!(no_panic_ret(|| f.pk.open_attached(&attached)).is_err());
+
+    // Each corruption that parses regenerates a key pair.
+    for bytes in corruptions(&f.keypair_bytes, 4).iter() {
+        no_panic(|| GravityKeyPair::deserialize(&mut bytes.iter()));
+        no_panic(|| SecKey::from_slice(bytes));
+        no_panic(|| PubKey::try_from(bytes.as_slice()));
+        no_panic(|| VerificationKey::deserialize(&mut bytes.iter()));
+        no_panic(|| Hash::deserialize(&mut bytes.iter()));
+    }
+
+    let cert = rotation::rotate_with(&f.sk, &f.pk, 1, b"note");
+    for bytes in corruptions(&Vec::<u8>::from(&cert), 16).iter() {
+        if let Ok(cert) = no_panic_ret(|| TransitionCert::try_from(bytes.as_slice())) {
+            no_panic(|| cert.verify(&f.pk));
+        }
+    }
+}
+
+#[test]
+fn test_signing() {
+    let f = fixture();
+    for msg in [&b""[..], &[0xFF; 1 << 16][..]].iter() {
+        let sign = no_panic_ret(|| f.sk.sign_bytes(msg));
+        assert_eq!(f.pk.verify_bytes(&sign, msg), Ok(()));
+        let (_, record) = no_panic_ret(|| f.sk.sign_bytes_and_record(msg));
+        for index in [0, record.leaf_index, u64::MAX].iter() {
+            no_panic(|| f.sk.sign_message_at_index(msg, *index));
+            no_panic(|| f.sk.audit_log_path(*index).map(|p| p.verify(&f.pk.h)));
+            no_panic(|| {
+                f.sk.prove_instance(*index)
+                    .map(|p| f.pk.verify_instance(&p))
+            });
+        }
+    }
+    for msg in [Hash::default(), Hash { h: [0xFF; 32] }].iter() {
+        no_panic_ret(|| f.sk.sign_hash_checked(msg)).unwrap();
+    }
+}
+
+#[test]
+fn test_signature_accessors() {
+    let f = fixture();
+    let sign = Signature::try_from(f.sign_bytes.as_slice()).unwrap();
+    let msg = hash::long_hash(f.msg);
+    let root = f.pk.h;
+    for index in [0, LeafIndex::COUNT, usize::MAX].iter() {
+        no_panic(|| sign.verify_auth_c_alone(&root, &root, *index));
+    }
+
+    let pors = sign.pors_signature();
+    for t in [0, pors.num_trees(), usize::MAX].iter() {
+        no_panic(|| pors.tree_leaf_value(*t));
+        no_panic(|| pors.tree_leaf_index(*t, &msg));
+        no_panic(|| pors.tree_auth_path(*t, &msg));
+    }
+    assert_eq!(
+        pors.tree_leaf_value(usize::MAX),
+        Err(GravityError::InvalidParameter("tree"))
+    );
+}
+
+#[test]
+fn test_building_blocks() {
+    for &(layer, instance) in [(0, 0), (u32::MAX, u64::MAX)].iter() {
+        let mut address = Address::new(layer, instance);
+        no_panic(|| {
+            address.next_layer();
+            address.incr_instance();
+            let _ = address.increment_instance(u64::MAX);
+            address.shift(usize::MAX);
+            address.normalize_index(u64::MAX);
+            address.leaf_index()
+        });
+    }
+    no_panic_ret(|| LeafIndex::try_from(usize::MAX)).unwrap_err();
+    let mut subtrees = no_panic_ret(|| address::for_every_subtree(usize::MAX, usize::MAX));
+    assert!(no_panic_ret(|| subtrees.next()).is_some());
+
+    let h = Hash::default();
+    no_panic(|| Hash::concat_and_hash(&[]));
+    no_panic(|| h.derive_subkeys(0));
+    for index in [0, u64::MAX].iter() {
+        no_panic(|| merkle::root_from_auth(&h, &[], *index, &hash::Tweak::NONE));
+        no_panic_ret(|| merkle::check_auth(&[h; 65], *index)).unwrap_err();
+    }
+    no_panic_ret(|| merkle::root_of(&[h; 3])).unwrap_err();
+    assert!(no_panic_ret(|| merkle::MerkleTree::from_leaves(&[h; 0])).is_err());
+    let mut tree = merkle::MerkleTree::new(2);
+    no_panic_ret(|| tree.batch_update(&[(usize::MAX, h)])).unwrap_err();
+    assert_eq!(no_panic_ret(|| tree.node_at(usize::MAX, usize::MAX)), None);
+
+    no_panic_ret(|| CostModel::for_params(gravity::params())).unwrap();
+    let count = Count::exact(u64::MAX);
+    assert_eq!(no_panic_ret(|| count + count).max, u64::MAX);
+    assert_eq!(no_panic_ret(|| count * u64::MAX).min, u64::MAX);
+    let params = gravity::params();
+    for (d, c, h, k, t) in [
+        (0, 0, 0, 0, 0),
+        (1, 64, 5, 24, 1 << 16),
+        (usize::MAX, 10, 5, 24, 1 << 16),
+        (1, 10, usize::MAX, 24, 1 << 16),
+        (1, 10, 5, 24, 3),
+        (1, 10, 5, 24, 1 << 63),
+        (1, 10, 5, usize::MAX, 1 << 16),
+    ] {
+        let info = gravity::ParamsInfo {
+            id: String::new(),
+            gravity_d: d,
+            gravity_c: c,
+            merkle_h: h,
+            pors_k: k,
+            pors_t: t,
+            ..*params
+        };
+        no_panic_ret(|| CostModel::for_params(&info)).unwrap_err();
+    }
+    // The largest accepted parameters.
+    for (d, c, h) in [(1, 32, 20), (0, 32, 1), (32, 32, 1)] {
+        let info = gravity::ParamsInfo {
+            id: String::new(),
+            gravity_d: d,
+            gravity_c: c,
+            merkle_h: h,
+            pors_k: 1 << 20,
+            pors_t: 1 << 20,
+            ..*params
+        };
+        no_panic_ret(|| CostModel::for_params(&info)).unwrap();
+    }
+}